@@ -0,0 +1,91 @@
+// Copyright 2021 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use rpc_perf::codec::*;
+use rpc_perf::config::Action;
+use rustcommon_buffer::Buffer;
+
+const KEY: &[u8] = b"00000042";
+const VALUE: &[u8] = &[b'a'; 64];
+
+// Compares formatting each request from scratch to patching the key and value
+// into a pre-rendered template. The buffer is drained after each request so
+// that only the encoding cost is measured.
+fn encode(c: &mut Criterion) {
+    let set = Shape {
+        action: Action::Set,
+        key: KEY.len(),
+        value: VALUE.len(),
+        ttl: Some(120),
+    };
+    let mut buf = Buffer::with_capacity(1024, 1024);
+    let mut sink = std::io::sink();
+
+    let memcache = Memcache::new();
+    let template = memcache.template(&set).unwrap();
+    let mut group = c.benchmark_group("memcache/set");
+    group.bench_function("format", |b| {
+        b.iter(|| {
+            memcache.set(&mut buf, KEY, VALUE, Some(120), None);
+            let _ = buf.write_to(&mut sink);
+        })
+    });
+    group.bench_function("template", |b| {
+        b.iter(|| {
+            template.render(&mut buf, KEY, VALUE);
+            let _ = buf.write_to(&mut sink);
+        })
+    });
+    group.finish();
+
+    let redis = Redis::new(RedisMode::Resp);
+    let template = redis.template(&set).unwrap();
+    let mut group = c.benchmark_group("redis/set");
+    group.bench_function("format", |b| {
+        b.iter(|| {
+            redis.set(&mut buf, KEY, VALUE, Some(120));
+            let _ = buf.write_to(&mut sink);
+        })
+    });
+    group.bench_function("template", |b| {
+        b.iter(|| {
+            template.render(&mut buf, KEY, VALUE);
+            let _ = buf.write_to(&mut sink);
+        })
+    });
+    group.finish();
+}
+
+// Response parsing for small values, which dominates client CPU at high rates
+fn decode(c: &mut Criterion) {
+    let mut hit = b"VALUE 00000042 0 64\r\n".to_vec();
+    hit.extend_from_slice(VALUE);
+    hit.extend_from_slice(b"\r\nEND\r\n");
+
+    let memcache = Memcache::new();
+    let mut group = c.benchmark_group("memcache/decode");
+    group.bench_function("hit", |b| b.iter(|| memcache.decode(black_box(&hit))));
+    group.bench_function("miss", |b| {
+        b.iter(|| memcache.decode(black_box(b"END\r\n")))
+    });
+    group.bench_function("stored", |b| {
+        b.iter(|| memcache.decode(black_box(b"STORED\r\n")))
+    });
+    group.finish();
+
+    let mut hit = b"$64\r\n".to_vec();
+    hit.extend_from_slice(VALUE);
+    hit.extend_from_slice(b"\r\n");
+
+    let redis = Redis::new(RedisMode::Resp);
+    let mut group = c.benchmark_group("redis/decode");
+    group.bench_function("hit", |b| b.iter(|| redis.decode(black_box(&hit))));
+    group.bench_function("miss", |b| b.iter(|| redis.decode(black_box(b"$-1\r\n"))));
+    group.bench_function("ok", |b| b.iter(|| redis.decode(black_box(b"+OK\r\n"))));
+    group.finish();
+}
+
+criterion_group!(benches, encode, decode);
+criterion_main!(benches);
@@ -0,0 +1,39 @@
+// Copyright 2021 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+use std::path::Path;
+use std::process::Command;
+
+// record the commit being built, so that a run bundle identifies the exact
+// version of rpc-perf which produced it
+fn main() {
+    if let Some(commit) = git(&["rev-parse", "HEAD"]) {
+        println!("cargo:rustc-env=RPC_PERF_COMMIT={}", commit);
+    }
+    // a new commit moves the branch which HEAD points to, rather than HEAD,
+    // and the branch may be a loose ref or in the packed refs
+    let mut paths = vec![".git/HEAD".to_string(), ".git/packed-refs".to_string()];
+    if let Some(branch) = git(&["rev-parse", "--symbolic-full-name", "HEAD"]) {
+        if branch.starts_with("refs/") {
+            paths.push(format!(".git/{}", branch));
+        }
+    }
+    for path in paths {
+        // a missing path would have the build script run on every build
+        if Path::new(&path).exists() {
+            println!("cargo:rerun-if-changed={}", path);
+        }
+    }
+}
+
+/// the trimmed output of a successful git command
+fn git(args: &[&str]) -> Option<String> {
+    Command::new("git")
+        .args(args)
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .and_then(|output| String::from_utf8(output.stdout).ok())
+        .map(|output| output.trim().to_string())
+}
@@ -2,25 +2,29 @@
 // Licensed under the Apache License, Version 2.0
 // http://www.apache.org/licenses/LICENSE-2.0
 
-use crate::ClientConfig;
+use rpc_perf::Runner;
 
+use serde_json::{json, Value};
 use tiny_http::{Method, Response, Server};
 
 use std::net::SocketAddr;
+use std::sync::Arc;
 
 pub(crate) struct Http {
-    client_config: ClientConfig,
+    // the runner for each group of endpoints, which are all updated together
+    // so that they keep receiving the same workload
+    runners: Vec<Arc<Runner>>,
     server: Server,
 }
 
 impl Http {
-    pub fn new(address: SocketAddr, client_config: ClientConfig) -> Self {
+    pub fn new(address: SocketAddr, runners: Vec<Arc<Runner>>) -> Self {
         let server = tiny_http::Server::http(address);
         if server.is_err() {
             fatal!("Failed to open {} for HTTP Admin listener", address);
         }
         Self {
-            client_config,
+            runners,
             server: server.unwrap(),
         }
     }
@@ -37,19 +41,21 @@ impl Http {
                         )));
                     }
                     "/ratelimit/request" => {
-                        if self.client_config.request_ratelimiter.is_some() {
-                            let _ = request.respond(Response::from_string(format!(
-                                "{}\n",
-                                self.client_config
-                                    .request_ratelimiter
-                                    .as_ref()
-                                    .unwrap()
-                                    .rate()
-                            )));
-                        } else {
-                            let _ = request.respond(Response::from_string("None\n".to_string()));
+                        match self.runners.first().and_then(|r| r.request_rate()) {
+                            Some(rate) => {
+                                let _ =
+                                    request.respond(Response::from_string(format!("{}\n", rate)));
+                            }
+                            None => {
+                                let _ =
+                                    request.respond(Response::from_string("None\n".to_string()));
+                            }
                         }
                     }
+                    "/samples" => {
+                        debug!("Serving GET on samples");
+                        let _ = request.respond(Response::from_string(self.samples()));
+                    }
                     url => {
                         debug!("GET on non-existent url: {}", url);
                         let _ = request.respond(Response::empty(404));
@@ -60,8 +66,11 @@ impl Http {
                         let mut content = String::new();
                         request.as_reader().read_to_string(&mut content).unwrap();
                         if let Ok(rate) = content.parse() {
-                            if let Some(ref ratelimiter) = self.client_config.request_ratelimiter {
-                                ratelimiter.set_rate(rate);
+                            let mut updated = false;
+                            for runner in &self.runners {
+                                updated |= runner.set_request_rate(rate);
+                            }
+                            if updated {
                                 let _ = request.respond(Response::empty(200));
                             } else {
                                 let _ = request.respond(Response::empty(400));
@@ -83,4 +92,26 @@ impl Http {
         }
         std::thread::sleep(std::time::Duration::from_millis(1));
     }
+
+    /// the recent requests and responses of the run as JSON, with those of
+    /// the compare group if there is one
+    fn samples(&self) -> String {
+        let samples = |runner: &Arc<Runner>| -> Value {
+            runner
+                .metrics()
+                .samples()
+                .iter()
+                .map(|sample| sample.json())
+                .collect()
+        };
+        let mut json = json!({});
+        if let Some(runner) = self.runners.first() {
+            json["protocol"] = json!(runner.config().protocol().name());
+            json["samples"] = samples(runner);
+        }
+        if let Some(runner) = self.runners.get(1) {
+            json["compare"] = samples(runner);
+        }
+        format!("{:#}\n", json)
+    }
 }
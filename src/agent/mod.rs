@@ -0,0 +1,103 @@
+// Copyright 2021 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+//! An agent is an rpc-perf instance which is driven by a coordinator over its
+//! admin port. The coordinator sends the config and a start time, reads the
+//! agent's metrics each window, and stops the agent at the end of the run.
+
+use crate::config::Config;
+use crate::stats::Metrics;
+
+use rustcommon_atomics::{Atomic, AtomicBool, Ordering};
+use tiny_http::{Method, Response, Server};
+
+use std::net::SocketAddr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+pub(crate) struct Agent {
+    server: Server,
+    config: Option<Config>,
+    start: Option<SystemTime>,
+}
+
+impl Agent {
+    pub fn new(address: SocketAddr) -> Self {
+        let server = tiny_http::Server::http(address);
+        if server.is_err() {
+            fatal!("Failed to open {} for HTTP Agent listener", address);
+        }
+        Self {
+            server: server.unwrap(),
+            config: None,
+            start: None,
+        }
+    }
+
+    /// serve the coordinator until it has sent the config and the start time
+    pub fn configure(&mut self, local: &Config) -> (Config, SystemTime) {
+        info!("Waiting for the coordinator...");
+        loop {
+            if let Ok(Some(mut request)) = self.server.recv_timeout(Duration::from_millis(100)) {
+                let mut content = String::new();
+                let _ = request.as_reader().read_to_string(&mut content);
+                let status = match (request.method(), request.url()) {
+                    (Method::Put, "/agent/config") => match Config::from_toml(&content) {
+                        Ok(config) => {
+                            debug!("received config from coordinator");
+                            self.config = Some(config.for_agent(local));
+                            200
+                        }
+                        Err(e) => {
+                            error!("coordinator sent an invalid config: {}", e);
+                            400
+                        }
+                    },
+                    (Method::Put, "/agent/start") if self.config.is_some() => {
+                        match content.trim().parse::<u64>() {
+                            Ok(ms) => {
+                                self.start = Some(UNIX_EPOCH + Duration::from_millis(ms));
+                                200
+                            }
+                            Err(_) => 400,
+                        }
+                    }
+                    (_, "/agent/start") | (_, "/agent/metrics") => 503,
+                    (_, url) => {
+                        debug!("request on non-existent url: {}", url);
+                        404
+                    }
+                };
+                let _ = request.respond(Response::empty(status));
+            }
+            if let Some(start) = self.start {
+                return (self.config.take().unwrap(), start);
+            }
+        }
+    }
+
+    /// serve metrics to the coordinator until it stops the run
+    pub fn run(&mut self, metrics: &Metrics, control: &AtomicBool) {
+        if let Ok(Some(request)) = self.server.try_recv() {
+            match (request.method(), request.url()) {
+                (Method::Get, "/agent/metrics") => {
+                    // the waterfall readings are sent once each, so that the
+                    // coordinator can merge them as they are
+                    let mut export = metrics.export();
+                    export.set_heatmap(metrics.take_heatmap());
+                    let _ = request.respond(Response::from_string(export.to_string()));
+                }
+                (Method::Put, "/agent/stop") => {
+                    info!("Stopped by the coordinator");
+                    control.store(false, Ordering::SeqCst);
+                    let _ = request.respond(Response::empty(200));
+                }
+                (_, url) => {
+                    debug!("request on non-existent url: {}", url);
+                    let _ = request.respond(Response::empty(404));
+                }
+            }
+        }
+        std::thread::sleep(std::time::Duration::from_millis(1));
+    }
+}
@@ -0,0 +1,135 @@
+// Copyright 2021 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+//! An archive of a run, so that the results can be understood and the run
+//! repeated long after it happened. The bundle is a tar file containing:
+//!
+//! * `config.toml` - the config with the command line settings applied
+//! * `run.txt` - the run metadata, see `metadata::Metadata`, and the command
+//!   line
+//! * `windows/NNNNN.txt` - the change in the metrics over each window
+//! * `histograms.txt` - the metrics at the end of the run
+//! * `groups/NAME/windows/NNNNN.txt` and `groups/NAME/histograms.txt` - the
+//!   same for each client group
+//! * `waterfall.png` - the waterfall, if one was rendered
+//!
+//! The metrics are in the export format, see `stats::Export`.
+
+mod tar;
+
+use self::tar::Archive;
+use crate::config::Config;
+use crate::metadata::Metadata;
+use crate::stats::{Export, Metrics};
+
+use std::fs::File;
+use std::io::{BufWriter, Error, Write};
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const PREFIX: &str = "rpc-perf";
+
+/// The windows of one set of metrics.
+struct Recording {
+    metrics: Arc<Metrics>,
+    previous: Export,
+    windows: Vec<Export>,
+}
+
+impl Recording {
+    fn new(metrics: Arc<Metrics>) -> Self {
+        Self {
+            metrics,
+            previous: Export::new(),
+            windows: Vec::new(),
+        }
+    }
+
+    fn window(&mut self, current: Export) {
+        self.windows.push(current.since(&self.previous));
+        self.previous = current;
+    }
+
+    fn append<W: Write>(&self, archive: &mut Archive<W>, prefix: &str) -> Result<(), Error> {
+        for (i, window) in self.windows.iter().enumerate() {
+            let file = format!("{}/windows/{:05}.txt", prefix, i + 1);
+            archive.append(&file, window.to_string().as_bytes())?;
+        }
+        archive.append(
+            &format!("{}/histograms.txt", prefix),
+            self.metrics.export().to_string().as_bytes(),
+        )
+    }
+}
+
+pub struct Bundle {
+    run: Recording,
+    groups: Vec<(String, Recording)>,
+}
+
+impl Bundle {
+    /// start recording the metrics for a bundle. Readings from before this is
+    /// called are counted in the first window.
+    pub fn new(metrics: Arc<Metrics>) -> Self {
+        Self {
+            run: Recording::new(metrics),
+            groups: Vec::new(),
+        }
+    }
+
+    /// also record the metrics of a client group
+    pub fn add_group(&mut self, name: &str, metrics: Arc<Metrics>) {
+        self.groups
+            .push((name.to_string(), Recording::new(metrics)));
+    }
+
+    /// record the change in the metrics over the window which just ended,
+    /// from the snapshot taken at its end, and that of each group, in the
+    /// order the groups were added
+    pub fn window(&mut self, current: Export, groups: &[Export]) {
+        self.run.window(current);
+        for ((_, group), current) in self.groups.iter_mut().zip(groups) {
+            group.window(current.clone());
+        }
+    }
+
+    /// write the bundle to the file
+    pub fn write(&self, path: &str, config: &Config, metadata: &Metadata) -> Result<(), Error> {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+
+        let mut archive = Archive::new(BufWriter::new(File::create(path)?), now);
+        let name = |file: &str| format!("{}/{}", PREFIX, file);
+
+        archive.append(&name("config.toml"), config.to_toml().as_bytes())?;
+        archive.append(&name("run.txt"), self.describe(metadata).as_bytes())?;
+        self.run.append(&mut archive, PREFIX)?;
+        for (group, recording) in &self.groups {
+            recording.append(&mut archive, &name(&format!("groups/{}", group)))?;
+        }
+        if let Some(waterfall) = config.waterfall() {
+            match std::fs::read(&waterfall) {
+                Ok(content) => archive.append(&name("waterfall.png"), &content)?,
+                Err(e) => warn!("failed to add waterfall to bundle: {}", e),
+            }
+        }
+        archive.finish()?;
+        Ok(())
+    }
+
+    /// describe how the run was started
+    fn describe(&self, metadata: &Metadata) -> String {
+        let mut lines: Vec<String> = metadata
+            .fields()
+            .iter()
+            .map(|(key, value)| format!("{} {}", key, value))
+            .collect();
+        lines.push(format!("windows {}", self.run.windows.len()));
+        let args: Vec<String> = std::env::args().collect();
+        lines.push(format!("command {}", args.join(" ")));
+        lines.join("\n") + "\n"
+    }
+}
@@ -0,0 +1,100 @@
+// Copyright 2021 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+//! A minimal writer for ustar archives, which can be read by `tar` and most
+//! archive tools. Only regular files are supported.
+
+use std::io::{Error, ErrorKind, Write};
+
+const BLOCK: usize = 512;
+
+pub struct Archive<W: Write> {
+    inner: W,
+    mtime: u64,
+}
+
+impl<W: Write> Archive<W> {
+    /// create an archive where all files have the modification time `mtime`
+    /// in seconds since the unix epoch
+    pub fn new(inner: W, mtime: u64) -> Self {
+        Self { inner, mtime }
+    }
+
+    /// append a file to the archive
+    pub fn append(&mut self, name: &str, content: &[u8]) -> Result<(), Error> {
+        if name.is_empty() || name.len() > 100 {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("bad file name for archive: {}", name),
+            ));
+        }
+        let mut header = [0; BLOCK];
+        header[0..name.len()].copy_from_slice(name.as_bytes());
+        octal(&mut header[100..108], 0o644);
+        octal(&mut header[108..116], 0);
+        octal(&mut header[116..124], 0);
+        octal(&mut header[124..136], content.len() as u64);
+        octal(&mut header[136..148], self.mtime);
+        header[156] = b'0';
+        header[257..263].copy_from_slice(b"ustar\0");
+        header[263..265].copy_from_slice(b"00");
+
+        // the checksum is calculated with its own field filled with spaces
+        header[148..156].copy_from_slice(b"        ");
+        let checksum: u64 = header.iter().map(|b| *b as u64).sum();
+        octal(&mut header[148..155], checksum);
+
+        self.inner.write_all(&header)?;
+        self.inner.write_all(content)?;
+        let padding = (BLOCK - content.len() % BLOCK) % BLOCK;
+        self.inner.write_all(&[0; BLOCK][0..padding])
+    }
+
+    /// write the end of the archive and return the writer
+    pub fn finish(mut self) -> Result<W, Error> {
+        self.inner.write_all(&[0; 2 * BLOCK])?;
+        self.inner.flush()?;
+        Ok(self.inner)
+    }
+}
+
+/// write a zero-padded octal number terminated by a nul into the field
+fn octal(field: &mut [u8], value: u64) {
+    let width = field.len() - 1;
+    let digits = format!("{:0width$o}", value, width = width);
+    let digits = digits.as_bytes();
+    field[..width].copy_from_slice(&digits[digits.len() - width..]);
+    field[width] = 0;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn layout() {
+        let mut archive = Archive::new(Vec::new(), 1_600_000_000);
+        archive.append("run/seed.txt", b"42\n").unwrap();
+        archive.append("run/empty.txt", b"").unwrap();
+        assert!(archive.append(&"x".repeat(101), b"").is_err());
+        let bytes = archive.finish().unwrap();
+
+        // two headers, one block of content and the two end blocks
+        assert_eq!(bytes.len(), 5 * BLOCK);
+        let header = &bytes[0..BLOCK];
+        assert_eq!(&header[0..12], b"run/seed.txt");
+        assert_eq!(&header[124..136], b"00000000003\0");
+        assert_eq!(&header[257..262], b"ustar");
+        assert_eq!(&bytes[BLOCK..BLOCK + 3], b"42\n");
+        assert_eq!(&bytes[2 * BLOCK..2 * BLOCK + 13], b"run/empty.txt");
+
+        let stored = std::str::from_utf8(&header[148..155]).unwrap();
+        let stored = stored.trim_end_matches('\0');
+        let mut blank = header.to_vec();
+        blank[148..156].copy_from_slice(b"        ");
+        let sum: u64 = blank.iter().map(|b| *b as u64).sum();
+        assert_eq!(u64::from_str_radix(stored, 8).unwrap(), sum);
+        assert!(bytes[3 * BLOCK..].iter().all(|b| *b == 0));
+    }
+}
@@ -0,0 +1,178 @@
+// Copyright 2021 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+use crate::config::{Fault, FaultKind};
+
+use rand::Rng;
+
+use std::collections::VecDeque;
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+
+/// The state of the faults injected by one client. The schedule is shared by
+/// all the clients of a runner, which count time from the same start.
+pub struct Chaos {
+    faults: Vec<Fault>,
+    active: Vec<bool>,
+    blackholed: Vec<Vec<SocketAddr>>,
+    start: Instant,
+    /// how often this client closes a connection during a disconnect fault
+    disconnect_interval: Option<Duration>,
+    next_disconnect: Instant,
+    /// sessions whose requests are held back, with the time to write them
+    pub delayed: VecDeque<(Instant, usize)>,
+    /// sessions which are kept idle while their endpoint is blackholed
+    pub parked: Vec<usize>,
+}
+
+impl Chaos {
+    pub fn new(faults: &[Fault], start: Instant, endpoints: &[SocketAddr], clients: usize) -> Self {
+        // the rate is shared between the clients
+        let rate: usize = faults
+            .iter()
+            .filter(|f| f.kind() == FaultKind::Disconnect)
+            .map(|f| f.rate())
+            .max()
+            .unwrap_or(0);
+        let disconnect_interval = if rate > 0 {
+            Some(Duration::from_secs(clients.max(1) as u64) / rate as u32)
+        } else {
+            None
+        };
+        Self {
+            faults: faults.to_vec(),
+            active: vec![false; faults.len()],
+            blackholed: faults.iter().map(|f| f.blackholed(endpoints)).collect(),
+            start,
+            disconnect_interval,
+            next_disconnect: start,
+            delayed: VecDeque::new(),
+            parked: Vec::new(),
+        }
+    }
+
+    /// update which faults are active, returning the faults which started or
+    /// stopped since the last update
+    pub fn update(&mut self, now: Instant) -> Vec<(&Fault, bool)> {
+        let elapsed = now.saturating_duration_since(self.start);
+        let mut changed = Vec::new();
+        for (fault, active) in self.faults.iter().zip(self.active.iter_mut()) {
+            let current = fault.is_active(elapsed);
+            if current != *active {
+                *active = current;
+                changed.push((fault, current));
+            }
+        }
+        changed
+    }
+
+    /// the number of faults which are active
+    pub fn active(&self) -> usize {
+        self.active.iter().filter(|a| **a).count()
+    }
+
+    fn active_faults(&self, kind: FaultKind) -> impl Iterator<Item = (usize, &Fault)> {
+        self.faults
+            .iter()
+            .enumerate()
+            .filter(move |(i, f)| self.active[*i] && f.kind() == kind)
+    }
+
+    /// how long to hold back a write, if it is chosen to be delayed
+    pub fn delay<R: Rng>(&self, rng: &mut R) -> Option<Duration> {
+        self.active_faults(FaultKind::Delay)
+            .find(|(_, fault)| rng.gen_bool(fault.ratio()))
+            .map(|(_, fault)| fault.delay())
+    }
+
+    /// whether requests to the endpoint are currently blackholed
+    pub fn is_blackholed(&self, addr: &SocketAddr) -> bool {
+        self.active_faults(FaultKind::Blackhole)
+            .any(|(i, _)| self.blackholed[i].contains(addr))
+    }
+
+    /// whether this client should close a connection now
+    pub fn disconnect(&mut self, now: Instant) -> bool {
+        let interval = match self.disconnect_interval {
+            Some(interval) if self.active_faults(FaultKind::Disconnect).next().is_some() => {
+                interval
+            }
+            _ => return false,
+        };
+        if now < self.next_disconnect {
+            return false;
+        }
+        // don't make up for time when no disconnect fault was active
+        if now.saturating_duration_since(self.next_disconnect) > interval {
+            self.next_disconnect = now;
+        }
+        self.next_disconnect += interval;
+        true
+    }
+
+    /// the delayed sessions which are due to be written
+    pub fn due(&mut self, now: Instant) -> Vec<usize> {
+        let mut due = Vec::new();
+        while let Some((time, token)) = self.delayed.front() {
+            if *time > now {
+                break;
+            }
+            due.push(*token);
+            self.delayed.pop_front();
+        }
+        due
+    }
+
+    /// forget a session which was closed
+    pub fn remove(&mut self, token: usize) {
+        self.delayed.retain(|(_, t)| *t != token);
+        self.parked.retain(|t| *t != token);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn faults() {
+        let faults: Vec<Fault> = toml::from_str::<toml::Value>(
+            "[[chaos]]\nfault = \"disconnect\"\nrate = 2\nstart = 1\n\
+             [[chaos]]\nfault = \"blackhole\"\npercent = 50.0\n\
+             [[chaos]]\nfault = \"delay\"\npercent = 100.0\ndelay = 10\n",
+        )
+        .unwrap()["chaos"]
+            .clone()
+            .try_into()
+            .unwrap();
+        let endpoints: Vec<SocketAddr> = vec![
+            "127.0.0.1:12321".parse().unwrap(),
+            "127.0.0.1:12322".parse().unwrap(),
+        ];
+        let start = Instant::now();
+        let mut chaos = Chaos::new(&faults, start, &endpoints, 1);
+
+        assert_eq!(chaos.update(start).len(), 2);
+        assert_eq!(chaos.active(), 2);
+        assert!(chaos.is_blackholed(&endpoints[0]));
+        assert!(!chaos.is_blackholed(&endpoints[1]));
+        let mut rng = rand::thread_rng();
+        assert_eq!(chaos.delay(&mut rng), Some(Duration::from_micros(10)));
+        assert!(!chaos.disconnect(start));
+
+        let later = start + Duration::from_secs(1);
+        let changed = chaos.update(later);
+        assert_eq!(changed.len(), 1);
+        assert_eq!(changed[0].0.kind(), FaultKind::Disconnect);
+        assert!(chaos.disconnect(later));
+        assert!(!chaos.disconnect(later));
+        assert!(chaos.disconnect(later + Duration::from_millis(500)));
+
+        chaos.delayed.push_back((later, 1));
+        chaos.delayed.push_back((later + Duration::from_secs(1), 2));
+        assert_eq!(chaos.due(later), vec![1]);
+        chaos.remove(2);
+        assert!(chaos.delayed.is_empty());
+    }
+}
@@ -0,0 +1,107 @@
+// Copyright 2021 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+use crate::codec::{Error, Response};
+use crate::stats::Stat;
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::Hasher;
+
+/// A summary of the response to a mirrored request
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Digest {
+    /// the response was lost with its connection
+    Lost,
+    Response {
+        ok: bool,
+        hit: Option<bool>,
+        hash: u64,
+    },
+}
+
+impl Digest {
+    pub fn new(result: &Result<Response, Error>, content: &[u8]) -> Self {
+        let mut hasher = DefaultHasher::new();
+        hasher.write(content);
+        Digest::Response {
+            ok: result.is_ok(),
+            hit: match result {
+                Ok(Response::Hit) => Some(true),
+                Ok(Response::Miss) => Some(false),
+                _ => None,
+            },
+            hash: hasher.finish(),
+        }
+    }
+}
+
+/// Pairs the responses from the primary and shadow endpoints for each
+/// mirrored request. Requests are identified by a sequence number which is
+/// assigned when the request is sent to both sides.
+#[derive(Default)]
+pub struct Mirror {
+    next: u64,
+    waiting: HashMap<u64, Digest>,
+}
+
+impl Mirror {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// the sequence number for the next mirrored request
+    pub fn sequence(&mut self) -> u64 {
+        self.next += 1;
+        self.next
+    }
+
+    /// record the digest from one side of a request. Once both sides are
+    /// recorded, returns the stat describing how they compare.
+    pub fn record(&mut self, sequence: u64, digest: Digest) -> Option<Stat> {
+        let other = match self.waiting.remove(&sequence) {
+            Some(other) => other,
+            None => {
+                self.waiting.insert(sequence, digest);
+                return None;
+            }
+        };
+        Some(match (digest, other) {
+            (Digest::Lost, _) | (_, Digest::Lost) => Stat::MirrorUnmatched,
+            (a, b) if a == b => Stat::MirrorMatched,
+            (Digest::Response { ok: a, hit: x, .. }, Digest::Response { ok: b, hit: y, .. })
+                if a != b || x != y =>
+            {
+                Stat::MirrorDivergedOutcome
+            }
+            _ => Stat::MirrorDivergedValue,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compare() {
+        let hit = Digest::new(&Ok(Response::Hit), b"VALUE 0 0 1\r\na\r\nEND\r\n");
+        let other = Digest::new(&Ok(Response::Hit), b"VALUE 0 0 1\r\nb\r\nEND\r\n");
+        let miss = Digest::new(&Ok(Response::Miss), b"END\r\n");
+
+        let mut mirror = Mirror::new();
+        let cases = [
+            (hit, hit, Stat::MirrorMatched),
+            (hit, other, Stat::MirrorDivergedValue),
+            (hit, miss, Stat::MirrorDivergedOutcome),
+            (Digest::Lost, miss, Stat::MirrorUnmatched),
+        ];
+        for (a, b, expected) in cases.iter() {
+            let sequence = mirror.sequence();
+            assert_eq!(mirror.record(sequence, *a), None);
+            assert_eq!(mirror.record(sequence, *b), Some(*expected));
+        }
+        assert!(mirror.waiting.is_empty());
+    }
+}
@@ -2,96 +2,266 @@
 // Licensed under the Apache License, Version 2.0
 // http://www.apache.org/licenses/LICENSE-2.0
 
-use std::collections::VecDeque;
+mod chaos;
+mod mirror;
+mod slow;
+
+pub use chaos::Chaos;
+pub use slow::{SlowLog, SLOW_LOG_RATE};
+
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::io::BufRead;
 use std::net::SocketAddr;
 use std::sync::Arc;
-use std::time::Instant;
+use std::time::{Duration, Instant, SystemTime};
 
 use mio::{Events, Poll, Token};
 use rand::prelude::SliceRandom;
-use rand::rngs::ThreadRng;
-use rand::thread_rng;
+use rand::rngs::StdRng;
+use rand::{thread_rng, Rng};
+use rustcommon_buffer::Buffer;
+use rustcommon_ratelimiter::Ratelimiter;
 use rustcommon_timer::Wheel;
 use rustls::ClientConfig;
 use slab::Slab;
 
 use crate::codec::*;
-use crate::config::Protocol;
+use crate::discovery::Endpoints;
+use crate::numa;
+use crate::ratelimit::BatchRatelimiter;
 use crate::session::{Session, State};
-use crate::stats::*;
+use crate::stats::{Exemplar, Exemplars, Metrics, Sample, Stat, SAMPLE_INTERVAL, SETTLE};
 use crate::*;
 
+use mirror::{Digest, Mirror};
+
+// how often thread-local metrics are merged, this matches the finest time
+// resolution of the latency summaries and the waterfall. They are also merged
+// at each window boundary, so that no flush spans two windows.
+const FLUSH_INTERVAL: u64 = SECOND as u64;
+
 pub struct Client {
     codec: Box<dyn Codec>,
+    pipeline: usize,
     sessions: Slab<Session>,
     config: Arc<Config>,
     ready_queue: VecDeque<usize>,
     connect_queue: VecDeque<SocketAddr>,
+    shadow_queue: VecDeque<SocketAddr>,
+    shadows: VecDeque<usize>,
+    mirror: Option<Mirror>,
+    // the requests which are copied, to the shadow endpoints or as a sample,
+    // are encoded into the scratch buffer and copied out of it, and the
+    // mirrored requests are held until they are sent to the shadow sessions,
+    // each with its sequence number and where it ends in the copies. These
+    // are kept so that they are allocated once.
+    scratch: Buffer,
+    copies: Vec<u8>,
+    mirrored: Vec<(u64, usize)>,
+    chaos: Option<Chaos>,
+    exemplars: Option<Exemplars>,
+    slow_log: Option<SlowLog>,
     tls_config: Option<Arc<ClientConfig>>,
     metrics: Arc<Metrics>,
     timers: Wheel<usize>,
     last_timeout: Instant,
+    last_flush: Instant,
+    next_flush: Instant,
+    last_cpu: Option<Duration>,
+    next_sample: Instant,
+    throttled: bool,
+    draining: bool,
     events: Option<Events>,
     poll: Poll,
     id: usize,
     connect: Option<Arc<Ratelimiter>>,
-    request: Option<Arc<Ratelimiter>>,
+    request: Option<Arc<BatchRatelimiter>>,
+    tokens: u64,
+    next_token: Instant,
+    token_interval: Duration,
     close: Option<Arc<Ratelimiter>>,
+    // the connections closed by the close rate which are yet to be replaced,
+    // for each endpoint
+    reconnects: HashMap<SocketAddr, usize>,
+    endpoints: HashSet<SocketAddr>,
+    discovery: Option<Discovered>,
+}
+
+/// the discovered endpoints which the client follows, with the version it
+/// last applied and the numa node whose part of them it connects to
+struct Discovered {
+    endpoints: Arc<Endpoints>,
+    version: u64,
+    numa: Option<(usize, usize)>,
 }
 
 impl Client {
     pub fn new(
         id: usize,
         config: Arc<Config>,
+        codec: Box<dyn Codec>,
         connect: Option<Arc<Ratelimiter>>,
-        request: Option<Arc<Ratelimiter>>,
+        request: Option<Arc<BatchRatelimiter>>,
         close: Option<Arc<Ratelimiter>>,
         metrics: Arc<Metrics>,
     ) -> Self {
-        let codec: Box<dyn Codec> = match config.protocol() {
-            Protocol::Echo => Box::new(crate::codec::Echo::new()),
-            Protocol::Memcache => Box::new(crate::codec::Memcache::new()),
-            Protocol::ThriftCache => Box::new(crate::codec::ThriftCache::new()),
-            Protocol::PelikanRds => Box::new(crate::codec::PelikanRds::new()),
-            Protocol::Ping => Box::new(crate::codec::Ping::new()),
-            Protocol::RedisResp => {
-                Box::new(crate::codec::Redis::new(crate::codec::RedisMode::Resp))
-            }
-            Protocol::RedisInline => {
-                Box::new(crate::codec::Redis::new(crate::codec::RedisMode::Inline))
-            }
+        // requests can only be pipelined if the codec can split the responses
+        let pipeline = if codec.pipelining() {
+            config.pipeline()
+        } else {
+            1
+        };
+
+        // mirrored responses can only be paired if the codec can split them
+        let mirror = if config.shadow().is_empty() {
+            None
+        } else if codec.pipelining() {
+            Some(Mirror::new())
+        } else {
+            warn!("protocol does not support mirroring, shadow endpoints are ignored");
+            None
         };
 
         let tls_config = load_tls_config(&config);
 
         Self {
             codec,
+            pipeline,
             sessions: Slab::new(),
             config,
             ready_queue: VecDeque::new(),
             connect_queue: VecDeque::new(),
+            shadow_queue: VecDeque::new(),
+            shadows: VecDeque::new(),
+            mirror,
+            scratch: Buffer::with_capacity(1024, 1024),
+            copies: Vec::new(),
+            mirrored: Vec::new(),
+            chaos: None,
+            exemplars: None,
+            slow_log: None,
             metrics,
             tls_config,
             timers: Wheel::<usize>::new(SECOND / MICROSECOND),
             last_timeout: Instant::now(),
+            last_flush: Instant::now(),
+            next_flush: Instant::now() + Duration::from_nanos(FLUSH_INTERVAL),
+            last_cpu: None,
+            next_sample: Instant::now(),
+            throttled: false,
+            draining: false,
             events: None,
             poll: Poll::new().expect("failed to create mio::Poll"),
             id,
             connect,
             request,
+            tokens: 0,
+            next_token: Instant::now(),
+            token_interval: Duration::from_nanos(0),
             close,
+            reconnects: HashMap::new(),
+            endpoints: HashSet::new(),
+            discovery: None,
         }
     }
 
     pub fn add_endpoint(&mut self, addr: &SocketAddr) {
         debug!("client({}) adding endpoint: {}", self.id, addr);
+        self.endpoints.insert(*addr);
         for _ in 0..self.config.poolsize() {
             self.connect_queue.push_back(*addr);
         }
         self.connect_shuffle();
     }
 
+    /// close the connections to an endpoint and stop reconnecting to it
+    fn remove_endpoint(&mut self, addr: &SocketAddr) {
+        debug!("client({}) removing endpoint: {}", self.id, addr);
+        self.endpoints.remove(addr);
+        self.connect_queue.retain(|a| a != addr);
+        self.reconnects.remove(addr);
+        let tokens: Vec<usize> = self
+            .sessions
+            .iter()
+            .filter(|(_, s)| !s.is_shadow() && s.addr() == *addr)
+            .map(|(token, _)| token)
+            .collect();
+        for token in tokens {
+            self.ready_queue.retain(|t| *t != token);
+            self.hangup(token);
+        }
+    }
+
+    /// follow the discovered endpoints, optionally only the part of them
+    /// for the numa node at `index` of `nodes`
+    pub fn set_discovery(&mut self, endpoints: Arc<Endpoints>, numa: Option<(usize, usize)>) {
+        let version = endpoints.version();
+        self.discovery = Some(Discovered {
+            endpoints,
+            version,
+            numa,
+        });
+    }
+
+    /// add a shadow endpoint, which receives a copy of each request so that
+    /// its responses can be compared with those of the primary endpoints
+    pub fn add_shadow(&mut self, addr: &SocketAddr) {
+        if self.mirror.is_some() {
+            debug!("client({}) adding shadow endpoint: {}", self.id, addr);
+            for _ in 0..self.config.poolsize() {
+                self.shadow_queue.push_back(*addr);
+            }
+        }
+    }
+
+    /// stop sending requests, the responses to in-flight requests are still
+    /// received
+    pub fn drain(&mut self) {
+        self.draining = true;
+    }
+
+    /// whether no requests are awaiting responses
+    pub fn is_idle(&self) -> bool {
+        self.sessions
+            .iter()
+            .all(|(_, session)| session.is_shadow() || session.pending() == 0)
+    }
+
+    /// inject the faults from the config into the workload
+    pub fn set_chaos(&mut self, chaos: Chaos) {
+        self.chaos = Some(chaos);
+    }
+
+    /// record the slowest requests, which are logged at the end of each
+    /// window, and tag sets with a trace id if the config asks for it
+    pub fn set_exemplars(&mut self, limit: usize) {
+        self.enable_tagging();
+        self.exemplars = Some(Exemplars::new(limit));
+    }
+
+    /// log each request which is slower than the threshold in microseconds
+    pub fn set_slow_log(&mut self, threshold: usize, ratelimit: Arc<Ratelimiter>) {
+        self.enable_tagging();
+        self.slow_log = Some(SlowLog::new(threshold, ratelimit));
+    }
+
+    /// whether the requests are tagged so that they can be described
+    fn tagging(&self) -> bool {
+        self.exemplars.is_some() || self.slow_log.is_some()
+    }
+
+    fn enable_tagging(&mut self) {
+        if self.tagging() {
+            return;
+        }
+        let trace = if self.config.trace() {
+            Some(thread_rng().gen())
+        } else {
+            None
+        };
+        self.codec.common_mut().set_tagging(trace);
+    }
+
     fn connect_shuffle(&mut self) {
         let mut tmp: Vec<SocketAddr> = self.connect_queue.drain(0..).collect();
         let mut rng = thread_rng();
@@ -127,11 +297,37 @@ impl Client {
                 }
                 if !self.config.soft_timeout() {
                     let session = self.sessions.remove(token);
-                    self.connect_queue.push_back(session.addr());
+                    self.requeue(token, session);
                 }
             }
         }
 
+        // a shadow endpoint which stops responding would hold the mirrored
+        // requests forever, so a shadow session is closed once its oldest
+        // request is older than the request timeout, which counts the
+        // requests awaiting a response on it as unmatched
+        if self.mirror.is_some() {
+            let timeout = Duration::from_micros(self.config.request_timeout() as u64);
+            let stale: Vec<usize> = self
+                .shadows
+                .iter()
+                .copied()
+                .filter(|token| {
+                    self.sessions
+                        .get(*token)
+                        .and_then(|session| session.mirrored.front())
+                        .map(|(_, sent)| now.saturating_duration_since(*sent) >= timeout)
+                        == Some(true)
+                })
+                .collect();
+            for token in stale {
+                debug!("shadow timed out: {}", token);
+                let mut session = self.sessions.remove(token);
+                session.deregister(&self.poll);
+                self.requeue(token, session);
+            }
+        }
+
         self.last_timeout = now;
     }
 
@@ -142,7 +338,7 @@ impl Client {
         self.metrics.increment(&Stat::ConnectionsServerClosed);
         let mut session = self.sessions.remove(token);
         session.deregister(&self.poll);
-        self.connect_queue.push_back(session.addr());
+        self.requeue(token, session);
     }
 
     fn hangup(&mut self, token: usize) {
@@ -151,20 +347,145 @@ impl Client {
             self.metrics.increment(&Stat::ConnectionsClientClosed);
             let mut session = self.sessions.remove(token);
             session.deregister(&self.poll);
+            self.requeue(token, session);
+        }
+    }
+
+    /// queue a reconnect for a removed session. Mirrored requests which were
+    /// awaiting a response on the session are lost.
+    fn requeue(&mut self, token: usize, mut session: Session) {
+        if let Some(ref mut chaos) = self.chaos {
+            chaos.remove(token);
+        }
+        if let Some(ref mut mirror) = self.mirror {
+            for (sequence, _) in session.mirrored.drain(..) {
+                if let Some(stat) = mirror.record(sequence, Digest::Lost) {
+                    self.metrics.increment(&stat);
+                }
+            }
+        }
+        if session.is_shadow() {
+            self.shadows.retain(|shadow| *shadow != token);
+            self.shadow_queue.push_back(session.addr());
+        } else if self.endpoints.contains(&session.addr()) {
             self.connect_queue.push_back(session.addr());
         }
     }
 
+    /// handle an event for a shadow session, whose responses are only
+    /// compared with the responses from the primary endpoints
+    fn shadow_event(&mut self, token: usize, readable: bool, writable: bool) {
+        let session = match self.sessions.get_mut(token) {
+            Some(session) => session,
+            None => return,
+        };
+        let read_status = if readable {
+            session.do_read()
+        } else {
+            Ok(None)
+        };
+        let write_status = if writable {
+            session.do_write()
+        } else {
+            Ok(None)
+        };
+        match (read_status, write_status) {
+            (Ok(Some(0)), _) | (Err(_), _) | (_, Err(_)) => {
+                trace!("shadow closed: {}", token);
+                let mut session = self.sessions.remove(token);
+                session.deregister(&self.poll);
+                self.requeue(token, session);
+                return;
+            }
+            _ => {}
+        }
+        loop {
+            let (length, digest) = match session.buffer.fill_buf() {
+                Ok(content) if !content.is_empty() => match self.codec.frame(content) {
+                    Some(length) => {
+                        let result = self.codec.decode(&content[..length]);
+                        (length, Digest::new(&result, &content[..length]))
+                    }
+                    None => break,
+                },
+                _ => break,
+            };
+            session.buffer.consume(length);
+            if let (Some((sequence, _)), Some(mirror)) =
+                (session.mirrored.pop_front(), &mut self.mirror)
+            {
+                if let Some(stat) = mirror.record(sequence, digest) {
+                    self.metrics.increment(&stat);
+                }
+            }
+        }
+        if session.state() == State::Connecting {
+            if session.is_handshaking() {
+                session.reregister(&self.poll);
+                return;
+            }
+            self.shadows.push_back(token);
+        }
+        // write any copied requests, then wait for the responses
+        if session.tx_pending() > 0 {
+            session.set_state(State::Writing);
+        } else {
+            session.set_state(State::Reading);
+        }
+        session.reregister(&self.poll);
+    }
+
+    /// send a copy of a request to the next shadow session
+    fn mirror_request(&mut self, sequence: u64, request: &[u8]) {
+        let mut session = None;
+        if let Some(token) = self.shadows.pop_front() {
+            self.shadows.push_back(token);
+            session = self.sessions.get_mut(token);
+        }
+        match session {
+            Some(session) => {
+                session.buffer.put_slice(request);
+                session.mirrored.push_back((sequence, Instant::now()));
+                if session.state() != State::Writing {
+                    session.set_state(State::Writing);
+                    session.reregister(&self.poll);
+                }
+            }
+            None => {
+                // no shadow session is connected
+                if let Some(ref mut mirror) = self.mirror {
+                    if let Some(stat) = mirror.record(sequence, Digest::Lost) {
+                        self.metrics.increment(&stat);
+                    }
+                }
+            }
+        }
+    }
+
     fn do_events(&mut self) {
         let mut events = self
             .events
             .take()
             .unwrap_or_else(|| Events::with_capacity(1024));
+        let start = Instant::now();
         self.poll
             .poll(&mut events, Some(Duration::from_millis(1)))
             .unwrap();
-        for event in events.iter() {
+        let waited = start.elapsed();
+        let waited = waited.as_secs() * SECOND as u64 + waited.subsec_nanos() as u64;
+        // a client which has ready sessions but no request tokens is waiting
+        // on the ratelimiter rather than on the sockets
+        if self.throttled {
+            self.metrics.add(&Stat::ProfileWaitRatelimit, waited);
+        } else {
+            self.metrics.add(&Stat::ProfileWaitSocket, waited);
+        }
+        'events: for event in events.iter() {
             let token = event.token();
+            if self.sessions.get(token.0).map(|s| s.is_shadow()) == Some(true) {
+                self.shadow_event(token.0, event.is_readable(), event.is_writable());
+                continue;
+            }
             if let Some(session) = self.sessions.get_mut(token.0) {
                 let read_status = if event.is_readable() {
                     trace!("handle read for: {}", token.0);
@@ -181,6 +502,10 @@ impl Client {
                     Ok(None)
                 };
 
+                let (reads, writes) = session.take_syscalls();
+                self.metrics.add(&Stat::SyscallsRead, reads as u64);
+                self.metrics.add(&Stat::SyscallsWrite, writes as u64);
+
                 match read_status {
                     Ok(Some(0)) => {
                         self.server_closed(token.0);
@@ -188,26 +513,77 @@ impl Client {
                     }
                     Ok(Some(bytes)) => {
                         let start = session.timestamp();
-                        // parse response
+                        let addr = session.addr();
                         trace!("read {} bytes: {}", bytes, token.0);
-                        if let Ok(content) = session.buffer.fill_buf() {
-                            trace!("read: {:?}", content);
-                            match self.codec.decode(content) {
+                        // parse each complete response in the buffer, a
+                        // single read may contain several pipelined responses
+                        loop {
+                            let (length, result, digest) = match session.buffer.fill_buf() {
+                                Ok(content) if !content.is_empty() => {
+                                    trace!("read: {:?}", content);
+                                    let length = self.codec.frame(content).unwrap_or(content.len());
+                                    let result = self.codec.decode(&content[..length]);
+                                    let digest = self
+                                        .mirror
+                                        .as_ref()
+                                        .map(|_| Digest::new(&result, &content[..length]));
+                                    if result != Err(Error::Incomplete) {
+                                        if let Some(request) = session.sample.take() {
+                                            self.metrics.record_sample(Sample::new(
+                                                self.id,
+                                                addr,
+                                                &request,
+                                                &content[..length],
+                                                format!("{:?}", result),
+                                            ));
+                                        }
+                                    }
+                                    (length, result, digest)
+                                }
+                                _ => break,
+                            };
+                            let stop = Instant::now();
+                            // keep the slowest of the requests which were
+                            // answered, and log those over the threshold. A
+                            // push back answers the request too, so its tag is
+                            // taken to keep the tags in step with the requests
+                            let answered = matches!(
+                                result,
+                                Ok(_)
+                                    | Err(Error::ChecksumMismatch(..))
+                                    | Err(Error::Corrupted)
+                                    | Err(Error::PushBack)
+                            );
+                            if answered && (self.exemplars.is_some() || self.slow_log.is_some()) {
+                                let tag = session.tags.pop_front();
+                                let latency = (stop - start).as_nanos() as u64;
+                                let wanted =
+                                    self.exemplars.as_ref().map(|e| e.wants(latency)) == Some(true);
+                                let slow = self.slow_log.as_ref().map(|s| s.is_slow(latency))
+                                    == Some(true);
+                                if wanted || slow {
+                                    let request = Exemplar {
+                                        latency,
+                                        time: SystemTime::now(),
+                                        endpoint: session.addr(),
+                                        tag: tag.unwrap_or_default(),
+                                    };
+                                    if let (Some(slow_log), true) = (&mut self.slow_log, slow) {
+                                        if let Some(line) = slow_log.record(&request) {
+                                            warn!("Slow request: {}", line);
+                                        }
+                                    }
+                                    if let (Some(exemplars), true) = (&mut self.exemplars, wanted) {
+                                        exemplars.record(request);
+                                    }
+                                }
+                            }
+                            match result {
                                 Ok(response) => {
-                                    let stop = Instant::now();
-
-                                    self.metrics.heatmap_increment(start, stop);
-                                    self.metrics.time_interval(
-                                        &Stat::ResponsesLatency,
-                                        start,
-                                        stop,
-                                    );
+                                    record_latency(&self.metrics, session, start, stop);
                                     self.metrics.increment(&Stat::ResponsesTotal);
                                     self.metrics.increment(&Stat::ResponsesOk);
 
-                                    self.ready_queue.push_back(token.0);
-                                    session.set_state(State::Writing);
-
                                     match response {
                                         Response::Hit => {
                                             self.metrics.increment(&Stat::ResponsesHit);
@@ -218,37 +594,54 @@ impl Client {
                                         _ => {}
                                     }
                                 }
-                                Err(error) => {
-                                    if error != Error::Incomplete {
-                                        self.metrics.increment(&Stat::ResponsesTotal);
-                                        self.metrics.increment(&Stat::ResponsesError);
-                                    }
-
-                                    match error {
-                                        Error::ChecksumMismatch(a, b) => {
-                                            let stop = Instant::now();
-                                            let start = session.timestamp();
-                                            self.metrics.heatmap_increment(start, stop);
-                                            self.metrics.time_interval(
-                                                &Stat::ResponsesLatency,
-                                                start,
-                                                stop,
-                                            );
-                                            warn!("Response checksum mismatch!");
-                                            warn!("Expected: {:?}", a);
-                                            warn!("Got: {:?}", b);
-                                            self.ready_queue.push_back(token.0);
-                                            session.set_state(State::Writing);
-                                        }
-                                        _ => {
-                                            self.hangup(token.0);
-                                            continue;
-                                        }
-                                    }
+                                Err(Error::Incomplete) => {
+                                    // wait for the rest of the response
+                                    break;
+                                }
+                                Err(Error::PushBack) => {
+                                    // the server is shedding load, but the
+                                    // connection is still usable
+                                    self.metrics.increment(&Stat::ResponsesTotal);
+                                    self.metrics.increment(&Stat::ResponsesError);
+                                    self.metrics.increment(&Stat::PushbackRejected);
+                                }
+                                Err(Error::Corrupted) => {
+                                    // the value was read, but it isn't valid
+                                    self.metrics.increment(&Stat::ResponsesTotal);
+                                    self.metrics.increment(&Stat::ResponsesError);
+                                    self.metrics.increment(&Stat::CompressionCorrupted);
+                                    record_latency(&self.metrics, session, start, stop);
+                                }
+                                Err(Error::ChecksumMismatch(a, b)) => {
+                                    self.metrics.increment(&Stat::ResponsesTotal);
+                                    self.metrics.increment(&Stat::ResponsesError);
+                                    record_latency(&self.metrics, session, start, stop);
+                                    warn!("Response checksum mismatch!");
+                                    warn!("Expected: {:?}", a);
+                                    warn!("Got: {:?}", b);
+                                }
+                                Err(_) => {
+                                    self.metrics.increment(&Stat::ResponsesTotal);
+                                    self.metrics.increment(&Stat::ResponsesError);
+                                    self.hangup(token.0);
+                                    continue 'events;
+                                }
+                            }
+                            session.buffer.consume(length);
+                            if let (Some((sequence, _)), Some(digest), Some(mirror)) =
+                                (session.mirrored.pop_front(), digest, &mut self.mirror)
+                            {
+                                if let Some(stat) = mirror.record(sequence, digest) {
+                                    self.metrics.increment(&stat);
                                 }
                             }
+                            let pending = session.pending().saturating_sub(1);
+                            session.set_pending(pending);
+                            if pending == 0 {
+                                self.ready_queue.push_back(token.0);
+                                session.set_state(State::Writing);
+                            }
                         }
-                        session.buffer.consume(bytes);
                     }
                     Ok(None) => {
                         // wasn't ready
@@ -267,11 +660,13 @@ impl Client {
                     Ok(Some(bytes)) => {
                         trace!("wrote: {} bytes: {}", bytes, token.0);
                         if session.tx_pending() > 0 {
-                            // incomplete write
-                            println!("have: {} bytes pending: {}", session.tx_pending(), token.0);
+                            // incomplete write, the server isn't keeping up
+                            self.metrics.increment(&Stat::PushbackStalled);
+                            trace!("have: {} bytes pending: {}", session.tx_pending(), token.0);
                         } else if bytes > 0 {
                             // completed write
-                            self.metrics.increment(&Stat::RequestsDequeued);
+                            self.metrics
+                                .add(&Stat::RequestsDequeued, session.pending() as u64);
                             session.set_state(State::Reading);
                         }
                     }
@@ -281,7 +676,7 @@ impl Client {
                     Err(_) => {
                         // got some error, close connection
                         let session = self.sessions.remove(token.0);
-                        self.connect_queue.push_back(session.addr());
+                        self.requeue(token.0, session);
                         continue;
                     }
                 }
@@ -307,34 +702,135 @@ impl Client {
         self.events = Some(events);
     }
 
-    fn send_request(&mut self, rng: &mut ThreadRng, token: usize) {
+    /// encode `count` requests into the session buffer so that they are sent
+    /// with a single write
+    fn send_request(&mut self, rng: &mut StdRng, token: usize, count: usize) {
+        let tagging = self.tagging();
         if let Some(session) = self.sessions.get_mut(token) {
-            trace!("send request: {}", token);
-            session.set_timestamp(Instant::now());
-            self.metrics.increment(&Stat::RequestsEnqueued);
-            self.codec.encode(&mut session.buffer, rng);
-            session.set_state(State::Writing);
+            trace!("send {} requests: {}", count, token);
+            let now = Instant::now();
+            session.set_timestamp(now);
+            // only the first request to an idle session is sampled, so that
+            // the next response read is the one to it
+            let mut sample = session.pending() == 0 && now >= self.next_sample;
+            if sample {
+                self.next_sample = now + SAMPLE_INTERVAL;
+            }
+            self.codec.select(&session.addr());
+            for _ in 0..count {
+                self.metrics.increment(&Stat::RequestsEnqueued);
+                if self.mirror.is_some() || sample {
+                    // encode once so that both sides get an identical request
+                    self.codec.encode(&mut self.scratch, rng);
+                    if tagging {
+                        let tag = self.codec.common_mut().take_tag().unwrap_or_default();
+                        session.tags.push_back(tag);
+                    }
+                    let start = self.copies.len();
+                    let _ = self.scratch.write_to(&mut self.copies);
+                    let request = &self.copies[start..];
+                    session.buffer.put_slice(request);
+                    if sample {
+                        session.sample = Some(request.to_vec());
+                        sample = false;
+                    }
+                    if let Some(ref mut mirror) = self.mirror {
+                        let sequence = mirror.sequence();
+                        session.mirrored.push_back((sequence, now));
+                        self.mirrored.push((sequence, self.copies.len()));
+                    } else {
+                        self.copies.truncate(start);
+                    }
+                } else {
+                    self.codec.encode(&mut session.buffer, rng);
+                    if tagging {
+                        let tag = self.codec.common_mut().take_tag().unwrap_or_default();
+                        session.tags.push_back(tag);
+                    }
+                }
+            }
+            session.set_pending(session.pending() + count);
+            let delay = self
+                .chaos
+                .as_ref()
+                .and_then(|chaos| chaos.delay(&mut thread_rng()));
+            match (delay, &mut self.chaos) {
+                (Some(delay), Some(chaos)) => {
+                    // hold the requests until the delay has passed
+                    self.metrics.add(&Stat::ChaosDelayed, count as u64);
+                    chaos.delayed.push_back((Instant::now() + delay, token));
+                    session.set_state(State::Connected);
+                }
+                _ => {
+                    session.set_state(State::Writing);
+                }
+            }
             session.reregister(&self.poll);
         }
+        let mut copies = std::mem::take(&mut self.copies);
+        let mut mirrored = std::mem::take(&mut self.mirrored);
+        let mut start = 0;
+        for (sequence, end) in mirrored.drain(..) {
+            self.mirror_request(sequence, &copies[start..end]);
+            start = end;
+        }
+        copies.clear();
+        self.copies = copies;
+        self.mirrored = mirrored;
     }
 
-    fn do_requests(&mut self, rng: &mut ThreadRng) {
-        loop {
-            if let Some(token) = self.ready_queue.pop_front() {
-                if let Some(ref mut request) = self.request {
-                    if request.try_wait().is_ok() {
-                        self.send_request(rng, token);
-                    } else {
-                        self.ready_queue.push_front(token);
-                        break;
-                    }
-                } else {
-                    self.send_request(rng, token);
+    fn do_requests(&mut self, rng: &mut StdRng) {
+        self.throttled = false;
+        if self.draining {
+            return;
+        }
+        while let Some(token) = self.ready_queue.pop_front() {
+            if let Some(ref mut chaos) = self.chaos {
+                let addr = self.sessions.get(token).map(|s| s.addr());
+                if addr.map(|addr| chaos.is_blackholed(&addr)) == Some(true) {
+                    self.metrics.increment(&Stat::ChaosBlackholed);
+                    chaos.parked.push(token);
+                    continue;
                 }
-            } else {
+            }
+            let mut count = 0;
+            while count < self.pipeline && self.take_token() {
+                count += 1;
+            }
+            if count == 0 {
+                self.ready_queue.push_front(token);
+                self.throttled = true;
                 break;
             }
+            self.send_request(rng, token, count);
+        }
+    }
+
+    /// take a request token. Tokens are taken from the shared ratelimiter in
+    /// batches and are spread evenly over the time the batch represents.
+    fn take_token(&mut self) -> bool {
+        let request = match self.request {
+            Some(ref request) => request,
+            None => return true,
+        };
+        let now = Instant::now();
+        if self.tokens == 0 {
+            match request.try_wait() {
+                Some(tokens) => {
+                    self.tokens = tokens;
+                    self.next_token = now;
+                    self.token_interval =
+                        Duration::from_nanos(SECOND as u64 / request.rate().max(1));
+                }
+                None => return false,
+            }
+        }
+        if now < self.next_token {
+            return false;
         }
+        self.tokens -= 1;
+        self.next_token += self.token_interval;
+        true
     }
 
     fn do_hangups(&mut self) {
@@ -344,6 +840,9 @@ impl Client {
                     trace!("hangup: {}", token);
                     if self.sessions.contains(token) {
                         if self.close.as_ref().unwrap().try_wait().is_ok() {
+                            if let Some(addr) = self.sessions.get(token).map(|s| s.addr()) {
+                                *self.reconnects.entry(addr).or_insert(0) += 1;
+                            }
                             self.hangup(token);
                         } else {
                             self.ready_queue.push_front(token);
@@ -357,7 +856,62 @@ impl Client {
         }
     }
 
-    fn connect(&mut self, addr: SocketAddr) {
+    /// start and stop faults, write delayed requests which are due, and
+    /// close connections for a disconnect fault
+    fn do_chaos(&mut self) {
+        let now = Instant::now();
+        let chaos = match self.chaos {
+            Some(ref mut chaos) => chaos,
+            None => return,
+        };
+        let changed = chaos.update(now);
+        // the first client reports for all of them
+        if self.id == 0 && !changed.is_empty() {
+            for (fault, active) in changed {
+                if active {
+                    info!("Chaos: started {} fault", fault.name());
+                } else {
+                    info!("Chaos: stopped {} fault", fault.name());
+                }
+            }
+            self.metrics
+                .gauge(&Stat::ChaosActive, chaos.active() as u64);
+        }
+
+        for token in chaos.due(now) {
+            if let Some(session) = self.sessions.get_mut(token) {
+                session.set_state(State::Writing);
+                session.reregister(&self.poll);
+            }
+        }
+
+        let parked: Vec<usize> = chaos.parked.drain(..).collect();
+        for token in parked {
+            match self.sessions.get(token).map(|s| s.addr()) {
+                Some(addr) if chaos.is_blackholed(&addr) => chaos.parked.push(token),
+                Some(_) if !self.ready_queue.contains(&token) => {
+                    self.ready_queue.push_back(token);
+                }
+                _ => {}
+            }
+        }
+
+        if chaos.disconnect(now) {
+            let tokens: Vec<usize> = self
+                .sessions
+                .iter()
+                .filter(|(_, s)| !s.is_shadow())
+                .map(|(token, _)| token)
+                .collect();
+            if let Some(token) = tokens.choose(&mut thread_rng()) {
+                self.metrics.increment(&Stat::ChaosDisconnected);
+                self.ready_queue.retain(|t| t != token);
+                self.hangup(*token);
+            }
+        }
+    }
+
+    fn connect(&mut self, addr: SocketAddr, shadow: bool) {
         let session = self.sessions.vacant_entry();
         let tls = if let Some(ref mut tls_config) = self.tls_config {
             Some(rustls::ClientSession::new(
@@ -370,6 +924,20 @@ impl Client {
         let start = Instant::now();
         if let Ok(mut s) = Session::new(addr, Token(session.key()), tls) {
             s.set_nodelay(self.config.tcp_nodelay());
+            s.set_shadow(shadow);
+            if let Some(reconnects) = self.reconnects.get_mut(&addr).filter(|r| **r > 0) {
+                *reconnects -= 1;
+                s.set_reconnected(true);
+            }
+            if shadow {
+                // shadow connections are not included in the connection stats
+                s.register(&self.poll);
+                if self.tls_config.is_none() {
+                    self.shadows.push_back(session.key());
+                }
+                session.insert(s);
+                return;
+            }
             self.metrics.increment(&Stat::ConnectionsTotal);
             if self.tls_config.is_some() {
                 s.register(&self.poll);
@@ -381,6 +949,8 @@ impl Client {
                 self.ready_queue.push_back(session.key());
             }
             session.insert(s);
+        } else if shadow {
+            self.shadow_queue.push_back(addr);
         } else {
             self.metrics.increment(&Stat::ConnectionsError);
             self.connect_queue.push_back(addr);
@@ -392,25 +962,105 @@ impl Client {
             trace!("connect: {}", addr);
             if let Some(ref mut connect) = self.connect {
                 if connect.try_wait().is_ok() {
-                    self.connect(addr);
+                    self.connect(addr, false);
                 } else {
                     self.connect_queue.push_back(addr);
                     break;
                 }
             } else {
-                self.connect(addr);
+                self.connect(addr, false);
+            }
+        }
+        // failed connects are requeued, so only try each address once
+        for _ in 0..self.shadow_queue.len() {
+            if let Some(addr) = self.shadow_queue.pop_front() {
+                trace!("connect shadow: {}", addr);
+                self.connect(addr, true);
             }
         }
     }
 
-    pub fn run(&mut self, rng: &mut ThreadRng) {
+    /// connect to endpoints which were discovered and disconnect from the
+    /// ones which are gone
+    fn do_discovery(&mut self) {
+        let discovery = match self.discovery {
+            Some(ref mut discovery) => discovery,
+            None => return,
+        };
+        let version = discovery.endpoints.version();
+        if version == discovery.version {
+            return;
+        }
+        discovery.version = version;
+        let mut latest = discovery.endpoints.get();
+        if let Some((index, nodes)) = discovery.numa {
+            latest = numa::endpoints_for(index, nodes, &latest);
+        }
+        let latest: HashSet<SocketAddr> = latest.into_iter().collect();
+        let removed: Vec<SocketAddr> = self.endpoints.difference(&latest).copied().collect();
+        let added: Vec<SocketAddr> = latest.difference(&self.endpoints).copied().collect();
+        for addr in removed {
+            self.remove_endpoint(&addr);
+        }
+        for addr in added {
+            self.add_endpoint(&addr);
+        }
+    }
+
+    pub fn run(&mut self, rng: &mut StdRng) {
+        self.metrics.increment(&Stat::ProfileLoops);
+        self.do_discovery();
         self.do_timeouts();
         self.do_events();
         self.do_connects();
+        self.do_chaos();
         if self.close.is_some() {
             self.do_hangups();
         }
         self.do_requests(rng);
+        self.do_flush();
+    }
+
+    /// merge the metrics recorded by this thread into the shared metrics
+    fn do_flush(&mut self) {
+        let now = Instant::now();
+        if now >= self.next_flush {
+            if let Some(cpu) = thread_cpu_time() {
+                if let Some(last) = self.last_cpu {
+                    let used = cpu - last;
+                    self.metrics.add(
+                        &Stat::ProfileCpu,
+                        used.as_secs() * SECOND as u64 + used.subsec_nanos() as u64,
+                    );
+                }
+                self.last_cpu = Some(cpu);
+            }
+            // readings from before a boundary whose snapshot has been taken
+            // are reported with the next window
+            if let Some(boundary) = self.metrics.next_window(self.last_flush) {
+                if boundary + SETTLE <= now {
+                    self.metrics.increment(&Stat::WindowLateFlushes);
+                }
+            }
+            self.flush();
+            self.last_flush = now;
+            let next = now + Duration::from_nanos(FLUSH_INTERVAL);
+            self.next_flush = match self.metrics.next_window(now) {
+                Some(boundary) => boundary.min(next),
+                None => next,
+            };
+        }
+    }
+
+    /// merge everything recorded by this thread into the shared metrics
+    pub fn flush(&mut self) {
+        self.metrics.flush();
+        if let Some(ref mut exemplars) = self.exemplars {
+            self.metrics.merge_exemplars(exemplars);
+        }
+        if let Some(popularity) = self.codec.common_mut().popularity_mut() {
+            self.metrics.merge_popularity(popularity);
+        }
     }
 
     fn stat_increment(&self, label: Stat) {
@@ -418,6 +1068,38 @@ impl Client {
     }
 }
 
+/// record the latency of a response. The first response on a connection which
+/// replaced one closed by the close rate includes the cost of reconnecting, so
+/// it is kept apart from the steady state latency.
+fn record_latency(metrics: &Metrics, session: &mut Session, start: Instant, stop: Instant) {
+    if session.take_reconnected() {
+        metrics.time_interval(&Stat::ResponsesReconnectLatency, start, stop);
+    } else {
+        metrics.heatmap_increment(start, stop);
+        metrics.time_interval(&Stat::ResponsesLatency, start, stop);
+    }
+}
+
+/// the CPU time consumed by the calling thread
+#[cfg(target_os = "linux")]
+fn thread_cpu_time() -> Option<Duration> {
+    let mut ts = libc::timespec {
+        tv_sec: 0,
+        tv_nsec: 0,
+    };
+    // safety: the timespec is valid for the duration of the call
+    if unsafe { libc::clock_gettime(libc::CLOCK_THREAD_CPUTIME_ID, &mut ts) } == 0 {
+        Some(Duration::new(ts.tv_sec as u64, ts.tv_nsec as u32))
+    } else {
+        None
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn thread_cpu_time() -> Option<Duration> {
+    None
+}
+
 fn load_tls_config(config: &Arc<Config>) -> Option<Arc<rustls::ClientConfig>> {
     let cert_chain = config.tls_ca();
     let cert = config.tls_cert();
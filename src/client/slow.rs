@@ -0,0 +1,55 @@
+// Copyright 2021 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+//! Requests slower than the `log_slow` threshold are logged as they are
+//! answered, so that sporadic stalls can be found in the logs of the servers.
+//! The clients share a ratelimiter for the lines, and the slow requests which
+//! aren't logged are counted in the next line which is.
+
+use crate::stats::Exemplar;
+
+use rustcommon_ratelimiter::Ratelimiter;
+
+use std::sync::Arc;
+
+/// the most slow requests which are logged each second, across all clients
+pub const SLOW_LOG_RATE: u64 = 10;
+
+pub struct SlowLog {
+    /// the threshold in nanoseconds
+    threshold: u64,
+    ratelimit: Arc<Ratelimiter>,
+    suppressed: u64,
+}
+
+impl SlowLog {
+    /// log requests slower than the threshold, in microseconds
+    pub fn new(threshold: usize, ratelimit: Arc<Ratelimiter>) -> Self {
+        Self {
+            threshold: threshold as u64 * 1000,
+            ratelimit,
+            suppressed: 0,
+        }
+    }
+
+    /// whether a request with this latency, in nanoseconds, is slow
+    pub fn is_slow(&self, latency: u64) -> bool {
+        latency > self.threshold
+    }
+
+    /// the line to log for the slow request, or `None` if too many have been
+    /// logged already
+    pub fn record(&mut self, request: &Exemplar) -> Option<String> {
+        if self.ratelimit.try_wait().is_err() {
+            self.suppressed += 1;
+            return None;
+        }
+        let mut line = request.describe();
+        if self.suppressed > 0 {
+            line += &format!(" Suppressed: {}", self.suppressed);
+            self.suppressed = 0;
+        }
+        Some(line)
+    }
+}
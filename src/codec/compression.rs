@@ -0,0 +1,55 @@
+// Copyright 2021 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+//! Values are compressed into a self describing format, a zstd frame or an
+//! lz4 block which starts with the length of the value, so that a value which
+//! is read can be decompressed without knowing what was written.
+
+use crate::config::{Algorithm, Compression};
+
+use lz4::block::CompressionMode;
+
+use std::io;
+
+/// compress a value before it is stored
+pub fn compress(compression: &Compression, value: &[u8]) -> io::Result<Vec<u8>> {
+    match compression.algorithm() {
+        Algorithm::Lz4 => {
+            let mode = compression.level().map(CompressionMode::HIGHCOMPRESSION);
+            lz4::block::compress(value, mode, true)
+        }
+        Algorithm::Zstd => zstd::encode_all(value, compression.level().unwrap_or(0)),
+    }
+}
+
+/// decompress a value which was read, which fails if it wasn't compressed
+/// with the algorithm
+pub fn decompress(compression: &Compression, value: &[u8]) -> io::Result<Vec<u8>> {
+    match compression.algorithm() {
+        Algorithm::Lz4 => lz4::block::decompress(value, None),
+        Algorithm::Zstd => zstd::decode_all(value),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let value = b"abcdefghabcdefghabcdefghabcdefghabcdefghabcdefghabcdefgh";
+        for algorithm in &[Algorithm::Lz4, Algorithm::Zstd] {
+            for level in &[None, Some(9)] {
+                let mut compression = Compression::new(*algorithm);
+                compression.set_level(*level);
+                let compressed = compress(&compression, value).unwrap();
+                assert!(compressed.len() < value.len());
+                assert_eq!(decompress(&compression, &compressed).unwrap(), &value[..]);
+            }
+            // a value which wasn't compressed isn't valid
+            let compression = Compression::new(*algorithm);
+            assert!(decompress(&compression, b"DEADBEEF").is_err());
+        }
+    }
+}
@@ -69,7 +69,7 @@ impl Codec for Echo {
         }
     }
 
-    fn encode(&mut self, buf: &mut Buffer, rng: &mut ThreadRng) {
+    fn encode(&mut self, buf: &mut Buffer, rng: &mut StdRng) {
         let command = self.generate(rng);
         self.echo(buf, command.key().unwrap());
     }
@@ -5,9 +5,6 @@
 use crate::codec::*;
 use crate::config::Action;
 use crate::stats::Stat;
-use std::io::{BufRead, BufReader};
-
-use bytes::Buf;
 
 pub struct Memcache {
     common: Common,
@@ -52,6 +49,23 @@ impl Memcache {
     }
 }
 
+/// tokenize a response line without allocating. The longest line we need to
+/// inspect is "VALUE <key> <flags> <bytes> <cas>"
+fn tokenize(line: &[u8]) -> ([&[u8]; 5], usize) {
+    let mut tokens: [&[u8]; 5] = [&[]; 5];
+    let mut num_tokens = 0;
+    for token in line
+        .split(|b| b.is_ascii_whitespace())
+        .filter(|t| !t.is_empty())
+    {
+        if num_tokens < tokens.len() {
+            tokens[num_tokens] = token;
+        }
+        num_tokens += 1;
+    }
+    (tokens, num_tokens)
+}
+
 impl Default for Memcache {
     fn default() -> Self {
         Self::new()
@@ -67,6 +81,23 @@ impl Codec for Memcache {
         &mut self.common
     }
 
+    fn template(&self, shape: &Shape) -> Option<Template> {
+        match shape.action {
+            Action::Get => Some(Template::new().literal(b"get ").key().literal(b"\r\n")),
+            Action::Set => Some(
+                Template::new()
+                    .literal(b"set ")
+                    .key()
+                    .literal(
+                        format!(" 0 {} {}\r\n", shape.ttl.unwrap_or(0), shape.value).as_bytes(),
+                    )
+                    .value()
+                    .literal(b"\r\n"),
+            ),
+            _ => None,
+        }
+    }
+
     fn decode(&self, buf: &[u8]) -> Result<Response, Error> {
         // Shortest response is "OK\r\n" at 4bytes
         if buf.len() < 4 {
@@ -74,58 +105,60 @@ impl Codec for Memcache {
         }
 
         // All complete responses end in CRLF
-        if &buf[buf.len() - 2..buf.len()] != b"\r\n" {
+        if !buf.ends_with(b"\r\n") {
             return Err(Error::Incomplete);
         }
 
-        // count the number of lines
-        let reader = BufReader::new(buf.reader());
-        let num_lines = reader.lines().count();
+        // the first line determines the response type
+        let line_end = parse::find_crlf(buf).unwrap_or(buf.len() - 2);
+        let line = &buf[..line_end];
 
-        // get lines iterator
-        let reader = BufReader::new(buf.reader());
-        let mut lines = reader.lines();
+        let (tokens, num_tokens) = tokenize(line);
+        if num_tokens == 0 {
+            return Err(Error::Unknown);
+        }
 
         // single line responses
-        if num_lines == 1 {
-            let line = lines.next().unwrap().unwrap();
-            let tokens: Vec<&str> = line.split_whitespace().collect();
-
+        if line_end + 2 == buf.len() {
             // Single token responses
-            if tokens.len() == 1 {
-                match &*tokens[0] {
-                    "OK" | "STORED" | "DELETED" => {
+            if num_tokens == 1 {
+                match tokens[0] {
+                    b"OK" | b"STORED" | b"DELETED" => {
                         return Ok(Response::Ok);
                     }
-                    "END" | "EXISTS" | "NOT_FOUND" | "NOT_STORED" => {
+                    b"END" | b"EXISTS" | b"NOT_FOUND" | b"NOT_STORED" => {
                         return Ok(Response::Miss);
                     }
-                    "VALUE" => {
+                    b"VALUE" => {
                         // a complete response would have more than one token
                         return Err(Error::Incomplete);
                     }
-                    "ERROR" => {
+                    b"ERROR" => {
                         return Err(Error::Error);
                     }
                     _ => {}
                 }
                 // incr/decr give a numeric single token response
-                if tokens[0].parse::<u64>().is_ok() {
+                if parse::parse_u64(tokens[0]).is_some() {
                     return Ok(Response::Ok);
                 }
             } else {
-                match &*tokens[0] {
-                    "VALUE" => {
+                match tokens[0] {
+                    b"VALUE" => {
                         // a complete response would have more than one line
                         return Err(Error::Incomplete);
                     }
-                    "VERSION" => {
+                    b"VERSION" => {
                         return Ok(Response::Version);
                     }
-                    "CLIENT_ERROR" => {
+                    b"CLIENT_ERROR" => {
                         return Err(Error::ClientError);
                     }
-                    "SERVER_ERROR" => {
+                    b"SERVER_ERROR" => {
+                        // memcached sheds sets once it can't evict to make room
+                        if line.windows(13).any(|w| w == b"out of memory") {
+                            return Err(Error::PushBack);
+                        }
                         return Err(Error::ServerError);
                     }
                     _ => {
@@ -134,47 +167,45 @@ impl Codec for Memcache {
                 }
             }
         } else {
-            let line = lines.next().unwrap().unwrap();
-            let tokens: Vec<&str> = line.split_whitespace().collect();
-
-            match &*tokens[0] {
-                "VALUE" => {
-                    if tokens.len() < 4 {
+            match tokens[0] {
+                b"VALUE" => {
+                    if num_tokens < 4 {
                         // first line of VALUE response has 4 tokens
                         return Err(Error::Incomplete);
                     }
                     // Field 3 is the byte length of the response
-                    let bytes: usize = match tokens[3].parse() {
-                        Ok(b) => b,
-                        Err(_) => return Err(Error::Unknown),
+                    let bytes = match parse::parse_u64(tokens[3]) {
+                        Some(b) => b as usize,
+                        None => return Err(Error::Unknown),
                     };
                     // Optional CAS field must be a u64
-                    if tokens.len() == 5 {
-                        match tokens[4].parse::<u64>() {
-                            Ok(_) => {}
-                            Err(_) => {
-                                return Err(Error::Unknown);
-                            }
-                        }
+                    if num_tokens == 5 && parse::parse_u64(tokens[4]).is_none() {
+                        return Err(Error::Unknown);
                     }
                     // Flags field must be a u32
-                    match tokens[2].parse::<u32>() {
-                        Ok(_) => {}
-                        Err(_) => {
+                    match parse::parse_u64(tokens[2]) {
+                        Some(flags) if flags <= u32::MAX as u64 => {}
+                        _ => {
                             return Err(Error::Unknown);
                         }
                     }
 
                     // All complete responses end in "END\r\n"
-                    if &buf[buf.len() - 7..buf.len()] != b"\r\nEND\r\n" {
+                    if !buf.ends_with(b"\r\nEND\r\n") {
                         return Err(Error::Incomplete);
                     }
 
-                    let non_data_len = line.len() + 2 + 7; // first line w/ CRLF and last line with both CRLF
-                    let data_len = buf.len() - non_data_len;
+                    // first line w/ CRLF and last line with both CRLF
+                    let non_data_len = line_end + 2 + 7;
+                    let data_len = buf.len().saturating_sub(non_data_len);
 
                     if data_len != bytes {
                         return Err(Error::Unknown);
+                    } else if !self
+                        .common
+                        .decompress(&buf[line_end + 2..line_end + 2 + bytes])
+                    {
+                        return Err(Error::Corrupted);
                     } else {
                         return Ok(Response::Hit);
                     }
@@ -188,7 +219,28 @@ impl Codec for Memcache {
         Err(Error::Unknown)
     }
 
-    fn encode(&mut self, buf: &mut Buffer, rng: &mut ThreadRng) {
+    fn frame(&self, buf: &[u8]) -> Option<usize> {
+        let line_end = parse::find_crlf(buf)?;
+        let (tokens, num_tokens) = tokenize(&buf[..line_end]);
+        if tokens[0] == b"VALUE" && num_tokens >= 4 {
+            // header, data with CRLF, and "END\r\n"
+            let bytes = parse::parse_u64(tokens[3])? as usize;
+            let length = line_end + 2 + bytes + 2 + 5;
+            if buf.len() >= length {
+                Some(length)
+            } else {
+                None
+            }
+        } else {
+            Some(line_end + 2)
+        }
+    }
+
+    fn pipelining(&self) -> bool {
+        true
+    }
+
+    fn encode(&mut self, buf: &mut Buffer, rng: &mut StdRng) {
         let command = self.generate(rng);
         match command.action() {
             Action::Get => {
@@ -197,7 +249,9 @@ impl Codec for Memcache {
                     metrics.increment(&Stat::CommandsGet);
                     metrics.distribution(&Stat::KeySize, key.len() as u64);
                 }
-                self.get(buf, key);
+                if !self.common.render(buf, Action::Get, key, b"", None) {
+                    self.get(buf, key);
+                }
             }
             Action::Set => {
                 let key = command.key().unwrap();
@@ -207,7 +261,16 @@ impl Codec for Memcache {
                     metrics.distribution(&Stat::KeySize, key.len() as u64);
                     metrics.distribution(&Stat::ValueSize, value.len() as u64);
                 }
-                self.set(buf, key, value, command.ttl().map(|ttl| ttl as u32), None);
+                let ttl = command.ttl().map(|ttl| ttl as u32);
+                // compressed values vary in length, so they aren't templated
+                if let Some(compressed) = self.common.compress(value) {
+                    self.set(buf, key, &compressed, ttl, None);
+                } else if !self
+                    .common
+                    .render(buf, Action::Set, key, value, command.ttl())
+                {
+                    self.set(buf, key, value, ttl, None);
+                }
             }
             action => {
                 fatal!("Action: {:?} unsupported for Memcache", action);
@@ -274,6 +337,15 @@ mod tests {
         decode_messages(messages, Err(Error::ServerError));
     }
 
+    #[test]
+    fn decode_push_back() {
+        let messages: Vec<&[u8]> = vec![
+            b"SERVER_ERROR out of memory storing object\r\n",
+            b"SERVER_ERROR out of memory\r\n",
+        ];
+        decode_messages(messages, Err(Error::PushBack));
+    }
+
     #[test]
     fn decode_client_error() {
         let messages: Vec<&[u8]> = vec![b"CLIENT_ERROR WHOOPS\r\n"];
@@ -307,6 +379,38 @@ mod tests {
         decode_messages(messages, Ok(Response::Hit));
     }
 
+    #[test]
+    fn decode_compressed() {
+        let mut decoder = Memcache::new();
+        decoder
+            .common_mut()
+            .set_compression(Some(Compression::new(crate::config::Algorithm::Lz4)));
+
+        let value = decoder.common().compress(b"DEADBEEF").unwrap();
+        let mut message = format!("VALUE 0 0 {}\r\n", value.len()).into_bytes();
+        message.extend_from_slice(&value);
+        message.extend_from_slice(b"\r\nEND\r\n");
+        assert_eq!(decoder.decode(&message), Ok(Response::Hit));
+
+        // a value which wasn't compressed is corrupt
+        assert_eq!(
+            decoder.decode(b"VALUE 0 0 8\r\nDEADBEEF\r\nEND\r\n"),
+            Err(Error::Corrupted)
+        );
+    }
+
+    #[test]
+    fn frame() {
+        let decoder = Memcache::new();
+        assert_eq!(decoder.frame(b"STORED\r\nEND\r\n"), Some(8));
+        assert_eq!(
+            decoder.frame(b"VALUE 0 0 4\r\nDEAD\r\nEND\r\nVALUE 0 0 4\r\n"),
+            Some(24)
+        );
+        assert_eq!(decoder.frame(b"VALUE 0 0 4\r\nDEAD\r\nEN"), None);
+        assert_eq!(decoder.frame(b"STOR"), None);
+    }
+
     #[test]
     fn encode_get() {
         let mut buf = Buffer::new();
@@ -332,4 +436,46 @@ mod tests {
 
         assert_eq!(buf, test_case);
     }
+
+    #[test]
+    fn encode_template() {
+        let codec = Memcache::new();
+        for ttl in &[None, Some(120)] {
+            let shape = Shape {
+                action: Action::Set,
+                key: 3,
+                value: 5,
+                ttl: *ttl,
+            };
+            let mut buf = Buffer::new();
+            let mut test_case = Buffer::new();
+            codec
+                .template(&shape)
+                .unwrap()
+                .render(&mut buf, b"abc", b"value");
+            codec.set(
+                &mut test_case,
+                b"abc",
+                b"value",
+                ttl.map(|ttl| ttl as u32),
+                None,
+            );
+            assert_eq!(buf, test_case);
+        }
+
+        let shape = Shape {
+            action: Action::Get,
+            key: 3,
+            value: 0,
+            ttl: None,
+        };
+        let mut buf = Buffer::new();
+        let mut test_case = Buffer::new();
+        codec
+            .template(&shape)
+            .unwrap()
+            .render(&mut buf, b"abc", b"");
+        codec.get(&mut test_case, b"abc");
+        assert_eq!(buf, test_case);
+    }
 }
@@ -2,27 +2,37 @@
 // Licensed under the Apache License, Version 2.0
 // http://www.apache.org/licenses/LICENSE-2.0
 
+mod compression;
 mod echo;
 mod memcache;
+mod negotiate;
+pub mod parse;
 mod pelikan_rds;
 mod ping;
 mod redis;
+mod registry;
+mod template;
 mod thrift;
 mod thrift_cache;
 
 pub use echo::Echo;
 pub use memcache::Memcache;
+pub use negotiate::{describe, negotiate, Negotiated};
 pub use pelikan_rds::PelikanRds;
 pub use ping::Ping;
 pub use redis::{Redis, RedisMode};
-use rustcommon_buffer::Buffer;
+pub use registry::{Constructor, Registry};
+pub use rustcommon_buffer::Buffer;
+pub use template::{Shape, Template, Templates};
 pub use thrift_cache::ThriftCache;
 
-use crate::config::{Action, Config, Generator};
-use crate::stats::Metrics;
+use crate::config::{Action, Compression, Config, Generator};
+use crate::stats::{Metrics, Popularity, Stat};
+use std::net::SocketAddr;
 use std::sync::Arc;
+use std::time::Instant;
 
-use rand::rngs::ThreadRng;
+use rand::rngs::StdRng;
 
 #[derive(Clone, Debug, PartialEq)]
 pub enum Response {
@@ -38,10 +48,27 @@ pub enum Error {
     Error,
     ClientError,
     ServerError,
+    /// the server refused the request because it is out of memory, loading
+    /// or busy, which is a sign that it is shedding load
+    PushBack,
+    /// the value of a hit didn't decompress, so it was corrupted or wasn't
+    /// written by a client which compresses
+    Corrupted,
     Unknown,
     ChecksumMismatch(Vec<u8>, Vec<u8>),
 }
 
+/// identifies a request, so that it can be found in the logs of the server
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Tag {
+    pub action: Option<Action>,
+    pub key: Option<String>,
+    pub trace: Option<String>,
+}
+
+// the length of a trace id, which is written in hex
+const TRACE_LENGTH: usize = 16;
+
 pub struct Command {
     action: Action,
     key: Option<String>,
@@ -300,13 +327,48 @@ pub trait Codec: Send {
     fn common(&self) -> &Common;
     fn common_mut(&mut self) -> &mut Common;
     fn decode(&self, buf: &[u8]) -> Result<Response, Error>;
-    fn encode(&mut self, buf: &mut Buffer, rng: &mut ThreadRng);
+    fn encode(&mut self, buf: &mut Buffer, rng: &mut StdRng);
 
-    fn generate(&self, rng: &mut ThreadRng) -> Command {
-        self.common().generator.generate(rng)
+    /// the length of the first complete response in the buffer, which allows
+    /// pipelined responses to be decoded one at a time. Returns `None` if the
+    /// response is incomplete or the codec can't split responses.
+    fn frame(&self, _buf: &[u8]) -> Option<usize> {
+        None
+    }
+
+    /// whether responses can be split with `frame()`
+    fn pipelining(&self) -> bool {
+        false
+    }
+
+    /// select the endpoint which the next requests are encoded for, for
+    /// codecs which speak a different protocol to each endpoint
+    fn select(&mut self, _endpoint: &SocketAddr) {}
+
+    /// build a request template for the shape, if the codec supports it
+    fn template(&self, _shape: &Shape) -> Option<Template> {
+        None
+    }
+
+    fn generate(&mut self, rng: &mut StdRng) -> Command {
+        let mut command = self.common().generator.generate(rng);
+        self.common_mut().tag(&mut command);
+        self.common_mut().count(&command);
+        command
     }
     fn set_generator(&mut self, generator: Generator) {
         self.common_mut().set_generator(generator);
+        self.prepare();
+    }
+    /// pre-render templates for all request shapes the generator produces
+    fn prepare(&mut self) {
+        let mut templates = Templates::new();
+        for shape in self.common().generator.shapes() {
+            if let Some(template) = self.template(&shape) {
+                templates.insert(shape, template);
+            }
+        }
+        self.common_mut().templates = templates;
     }
     fn set_metrics(&mut self, metrics: Arc<Metrics>) {
         self.common_mut().set_metrics(metrics);
@@ -316,6 +378,12 @@ pub trait Codec: Send {
 pub struct Common {
     generator: Generator,
     metrics: Option<Arc<Metrics>>,
+    templates: Templates,
+    tagging: bool,
+    trace: Option<u64>,
+    tag: Option<Tag>,
+    popularity: Option<Popularity>,
+    compression: Option<Compression>,
 }
 
 impl Common {
@@ -323,6 +391,12 @@ impl Common {
         Self {
             generator: Config::default().generator(),
             metrics: None,
+            templates: Templates::new(),
+            tagging: false,
+            trace: None,
+            tag: None,
+            popularity: None,
+            compression: None,
         }
     }
 
@@ -337,6 +411,123 @@ impl Common {
     pub fn metrics(&self) -> Option<&Arc<Metrics>> {
         self.metrics.as_ref()
     }
+
+    /// record the key of each request, so that it can be taken with
+    /// `take_tag()` once the request is encoded. With a trace id base, the
+    /// value of each set starts with a unique trace id, which is the base
+    /// plus the number of sets so far.
+    pub fn set_tagging(&mut self, trace: Option<u64>) {
+        self.tagging = true;
+        self.trace = trace;
+    }
+
+    /// tag the command, if tagging is enabled. The trace id replaces the
+    /// start of the value, so that the length of the value is unchanged and
+    /// the key still belongs to the keyspace.
+    pub fn tag(&mut self, command: &mut Command) {
+        if !self.tagging {
+            return;
+        }
+        let mut tag = Tag {
+            action: Some(command.action),
+            key: command.key.clone(),
+            trace: None,
+        };
+        if let (Action::Set, Some(trace), Some(values)) =
+            (command.action, self.trace, command.values.as_mut())
+        {
+            if values[0].len() >= TRACE_LENGTH {
+                let id = format!("{:016x}", trace);
+                values[0].replace_range(..TRACE_LENGTH, &id);
+                tag.trace = Some(id);
+                self.trace = Some(trace.wrapping_add(1));
+            }
+        }
+        self.tag = Some(tag);
+    }
+
+    /// the tag of the last request which was generated
+    pub fn take_tag(&mut self) -> Option<Tag> {
+        self.tag.take()
+    }
+
+    /// count the requests generated for each key
+    pub fn set_popularity(&mut self) {
+        self.popularity = Some(Popularity::new());
+    }
+
+    /// count the key of the command, if counting is enabled
+    pub fn count(&mut self, command: &Command) {
+        if let (Some(popularity), Some(key)) = (&mut self.popularity, &command.key) {
+            popularity.record(key);
+        }
+    }
+
+    /// the key counts since they were last merged
+    pub fn popularity_mut(&mut self) -> Option<&mut Popularity> {
+        self.popularity.as_mut()
+    }
+
+    /// compress the values which are written and decompress the values which
+    /// are read
+    pub fn set_compression(&mut self, compression: Option<Compression>) {
+        self.compression = compression;
+    }
+
+    /// compress the value, if compression is enabled, and record the time it
+    /// took and the bytes saved
+    pub fn compress(&self, value: &[u8]) -> Option<Vec<u8>> {
+        let compression = self.compression.as_ref()?;
+        let start = Instant::now();
+        let compressed = compression::compress(compression, value).unwrap_or_else(|e| {
+            fatal!("failed to compress value: {}", e);
+        });
+        if let Some(metrics) = &self.metrics {
+            metrics.time_interval(&Stat::CompressionCompress, start, Instant::now());
+            metrics.add(&Stat::CompressionBytesRaw, value.len() as u64);
+            metrics.add(&Stat::CompressionBytesStored, compressed.len() as u64);
+        }
+        Some(compressed)
+    }
+
+    /// check that a value which was read decompresses, if compression is
+    /// enabled, and record the time it took
+    pub fn decompress(&self, value: &[u8]) -> bool {
+        let compression = match self.compression {
+            Some(ref compression) => compression,
+            None => return true,
+        };
+        let start = Instant::now();
+        let valid = compression::decompress(compression, value).is_ok();
+        if let Some(metrics) = &self.metrics {
+            metrics.time_interval(&Stat::CompressionDecompress, start, Instant::now());
+        }
+        valid
+    }
+
+    /// write the request using a prepared template. Returns false if there is
+    /// no template for this request and the codec must serialize it instead.
+    pub fn render(
+        &self,
+        buf: &mut Buffer,
+        action: Action,
+        key: &[u8],
+        value: &[u8],
+        ttl: Option<usize>,
+    ) -> bool {
+        let shape = Shape {
+            action,
+            key: key.len(),
+            value: value.len(),
+            ttl,
+        };
+        if let Some(template) = self.templates.get(&shape) {
+            template.render(buf, key, value);
+            true
+        } else {
+            false
+        }
+    }
 }
 
 impl Default for Common {
@@ -344,3 +535,39 @@ impl Default for Common {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tagging() {
+        let mut common = Common::new();
+        let mut command = Command::set("42".to_string(), "a".repeat(20), None);
+        common.tag(&mut command);
+        assert_eq!(common.take_tag(), None);
+
+        common.set_tagging(Some(0xabc));
+        common.tag(&mut command);
+        assert_eq!(command.value(), Some(&b"0000000000000abcaaaa"[..]));
+        assert_eq!(
+            common.take_tag(),
+            Some(Tag {
+                action: Some(Action::Set),
+                key: Some("42".to_string()),
+                trace: Some("0000000000000abc".to_string()),
+            })
+        );
+
+        // the key of a get is recorded, and short values are left as they are
+        let mut command = Command::get("7".to_string());
+        common.tag(&mut command);
+        let tag = common.take_tag().unwrap();
+        assert_eq!(tag.action, Some(Action::Get));
+        assert_eq!(tag.key, Some("7".to_string()));
+        let mut command = Command::set("42".to_string(), "short".to_string(), None);
+        common.tag(&mut command);
+        assert_eq!(command.value(), Some(&b"short"[..]));
+        assert_eq!(common.take_tag().unwrap().trace, None);
+    }
+}
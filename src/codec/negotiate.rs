@@ -0,0 +1,177 @@
+// Copyright 2021 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+//! The `redis_auto` protocol probes each endpoint before the run to find the
+//! protocol it speaks. A `HELLO 3` which is answered with a map means RESP3,
+//! otherwise a `PING` sent as a RESP array which is answered with `PONG`
+//! means RESP2, and anything else is taken to only speak the inline protocol.
+//! Each endpoint is then spoken to in its own protocol.
+
+use crate::codec::RedisMode;
+
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::time::Duration;
+
+const HELLO: &[u8] = b"*2\r\n$5\r\nHELLO\r\n$1\r\n3\r\n";
+const PING: &[u8] = b"*1\r\n$4\r\nPING\r\n";
+
+/// the protocols a redis endpoint may speak, from the least capable
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Negotiated {
+    Inline,
+    Resp2,
+    Resp3,
+}
+
+impl Negotiated {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Negotiated::Inline => "inline",
+            Negotiated::Resp2 => "resp2",
+            Negotiated::Resp3 => "resp3",
+        }
+    }
+
+    /// the mode requests are encoded with. Connections start in RESP2 and
+    /// `HELLO 3` isn't sent on them, so RESP3 endpoints are spoken to in RESP2.
+    pub fn mode(&self) -> RedisMode {
+        match self {
+            Negotiated::Inline => RedisMode::Inline,
+            Negotiated::Resp2 | Negotiated::Resp3 => RedisMode::Resp,
+        }
+    }
+}
+
+/// probe each endpoint, returning the mode to speak to each endpoint which
+/// answered
+pub fn negotiate(endpoints: &[SocketAddr], timeout: Duration) -> HashMap<SocketAddr, RedisMode> {
+    let mut negotiated = HashMap::new();
+    for endpoint in endpoints {
+        match probe(endpoint, timeout) {
+            Ok(protocol) => {
+                info!(
+                    "Negotiated: Endpoint: {} Protocol: {} Speaking: {}",
+                    endpoint,
+                    protocol.name(),
+                    protocol.mode().name()
+                );
+                negotiated.insert(*endpoint, protocol.mode());
+            }
+            Err(e) => {
+                warn!("Failed to probe endpoint {}: {}", endpoint, e);
+            }
+        }
+    }
+    negotiated
+}
+
+/// describe the modes which are spoken to the endpoints, such as `resp2` or
+/// `inline: 1, resp2: 2` for a mix of them
+pub fn describe(negotiated: &HashMap<SocketAddr, RedisMode>) -> String {
+    let mut counts: Vec<(&str, usize)> = Vec::new();
+    for mode in negotiated.values() {
+        match counts.iter_mut().find(|(name, _)| *name == mode.name()) {
+            Some((_, count)) => *count += 1,
+            None => counts.push((mode.name(), 1)),
+        }
+    }
+    counts.sort_unstable();
+    match counts.as_slice() {
+        [(name, _)] => name.to_string(),
+        counts => counts
+            .iter()
+            .map(|(name, count)| format!("{}: {}", name, count))
+            .collect::<Vec<String>>()
+            .join(", "),
+    }
+}
+
+/// find the protocol the endpoint speaks
+pub fn probe(endpoint: &SocketAddr, timeout: Duration) -> Result<Negotiated, String> {
+    if exchange(endpoint, timeout, HELLO)?.starts_with(b"%") {
+        return Ok(Negotiated::Resp3);
+    }
+    if exchange(endpoint, timeout, PING)? == b"+PONG" {
+        Ok(Negotiated::Resp2)
+    } else {
+        Ok(Negotiated::Inline)
+    }
+}
+
+/// send the request on a new connection and return the first line of the
+/// reply, which is empty if the endpoint closed the connection or didn't
+/// answer in time
+fn exchange(endpoint: &SocketAddr, timeout: Duration, request: &[u8]) -> Result<Vec<u8>, String> {
+    let mut stream = TcpStream::connect_timeout(endpoint, timeout).map_err(|e| e.to_string())?;
+    let _ = stream.set_read_timeout(Some(timeout));
+    let _ = stream.set_write_timeout(Some(timeout));
+    stream.write_all(request).map_err(|e| e.to_string())?;
+    let mut reply = Vec::new();
+    let mut buf = [0; 512];
+    loop {
+        if let Some(end) = reply.windows(2).position(|w| w == b"\r\n") {
+            reply.truncate(end);
+            return Ok(reply);
+        }
+        match stream.read(&mut buf) {
+            Ok(0) | Err(_) => return Ok(Vec::new()),
+            Ok(bytes) => reply.extend_from_slice(&buf[..bytes]),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+    use std::thread;
+
+    /// a server which answers the first line of each connection
+    fn server(answer: fn(&[u8]) -> &'static [u8]) -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut buf = [0; 512];
+                let bytes = stream.read(&mut buf).unwrap_or(0);
+                let _ = stream.write_all(answer(&buf[..bytes]));
+            }
+        });
+        addr
+    }
+
+    #[test]
+    fn probed() {
+        let timeout = Duration::from_secs(1);
+        let resp3 = server(|request| {
+            if request == HELLO {
+                b"%1\r\n$6\r\nserver\r\n$5\r\nredis\r\n"
+            } else {
+                b"+PONG\r\n"
+            }
+        });
+        let resp2 = server(|request| {
+            if request == PING {
+                b"+PONG\r\n"
+            } else {
+                b"-ERR unknown command 'HELLO'\r\n"
+            }
+        });
+        let inline = server(|_| b"-ERR unknown command\r\n");
+        assert_eq!(probe(&resp3, timeout), Ok(Negotiated::Resp3));
+        assert_eq!(probe(&resp2, timeout), Ok(Negotiated::Resp2));
+        assert_eq!(probe(&inline, timeout), Ok(Negotiated::Inline));
+
+        let negotiated = negotiate(&[resp3, resp2, inline], timeout);
+        assert_eq!(negotiated.get(&resp3), Some(&RedisMode::Resp));
+        assert_eq!(negotiated.get(&resp2), Some(&RedisMode::Resp));
+        assert_eq!(negotiated.get(&inline), Some(&RedisMode::Inline));
+        assert_eq!(describe(&negotiated), "inline: 1, resp2: 2");
+        assert_eq!(describe(&negotiate(&[resp3], timeout)), "resp2");
+        assert!(negotiate(&[], timeout).is_empty());
+    }
+}
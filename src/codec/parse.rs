@@ -0,0 +1,220 @@
+// Copyright 2021 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+//! Helpers for the response parsing hot path. These operate directly on the
+//! read buffer and avoid allocation, using `memchr` to find line endings.
+
+use crate::codec::{Error, Response};
+
+use std::cmp::Ordering;
+
+/// returns the position of the first CRLF in the buffer
+#[inline]
+pub fn find_crlf(buf: &[u8]) -> Option<usize> {
+    let mut offset = 0;
+    while let Some(pos) = memchr::memchr(b'\r', &buf[offset..]) {
+        let pos = offset + pos;
+        match buf.get(pos + 1) {
+            Some(b'\n') => return Some(pos),
+            Some(_) => offset = pos + 1,
+            None => return None,
+        }
+    }
+    None
+}
+
+/// parse an unsigned decimal number, rejecting empty input, non-digits, and
+/// overflow
+#[inline]
+pub fn parse_u64(bytes: &[u8]) -> Option<u64> {
+    if bytes.is_empty() {
+        return None;
+    }
+    let mut value: u64 = 0;
+    for byte in bytes {
+        let digit = byte.wrapping_sub(b'0');
+        if digit > 9 {
+            return None;
+        }
+        value = value.checked_mul(10)?.checked_add(digit as u64)?;
+    }
+    Some(value)
+}
+
+/// parse a signed decimal number with an optional leading sign
+#[inline]
+pub fn parse_i64(bytes: &[u8]) -> Option<i64> {
+    let (negative, digits) = match bytes.first() {
+        Some(b'-') => (true, &bytes[1..]),
+        Some(b'+') => (false, &bytes[1..]),
+        _ => (false, bytes),
+    };
+    let magnitude = parse_u64(digits)?;
+    if negative && magnitude <= i64::MAX as u64 + 1 {
+        Some((magnitude as i64).wrapping_neg())
+    } else if !negative && magnitude <= i64::MAX as u64 {
+        Some(magnitude as i64)
+    } else {
+        None
+    }
+}
+
+/// the length of the first complete RESP response in the buffer
+pub fn resp_frame(buf: &[u8]) -> Option<usize> {
+    let header = find_crlf(buf)?;
+    match buf.first()? {
+        b'+' | b'-' | b':' => Some(header + 2),
+        b'$' => {
+            let length = parse_i64(&buf[1..header])?;
+            let total = if length < 0 {
+                header + 2
+            } else {
+                header + 2 + length as usize + 2
+            };
+            if buf.len() >= total {
+                Some(total)
+            } else {
+                None
+            }
+        }
+        b'*' if &buf[1..header] == b"-1" => Some(header + 2),
+        _ => None,
+    }
+}
+
+/// the kinds of RESP errors which mean that the server is shedding load
+const PUSH_BACK: [&[u8]; 3] = [b"LOADING", b"BUSY", b"OOM"];
+
+/// decode a RESP response, treating any of the `ok` simple strings as success
+pub fn resp(buf: &[u8], ok: &[&[u8]]) -> Result<Response, Error> {
+    // All complete responses end in CRLF
+    if buf.len() < 3 || !buf.ends_with(b"\r\n") {
+        return Err(Error::Incomplete);
+    }
+
+    let msg = &buf[1..buf.len() - 2];
+    match buf[0] {
+        b'+' => {
+            // simple string
+            if buf.len() < 5 {
+                Err(Error::Incomplete)
+            } else if ok.contains(&msg) {
+                Ok(Response::Ok)
+            } else {
+                Err(Error::Unknown)
+            }
+        }
+        b'-' => {
+            // error response, the first word of which is the kind of error
+            let kind = msg.split(|b| *b == b' ').next().unwrap_or(msg);
+            if PUSH_BACK.contains(&kind) {
+                Err(Error::PushBack)
+            } else {
+                Err(Error::Error)
+            }
+        }
+        b':' => {
+            // numeric response
+            match parse_i64(msg) {
+                Some(_) => Ok(Response::Ok),
+                None => Err(Error::Unknown),
+            }
+        }
+        b'$' => {
+            // bulk string
+            if msg == b"-1" {
+                return Ok(Response::Miss);
+            }
+            // the header is terminated by the first CRLF
+            let header = find_crlf(buf).unwrap_or(buf.len() - 2);
+            match parse_u64(&buf[1..header]) {
+                Some(expected) => {
+                    // data is everything between the header and trailing CRLF
+                    match (buf.len() - 2).checked_sub(header + 2) {
+                        Some(have) => match (have as u64).cmp(&expected) {
+                            Ordering::Less => Err(Error::Incomplete),
+                            Ordering::Equal => Ok(Response::Hit),
+                            Ordering::Greater => Err(Error::Error),
+                        },
+                        None => Err(Error::Incomplete),
+                    }
+                }
+                None => Err(Error::Unknown),
+            }
+        }
+        b'*' => {
+            // arrays
+            if msg == b"-1" {
+                Ok(Response::Miss)
+            } else {
+                // TODO: implement array parsing
+                Err(Error::Unknown)
+            }
+        }
+        _ => Err(Error::Unknown),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn crlf() {
+        assert_eq!(find_crlf(b""), None);
+        assert_eq!(find_crlf(b"\r"), None);
+        assert_eq!(find_crlf(b"abc\r\n"), Some(3));
+        assert_eq!(find_crlf(b"a\rb\r\nc\r\n"), Some(3));
+        assert_eq!(find_crlf(b"a\nb\r"), None);
+    }
+
+    #[test]
+    fn numbers() {
+        assert_eq!(parse_u64(b""), None);
+        assert_eq!(parse_u64(b"0"), Some(0));
+        assert_eq!(parse_u64(b"12345"), Some(12345));
+        assert_eq!(parse_u64(b"18446744073709551615"), Some(u64::MAX));
+        assert_eq!(parse_u64(b"18446744073709551616"), None);
+        assert_eq!(parse_u64(b"12a"), None);
+        assert_eq!(parse_u64(b"-1"), None);
+
+        assert_eq!(parse_i64(b"-1"), Some(-1));
+        assert_eq!(parse_i64(b"+7"), Some(7));
+        assert_eq!(parse_i64(b"-"), None);
+        assert_eq!(parse_i64(b"9223372036854775807"), Some(i64::MAX));
+        assert_eq!(parse_i64(b"-9223372036854775808"), Some(i64::MIN));
+        assert_eq!(parse_i64(b"9223372036854775808"), None);
+    }
+
+    #[test]
+    fn push_back() {
+        assert_eq!(
+            resp(b"-LOADING Redis is loading the dataset in memory\r\n", &[]),
+            Err(Error::PushBack)
+        );
+        assert_eq!(
+            resp(b"-BUSY Redis is busy running a script\r\n", &[]),
+            Err(Error::PushBack)
+        );
+        assert_eq!(
+            resp(b"-OOM command not allowed\r\n", &[]),
+            Err(Error::PushBack)
+        );
+        assert_eq!(resp(b"-ERR unknown command\r\n", &[]), Err(Error::Error));
+        assert_eq!(
+            resp(b"-BUSYKEY Target key exists\r\n", &[]),
+            Err(Error::Error)
+        );
+    }
+
+    #[test]
+    fn frame() {
+        assert_eq!(resp_frame(b"+OK\r\n+OK\r\n"), Some(5));
+        assert_eq!(resp_frame(b":12\r\n"), Some(5));
+        assert_eq!(resp_frame(b"$-1\r\n$1\r\n"), Some(5));
+        assert_eq!(resp_frame(b"$4\r\nab\r\n\r\n$-1\r\n"), Some(10));
+        assert_eq!(resp_frame(b"$4\r\nab\r\n"), None);
+        assert_eq!(resp_frame(b"+OK"), None);
+    }
+}
@@ -2,16 +2,10 @@
 // Licensed under the Apache License, Version 2.0
 // http://www.apache.org/licenses/LICENSE-2.0
 
-use std::cmp::Ordering;
-use std::io::{BufRead, BufReader};
-use std::str;
-
 use crate::codec::*;
 use crate::config::Action;
 use crate::stats::Stat;
 
-use bytes::Buf;
-
 pub struct PelikanRds {
     common: Common,
 }
@@ -156,6 +150,12 @@ impl PelikanRds {
     }
 }
 
+impl Default for PelikanRds {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Codec for PelikanRds {
     fn common(&self) -> &Common {
         &self.common
@@ -165,86 +165,46 @@ impl Codec for PelikanRds {
         &mut self.common
     }
 
-    fn decode(&self, buf: &[u8]) -> Result<Response, Error> {
-        let end = &buf[buf.len() - 2..buf.len()];
-
-        // All complete responses end in CRLF
-        if &end[..] != b"\r\n" {
-            return Err(Error::Incomplete);
-        }
-
-        let first_char = &buf[0..1];
-        match str::from_utf8(&first_char[..]) {
-            Ok("+") => {
-                // simple string
-                if buf.len() < 5 {
-                    Err(Error::Incomplete)
-                } else {
-                    let msg = &buf[1..buf.len() - 2];
-                    match str::from_utf8(&msg[..]) {
-                        Ok("OK") | Ok("PONG") | Ok("NOOP") => Ok(Response::Ok),
-                        _ => Err(Error::Unknown),
-                    }
-                }
-            }
-            Ok("-") => {
-                // error response
-                Err(Error::Error)
-            }
-            Ok(":") => {
-                // numeric response
-                let msg = &buf[1..buf.len() - 2];
-                match str::from_utf8(&msg[..]) {
-                    Ok(msg) => match msg.parse::<i64>() {
-                        Ok(_) => Ok(Response::Ok),
-                        Err(_) => Err(Error::Unknown),
-                    },
-                    Err(_) => Err(Error::Unknown),
-                }
-            }
-            Ok("$") => {
-                // bulk string
-                let msg = &buf[1..buf.len() - 2];
-                match str::from_utf8(&msg[..]) {
-                    Ok("-1") => Ok(Response::Miss),
-                    Ok(_) => {
-                        let reader = BufReader::new(buf.reader());
-                        let mut lines = reader.lines();
-                        let mut line = lines.next().unwrap().unwrap();
-                        let _ = line.remove(0);
-                        match line.parse::<usize>() {
-                            Ok(expected) => {
-                                // data len = buf.len() - line.len() - 2x CRLF - 1
-                                let have = buf.len() - line.len() - 5;
-                                match have.cmp(&expected) {
-                                    Ordering::Less => Err(Error::Incomplete),
-                                    Ordering::Equal => Ok(Response::Hit),
-                                    Ordering::Greater => Err(Error::Error),
-                                }
-                            }
-                            Err(_) => Err(Error::Unknown),
-                        }
-                    }
-                    Err(_) => Err(Error::Unknown),
-                }
-            }
-            Ok("*") => {
-                // arrays
-                let msg = &buf[1..buf.len() - 2];
-                match str::from_utf8(&msg[..]) {
-                    Ok("-1") => Ok(Response::Miss),
-                    Ok(_) => {
-                        // TODO: implement array parsing
-                        Err(Error::Unknown)
-                    }
-                    Err(_) => Err(Error::Unknown),
+    fn template(&self, shape: &Shape) -> Option<Template> {
+        match shape.action {
+            Action::Get => Some(
+                Template::new()
+                    .literal(format!("*2\r\n$3\r\nget\r\n${}\r\n", shape.key).as_bytes())
+                    .key()
+                    .literal(b"\r\n"),
+            ),
+            Action::Set => {
+                let args = if shape.ttl.is_some() { 5 } else { 3 };
+                let mut template = Template::new()
+                    .literal(format!("*{}\r\n$3\r\nset\r\n${}\r\n", args, shape.key).as_bytes())
+                    .key()
+                    .literal(format!("\r\n${}\r\n", shape.value).as_bytes())
+                    .value()
+                    .literal(b"\r\n");
+                if let Some(ttl) = shape.ttl {
+                    let ttl = format!("{}", ttl);
+                    template = template
+                        .literal(format!("$2\r\nEX\r\n${}\r\n{}\r\n", ttl.len(), ttl).as_bytes());
                 }
+                Some(template)
             }
-            _ => Err(Error::Unknown),
+            _ => None,
         }
     }
 
-    fn encode(&mut self, buf: &mut Buffer, rng: &mut ThreadRng) {
+    fn decode(&self, buf: &[u8]) -> Result<Response, Error> {
+        parse::resp(buf, &[b"OK", b"PONG", b"NOOP"])
+    }
+
+    fn frame(&self, buf: &[u8]) -> Option<usize> {
+        parse::resp_frame(buf)
+    }
+
+    fn pipelining(&self) -> bool {
+        true
+    }
+
+    fn encode(&mut self, buf: &mut Buffer, rng: &mut StdRng) {
         let command = self.generate(rng);
         match command.action() {
             Action::Get => {
@@ -253,7 +213,9 @@ impl Codec for PelikanRds {
                     metrics.increment(&Stat::CommandsGet);
                     metrics.distribution(&Stat::KeySize, key.len() as u64);
                 }
-                self.get(buf, key);
+                if !self.common.render(buf, Action::Get, key, b"", None) {
+                    self.get(buf, key);
+                }
             }
             Action::Set => {
                 let key = command.key().unwrap();
@@ -263,7 +225,12 @@ impl Codec for PelikanRds {
                     metrics.distribution(&Stat::KeySize, key.len() as u64);
                     metrics.distribution(&Stat::ValueSize, value.len() as u64);
                 }
-                self.set(buf, key, value, command.ttl());
+                if !self
+                    .common
+                    .render(buf, Action::Set, key, value, command.ttl())
+                {
+                    self.set(buf, key, value, command.ttl());
+                }
             }
             Action::SarrayCreate => {
                 let key = command.key().unwrap();
@@ -428,6 +395,26 @@ mod tests {
         assert_eq!(test_case, buf);
     }
 
+    #[test]
+    fn encode_template() {
+        let c = PelikanRds::new();
+        for ttl in &[None, Some(9876)] {
+            let shape = Shape {
+                action: Action::Set,
+                key: 3,
+                value: 4,
+                ttl: *ttl,
+            };
+            let mut buf = Buffer::new();
+            let mut test_case = Buffer::new();
+            c.template(&shape)
+                .unwrap()
+                .render(&mut buf, b"abc", b"1234");
+            c.set(&mut test_case, b"abc", b"1234", *ttl);
+            assert_eq!(test_case, buf);
+        }
+    }
+
     #[test]
     fn encode_sarray_create() {
         let c = PelikanRds::new();
@@ -44,7 +44,15 @@ impl Codec for Ping {
         }
     }
 
-    fn encode(&mut self, buf: &mut Buffer, _rng: &mut ThreadRng) {
+    fn frame(&self, buf: &[u8]) -> Option<usize> {
+        parse::find_crlf(buf).map(|end| end + 2)
+    }
+
+    fn pipelining(&self) -> bool {
+        true
+    }
+
+    fn encode(&mut self, buf: &mut Buffer, _rng: &mut StdRng) {
         self.ping(buf);
     }
 }
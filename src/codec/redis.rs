@@ -2,24 +2,35 @@
 // Licensed under the Apache License, Version 2.0
 // http://www.apache.org/licenses/LICENSE-2.0
 
-use std::cmp::Ordering;
-use std::io::{BufRead, BufReader};
-use std::str;
-
 use crate::codec::*;
 use crate::config::Action;
 use crate::stats::Stat;
 
-use bytes::Buf;
+use std::collections::HashMap;
 
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum RedisMode {
     Inline,
     Resp,
 }
 
+impl RedisMode {
+    /// the name of the protocol on the wire
+    pub fn name(&self) -> &'static str {
+        match self {
+            RedisMode::Inline => "inline",
+            RedisMode::Resp => "resp2",
+        }
+    }
+}
+
 pub struct Redis {
     common: Common,
     mode: RedisMode,
+    // the mode of each endpoint, if they were negotiated separately, and the
+    // mode of any other endpoint
+    modes: Arc<HashMap<SocketAddr, RedisMode>>,
+    default: RedisMode,
 }
 
 impl Redis {
@@ -27,6 +38,25 @@ impl Redis {
         Self {
             common: Common::new(),
             mode,
+            modes: Arc::new(HashMap::new()),
+            default: mode,
+        }
+    }
+
+    /// a codec which speaks the mode negotiated with each endpoint. Other
+    /// endpoints are spoken to in the mode of all the negotiated endpoints
+    /// if they agree, otherwise in RESP.
+    pub fn negotiated(modes: Arc<HashMap<SocketAddr, RedisMode>>) -> Self {
+        let mut all = modes.values();
+        let default = match all.next() {
+            Some(first) if all.all(|mode| mode == first) => *first,
+            _ => RedisMode::Resp,
+        };
+        Self {
+            common: Common::new(),
+            mode: default,
+            modes,
+            default,
         }
     }
 
@@ -53,6 +83,51 @@ impl Redis {
         }
     }
 
+    /// a template for a command with a single key and an optional value
+    fn command_template(&self, command: &str, shape: &Shape, suffix: &[String]) -> Template {
+        let has_value = shape.action == Action::Set;
+        match self.mode {
+            RedisMode::Inline => {
+                let mut template = Template::new()
+                    .literal(command.as_bytes())
+                    .literal(b" ")
+                    .key();
+                if has_value {
+                    template = template.literal(b" ").value();
+                }
+                for arg in suffix {
+                    template = template.literal(b" ").literal(arg.as_bytes());
+                }
+                template.literal(b"\r\n")
+            }
+            RedisMode::Resp => {
+                let args = 2 + has_value as usize + suffix.len();
+                let mut template = Template::new()
+                    .literal(
+                        format!(
+                            "*{}\r\n${}\r\n{}\r\n${}\r\n",
+                            args,
+                            command.len(),
+                            command,
+                            shape.key
+                        )
+                        .as_bytes(),
+                    )
+                    .key();
+                if has_value {
+                    template = template
+                        .literal(format!("\r\n${}\r\n", shape.value).as_bytes())
+                        .value();
+                }
+                for arg in suffix {
+                    template =
+                        template.literal(format!("\r\n${}\r\n{}", arg.len(), arg).as_bytes());
+                }
+                template.literal(b"\r\n")
+            }
+        }
+    }
+
     pub fn delete(&self, buf: &mut Buffer, keys: &[&[u8]]) {
         self.command(buf, "delete", keys);
     }
@@ -163,86 +238,44 @@ impl Codec for Redis {
         &mut self.common
     }
 
-    fn decode(&self, buf: &[u8]) -> Result<Response, Error> {
-        let end = &buf[buf.len() - 2..buf.len()];
-
-        // All complete responses end in CRLF
-        if &end[..] != b"\r\n" {
-            return Err(Error::Incomplete);
+    fn template(&self, shape: &Shape) -> Option<Template> {
+        // the templates are rendered in one mode, so requests to a mix of
+        // modes are serialized for each endpoint instead
+        if self.modes.values().any(|mode| *mode != self.default) {
+            return None;
         }
-
-        let first_char = &buf[0..1];
-        match str::from_utf8(&first_char[..]) {
-            Ok("+") => {
-                // simple string
-                if buf.len() < 5 {
-                    Err(Error::Incomplete)
+        match shape.action {
+            Action::Delete => Some(self.command_template("delete", shape, &[])),
+            Action::Get => Some(self.command_template("get", shape, &[])),
+            Action::Set => {
+                let suffix = if let Some(ttl) = shape.ttl {
+                    vec!["EX".to_string(), format!("{}", ttl)]
                 } else {
-                    let msg = &buf[1..buf.len() - 2];
-                    match str::from_utf8(&msg[..]) {
-                        Ok("OK") | Ok("PONG") => Ok(Response::Ok),
-                        _ => Err(Error::Unknown),
-                    }
-                }
-            }
-            Ok("-") => {
-                // error response
-                Err(Error::Error)
+                    Vec::new()
+                };
+                Some(self.command_template("set", shape, &suffix))
             }
-            Ok(":") => {
-                // numeric response
-                let msg = &buf[1..buf.len() - 2];
-                match str::from_utf8(&msg[..]) {
-                    Ok(msg) => match msg.parse::<i64>() {
-                        Ok(_) => Ok(Response::Ok),
-                        Err(_) => Err(Error::Unknown),
-                    },
-                    Err(_) => Err(Error::Unknown),
-                }
-            }
-            Ok("$") => {
-                // bulk string
-                let msg = &buf[1..buf.len() - 2];
-                match str::from_utf8(&msg[..]) {
-                    Ok("-1") => Ok(Response::Miss),
-                    Ok(_) => {
-                        let reader = BufReader::new(buf.reader());
-                        let mut lines = reader.lines();
-                        let mut line = lines.next().unwrap().unwrap();
-                        let _ = line.remove(0);
-                        match line.parse::<usize>() {
-                            Ok(expected) => {
-                                // data len = buf.len() - line.len() - 2x CRLF - 1
-                                let have = buf.len() - line.len() - 5;
-                                match have.cmp(&expected) {
-                                    Ordering::Less => Err(Error::Incomplete),
-                                    Ordering::Equal => Ok(Response::Hit),
-                                    Ordering::Greater => Err(Error::Error),
-                                }
-                            }
-                            Err(_) => Err(Error::Unknown),
-                        }
-                    }
-                    Err(_) => Err(Error::Unknown),
-                }
-            }
-            Ok("*") => {
-                // arrays
-                let msg = &buf[1..buf.len() - 2];
-                match str::from_utf8(&msg[..]) {
-                    Ok("-1") => Ok(Response::Miss),
-                    Ok(_) => {
-                        // TODO: implement array parsing
-                        Err(Error::Unknown)
-                    }
-                    Err(_) => Err(Error::Unknown),
-                }
-            }
-            _ => Err(Error::Unknown),
+            _ => None,
         }
     }
 
-    fn encode(&mut self, buf: &mut Buffer, rng: &mut ThreadRng) {
+    fn decode(&self, buf: &[u8]) -> Result<Response, Error> {
+        parse::resp(buf, &[b"OK", b"PONG"])
+    }
+
+    fn frame(&self, buf: &[u8]) -> Option<usize> {
+        parse::resp_frame(buf)
+    }
+
+    fn pipelining(&self) -> bool {
+        true
+    }
+
+    fn select(&mut self, endpoint: &SocketAddr) {
+        self.mode = *self.modes.get(endpoint).unwrap_or(&self.default);
+    }
+
+    fn encode(&mut self, buf: &mut Buffer, rng: &mut StdRng) {
         let command = self.generate(rng);
         match command.action() {
             Action::Delete => {
@@ -252,7 +285,9 @@ impl Codec for Redis {
                     metrics.increment(&Stat::CommandsDelete);
                     metrics.distribution(&Stat::KeySize, key.len() as u64);
                 }
-                self.delete(buf, &keys);
+                if !self.common.render(buf, Action::Delete, key, b"", None) {
+                    self.delete(buf, &keys);
+                }
             }
             Action::Get => {
                 let key = command.key().unwrap();
@@ -260,7 +295,9 @@ impl Codec for Redis {
                     metrics.increment(&Stat::CommandsGet);
                     metrics.distribution(&Stat::KeySize, key.len() as u64);
                 }
-                self.get(buf, key);
+                if !self.common.render(buf, Action::Get, key, b"", None) {
+                    self.get(buf, key);
+                }
             }
             Action::Llen => {
                 let key = command.key().unwrap();
@@ -340,7 +377,12 @@ impl Codec for Redis {
                     metrics.distribution(&Stat::KeySize, key.len() as u64);
                     metrics.distribution(&Stat::ValueSize, value.len() as u64);
                 }
-                self.set(buf, key, value, command.ttl());
+                if !self
+                    .common
+                    .render(buf, Action::Set, key, value, command.ttl())
+                {
+                    self.set(buf, key, value, command.ttl());
+                }
             }
             action => {
                 fatal!("Action: {:?} unsupported for Redis", action);
@@ -726,4 +768,98 @@ mod tests {
         redis.rpushx(&mut buf, b"abc", &values);
         assert_eq!(test_case, buf);
     }
+
+    #[test]
+    fn encode_template() {
+        for mode in &[RedisMode::Inline, RedisMode::Resp] {
+            let redis = Redis::new(*mode);
+            for ttl in &[None, Some(9876)] {
+                let shape = Shape {
+                    action: Action::Set,
+                    key: 3,
+                    value: 4,
+                    ttl: *ttl,
+                };
+                let mut buf = Buffer::new();
+                let mut test_case = Buffer::new();
+                redis
+                    .template(&shape)
+                    .unwrap()
+                    .render(&mut buf, b"abc", b"1234");
+                redis.set(&mut test_case, b"abc", b"1234", *ttl);
+                assert_eq!(test_case, buf);
+            }
+
+            let shape = Shape {
+                action: Action::Get,
+                key: 3,
+                value: 0,
+                ttl: None,
+            };
+            let mut buf = Buffer::new();
+            let mut test_case = Buffer::new();
+            redis
+                .template(&shape)
+                .unwrap()
+                .render(&mut buf, b"abc", b"");
+            redis.get(&mut test_case, b"abc");
+            assert_eq!(test_case, buf);
+
+            let shape = Shape {
+                action: Action::Delete,
+                key: 3,
+                value: 0,
+                ttl: None,
+            };
+            let mut buf = Buffer::new();
+            let mut test_case = Buffer::new();
+            redis
+                .template(&shape)
+                .unwrap()
+                .render(&mut buf, b"abc", b"");
+            redis.delete(&mut test_case, &[b"abc"]);
+            assert_eq!(test_case, buf);
+        }
+    }
+
+    #[test]
+    fn negotiated() {
+        let inline: SocketAddr = "127.0.0.1:6379".parse().unwrap();
+        let resp: SocketAddr = "127.0.0.1:6380".parse().unwrap();
+        let other: SocketAddr = "127.0.0.1:6381".parse().unwrap();
+        let shape = Shape {
+            action: Action::Get,
+            key: 3,
+            value: 0,
+            ttl: None,
+        };
+
+        let modes = vec![(inline, RedisMode::Inline)].into_iter().collect();
+        let mut redis = Redis::negotiated(Arc::new(modes));
+        assert!(redis.template(&shape).is_some());
+        redis.select(&other);
+        let mut buf = Buffer::new();
+        redis.get(&mut buf, b"abc");
+        let mut test_case = Buffer::new();
+        test_case.put_slice(b"get abc\r\n");
+        assert_eq!(buf, test_case);
+
+        let modes = vec![(inline, RedisMode::Inline), (resp, RedisMode::Resp)]
+            .into_iter()
+            .collect();
+        let mut redis = Redis::negotiated(Arc::new(modes));
+        assert!(redis.template(&shape).is_none());
+        for (endpoint, expected) in &[
+            (inline, &b"get abc\r\n"[..]),
+            (resp, &b"*2\r\n$3\r\nget\r\n$3\r\nabc\r\n"[..]),
+            (other, &b"*2\r\n$3\r\nget\r\n$3\r\nabc\r\n"[..]),
+        ] {
+            redis.select(endpoint);
+            let mut buf = Buffer::new();
+            redis.get(&mut buf, b"abc");
+            let mut test_case = Buffer::new();
+            test_case.put_slice(expected);
+            assert_eq!(buf, test_case);
+        }
+    }
 }
@@ -0,0 +1,95 @@
+// Copyright 2021 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+use crate::codec::*;
+use crate::config::Protocol;
+
+use std::collections::HashMap;
+
+/// builds a new codec for each client
+pub type Constructor = Arc<dyn Fn() -> Box<dyn Codec> + Send + Sync>;
+
+/// The codecs which can be selected by the protocol in the config. The
+/// default registry has the builtin protocols, others can be added under the
+/// name used for `protocol` in the config file.
+#[derive(Clone)]
+pub struct Registry {
+    codecs: HashMap<String, Constructor>,
+}
+
+impl Registry {
+    /// an empty registry
+    pub fn new() -> Self {
+        Self {
+            codecs: HashMap::new(),
+        }
+    }
+
+    /// register a codec for the protocol name, replacing any codec which was
+    /// registered under the same name
+    pub fn register<F>(&mut self, name: &str, constructor: F)
+    where
+        F: Fn() -> Box<dyn Codec> + Send + Sync + 'static,
+    {
+        self.codecs.insert(name.to_string(), Arc::new(constructor));
+    }
+
+    /// the constructor for the codec registered for the protocol
+    pub fn get(&self, protocol: &Protocol) -> Option<Constructor> {
+        self.codecs.get(protocol.name()).cloned()
+    }
+}
+
+impl Default for Registry {
+    fn default() -> Self {
+        let mut registry = Self::new();
+        registry.register("echo", || Box::new(Echo::new()));
+        registry.register("memcache", || Box::new(Memcache::new()));
+        registry.register("pelikan_rds", || Box::new(PelikanRds::new()));
+        registry.register("ping", || Box::new(Ping::new()));
+        registry.register("redis_resp", || Box::new(Redis::new(RedisMode::Resp)));
+        registry.register("redis_inline", || Box::new(Redis::new(RedisMode::Inline)));
+        // the runner speaks the mode negotiated with each endpoint instead
+        registry.register("redis_auto", || Box::new(Redis::new(RedisMode::Resp)));
+        registry.register("thrift_cache", || Box::new(ThriftCache::new()));
+        registry
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lookup() {
+        let mut registry = Registry::default();
+        assert!(registry.get(&Protocol::Memcache).is_some());
+        assert!(registry.get(&Protocol::RedisInline).is_some());
+
+        let custom = Protocol::from("custom".to_string());
+        assert_eq!(custom, Protocol::Custom("custom".to_string()));
+        assert!(registry.get(&custom).is_none());
+
+        registry.register("custom", || Box::new(Ping::new()));
+        let codec = registry.get(&custom).map(|constructor| constructor());
+        assert!(codec.map(|c| c.pipelining()).unwrap_or(false));
+    }
+
+    #[test]
+    fn config() {
+        let content = "[general]\n\
+                       protocol = \"custom\"\n\
+                       [[keyspace]]\n\
+                       length = 8\n\
+                       weight = 1\n\
+                       commands = [{action = \"get\", weight = 1}]\n\
+                       values = [{length = 16, weight = 1}]";
+        assert!(Config::from_toml(content).is_err());
+
+        let mut registry = Registry::default();
+        registry.register("custom", || Box::new(Ping::new()));
+        let config = Config::from_toml_with_codecs(content, &registry).unwrap();
+        assert_eq!(config.protocol(), Protocol::Custom("custom".to_string()));
+    }
+}
@@ -0,0 +1,123 @@
+// Copyright 2021 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+use crate::config::Action;
+
+use rustcommon_buffer::Buffer;
+
+use std::collections::HashMap;
+
+/// Describes a request whose framing is fully determined by the command and
+/// the lengths of its key and value. Requests with the same shape only differ
+/// in the key and value bytes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Shape {
+    pub action: Action,
+    pub key: usize,
+    pub value: usize,
+    pub ttl: Option<usize>,
+}
+
+enum Segment {
+    Literal(Vec<u8>),
+    Key,
+    Value,
+}
+
+/// A pre-serialized request skeleton. The fixed bytes are rendered once at
+/// startup and only the key and value are copied in for each request.
+#[derive(Default)]
+pub struct Template {
+    segments: Vec<Segment>,
+}
+
+impl Template {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// append fixed bytes to the template
+    pub fn literal(mut self, bytes: &[u8]) -> Self {
+        if let Some(Segment::Literal(ref mut previous)) = self.segments.last_mut() {
+            previous.extend_from_slice(bytes);
+        } else {
+            self.segments.push(Segment::Literal(bytes.to_vec()));
+        }
+        self
+    }
+
+    /// mark the position where the key is written
+    pub fn key(mut self) -> Self {
+        self.segments.push(Segment::Key);
+        self
+    }
+
+    /// mark the position where the value is written
+    pub fn value(mut self) -> Self {
+        self.segments.push(Segment::Value);
+        self
+    }
+
+    /// write a request to the buffer using this template
+    pub fn render(&self, buf: &mut Buffer, key: &[u8], value: &[u8]) {
+        for segment in &self.segments {
+            match segment {
+                Segment::Literal(bytes) => buf.put_slice(bytes),
+                Segment::Key => buf.put_slice(key),
+                Segment::Value => buf.put_slice(value),
+            }
+        }
+    }
+}
+
+/// A collection of templates indexed by the request shape they encode
+#[derive(Default)]
+pub struct Templates {
+    templates: HashMap<Shape, Template>,
+}
+
+impl Templates {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(&mut self, shape: Shape, template: Template) {
+        self.templates.insert(shape, template);
+    }
+
+    pub fn get(&self, shape: &Shape) -> Option<&Template> {
+        self.templates.get(shape)
+    }
+
+    pub fn len(&self) -> usize {
+        self.templates.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.templates.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render() {
+        let template = Template::new()
+            .literal(b"set ")
+            .key()
+            .literal(b" 0 ")
+            .literal(b"0 5\r\n")
+            .value()
+            .literal(b"\r\n");
+        assert_eq!(template.segments.len(), 5);
+
+        let mut buf = Buffer::new();
+        let mut test_case = Buffer::new();
+        test_case.put_slice(b"set 0 0 0 5\r\nvalue\r\n");
+        template.render(&mut buf, b"0", b"value");
+        assert_eq!(buf, test_case);
+    }
+}
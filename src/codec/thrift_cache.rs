@@ -583,8 +583,22 @@ impl Codec for ThriftCache {
         }
     }
 
+    fn frame(&self, buf: &[u8]) -> Option<usize> {
+        if buf.len() >= 4 {
+            let length = u32::from_be_bytes([buf[0], buf[1], buf[2], buf[3]]) as usize + 4;
+            if buf.len() >= length {
+                return Some(length);
+            }
+        }
+        None
+    }
+
+    fn pipelining(&self) -> bool {
+        true
+    }
+
     // TODO(bmartin): fix stats
-    fn encode(&mut self, buf: &mut Buffer, rng: &mut ThreadRng) {
+    fn encode(&mut self, buf: &mut Buffer, rng: &mut StdRng) {
         let command = self.generate(rng);
         match command.action() {
             Action::Hget => {
@@ -715,4 +729,14 @@ mod test {
         ]);
         assert_eq!(buf, check);
     }
+
+    #[test]
+    fn frame() {
+        let codec = ThriftCache::new();
+        assert_eq!(codec.frame(&[0, 0, 0]), None);
+        // an empty frame is just its length
+        assert_eq!(codec.frame(&[0, 0, 0, 0]), Some(4));
+        assert_eq!(codec.frame(&[0, 0, 0, 2, 1]), None);
+        assert_eq!(codec.frame(&[0, 0, 0, 2, 1, 2, 3]), Some(6));
+    }
 }
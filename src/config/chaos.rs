@@ -0,0 +1,169 @@
+// Copyright 2021 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+use crate::config::units;
+
+use serde_derive::*;
+
+use std::net::SocketAddr;
+use std::time::Duration;
+
+#[derive(Copy, Clone, Debug, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum FaultKind {
+    /// hold back a percentage of writes for `delay` microseconds
+    Delay,
+    /// close `rate` connections per second, across all clients
+    Disconnect,
+    /// stop sending requests to a percentage of the endpoints
+    Blackhole,
+}
+
+/// A fault which the clients inject into the workload. Faults are active from
+/// `start` seconds into the run, for `duration` seconds or until the end of
+/// the run, and repeat every `period` seconds if set.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Fault {
+    fault: FaultKind,
+    percent: Option<f64>,
+    delay: Option<usize>,
+    #[serde(default, deserialize_with = "units::optional_count")]
+    rate: Option<usize>,
+    #[serde(default)]
+    start: usize,
+    duration: Option<usize>,
+    period: Option<usize>,
+}
+
+impl Fault {
+    pub fn kind(&self) -> FaultKind {
+        self.fault
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self.fault {
+            FaultKind::Delay => "delay",
+            FaultKind::Disconnect => "disconnect",
+            FaultKind::Blackhole => "blackhole",
+        }
+    }
+
+    /// the share of writes or endpoints affected, from 0.0 to 1.0
+    pub fn ratio(&self) -> f64 {
+        self.percent.unwrap_or(0.0) / 100.0
+    }
+
+    pub fn delay(&self) -> Duration {
+        Duration::from_micros(self.delay.unwrap_or(0) as u64)
+    }
+
+    /// connections to close per second
+    pub fn rate(&self) -> usize {
+        self.rate.unwrap_or(0)
+    }
+
+    /// check that the settings the fault needs are present
+    pub fn validate(&self) -> Result<(), String> {
+        let percent = match self.percent {
+            Some(percent) if !(0.0..=100.0).contains(&percent) => {
+                return Err(format!("{} percent must be from 0 to 100", self.name()));
+            }
+            percent => percent,
+        };
+        match self.fault {
+            FaultKind::Delay if percent.is_none() || self.delay.is_none() => {
+                Err("delay requires percent and delay".to_string())
+            }
+            FaultKind::Disconnect if self.rate.unwrap_or(0) == 0 => {
+                Err("disconnect requires a rate".to_string())
+            }
+            FaultKind::Blackhole if percent.is_none() => {
+                Err("blackhole requires percent".to_string())
+            }
+            _ if self.period.map(|p| p == 0).unwrap_or(false) => {
+                Err(format!("{} period must be at least 1", self.name()))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// describe when the fault is active
+    pub fn schedule(&self) -> String {
+        let mut schedule = format!("from {}s", self.start);
+        if let Some(duration) = self.duration {
+            schedule += &format!(" for {}s", duration);
+        }
+        if let Some(period) = self.period {
+            schedule += &format!(" every {}s", period);
+        }
+        schedule
+    }
+
+    /// whether the fault is active at the time since the start of the run
+    pub fn is_active(&self, elapsed: Duration) -> bool {
+        let start = Duration::from_secs(self.start as u64);
+        if elapsed < start {
+            return false;
+        }
+        let mut offset = elapsed - start;
+        if let Some(period) = self.period {
+            let period = period as u64 * 1_000_000_000;
+            offset = Duration::from_nanos((offset.as_nanos() % period as u128) as u64);
+        }
+        match self.duration {
+            Some(duration) => offset < Duration::from_secs(duration as u64),
+            None => true,
+        }
+    }
+
+    /// the endpoints a blackhole applies to. The first endpoints in the config
+    /// are chosen, so that every client blackholes the same endpoints.
+    pub fn blackholed(&self, endpoints: &[SocketAddr]) -> Vec<SocketAddr> {
+        let count = (endpoints.len() as f64 * self.ratio()).ceil() as usize;
+        endpoints.iter().take(count).cloned().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fault(content: &str) -> Fault {
+        toml::from_str(content).unwrap()
+    }
+
+    #[test]
+    fn schedule() {
+        let once = fault("fault = \"disconnect\"\nrate = 1\nstart = 10\nduration = 5");
+        assert!(once.validate().is_ok());
+        assert!(!once.is_active(Duration::from_secs(9)));
+        assert!(once.is_active(Duration::from_secs(10)));
+        assert!(once.is_active(Duration::from_millis(14_999)));
+        assert!(!once.is_active(Duration::from_secs(15)));
+
+        let repeated =
+            fault("fault = \"delay\"\npercent = 5.0\ndelay = 1000\nduration = 1\nperiod = 3");
+        assert!(repeated.validate().is_ok());
+        assert!(repeated.is_active(Duration::from_secs(0)));
+        assert!(!repeated.is_active(Duration::from_secs(2)));
+        assert!(repeated.is_active(Duration::from_secs(6)));
+
+        assert!(fault("fault = \"delay\"\npercent = 5.0")
+            .validate()
+            .is_err());
+        assert!(fault("fault = \"blackhole\"\npercent = 150.0")
+            .validate()
+            .is_err());
+    }
+
+    #[test]
+    fn blackholed() {
+        let endpoints: Vec<SocketAddr> = (0..3)
+            .map(|i| format!("127.0.0.1:{}", 12000 + i).parse().unwrap())
+            .collect();
+        let fault = fault("fault = \"blackhole\"\npercent = 50.0");
+        assert_eq!(fault.blackholed(&endpoints), endpoints[0..2].to_vec());
+    }
+}
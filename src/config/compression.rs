@@ -0,0 +1,116 @@
+// Copyright 2021 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+use serde_derive::*;
+
+use std::fmt;
+
+#[derive(Copy, Clone, Debug, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Algorithm {
+    Lz4,
+    Zstd,
+}
+
+impl Algorithm {
+    /// parse the algorithm from its name in the config file
+    pub fn parse(name: &str) -> Result<Algorithm, String> {
+        match name {
+            "lz4" => Ok(Algorithm::Lz4),
+            "zstd" => Ok(Algorithm::Zstd),
+            _ => Err(format!("unknown compression algorithm: {}", name)),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        match self {
+            Algorithm::Lz4 => "lz4",
+            Algorithm::Zstd => "zstd",
+        }
+    }
+
+    /// the lowest and highest levels of the algorithm
+    fn levels(&self) -> (i32, i32) {
+        match self {
+            // the levels of the high compression mode
+            Algorithm::Lz4 => (1, 12),
+            Algorithm::Zstd => (1, 22),
+        }
+    }
+}
+
+/// How values are compressed by the client before they are stored, to model
+/// clients which compress before caching. Without a level, lz4 uses its fast
+/// mode and zstd uses its default level.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Compression {
+    algorithm: Algorithm,
+    level: Option<i32>,
+}
+
+impl Compression {
+    pub fn new(algorithm: Algorithm) -> Self {
+        Self {
+            algorithm,
+            level: None,
+        }
+    }
+
+    pub fn algorithm(&self) -> Algorithm {
+        self.algorithm
+    }
+
+    pub fn level(&self) -> Option<i32> {
+        self.level
+    }
+
+    pub fn set_level(&mut self, level: Option<i32>) {
+        self.level = level;
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        let (min, max) = self.algorithm.levels();
+        match self.level {
+            Some(level) if level < min || level > max => Err(format!(
+                "level for {} must be from {} to {}",
+                self.algorithm.name(),
+                min,
+                max
+            )),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl fmt::Display for Compression {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.level {
+            Some(level) => write!(f, "{} Level: {}", self.algorithm.name(), level),
+            None => write!(f, "{} Level: default", self.algorithm.name()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compression() {
+        let compression: Compression = toml::from_str("algorithm = \"zstd\"\nlevel = 19").unwrap();
+        assert_eq!(compression.algorithm(), Algorithm::Zstd);
+        assert_eq!(compression.level(), Some(19));
+        assert!(compression.validate().is_ok());
+        assert_eq!(compression.to_string(), "zstd Level: 19");
+
+        let compression: Compression = toml::from_str("algorithm = \"lz4\"\nlevel = 19").unwrap();
+        assert!(compression.validate().is_err());
+        assert!(toml::from_str::<Compression>("algorithm = \"gzip\"").is_err());
+        assert!(toml::from_str::<Compression>("level = 3").is_err());
+
+        assert_eq!(Algorithm::parse("lz4"), Ok(Algorithm::Lz4));
+        assert!(Algorithm::parse("gzip").is_err());
+    }
+}
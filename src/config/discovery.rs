@@ -0,0 +1,149 @@
+// Copyright 2021 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+use crate::config::duration;
+
+use serde_derive::*;
+
+use std::fmt;
+
+/// where the endpoints are discovered from
+#[derive(Copy, Clone, Debug, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Source {
+    /// the targets of a DNS SRV record
+    Srv,
+    /// the masters listed by `CLUSTER NODES` on a seed node
+    RedisCluster,
+    /// the servers in the stats of a twemproxy seed node
+    Twemproxy,
+}
+
+impl Source {
+    pub fn name(&self) -> &str {
+        match self {
+            Source::Srv => "srv",
+            Source::RedisCluster => "redis_cluster",
+            Source::Twemproxy => "twemproxy",
+        }
+    }
+}
+
+/// Discover the endpoints at startup, instead of listing them in the config,
+/// and optionally refresh them during the run.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Discovery {
+    source: Source,
+    /// the name of the SRV record, or the address of the seed node
+    target: String,
+    #[serde(default, deserialize_with = "duration::optional_seconds")]
+    refresh: Option<usize>,
+}
+
+impl Discovery {
+    /// parse the discovery from `SOURCE:TARGET`, such as
+    /// `srv:_memcache._tcp.cache.example.com` or `redis-cluster:10.0.0.1:7000`
+    pub fn parse(value: &str) -> Result<Discovery, String> {
+        let mut parts = value.splitn(2, ':');
+        let source = match parts.next().unwrap_or("") {
+            "srv" => Source::Srv,
+            "redis-cluster" | "redis_cluster" => Source::RedisCluster,
+            "twemproxy" => Source::Twemproxy,
+            source => return Err(format!("unknown discovery source: {}", source)),
+        };
+        let target = parts
+            .next()
+            .filter(|target| !target.is_empty())
+            .ok_or_else(|| format!("expected SOURCE:TARGET, got: {}", value))?;
+        Ok(Discovery {
+            source,
+            target: target.to_string(),
+            refresh: None,
+        })
+    }
+
+    pub fn source(&self) -> Source {
+        self.source
+    }
+
+    pub fn target(&self) -> &str {
+        &self.target
+    }
+
+    /// how often the endpoints are discovered again, in seconds
+    pub fn refresh(&self) -> Option<usize> {
+        self.refresh
+    }
+
+    pub fn set_refresh(&mut self, seconds: Option<usize>) {
+        self.refresh = seconds;
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if self.target.is_empty() {
+            return Err("target must not be empty".to_string());
+        }
+        if self.source != Source::Srv && !self.target.contains(':') {
+            return Err(format!(
+                "the seed node must be HOST:PORT, got: {}",
+                self.target
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Discovery {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {}", self.source.name(), self.target)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn discovery() {
+        let discovery: Discovery = toml::from_str(
+            "source = \"srv\"\ntarget = \"_memcache._tcp.cache.example.com\"\nrefresh = \"1m\"",
+        )
+        .unwrap();
+        assert_eq!(discovery.source(), Source::Srv);
+        assert_eq!(discovery.refresh(), Some(60));
+        assert!(discovery.validate().is_ok());
+
+        let discovery = Discovery::parse("redis-cluster:10.0.0.1:7000").unwrap();
+        assert_eq!(discovery.source(), Source::RedisCluster);
+        assert_eq!(discovery.target(), "10.0.0.1:7000");
+        assert_eq!(discovery.to_string(), "redis_cluster 10.0.0.1:7000");
+        assert!(discovery.validate().is_ok());
+
+        assert!(Discovery::parse("twemproxy:10.0.0.1")
+            .unwrap()
+            .validate()
+            .is_err());
+        assert!(Discovery::parse("consul:cache").is_err());
+        assert!(Discovery::parse("srv").is_err());
+        assert!(toml::from_str::<Discovery>("source = \"srv\"").is_err());
+    }
+
+    #[test]
+    fn config() {
+        use crate::config::Config;
+
+        let keyspace = "[[keyspace]]\n\
+                        length = 8\n\
+                        weight = 1\n\
+                        commands = [{action = \"get\", weight = 1}]\n\
+                        values = [{length = 16, weight = 1}]\n";
+        // the endpoints are discovered as the config is loaded, and nothing
+        // is listening on the seed node
+        let discovery = "[discovery]\nsource = \"twemproxy\"\ntarget = \"127.0.0.1:1\"\n";
+        assert!(Config::from_toml(&format!("{}{}", keyspace, discovery)).is_err());
+        let endpoints = "[general]\nendpoints = [\"127.0.0.1:11211\"]\n";
+        assert!(Config::from_toml(&format!("{}{}{}", endpoints, keyspace, discovery)).is_err());
+    }
+}
@@ -0,0 +1,122 @@
+// Copyright 2021 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+//! Durations in the config and on the command line may be given as a number
+//! of seconds or with units, such as `90s`, `15m` or `1h30m`.
+
+use serde::de::{self, Deserializer, Visitor};
+
+use std::fmt;
+
+/// parse a duration into whole seconds
+pub fn parse(value: &str) -> Result<usize, String> {
+    let value = value.trim();
+    if let Ok(seconds) = value.parse() {
+        return Ok(seconds);
+    }
+    let mut seconds: usize = 0;
+    let mut number = String::new();
+    for c in value.chars() {
+        if c.is_ascii_digit() {
+            number.push(c);
+            continue;
+        }
+        let unit = match c {
+            's' => 1,
+            'm' => 60,
+            'h' => 3600,
+            'd' => 86400,
+            _ => return Err(format!("invalid duration: {}", value)),
+        };
+        let n: usize = number
+            .parse()
+            .map_err(|_| format!("invalid duration: {}", value))?;
+        seconds = n
+            .checked_mul(unit)
+            .and_then(|n| seconds.checked_add(n))
+            .ok_or_else(|| format!("duration is too long: {}", value))?;
+        number.clear();
+    }
+    if !number.is_empty() || value.is_empty() {
+        return Err(format!("invalid duration: {}", value));
+    }
+    Ok(seconds)
+}
+
+struct SecondsVisitor;
+
+impl<'de> Visitor<'de> for SecondsVisitor {
+    type Value = usize;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a number of seconds or a duration such as \"15m\"")
+    }
+
+    fn visit_i64<E: de::Error>(self, value: i64) -> Result<usize, E> {
+        if value < 0 {
+            return Err(E::custom("duration must not be negative"));
+        }
+        Ok(value as usize)
+    }
+
+    fn visit_u64<E: de::Error>(self, value: u64) -> Result<usize, E> {
+        Ok(value as usize)
+    }
+
+    fn visit_str<E: de::Error>(self, value: &str) -> Result<usize, E> {
+        parse(value).map_err(E::custom)
+    }
+}
+
+/// deserialize a duration into seconds
+pub fn seconds<'de, D: Deserializer<'de>>(deserializer: D) -> Result<usize, D::Error> {
+    deserializer.deserialize_any(SecondsVisitor)
+}
+
+/// deserialize an optional duration into seconds
+pub fn optional_seconds<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<usize>, D::Error> {
+    seconds(deserializer).map(Some)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_derive::Deserialize;
+
+    #[derive(Deserialize)]
+    struct Run {
+        #[serde(deserialize_with = "seconds")]
+        interval: usize,
+        #[serde(default, deserialize_with = "optional_seconds")]
+        duration: Option<usize>,
+    }
+
+    #[test]
+    fn durations() {
+        assert_eq!(parse("60"), Ok(60));
+        assert_eq!(parse("90s"), Ok(90));
+        assert_eq!(parse("15m"), Ok(900));
+        assert_eq!(parse("1h30m"), Ok(5400));
+        assert_eq!(parse("2d"), Ok(172_800));
+        assert!(parse("").is_err());
+        assert!(parse("1.5m").is_err());
+        assert!(parse("15").is_ok());
+        assert!(parse("15x").is_err());
+        assert!(parse("m").is_err());
+        assert!(parse("1h30").is_err());
+    }
+
+    #[test]
+    fn deserialize() {
+        let run: Run = toml::from_str("interval = \"10s\"\nduration = \"15m\"").unwrap();
+        assert_eq!(run.interval, 10);
+        assert_eq!(run.duration, Some(900));
+        let run: Run = toml::from_str("interval = 60").unwrap();
+        assert_eq!(run.interval, 60);
+        assert_eq!(run.duration, None);
+        assert!(toml::from_str::<Run>("interval = \"ten\"").is_err());
+    }
+}
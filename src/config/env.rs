@@ -0,0 +1,98 @@
+// Copyright 2021 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+//! Substitution of environment variables into a config file. `${NAME}` is
+//! replaced by the value of the variable, which must be set, and
+//! `${NAME:-default}` falls back to the default if it is unset or empty.
+//! `$${` is left as a literal `${`.
+
+/// substitute the variables in the content, looking up their values with
+/// `lookup`
+pub fn interpolate<F>(content: &str, lookup: F) -> Result<String, String>
+where
+    F: Fn(&str) -> Option<String>,
+{
+    let mut result = String::with_capacity(content.len());
+    let mut rest = content;
+    while let Some(start) = rest.find('$') {
+        result.push_str(&rest[..start]);
+        rest = &rest[start..];
+        if rest.starts_with("$${") {
+            result.push_str("${");
+            rest = &rest[3..];
+            continue;
+        }
+        if !rest.starts_with("${") {
+            result.push('$');
+            rest = &rest[1..];
+            continue;
+        }
+        let end = match rest.find('}') {
+            Some(end) => end,
+            None => return Err(format!("unterminated variable: {}", line(rest))),
+        };
+        let expression = &rest[2..end];
+        let (name, default) = match expression.find(":-") {
+            Some(index) => (&expression[..index], Some(&expression[index + 2..])),
+            None => (expression, None),
+        };
+        if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+            return Err(format!("invalid variable name: ${{{}}}", expression));
+        }
+        match (lookup(name).filter(|v| !v.is_empty()), default) {
+            (Some(value), _) => result.push_str(&value),
+            (None, Some(default)) => result.push_str(default),
+            (None, None) => return Err(format!("environment variable is not set: {}", name)),
+        }
+        rest = &rest[end + 1..];
+    }
+    result.push_str(rest);
+    Ok(result)
+}
+
+/// substitute the variables in the content from the environment
+pub fn interpolate_env(content: &str) -> Result<String, String> {
+    interpolate(content, |name| std::env::var(name).ok())
+}
+
+fn line(content: &str) -> &str {
+    content.lines().next().unwrap_or("")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lookup(name: &str) -> Option<String> {
+        match name {
+            "HOST" => Some("10.0.0.1".to_string()),
+            "EMPTY" => Some("".to_string()),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn substitute() {
+        assert_eq!(
+            interpolate("endpoints = [\"${HOST}:${PORT:-6379}\"]", lookup).unwrap(),
+            "endpoints = [\"10.0.0.1:6379\"]"
+        );
+        assert_eq!(
+            interpolate("a = \"${EMPTY:-b}\"", lookup).unwrap(),
+            "a = \"b\""
+        );
+        assert_eq!(
+            interpolate("a = \"$${HOST} $5\"", lookup).unwrap(),
+            "a = \"${HOST} $5\""
+        );
+        assert_eq!(
+            interpolate("a = \"${HOST:-}\"", lookup).unwrap(),
+            "a = \"10.0.0.1\""
+        );
+
+        assert!(interpolate("a = \"${PORT}\"", lookup).is_err());
+        assert!(interpolate("a = \"${HOST\"", lookup).is_err());
+        assert!(interpolate("a = \"${HO ST}\"", lookup).is_err());
+    }
+}
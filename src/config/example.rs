@@ -0,0 +1,566 @@
+// Copyright 2021 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+//! An example config for a protocol. The config is rendered from the config
+//! structs, and each setting is preceded by its description. Optional settings
+//! which aren't set are included as comments with an example value.
+
+use crate::config::{Action, Class, Command, Config, KeyDistribution, Keyspace, Protocol, Value};
+
+use std::collections::HashSet;
+
+/// the description of each setting, by section, and an example value for
+/// settings which are optional
+const DOCS: &[(&str, &str, &str, Option<&str>)] = &[
+    (
+        "general",
+        "protocol",
+        "the protocol: memcache, pelikan_rds, ping, echo, redis_resp, redis_inline, redis_auto or thrift_cache",
+        None,
+    ),
+    (
+        "general",
+        "interval",
+        "the length of each window of stats, in seconds or with units",
+        None,
+    ),
+    (
+        "general",
+        "windows",
+        "the number of windows to run for, forever if unset",
+        None,
+    ),
+    (
+        "general",
+        "duration",
+        "run for this long instead of a number of windows",
+        Some("\"15m\""),
+    ),
+    ("general", "clients", "the number of client threads", None),
+    (
+        "general",
+        "poolsize",
+        "the number of connections each client opens to each endpoint",
+        None,
+    ),
+    (
+        "general",
+        "pipeline",
+        "the number of requests in-flight on each connection",
+        None,
+    ),
+    (
+        "general",
+        "listen",
+        "the address of the stats port",
+        Some("\"0.0.0.0:9090\""),
+    ),
+    (
+        "general",
+        "admin",
+        "the address of the admin port",
+        Some("\"0.0.0.0:9091\""),
+    ),
+    (
+        "general",
+        "logging",
+        "the log level: error, warn, info, debug or trace",
+        None,
+    ),
+    (
+        "general",
+        "endpoints",
+        "the servers to send requests to",
+        None,
+    ),
+    (
+        "general",
+        "compare",
+        "a second group of servers which is sent the same workload",
+        Some("[\"127.0.0.1:12322\"]"),
+    ),
+    (
+        "general",
+        "shadow",
+        "servers which are sent a copy of each request",
+        Some("[\"127.0.0.1:12323\"]"),
+    ),
+    (
+        "general",
+        "request_ratelimit",
+        "the total requests per second, such as 10000 or \"10k\", unlimited if unset",
+        Some("\"10k\""),
+    ),
+    (
+        "general",
+        "request_distribution",
+        "how requests are spread over each second: smooth, uniform or normal",
+        None,
+    ),
+    (
+        "general",
+        "request_batch",
+        "the number of requests sent together by the ratelimiter",
+        None,
+    ),
+    (
+        "general",
+        "request_burst",
+        "the most requests the ratelimiter lets through at once after an idle spell",
+        Some("\"1k\""),
+    ),
+    (
+        "general",
+        "request_partition",
+        "how the request ratelimit is divided between the clients: shared, equal or weighted",
+        None,
+    ),
+    (
+        "general",
+        "request_weights",
+        "the weight of each client's share of the request ratelimit, when weighted",
+        Some("[2, 1, 1, 1]"),
+    ),
+    (
+        "general",
+        "connect_ratelimit",
+        "the total connections per second, unlimited if unset",
+        Some("100"),
+    ),
+    (
+        "general",
+        "close_rate",
+        "the total connections closed by the clients per second",
+        Some("10"),
+    ),
+    (
+        "general",
+        "pushback_backoff",
+        "lower the request ratelimit after a window where more than this percentage of the responses pushed back",
+        Some("1.0"),
+    ),
+    (
+        "general",
+        "tls_key",
+        "the private key for TLS",
+        Some("\"client.key\""),
+    ),
+    (
+        "general",
+        "tls_cert",
+        "the certificate for TLS",
+        Some("\"client.crt\""),
+    ),
+    (
+        "general",
+        "tls_ca",
+        "the certificate authority for TLS",
+        Some("\"ca.crt\""),
+    ),
+    (
+        "general",
+        "warmup_hitrate",
+        "send requests before measuring until the hitrate reaches this ratio",
+        Some("0.9"),
+    ),
+    (
+        "general",
+        "warmup_timeout",
+        "give up if the warmup hasn't reached the hitrate in this time",
+        Some("\"10m\""),
+    ),
+    (
+        "general",
+        "tcp_nodelay",
+        "whether to set the TCP_NODELAY socket option",
+        None,
+    ),
+    (
+        "general",
+        "request_timeout",
+        "the request timeout in microseconds",
+        None,
+    ),
+    (
+        "general",
+        "connect_timeout",
+        "the connect timeout in microseconds",
+        None,
+    ),
+    (
+        "general",
+        "waterfall",
+        "save a waterfall plot of request latency",
+        Some("\"waterfall.png\""),
+    ),
+    (
+        "general",
+        "bundle",
+        "archive the config, metrics and outputs of the run",
+        Some("\"run.tar\""),
+    ),
+    (
+        "general",
+        "summary",
+        "write the summary of the run to a JSON file",
+        Some("\"summary.json\""),
+    ),
+    (
+        "general",
+        "sparklines",
+        "print trend lines of the rate and p99 latency over this many windows",
+        Some("30"),
+    ),
+    (
+        "general",
+        "popularity",
+        "write the number of requests for each key to a CSV file",
+        Some("\"popularity.csv\""),
+    ),
+    (
+        "general",
+        "keyfile",
+        "preload the keys in the file and read only those keys",
+        Some("\"keys.txt\""),
+    ),
+    (
+        "general",
+        "preload_timeout",
+        "give up if the keys haven't been preloaded in this time",
+        None,
+    ),
+    (
+        "general",
+        "soft_timeout",
+        "whether to keep the connection open when a request times out",
+        None,
+    ),
+    (
+        "general",
+        "numa",
+        "whether to spread the client threads across NUMA nodes",
+        None,
+    ),
+    (
+        "general",
+        "numa_bind_endpoints",
+        "whether to partition the endpoints across NUMA nodes",
+        None,
+    ),
+    (
+        "general",
+        "raise_fd_limit",
+        "whether to raise the open files limit to what the connections need",
+        None,
+    ),
+    (
+        "general",
+        "agent",
+        "whether to wait for a coordinator to configure and start the run",
+        None,
+    ),
+    (
+        "general",
+        "agents",
+        "the admin addresses of agents to split the run across",
+        Some("[\"10.0.0.2:9091\"]"),
+    ),
+    (
+        "general",
+        "start_at",
+        "the unix time to start at, so that the windows of separate instances line up",
+        Some("1_609_459_200"),
+    ),
+    (
+        "general",
+        "grace_period",
+        "the time allowed to drain and save the results after SIGTERM",
+        None,
+    ),
+    (
+        "general",
+        "exemplars",
+        "log this many of the slowest requests of each window",
+        Some("10"),
+    ),
+    (
+        "general",
+        "trace",
+        "whether to write a trace id at the start of the value of each set",
+        None,
+    ),
+    (
+        "general",
+        "log_slow",
+        "log each request slower than this, in microseconds or with a unit",
+        Some("\"50ms\""),
+    ),
+    (
+        "general",
+        "run_id",
+        "identify the run in its outputs, which are named after it",
+        Some("\"sweep-1\""),
+    ),
+    (
+        "keyspace",
+        "length",
+        "the length of the keys in bytes",
+        None,
+    ),
+    (
+        "keyspace",
+        "weight",
+        "how often this keyspace is chosen, relative to the others",
+        None,
+    ),
+    (
+        "keyspace",
+        "count",
+        "the number of keys, which must fit in the length",
+        None,
+    ),
+    (
+        "keyspace",
+        "distribution",
+        "how keys are chosen: uniform, zipfian or latest",
+        None,
+    ),
+    ("keyspace.commands", "action", "the command to send", None),
+    (
+        "keyspace.commands",
+        "weight",
+        "how often this command is chosen, relative to the others",
+        None,
+    ),
+    (
+        "keyspace.commands",
+        "ttl",
+        "the TTL of the values which are set, in seconds",
+        Some("3600"),
+    ),
+    (
+        "keyspace.commands",
+        "items",
+        "the number of items in each request",
+        None,
+    ),
+    (
+        "keyspace.commands",
+        "watermark_low",
+        "the low watermark of created sorted arrays",
+        None,
+    ),
+    (
+        "keyspace.commands",
+        "watermark_high",
+        "the high watermark of created sorted arrays",
+        None,
+    ),
+    (
+        "keyspace.values",
+        "length",
+        "the length of the values in bytes, or a size such as \"16KiB\"",
+        None,
+    ),
+    (
+        "keyspace.values",
+        "weight",
+        "how often this value length is chosen, relative to the others",
+        None,
+    ),
+    (
+        "keyspace.values",
+        "class",
+        "the kind of values: alphanumeric or integer",
+        None,
+    ),
+];
+
+/// a runnable config for the protocol, with a description of each setting
+pub fn example(protocol: Protocol) -> String {
+    let mut config = Config::default();
+    config
+        .general
+        .set_endpoints(Some(vec![format!("127.0.0.1:{}", port(&protocol))]));
+    config.keyspace = vec![keyspace(&protocol)];
+    config.general.set_protocol(protocol);
+    annotate(&config.to_toml())
+}
+
+/// the port the servers for the protocol usually listen on
+fn port(protocol: &Protocol) -> u16 {
+    match protocol {
+        Protocol::Memcache => 11211,
+        Protocol::RedisResp | Protocol::RedisInline | Protocol::RedisAuto => 6379,
+        Protocol::ThriftCache => 9090,
+        _ => 12321,
+    }
+}
+
+fn command(action: Action, weight: usize) -> Command {
+    Command {
+        action,
+        weight,
+        ttl: None,
+        items: None,
+        watermark_low: None,
+        watermark_high: None,
+    }
+}
+
+fn keyspace(protocol: &Protocol) -> Keyspace {
+    let (commands, class, length) = match protocol {
+        Protocol::PelikanRds => (
+            vec![
+                Command {
+                    watermark_low: Some(3000),
+                    watermark_high: Some(3200),
+                    ..command(Action::SarrayCreate, 1)
+                },
+                command(Action::SarrayDelete, 1),
+                command(Action::SarrayFind, 1),
+                command(Action::SarrayGet, 1),
+                Command {
+                    items: Some(1),
+                    ..command(Action::SarrayInsert, 1)
+                },
+                command(Action::SarrayLen, 1),
+            ],
+            Class::Integer,
+            8,
+        ),
+        Protocol::ThriftCache => (
+            vec![
+                Command {
+                    items: Some(8),
+                    ..command(Action::Hget, 4)
+                },
+                Command {
+                    items: Some(8),
+                    ..command(Action::Hset, 1)
+                },
+            ],
+            Class::Alphanumeric,
+            64,
+        ),
+        Protocol::Ping | Protocol::Echo => (vec![command(Action::Get, 1)], Class::Alphanumeric, 64),
+        _ => (
+            vec![command(Action::Get, 4), command(Action::Set, 1)],
+            Class::Alphanumeric,
+            64,
+        ),
+    };
+    Keyspace {
+        length: 8,
+        weight: 1,
+        count: Some(1_000_000),
+        hitrate: None,
+        distribution: KeyDistribution::Uniform,
+        commands,
+        values: vec![Value {
+            length,
+            weight: 1,
+            class,
+        }],
+    }
+}
+
+/// precede each setting with its description, and add the optional settings
+/// of each section as comments the first time the section appears
+fn annotate(content: &str) -> String {
+    let mut result = "# generated by rpc-perf generate-config, see the README for the\n\
+                      # [ycsb], [thresholds], [warmup], [compression], [discovery],\n\
+                      # [[group]] and [[chaos]] sections and for `include` and `preset`\n"
+        .to_string();
+    let mut section = String::new();
+    let mut present = Vec::new();
+    let mut seen = HashSet::new();
+    for line in content.lines().filter(|l| !l.trim().is_empty()) {
+        if line.starts_with('[') {
+            optional(&mut result, &section, &present, &mut seen);
+            section = line.trim_matches(|c| c == '[' || c == ']').to_string();
+            present.clear();
+            result += "\n";
+            result += line;
+            result += "\n";
+            continue;
+        }
+        if let Some(index) = line.find(" = ") {
+            let key = line[..index].trim().to_string();
+            if let Some((_, _, doc, _)) = DOCS.iter().find(|d| d.0 == section && d.1 == key) {
+                result += &format!("# {}\n", doc);
+            }
+            present.push(key);
+        }
+        result += line;
+        result += "\n";
+    }
+    optional(&mut result, &section, &present, &mut seen);
+    result
+}
+
+fn optional(result: &mut String, section: &str, present: &[String], seen: &mut HashSet<String>) {
+    if !seen.insert(section.to_string()) {
+        return;
+    }
+    for (_, key, doc, example) in DOCS.iter().filter(|d| d.0 == section) {
+        if let Some(example) = example {
+            if !present.iter().any(|k| k == key) {
+                *result += &format!("# {}\n# {} = {}\n", doc, key, example);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PROTOCOLS: [Protocol; 8] = [
+        Protocol::Memcache,
+        Protocol::PelikanRds,
+        Protocol::Ping,
+        Protocol::Echo,
+        Protocol::RedisResp,
+        Protocol::RedisInline,
+        Protocol::RedisAuto,
+        Protocol::ThriftCache,
+    ];
+
+    #[test]
+    fn documented() {
+        for protocol in PROTOCOLS.iter() {
+            let example = example(protocol.clone());
+            let config = Config::from_toml(&example).unwrap();
+            assert_eq!(&config.protocol(), protocol);
+
+            // every setting is described
+            let lines: Vec<&str> = example.lines().collect();
+            for (i, line) in lines.iter().enumerate() {
+                if line.contains(" = ") && !line.starts_with('#') {
+                    assert!(lines[i - 1].starts_with("# "), "{}", line);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn examples() {
+        // the optional settings are valid once they are uncommented
+        let example = example(Protocol::Memcache);
+        let uncommented: Vec<&str> = example
+            .lines()
+            .map(|line| {
+                let setting = line.trim_start_matches("# ");
+                match setting.find(" = ") {
+                    Some(index) if DOCS.iter().any(|d| d.1 == &setting[..index]) => setting,
+                    _ => line,
+                }
+            })
+            .collect();
+        let config = Config::from_toml(&uncommented.join("\n")).unwrap();
+        assert_eq!(config.request_ratelimit(), Some(10_000));
+        assert_eq!(config.keyspace[0].commands[0].ttl(), Some(3600));
+    }
+}
@@ -3,39 +3,61 @@
 // http://www.apache.org/licenses/LICENSE-2.0
 
 use crate::config::*;
+use crate::config::{duration, units};
 
 use rustcommon_logger::Level;
 use rustcommon_ratelimiter::Refill;
 
-#[derive(Clone, Debug, Deserialize)]
+use std::collections::BTreeMap;
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
 #[serde(deny_unknown_fields)]
 pub struct General {
     #[serde(default)]
     protocol: Protocol,
-    #[serde(default = "default_interval")]
+    #[serde(default = "default_interval", deserialize_with = "duration::seconds")]
     interval: usize,
     #[serde(default = "default_windows")]
     windows: Option<usize>,
+    #[serde(default, deserialize_with = "duration::optional_seconds")]
+    duration: Option<usize>,
     #[serde(default = "default_clients")]
     clients: usize,
     #[serde(default = "default_poolsize")]
     poolsize: usize,
+    #[serde(default = "default_pipeline")]
+    pipeline: usize,
     listen: Option<String>,
     admin: Option<String>,
     #[serde(with = "LevelDef")]
     #[serde(default = "default_logging_level")]
     logging: Level,
     endpoints: Option<Vec<String>>,
+    compare: Option<Vec<String>>,
+    shadow: Option<Vec<String>>,
+    #[serde(default, deserialize_with = "units::optional_count")]
     request_ratelimit: Option<usize>,
     #[serde(with = "Distribution")]
     #[serde(default = "default_request_distribution")]
     request_distribution: Refill,
+    #[serde(default = "default_request_batch")]
+    request_batch: usize,
+    #[serde(default, deserialize_with = "units::optional_count")]
+    request_burst: Option<usize>,
+    #[serde(default)]
+    request_partition: Partition,
+    request_weights: Option<Vec<usize>>,
+    #[serde(default, deserialize_with = "units::optional_count")]
     connect_ratelimit: Option<usize>,
+    #[serde(default, deserialize_with = "units::optional_count")]
     close_rate: Option<usize>,
+    pushback_backoff: Option<f64>,
     tls_key: Option<String>,
     tls_cert: Option<String>,
     tls_ca: Option<String>,
     warmup_hitrate: Option<f64>,
+    #[serde(default, deserialize_with = "duration::optional_seconds")]
+    warmup_timeout: Option<usize>,
     #[serde(default = "default_tcp_nodelay")]
     tcp_nodelay: bool,
     #[serde(default = "default_request_timeout")]
@@ -43,8 +65,41 @@ pub struct General {
     #[serde(default = "default_connect_timeout")]
     connect_timeout: usize,
     waterfall: Option<String>,
+    bundle: Option<String>,
+    summary: Option<String>,
+    sparklines: Option<usize>,
+    popularity: Option<String>,
+    keyfile: Option<String>,
+    #[serde(
+        default = "default_preload_timeout",
+        deserialize_with = "duration::seconds"
+    )]
+    preload_timeout: usize,
     #[serde(default = "default_soft_timeout")]
     soft_timeout: bool,
+    #[serde(default)]
+    numa: bool,
+    #[serde(default)]
+    numa_bind_endpoints: bool,
+    #[serde(default)]
+    raise_fd_limit: bool,
+    #[serde(default)]
+    agent: bool,
+    agents: Option<Vec<String>>,
+    start_at: Option<u64>,
+    #[serde(
+        default = "default_grace_period",
+        deserialize_with = "duration::seconds"
+    )]
+    grace_period: usize,
+    exemplars: Option<usize>,
+    #[serde(default)]
+    trace: bool,
+    #[serde(default, deserialize_with = "units::optional_latency")]
+    log_slow: Option<usize>,
+    run_id: Option<String>,
+    // tables come after the other settings when the config is rendered
+    labels: Option<BTreeMap<String, String>>,
 }
 
 impl General {
@@ -80,8 +135,16 @@ impl General {
         self.poolsize = poolsize;
     }
 
+    pub fn pipeline(&self) -> usize {
+        self.pipeline
+    }
+
+    pub fn set_pipeline(&mut self, pipeline: usize) {
+        self.pipeline = pipeline;
+    }
+
     pub fn protocol(&self) -> Protocol {
-        self.protocol
+        self.protocol.clone()
     }
 
     pub fn set_protocol(&mut self, protocol: Protocol) {
@@ -104,6 +167,14 @@ impl General {
         self.windows = windows;
     }
 
+    pub fn duration(&self) -> Option<usize> {
+        self.duration
+    }
+
+    pub fn set_duration(&mut self, seconds: Option<usize>) {
+        self.duration = seconds;
+    }
+
     pub fn logging(&self) -> Level {
         self.logging
     }
@@ -124,6 +195,38 @@ impl General {
         self.request_distribution
     }
 
+    pub fn request_batch(&self) -> usize {
+        self.request_batch
+    }
+
+    pub fn set_request_batch(&mut self, batch: usize) {
+        self.request_batch = batch;
+    }
+
+    pub fn request_burst(&self) -> Option<usize> {
+        self.request_burst
+    }
+
+    pub fn set_request_burst(&mut self, requests: Option<usize>) {
+        self.request_burst = requests;
+    }
+
+    pub fn request_partition(&self) -> Partition {
+        self.request_partition
+    }
+
+    pub fn set_request_partition(&mut self, partition: Partition) {
+        self.request_partition = partition;
+    }
+
+    pub fn request_weights(&self) -> Option<Vec<usize>> {
+        self.request_weights.clone()
+    }
+
+    pub fn set_request_weights(&mut self, weights: Option<Vec<usize>>) {
+        self.request_weights = weights;
+    }
+
     pub fn connect_ratelimit(&self) -> Option<usize> {
         self.connect_ratelimit
     }
@@ -152,6 +255,102 @@ impl General {
         self.soft_timeout
     }
 
+    pub fn set_numa(&mut self, enabled: bool) {
+        self.numa = enabled;
+    }
+
+    pub fn numa(&self) -> bool {
+        self.numa
+    }
+
+    pub fn set_numa_bind_endpoints(&mut self, enabled: bool) {
+        self.numa_bind_endpoints = enabled;
+    }
+
+    pub fn numa_bind_endpoints(&self) -> bool {
+        self.numa_bind_endpoints
+    }
+
+    pub fn set_raise_fd_limit(&mut self, enabled: bool) {
+        self.raise_fd_limit = enabled;
+    }
+
+    pub fn raise_fd_limit(&self) -> bool {
+        self.raise_fd_limit
+    }
+
+    pub fn set_agent(&mut self, enabled: bool) {
+        self.agent = enabled;
+    }
+
+    pub fn agent(&self) -> bool {
+        self.agent
+    }
+
+    pub fn set_agents(&mut self, agents: Option<Vec<String>>) {
+        self.agents = agents;
+    }
+
+    pub fn agents(&self) -> Option<Vec<String>> {
+        self.agents.clone()
+    }
+
+    pub fn set_start_at(&mut self, start_at: Option<u64>) {
+        self.start_at = start_at;
+    }
+
+    pub fn start_at(&self) -> Option<u64> {
+        self.start_at
+    }
+
+    pub fn set_grace_period(&mut self, seconds: usize) {
+        self.grace_period = seconds;
+    }
+
+    pub fn grace_period(&self) -> usize {
+        self.grace_period
+    }
+
+    pub fn exemplars(&self) -> Option<usize> {
+        self.exemplars
+    }
+
+    pub fn set_exemplars(&mut self, exemplars: Option<usize>) {
+        self.exemplars = exemplars;
+    }
+
+    pub fn trace(&self) -> bool {
+        self.trace
+    }
+
+    pub fn set_trace(&mut self, trace: bool) {
+        self.trace = trace;
+    }
+
+    pub fn log_slow(&self) -> Option<usize> {
+        self.log_slow
+    }
+
+    pub fn set_log_slow(&mut self, log_slow: Option<usize>) {
+        self.log_slow = log_slow;
+    }
+
+    pub fn run_id(&self) -> Option<String> {
+        self.run_id.clone()
+    }
+
+    pub fn set_run_id(&mut self, run_id: Option<String>) {
+        self.run_id = run_id;
+    }
+
+    pub fn labels(&self) -> Option<BTreeMap<String, String>> {
+        self.labels.clone()
+    }
+
+    pub fn set_labels(&mut self, labels: Option<BTreeMap<String, String>>) {
+        self.labels = labels;
+    }
+
     pub fn set_connect_ratelimit(&mut self, per_second: Option<usize>) {
         self.connect_ratelimit = per_second;
     }
@@ -164,6 +363,14 @@ impl General {
         self.close_rate
     }
 
+    pub fn set_pushback_backoff(&mut self, percent: Option<f64>) {
+        self.pushback_backoff = percent;
+    }
+
+    pub fn pushback_backoff(&self) -> Option<f64> {
+        self.pushback_backoff
+    }
+
     pub fn endpoints(&self) -> Option<Vec<String>> {
         self.endpoints.clone()
     }
@@ -172,6 +379,22 @@ impl General {
         self.endpoints = endpoints;
     }
 
+    pub fn compare(&self) -> Option<Vec<String>> {
+        self.compare.clone()
+    }
+
+    pub fn set_compare(&mut self, endpoints: Option<Vec<String>>) {
+        self.compare = endpoints;
+    }
+
+    pub fn shadow(&self) -> Option<Vec<String>> {
+        self.shadow.clone()
+    }
+
+    pub fn set_shadow(&mut self, endpoints: Option<Vec<String>>) {
+        self.shadow = endpoints;
+    }
+
     pub fn set_tcp_nodelay(&mut self, nodelay: bool) {
         self.tcp_nodelay = nodelay;
     }
@@ -212,6 +435,14 @@ impl General {
         self.warmup_hitrate
     }
 
+    pub fn set_warmup_timeout(&mut self, seconds: Option<usize>) {
+        self.warmup_timeout = seconds;
+    }
+
+    pub fn warmup_timeout(&self) -> Option<usize> {
+        self.warmup_timeout
+    }
+
     pub fn set_waterfall(&mut self, path: Option<String>) {
         self.waterfall = path;
     }
@@ -219,6 +450,54 @@ impl General {
     pub fn waterfall(&self) -> Option<String> {
         self.waterfall.clone()
     }
+
+    pub fn set_bundle(&mut self, path: Option<String>) {
+        self.bundle = path;
+    }
+
+    pub fn bundle(&self) -> Option<String> {
+        self.bundle.clone()
+    }
+
+    pub fn set_summary(&mut self, path: Option<String>) {
+        self.summary = path;
+    }
+
+    pub fn summary(&self) -> Option<String> {
+        self.summary.clone()
+    }
+
+    pub fn set_sparklines(&mut self, windows: Option<usize>) {
+        self.sparklines = windows;
+    }
+
+    pub fn sparklines(&self) -> Option<usize> {
+        self.sparklines
+    }
+
+    pub fn set_popularity(&mut self, path: Option<String>) {
+        self.popularity = path;
+    }
+
+    pub fn popularity(&self) -> Option<String> {
+        self.popularity.clone()
+    }
+
+    pub fn set_keyfile(&mut self, path: Option<String>) {
+        self.keyfile = path;
+    }
+
+    pub fn keyfile(&self) -> Option<String> {
+        self.keyfile.clone()
+    }
+
+    pub fn set_preload_timeout(&mut self, seconds: usize) {
+        self.preload_timeout = seconds;
+    }
+
+    pub fn preload_timeout(&self) -> usize {
+        self.preload_timeout
+    }
 }
 
 impl Default for General {
@@ -226,30 +505,66 @@ impl Default for General {
         General {
             interval: default_interval(),
             windows: default_windows(),
+            duration: None,
             clients: default_clients(),
             poolsize: default_poolsize(),
+            pipeline: default_pipeline(),
             endpoints: None, // no reasonable default endpoints
+            compare: None,
+            shadow: None,
             listen: None,
             admin: None,
             logging: Level::Info,
             protocol: Default::default(),
             request_ratelimit: None,
             request_distribution: default_request_distribution(),
+            request_batch: default_request_batch(),
+            request_burst: None,
+            request_partition: Default::default(),
+            request_weights: None,
             connect_ratelimit: None,
             close_rate: None,
+            pushback_backoff: None,
             tls_key: None,
             tls_cert: None,
             tls_ca: None,
             warmup_hitrate: None,
+            warmup_timeout: None,
             tcp_nodelay: default_tcp_nodelay(),
             request_timeout: default_request_timeout(),
             connect_timeout: default_connect_timeout(),
             waterfall: None,
+            bundle: None,
+            summary: None,
+            sparklines: None,
+            popularity: None,
+            keyfile: None,
+            preload_timeout: default_preload_timeout(),
             soft_timeout: false,
+            numa: false,
+            numa_bind_endpoints: false,
+            raise_fd_limit: false,
+            agent: false,
+            agents: None,
+            start_at: None,
+            grace_period: default_grace_period(),
+            exemplars: None,
+            trace: false,
+            log_slow: None,
+            run_id: None,
+            labels: None,
         }
     }
 }
 
+fn default_grace_period() -> usize {
+    20
+}
+
+fn default_preload_timeout() -> usize {
+    600
+}
+
 fn default_interval() -> usize {
     60
 }
@@ -266,6 +581,10 @@ fn default_poolsize() -> usize {
     1
 }
 
+fn default_pipeline() -> usize {
+    1
+}
+
 fn default_tcp_nodelay() -> bool {
     false
 }
@@ -282,9 +601,8 @@ fn default_soft_timeout() -> bool {
     false
 }
 
-#[derive(Copy, Clone, Deserialize, Debug)]
-#[serde(rename_all = "snake_case")]
-#[serde(deny_unknown_fields)]
+#[derive(Clone, Deserialize, Serialize, Debug, PartialEq)]
+#[serde(from = "String", into = "String")]
 pub enum Protocol {
     Memcache,
     PelikanRds,
@@ -292,7 +610,50 @@ pub enum Protocol {
     Echo,
     RedisResp,
     RedisInline,
+    /// redis, speaking RESP or inline as negotiated with the endpoints
+    RedisAuto,
     ThriftCache,
+    /// a protocol provided by a codec registered with the runner
+    Custom(String),
+}
+
+impl Protocol {
+    /// the name of the protocol in the config file
+    pub fn name(&self) -> &str {
+        match self {
+            Protocol::Memcache => "memcache",
+            Protocol::PelikanRds => "pelikan_rds",
+            Protocol::Ping => "ping",
+            Protocol::Echo => "echo",
+            Protocol::RedisResp => "redis_resp",
+            Protocol::RedisInline => "redis_inline",
+            Protocol::RedisAuto => "redis_auto",
+            Protocol::ThriftCache => "thrift_cache",
+            Protocol::Custom(name) => name,
+        }
+    }
+}
+
+impl From<String> for Protocol {
+    fn from(name: String) -> Self {
+        match name.as_str() {
+            "memcache" => Protocol::Memcache,
+            "pelikan_rds" => Protocol::PelikanRds,
+            "ping" => Protocol::Ping,
+            "echo" => Protocol::Echo,
+            "redis_resp" => Protocol::RedisResp,
+            "redis_inline" => Protocol::RedisInline,
+            "redis_auto" => Protocol::RedisAuto,
+            "thrift_cache" => Protocol::ThriftCache,
+            _ => Protocol::Custom(name),
+        }
+    }
+}
+
+impl From<Protocol> for String {
+    fn from(protocol: Protocol) -> Self {
+        protocol.name().to_string()
+    }
 }
 
 impl Default for Protocol {
@@ -301,7 +662,32 @@ impl Default for Protocol {
     }
 }
 
-#[derive(Clone, Deserialize, Debug)]
+/// how the request ratelimit is divided between the clients
+#[derive(Copy, Clone, Default, Deserialize, Serialize, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Partition {
+    /// the clients take from one bucket, so a client which is held up by a
+    /// slow endpoint leaves its requests to the others
+    #[default]
+    Shared,
+    /// each client has its own bucket with an equal share of the rate
+    Equal,
+    /// each client has its own bucket with a share of the rate in proportion
+    /// to its weight in `request_weights`
+    Weighted,
+}
+
+impl Partition {
+    pub fn name(&self) -> &str {
+        match self {
+            Partition::Shared => "shared",
+            Partition::Equal => "equal",
+            Partition::Weighted => "weighted",
+        }
+    }
+}
+
+#[derive(Clone, Deserialize, Serialize, Debug)]
 #[serde(rename_all = "lowercase")]
 #[serde(remote = "Level")]
 #[serde(deny_unknown_fields)]
@@ -317,7 +703,7 @@ fn default_logging_level() -> Level {
     Level::Info
 }
 
-#[derive(Clone, Deserialize, Debug)]
+#[derive(Clone, Deserialize, Serialize, Debug)]
 #[serde(rename_all = "lowercase")]
 #[serde(remote = "Refill")]
 #[serde(deny_unknown_fields)]
@@ -327,6 +713,10 @@ enum Distribution {
     Normal,
 }
 
+fn default_request_batch() -> usize {
+    1
+}
+
 fn default_request_distribution() -> Refill {
     Refill::Smooth
 }
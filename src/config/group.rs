@@ -0,0 +1,209 @@
+// Copyright 2021 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+use crate::config::{units, Keyspace, Protocol, Thresholds};
+
+use serde_derive::*;
+
+use std::net::ToSocketAddrs;
+
+/// A group of clients which runs alongside the clients of the config, with
+/// its own endpoints, request ratelimit and optionally its own protocol and
+/// keyspaces, to model a host which talks to several cache tiers at once.
+/// Settings which aren't given are taken from the config, except for the
+/// request ratelimit, which is unlimited if it isn't given. A group which
+/// speaks a protocol other than the config's must have keyspaces of its own,
+/// as the commands of the config's workload are for the config's protocol.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Group {
+    name: String,
+    protocol: Option<Protocol>,
+    endpoints: Vec<String>,
+    clients: Option<usize>,
+    poolsize: Option<usize>,
+    #[serde(default, deserialize_with = "units::optional_count")]
+    request_ratelimit: Option<usize>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    keyspace: Vec<Keyspace>,
+    thresholds: Option<Thresholds>,
+}
+
+impl Group {
+    /// the name the group's stats are reported under
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn protocol(&self) -> Option<Protocol> {
+        self.protocol.clone()
+    }
+
+    pub fn endpoints(&self) -> &[String] {
+        &self.endpoints
+    }
+
+    pub fn clients(&self) -> Option<usize> {
+        self.clients
+    }
+
+    pub fn poolsize(&self) -> Option<usize> {
+        self.poolsize
+    }
+
+    pub fn request_ratelimit(&self) -> Option<usize> {
+        self.request_ratelimit
+    }
+
+    /// the keyspaces of the group's workload, which is the workload of the
+    /// config if there are none
+    pub fn keyspace(&self) -> &[Keyspace] {
+        &self.keyspace
+    }
+
+    /// the thresholds the group's stats are checked against, which are the
+    /// thresholds of the config if there are none
+    pub fn thresholds(&self) -> Option<Thresholds> {
+        self.thresholds.clone()
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if self.name.is_empty() {
+            return Err("name must not be empty".to_string());
+        }
+        // the name labels the group's stats and names its files in a bundle
+        if !self
+            .name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        {
+            return Err("name may only contain letters, digits, '-' and '_'".to_string());
+        }
+        if self.endpoints.is_empty() {
+            return Err("at least one endpoint is required".to_string());
+        }
+        for endpoint in &self.endpoints {
+            if endpoint
+                .to_socket_addrs()
+                .ok()
+                .and_then(|mut a| a.next())
+                .is_none()
+            {
+                return Err(format!("failed to resolve address: {}", endpoint));
+            }
+        }
+        if self.clients == Some(0) {
+            return Err("clients must be at least 1".to_string());
+        }
+        if self.poolsize == Some(0) {
+            return Err("poolsize must be at least 1".to_string());
+        }
+        if self.request_ratelimit == Some(0) {
+            return Err("request_ratelimit must be at least 1".to_string());
+        }
+        if let Some(ref thresholds) = self.thresholds {
+            thresholds
+                .validate()
+                .map_err(|e| format!("thresholds are invalid: {}", e))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn group() {
+        let group: Group = toml::from_str(
+            "name = \"redis\"\n\
+             protocol = \"redis_resp\"\n\
+             endpoints = [\"127.0.0.1:6379\"]\n\
+             request_ratelimit = \"2k\"\n\
+             [[keyspace]]\n\
+             length = 8\n\
+             count = 1000\n\
+             weight = 1\n\
+             commands = [{action = \"get\", weight = 1}]\n\
+             values = [{length = 16, weight = 1}]",
+        )
+        .unwrap();
+        assert_eq!(group.name(), "redis");
+        assert_eq!(group.protocol(), Some(Protocol::RedisResp));
+        assert_eq!(group.request_ratelimit(), Some(2000));
+        assert_eq!(group.clients(), None);
+        assert_eq!(group.keyspace().len(), 1);
+        assert!(group.validate().is_ok());
+
+        let group: Group = toml::from_str("name = \"empty\"\nendpoints = []").unwrap();
+        assert!(group.validate().is_err());
+        let group: Group =
+            toml::from_str("name = \"a b\"\nendpoints = [\"127.0.0.1:6379\"]").unwrap();
+        assert!(group.validate().is_err());
+        assert!(toml::from_str::<Group>("name = \"a\"\nendpoints = []\nwarmup = 1").is_err());
+    }
+
+    #[test]
+    fn for_group() {
+        let config = crate::config::Config::from_toml(
+            "[general]\n\
+             protocol = \"memcache\"\n\
+             endpoints = [\"127.0.0.1:11211\"]\n\
+             clients = 4\n\
+             request_ratelimit = 8000\n\
+             summary = \"summary.json\"\n\
+             [[keyspace]]\n\
+             length = 8\n\
+             weight = 1\n\
+             commands = [{action = \"get\", weight = 1}]\n\
+             values = [{length = 16, weight = 1}]\n\
+             [thresholds]\n\
+             p99 = 1000\n\
+             [[group]]\n\
+             name = \"redis\"\n\
+             protocol = \"redis_resp\"\n\
+             endpoints = [\"127.0.0.1:6379\"]\n\
+             request_ratelimit = 2000\n\
+             [group.thresholds]\n\
+             p99 = 2000\n\
+             [[group.keyspace]]\n\
+             length = 8\n\
+             weight = 1\n\
+             commands = [{action = \"get\", weight = 1}]\n\
+             values = [{length = 16, weight = 1}]",
+        )
+        .unwrap();
+        assert_eq!(config.groups().len(), 1);
+        let group = config.for_group(&config.groups()[0]);
+        assert_eq!(group.protocol(), Protocol::RedisResp);
+        assert_eq!(group.endpoints(), vec!["127.0.0.1:6379".parse().unwrap()]);
+        assert_eq!(group.clients(), 4);
+        assert_eq!(group.request_ratelimit(), Some(2000));
+        assert_eq!(group.summary(), None);
+        assert_eq!(group.thresholds().latency(), vec![(99.0, 2000)]);
+        assert!(group.groups().is_empty());
+
+        // the groups are validated as the config is loaded
+        let repeated = config.to_toml()
+            + "[[group]]\n\
+               name = \"redis\"\n\
+               endpoints = [\"127.0.0.1:6380\"]\n";
+        assert!(crate::config::Config::from_toml(&repeated).is_err());
+
+        // the commands of the config's workload are for the config's protocol
+        let inherited = config.to_toml()
+            + "[[group]]\n\
+               name = \"other\"\n\
+               protocol = \"redis_inline\"\n\
+               endpoints = [\"127.0.0.1:6380\"]\n";
+        assert!(crate::config::Config::from_toml(&inherited).is_err());
+        let same = config.to_toml()
+            + "[[group]]\n\
+               name = \"other\"\n\
+               protocol = \"memcache\"\n\
+               endpoints = [\"127.0.0.1:11212\"]\n";
+        assert!(crate::config::Config::from_toml(&same).is_ok());
+    }
+}
@@ -0,0 +1,171 @@
+// Copyright 2021 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+//! Configs may include other configs with a top-level `include`, which is a
+//! path or a list of paths relative to the including file. The included files
+//! are merged in order and the including file is merged over them. Tables are
+//! merged key by key, while any other value, including arrays of tables such
+//! as `keyspace`, replaces the included value.
+
+use toml::Value;
+
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+/// read a config and the configs it includes, returning the merged config
+pub fn resolve<F>(path: &Path, read: &F) -> Result<String, String>
+where
+    F: Fn(&Path) -> Result<String, String>,
+{
+    resolve_from(path, read, &mut Vec::new())
+}
+
+fn resolve_from<F>(path: &Path, read: &F, stack: &mut Vec<PathBuf>) -> Result<String, String>
+where
+    F: Fn(&Path) -> Result<String, String>,
+{
+    let identity = identify(path);
+    if stack.contains(&identity) {
+        return Err(format!("config includes itself: {}", path.display()));
+    }
+    let content = read(path)?;
+    let mut toml: Value =
+        toml::from_str(&content).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let includes = match toml.as_table_mut().and_then(|t| t.remove("include")) {
+        None => return Ok(content),
+        Some(Value::String(include)) => vec![include],
+        Some(Value::Array(includes)) => includes
+            .into_iter()
+            .map(|v| match v {
+                Value::String(include) => Ok(include),
+                _ => Err(format!(
+                    "{}: include must be a list of paths",
+                    path.display()
+                )),
+            })
+            .collect::<Result<_, _>>()?,
+        Some(_) => {
+            return Err(format!(
+                "{}: include must be a path or a list of paths",
+                path.display()
+            ))
+        }
+    };
+
+    stack.push(identity);
+    let dir = path.parent().unwrap_or_else(|| Path::new(""));
+    let mut merged = Value::Table(Default::default());
+    for include in includes {
+        let content = resolve_from(&dir.join(include), read, stack)?;
+        // the included content was already parsed when it was resolved
+        merge(&mut merged, toml::from_str(&content).unwrap());
+    }
+    stack.pop();
+
+    merge(&mut merged, toml);
+    Ok(merged.to_string())
+}
+
+/// the path which identifies a file, so that a file is recognized however it
+/// is included. Paths which can't be canonicalized, as there is no such file,
+/// are normalized instead.
+fn identify(path: &Path) -> PathBuf {
+    fs::canonicalize(path).unwrap_or_else(|_| normalize(path))
+}
+
+/// remove the `.` components of the path and the `..` components which
+/// follow a directory
+fn normalize(path: &Path) -> PathBuf {
+    let mut normal = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir
+                if matches!(normal.components().next_back(), Some(Component::Normal(_))) =>
+            {
+                normal.pop();
+            }
+            component => normal.push(component),
+        }
+    }
+    normal
+}
+
+/// merge the local config over the base config
+pub fn merge(base: &mut Value, local: Value) {
+    match (base, local) {
+        (Value::Table(base), Value::Table(local)) => {
+            for (key, value) in local {
+                match base.get_mut(&key) {
+                    Some(existing) => merge(existing, value),
+                    None => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (base, local) => *base = local,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read(path: &Path) -> Result<String, String> {
+        let content = match normalize(path).to_str().unwrap() {
+            "configs/base.toml" => {
+                "[general]\nprotocol = \"redis\"\nclients = 2\ntls_ca = \"ca.pem\"\n"
+            }
+            "configs/large.toml" => "[[keyspace]]\nlength = 64\n",
+            "configs/local.toml" => {
+                "include = [\"base.toml\", \"large.toml\"]\n\
+                 [general]\nclients = 4\n[[keyspace]]\nlength = 8\n"
+            }
+            "configs/loop.toml" => "include = \"loop.toml\"\n",
+            "configs/parent.toml" => "include = \"../configs/./parent.toml\"\n",
+            "configs/plain.toml" => "# nothing to include\n[general]\n",
+            _ => return Err(format!("not found: {}", path.display())),
+        };
+        Ok(content.to_string())
+    }
+
+    #[test]
+    fn includes() {
+        let merged: Value =
+            toml::from_str(&resolve(Path::new("configs/local.toml"), &read).unwrap()).unwrap();
+        assert_eq!(merged.get("include"), None);
+        assert_eq!(merged["general"]["protocol"].as_str(), Some("redis"));
+        assert_eq!(merged["general"]["tls_ca"].as_str(), Some("ca.pem"));
+        assert_eq!(merged["general"]["clients"].as_integer(), Some(4));
+        let keyspace = merged["keyspace"].as_array().unwrap();
+        assert_eq!(keyspace.len(), 1);
+        assert_eq!(keyspace[0]["length"].as_integer(), Some(8));
+
+        // a config without includes is unchanged
+        assert_eq!(
+            resolve(Path::new("configs/plain.toml"), &read).unwrap(),
+            "# nothing to include\n[general]\n"
+        );
+
+        assert!(resolve(Path::new("configs/loop.toml"), &read).is_err());
+        assert!(resolve(Path::new("configs/parent.toml"), &read)
+            .unwrap_err()
+            .contains("includes itself"));
+        assert!(resolve(Path::new("configs/missing.toml"), &read).is_err());
+    }
+
+    #[test]
+    fn loops() {
+        let dir = std::env::temp_dir().join(format!("rpc-perf-include-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let name = dir.file_name().unwrap().to_str().unwrap();
+        let path = dir.join("a.toml");
+        fs::write(&path, format!("include = \"../{}/a.toml\"\n", name)).unwrap();
+        let read = |path: &Path| fs::read_to_string(path).map_err(|e| e.to_string());
+        let result = resolve(&path, &read);
+        fs::remove_dir_all(&dir).unwrap();
+        assert!(result.unwrap_err().contains("includes itself"));
+    }
+}
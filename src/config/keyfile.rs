@@ -0,0 +1,127 @@
+// Copyright 2021 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+//! A file of keys to preload and read, so that a benchmark can mirror the
+//! contents of a real cache. Each line holds a key, optionally followed by the
+//! size of its value in bytes and its TTL in seconds. Blank lines and lines
+//! starting with `#` are ignored.
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Key {
+    pub key: String,
+    pub size: Option<usize>,
+    pub ttl: Option<usize>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Keyfile {
+    keys: Vec<Key>,
+}
+
+impl Keyfile {
+    pub fn parse(content: &str) -> Result<Self, String> {
+        let mut keys = Vec::new();
+        for (number, line) in content.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let mut parts = line.split_whitespace();
+            let key = parts.next().unwrap().to_string();
+            let mut field = |name: &str| -> Result<Option<usize>, String> {
+                match parts.next() {
+                    Some(value) => value.parse().map(Some).map_err(|_| {
+                        format!("line {}: {} is not a number: {}", number + 1, name, value)
+                    }),
+                    None => Ok(None),
+                }
+            };
+            let size = field("size")?;
+            let ttl = field("ttl")?;
+            if parts.next().is_some() {
+                return Err(format!("line {}: too many fields", number + 1));
+            }
+            keys.push(Key { key, size, ttl });
+        }
+        if keys.is_empty() {
+            return Err("no keys".to_string());
+        }
+        Ok(Self { keys })
+    }
+
+    pub fn load(path: &str) -> Result<Self, String> {
+        let content = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+        Self::parse(&content)
+    }
+
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    pub fn get(&self, index: usize) -> &Key {
+        &self.keys[index]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        let keyfile = Keyfile::parse("# keys\nuser:1\n\nuser:2 128\nuser:3 64 3600\n").unwrap();
+        assert_eq!(keyfile.len(), 3);
+        assert_eq!(
+            keyfile.get(0),
+            &Key {
+                key: "user:1".to_string(),
+                size: None,
+                ttl: None
+            }
+        );
+        assert_eq!(keyfile.get(1).size, Some(128));
+        assert_eq!(keyfile.get(2).ttl, Some(3600));
+
+        assert!(Keyfile::parse("user:1 big").is_err());
+        assert!(Keyfile::parse("user:1 1 2 3").is_err());
+        assert!(Keyfile::parse("# nothing\n").is_err());
+    }
+
+    #[test]
+    fn generate() {
+        use crate::config::{Action, Config};
+        use rand::rngs::StdRng;
+        use rand::SeedableRng;
+        use std::sync::Arc;
+
+        let config = Config {
+            keys: Some(Arc::new(Keyfile::parse("a 3\nb\nc 5 60\n").unwrap())),
+            ..Default::default()
+        };
+        let mut rng = StdRng::seed_from_u64(0);
+
+        // the first of two clients sets every other key
+        let preload = config.preload_generator(0, 2);
+        let keys: Vec<Vec<u8>> = (0..3)
+            .map(|_| {
+                let command = preload.generate(&mut rng);
+                assert_eq!(command.action(), Action::Set);
+                command.key().unwrap().to_vec()
+            })
+            .collect();
+        assert_eq!(keys, vec![b"a".to_vec(), b"c".to_vec(), b"b".to_vec()]);
+
+        let generator = config.generator();
+        for _ in 0..100 {
+            let command = generator.generate(&mut rng);
+            if command.action() == Action::Get {
+                assert!([&b"a"[..], b"b", b"c"].contains(&command.key().unwrap()));
+            }
+        }
+    }
+}
@@ -2,34 +2,96 @@
 // Licensed under the Apache License, Version 2.0
 // http://www.apache.org/licenses/LICENSE-2.0
 
+mod chaos;
+mod compression;
+mod discovery;
+mod duration;
+mod env;
+mod example;
 mod general;
-
-pub use self::general::Protocol;
-
+mod group;
+mod include;
+mod keyfile;
+mod overrides;
+mod presets;
+mod shard;
+mod thresholds;
+mod units;
+mod warmup;
+mod ycsb;
+mod zipfian;
+
+pub use self::chaos::{Fault, FaultKind};
+pub use self::compression::{Algorithm, Compression};
+pub use self::discovery::{Discovery, Source};
+pub use self::general::{Partition, Protocol};
+pub use self::group::Group;
+pub use self::keyfile::{Key, Keyfile};
+pub use self::shard::Shard;
+pub use self::thresholds::Thresholds;
+pub use self::warmup::Warmup;
+pub use self::ycsb::Ycsb;
+
+use self::zipfian::Zipfian;
+
+use crate::codec::{Registry, Shape};
 use crate::config::general::General;
+use crate::metadata::Metadata;
 use crate::*;
 
-use clap::{App, Arg, ArgMatches};
+use clap::{App, Arg, ArgMatches, SubCommand};
 use rand::distributions::{Alphanumeric, Distribution, Uniform};
-use rand::rngs::ThreadRng;
+use rand::rngs::StdRng;
 use rand::seq::SliceRandom;
 use rand::Rng;
 use rustcommon_logger::Level;
 use rustcommon_ratelimiter::Refill;
+use serde::de;
 use serde_derive::*;
 
-use std::io::Read;
+use std::cell::Cell;
+use std::collections::BTreeMap;
 use std::net::{SocketAddr, ToSocketAddrs};
+use std::path::Path;
 use std::process;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 pub const VERSION: &str = env!("CARGO_PKG_VERSION");
 pub const NAME: &str = env!("CARGO_PKG_NAME");
 
-#[derive(Clone, Debug, Deserialize)]
+#[derive(Clone, Debug, Deserialize, Serialize)]
 #[serde(deny_unknown_fields)]
 pub struct Config {
     general: General,
+    #[serde(default)]
     keyspace: Vec<Keyspace>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    ycsb: Option<Ycsb>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    thresholds: Option<Thresholds>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    warmup: Option<Warmup>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    compression: Option<Compression>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    discovery: Option<Discovery>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    chaos: Vec<Fault>,
+    #[serde(default, rename = "group", skip_serializing_if = "Vec::is_empty")]
+    groups: Vec<Group>,
+    #[serde(skip)]
+    source: Option<String>,
+    #[serde(skip)]
+    keys: Option<Arc<Keyfile>>,
+    #[serde(skip)]
+    dashboard: bool,
+    #[serde(skip)]
+    overrides: Vec<String>,
+    #[serde(skip)]
+    preset: Option<String>,
+    #[serde(skip)]
+    shard: Option<Shard>,
 }
 
 impl Default for Config {
@@ -61,17 +123,31 @@ impl Default for Config {
             count: Some(10_000_000),
             weight: 1,
             hitrate: None,
+            distribution: KeyDistribution::Uniform,
             commands: vec![get, set],
             values: vec![value],
         });
         Config {
             general: Default::default(),
             keyspace,
+            ycsb: None,
+            thresholds: None,
+            warmup: None,
+            compression: None,
+            discovery: None,
+            chaos: Vec::new(),
+            groups: Vec::new(),
+            source: None,
+            keys: None,
+            dashboard: false,
+            overrides: Vec::new(),
+            preset: None,
+            shard: None,
         }
     }
 }
 
-#[derive(Copy, Clone, Deserialize, Debug)]
+#[derive(Copy, Clone, Deserialize, Serialize, Debug, PartialEq, Eq, Hash)]
 #[serde(rename_all = "snake_case")]
 #[serde(deny_unknown_fields)]
 pub enum Action {
@@ -98,23 +174,51 @@ pub enum Action {
     Set,
 }
 
-#[derive(Clone, Debug, Deserialize)]
+#[derive(Clone, Debug, Deserialize, Serialize)]
 #[serde(deny_unknown_fields)]
 pub struct Keyspace {
     length: usize,
     weight: usize,
+    #[serde(default, deserialize_with = "units::optional_count")]
     count: Option<usize>,
     hitrate: Option<f64>,
+    #[serde(default)]
+    distribution: KeyDistribution,
     commands: Vec<Command>,
     values: Vec<Value>,
 }
 
+/// how keys are chosen from a keyspace
+#[derive(Copy, Clone, Debug, Default, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum KeyDistribution {
+    /// every key is equally likely
+    #[default]
+    Uniform,
+    /// a few keys are much more popular than the rest
+    Zipfian,
+    /// zipfian, with the highest keys being the most popular
+    Latest,
+}
+
 pub struct Generator {
     keyspaces: Vec<KeyspaceGenerator>,
+    keys: Option<Arc<Keyfile>>,
+    preload: Option<Preload>,
+    shard: Option<Shard>,
+}
+
+/// the position of a client in its share of the keyfile while preloading
+struct Preload {
+    next: Cell<usize>,
+    step: usize,
 }
 
 impl Generator {
-    pub fn generate(&self, rng: &mut ThreadRng) -> crate::codec::Command {
+    pub fn generate(&self, rng: &mut StdRng) -> crate::codec::Command {
+        if let (Some(keys), Some(preload)) = (&self.keys, &self.preload) {
+            return self.preload(keys, preload, rng);
+        }
         let keyspace = self
             .keyspaces
             .choose_weighted(rng, config::KeyspaceGenerator::weight)
@@ -127,7 +231,7 @@ impl Generator {
                 crate::codec::Command::delete(key)
             }
             Action::Get => {
-                let key = keyspace.choose_key(rng);
+                let key = self.read_key(keyspace, rng);
                 crate::codec::Command::get(key)
             }
             Action::Hdel => {
@@ -141,7 +245,7 @@ impl Generator {
                 crate::codec::Command::hdel(key, fields)
             }
             Action::Hget => {
-                let key = keyspace.choose_key(rng);
+                let key = self.read_key(keyspace, rng);
                 let mut fields = Vec::new();
                 for _ in 0..command.items().unwrap_or(1) {
                     // TODO(bmartin): we should allow for different ways of
@@ -165,7 +269,7 @@ impl Generator {
                 crate::codec::Command::hset(key, fields, values, command.ttl())
             }
             Action::Llen => {
-                let key = keyspace.choose_key(rng);
+                let key = self.read_key(keyspace, rng);
                 crate::codec::Command::llen(key)
             }
             Action::Lpush => {
@@ -185,7 +289,7 @@ impl Generator {
                 crate::codec::Command::lpushx(key, values)
             }
             Action::Lrange => {
-                let key = keyspace.choose_key(rng);
+                let key = self.read_key(keyspace, rng);
                 crate::codec::Command::lrange(key, 0, command.items().unwrap_or(1))
             }
             Action::Ltrim => {
@@ -229,12 +333,12 @@ impl Generator {
                 crate::codec::Command::sarray_delete(key)
             }
             Action::SarrayFind => {
-                let key = keyspace.choose_key(rng);
+                let key = self.read_key(keyspace, rng);
                 let value = keyspace.choose_value_string(rng);
                 crate::codec::Command::sarray_find(key, value)
             }
             Action::SarrayGet => {
-                let key = keyspace.choose_key(rng);
+                let key = self.read_key(keyspace, rng);
                 // TODO: implement index
                 crate::codec::Command::sarray_get(key, None, command.items().map(|v| v as u64))
             }
@@ -247,7 +351,7 @@ impl Generator {
                 crate::codec::Command::sarray_insert(key, values)
             }
             Action::SarrayLen => {
-                let key = keyspace.choose_key(rng);
+                let key = self.read_key(keyspace, rng);
                 crate::codec::Command::sarray_len(key)
             }
             Action::SarrayRemove => {
@@ -264,12 +368,94 @@ impl Generator {
             }
         }
     }
+
+    /// the key for a read, which is from the keyfile if there is one
+    fn read_key(&self, keyspace: &KeyspaceGenerator, rng: &mut StdRng) -> String {
+        match self.keys {
+            Some(ref keys) => {
+                let index = match self.shard {
+                    Some(shard) => shard.item(rng.gen_range(0, shard.share(keys.len()))),
+                    None => rng.gen_range(0, keys.len()),
+                };
+                keys.get(index).key.clone()
+            }
+            None => keyspace.choose_key(rng),
+        }
+    }
+
+    /// set the next key in this client's share of the keyfile. The keys are
+    /// set in turn, starting over once they have all been set.
+    fn preload(
+        &self,
+        keys: &Keyfile,
+        preload: &Preload,
+        rng: &mut StdRng,
+    ) -> crate::codec::Command {
+        let index = preload.next.get() % keys.len();
+        preload.next.set(index + preload.step);
+        let key = keys.get(index);
+        let value = match key.size {
+            Some(size) => rng.sample_iter(&Alphanumeric).take(size).collect(),
+            None => self.keyspaces[0].choose_value_string(rng),
+        };
+        crate::codec::Command::set(key.key.clone(), value, key.ttl)
+    }
+
+    /// the single key requests this generator produces, used by the codecs to
+    /// pre-render request templates
+    pub fn shapes(&self) -> Vec<Shape> {
+        let mut shapes = Vec::new();
+        for keyspace in &self.keyspaces {
+            for command in &keyspace.commands {
+                match command.action() {
+                    Action::Delete | Action::Get => {
+                        shapes.push(Shape {
+                            action: command.action(),
+                            key: keyspace.length,
+                            value: 0,
+                            ttl: None,
+                        });
+                    }
+                    Action::Set => {
+                        for value in &keyspace.values {
+                            shapes.push(Shape {
+                                action: command.action(),
+                                key: keyspace.length,
+                                value: value.length(),
+                                ttl: command.ttl(),
+                            });
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+        shapes
+    }
+}
+
+/// chooses the index of a key in a keyspace
+enum KeyChooser {
+    Uniform(Uniform<usize>),
+    Zipfian(Zipfian, usize),
+    Latest(Zipfian, usize),
+}
+
+impl KeyChooser {
+    fn sample(&self, rng: &mut StdRng) -> usize {
+        match self {
+            KeyChooser::Uniform(uniform) => uniform.sample(rng),
+            KeyChooser::Zipfian(zipfian, count) => zipfian::scramble(zipfian.sample(rng), *count),
+            KeyChooser::Latest(zipfian, count) => count - 1 - zipfian.sample(rng),
+        }
+    }
 }
 
 pub struct KeyspaceGenerator {
     length: usize,
     weight: usize,
-    distribution: Uniform<usize>,
+    distribution: KeyChooser,
+    shard: Option<Shard>,
     commands: Vec<Command>,
     values: Vec<Value>,
 }
@@ -279,21 +465,31 @@ impl KeyspaceGenerator {
         self.weight
     }
 
-    pub fn choose_command(&self, rng: &mut ThreadRng) -> &Command {
+    pub fn choose_command(&self, rng: &mut StdRng) -> &Command {
         self.commands
             .choose_weighted(rng, config::Command::weight)
             .unwrap()
     }
 
-    pub fn choose_key(&self, rng: &mut ThreadRng) -> String {
-        format!(
-            "{:0width$}",
-            self.distribution.sample(rng),
-            width = self.length
-        )
+    pub fn choose_key(&self, rng: &mut StdRng) -> String {
+        let key = match (self.shard, &self.distribution) {
+            (None, distribution) => distribution.sample(rng),
+            // the uniform distribution is over the shard's part of the keys
+            (Some(shard), KeyChooser::Uniform(uniform)) => shard.item(uniform.sample(rng)),
+            // the skewed distributions are over the whole keyspace, and each
+            // key drawn is mapped to the shard's key in the same block of
+            // keys, so each shard keeps the shape of the keyspace over its own
+            // keys with one draw for each key
+            (Some(shard), KeyChooser::Zipfian(_, count))
+            | (Some(shard), KeyChooser::Latest(_, count)) => {
+                let n = self.distribution.sample(rng) / shard.count();
+                shard.item(n.min(shard.share(*count) - 1))
+            }
+        };
+        format!("{:0width$}", key, width = self.length)
     }
 
-    pub fn choose_value_string(&self, rng: &mut ThreadRng) -> String {
+    pub fn choose_value_string(&self, rng: &mut StdRng) -> String {
         let value = self
             .values
             .choose_weighted(rng, config::Value::weight)
@@ -315,7 +511,7 @@ impl KeyspaceGenerator {
         }
     }
 
-    pub fn choose_value(&self, rng: &mut ThreadRng) -> &Value {
+    pub fn choose_value(&self, rng: &mut StdRng) -> &Value {
         self.values
             .choose_weighted(rng, config::Value::weight)
             .unwrap()
@@ -323,8 +519,9 @@ impl KeyspaceGenerator {
 }
 
 impl Keyspace {
-    pub fn generator(&self) -> KeyspaceGenerator {
-        let count = if let Some(count) = self.count {
+    /// the number of keys in the keyspace
+    pub fn count(&self) -> usize {
+        if let Some(count) = self.count {
             let digits = (count as f64).log10().ceil() as usize;
             if digits > self.length {
                 fatal!(
@@ -342,20 +539,37 @@ impl Keyspace {
                 );
             }
             10_usize.pow(self.length as u32)
-        };
+        }
+    }
 
-        let distribution = Uniform::from(0..count);
+    /// a generator for the keyspace, or for the shard's part of it
+    pub fn generator(&self, shard: Option<Shard>) -> KeyspaceGenerator {
+        let count = self.count();
+
+        let distribution = match self.distribution {
+            KeyDistribution::Uniform => {
+                let count = shard.map_or(count, |shard| shard.share(count));
+                KeyChooser::Uniform(Uniform::from(0..count))
+            }
+            KeyDistribution::Zipfian => {
+                KeyChooser::Zipfian(Zipfian::new(count, zipfian::THETA), count)
+            }
+            KeyDistribution::Latest => {
+                KeyChooser::Latest(Zipfian::new(count, zipfian::THETA), count)
+            }
+        };
         KeyspaceGenerator {
             length: self.length,
             weight: self.weight,
             distribution,
+            shard,
             commands: self.commands.clone(),
             values: self.values.clone(),
         }
     }
 }
 
-#[derive(Clone, Debug, Deserialize)]
+#[derive(Clone, Debug, Deserialize, Serialize)]
 #[serde(deny_unknown_fields)]
 pub struct Command {
     action: Action,
@@ -392,16 +606,17 @@ impl Command {
     }
 }
 
-#[derive(Clone, Debug, Deserialize)]
+#[derive(Clone, Debug, Deserialize, Serialize)]
 #[serde(rename_all = "snake_case")]
 pub enum Class {
     Alphanumeric,
     Integer,
 }
 
-#[derive(Clone, Debug, Deserialize)]
+#[derive(Clone, Debug, Deserialize, Serialize)]
 #[serde(deny_unknown_fields)]
 pub struct Value {
+    #[serde(deserialize_with = "units::size")]
     length: usize,
     weight: usize,
     #[serde(default = "default_value_class")]
@@ -426,6 +641,7 @@ impl Value {
 impl Config {
     /// parse command line options and return `Config`
     pub fn new() -> Config {
+        let preset_names = presets::names();
         let app = App::new(NAME)
             .version(VERSION)
             .author("Brian Martin <bmartin@twitter.com>")
@@ -451,6 +667,35 @@ impl Config {
                     .help("Optional listen address for admin port")
                     .takes_value(true),
             )
+            .arg(
+                Arg::with_name("agent")
+                    .long("agent")
+                    .help("Wait for a coordinator to provide the config and start the run")
+                    .requires("admin")
+                    .conflicts_with("agents"),
+            )
+            .arg(
+                Arg::with_name("agents")
+                    .long("agents")
+                    .value_name("IP:PORT")
+                    .help("Admin address of an agent to coordinate, the run is split across them")
+                    .multiple(true)
+                    .takes_value(true),
+            )
+            .arg(
+                Arg::with_name("start-at")
+                    .long("start-at")
+                    .value_name("Unix Seconds")
+                    .help("Wait until this time to start, so that the windows of separate instances line up")
+                    .takes_value(true),
+            )
+            .arg(
+                Arg::with_name("grace-period")
+                    .long("grace-period")
+                    .value_name("Duration")
+                    .help("Time allowed to drain and save the results after SIGTERM")
+                    .takes_value(true),
+            )
             .arg(
                 Arg::with_name("verbose")
                     .short("v")
@@ -461,7 +706,7 @@ impl Config {
             .arg(
                 Arg::with_name("interval")
                     .long("interval")
-                    .value_name("Seconds")
+                    .value_name("Duration")
                     .help("Integration window duration and period for stats output")
                     .takes_value(true),
             )
@@ -472,6 +717,14 @@ impl Config {
                     .help("The number of intervals before exit")
                     .takes_value(true),
             )
+            .arg(
+                Arg::with_name("duration")
+                    .long("duration")
+                    .value_name("Duration")
+                    .help("Run for this long instead of a number of windows, eg: 15m")
+                    .takes_value(true)
+                    .conflicts_with("windows"),
+            )
             .arg(
                 Arg::with_name("clients")
                     .long("clients")
@@ -486,6 +739,13 @@ impl Config {
                     .help("The number of connections from each client to each endpoint")
                     .takes_value(true),
             )
+            .arg(
+                Arg::with_name("pipeline")
+                    .long("pipeline")
+                    .value_name("# Requests")
+                    .help("The number of requests in-flight on each connection")
+                    .takes_value(true),
+            )
             .arg(
                 Arg::with_name("service")
                     .long("service")
@@ -499,6 +759,38 @@ impl Config {
                     .multiple(true)
                     .takes_value(true),
             )
+            .arg(
+                Arg::with_name("compare")
+                    .long("compare")
+                    .value_name("HOST:PORT or IP:PORT")
+                    .help("Drive a second group of endpoints with the same workload and compare them")
+                    .multiple(true)
+                    .takes_value(true)
+                    .conflicts_with_all(&["agent", "agents"]),
+            )
+            .arg(
+                Arg::with_name("shadow")
+                    .long("shadow")
+                    .value_name("HOST:PORT or IP:PORT")
+                    .help("Mirror each request to a shadow endpoint and compare the responses")
+                    .multiple(true)
+                    .takes_value(true),
+            )
+            .arg(
+                Arg::with_name("discover")
+                    .long("discover")
+                    .value_name("SOURCE:TARGET")
+                    .help("Discover the endpoints from an SRV record or a seed node: srv:NAME, redis-cluster:HOST:PORT or twemproxy:HOST:PORT")
+                    .takes_value(true)
+                    .conflicts_with("endpoint"),
+            )
+            .arg(
+                Arg::with_name("discover-refresh")
+                    .long("discover-refresh")
+                    .value_name("DURATION")
+                    .help("How often the endpoints are discovered again during the run")
+                    .takes_value(true),
+            )
             .arg(
                 Arg::with_name("protocol")
                     .long("protocol")
@@ -509,6 +801,7 @@ impl Config {
                     .possible_value("ping")
                     .possible_value("redis")
                     .possible_value("redis-inline")
+                    .possible_value("redis-auto")
                     .takes_value(true),
             )
             .arg(
@@ -518,6 +811,39 @@ impl Config {
                     .help("Ratelimit for requests per-second")
                     .takes_value(true),
             )
+            .arg(
+                Arg::with_name("request-batch")
+                    .long("request-batch")
+                    .value_name("# Requests")
+                    .help("Number of request tokens each client takes from the ratelimiter at once")
+                    .takes_value(true),
+            )
+            .arg(
+                Arg::with_name("request-burst")
+                    .long("request-burst")
+                    .value_name("# Requests")
+                    .help("Number of requests the ratelimiter may let through at once")
+                    .takes_value(true),
+            )
+            .arg(
+                Arg::with_name("request-partition")
+                    .long("request-partition")
+                    .value_name("STRATEGY")
+                    .help("How the request ratelimit is divided between the clients")
+                    .possible_value("shared")
+                    .possible_value("equal")
+                    .possible_value("weighted")
+                    .takes_value(true),
+            )
+            .arg(
+                Arg::with_name("request-weights")
+                    .long("request-weights")
+                    .value_name("WEIGHT,...")
+                    .help("The weight of each client's share of the request ratelimit")
+                    .use_delimiter(true)
+                    .multiple(true)
+                    .takes_value(true),
+            )
             .arg(
                 Arg::with_name("request-timeout")
                     .long("request-timeout")
@@ -544,6 +870,22 @@ impl Config {
                     .long("soft_timeout")
                     .help("Don't close connection on timeout"),
             )
+            .arg(
+                Arg::with_name("numa")
+                    .long("numa")
+                    .help("Spread client threads across NUMA nodes and bind each to its node"),
+            )
+            .arg(
+                Arg::with_name("numa-bind-endpoints")
+                    .long("numa-bind-endpoints")
+                    .help("Partition the endpoints across NUMA nodes")
+                    .requires("numa"),
+            )
+            .arg(
+                Arg::with_name("raise-fd-limit")
+                    .long("raise-fd-limit")
+                    .help("Raise the soft open files limit if it's too low for the connections"),
+            )
             .arg(
                 Arg::with_name("close-rate")
                     .long("close-rate")
@@ -551,6 +893,13 @@ impl Config {
                     .help("Rate of connections/s that should be client closed")
                     .takes_value(true),
             )
+            .arg(
+                Arg::with_name("pushback-backoff")
+                    .long("pushback-backoff")
+                    .value_name("Percent")
+                    .help("Lower the request ratelimit while more than this percentage of responses push back")
+                    .takes_value(true),
+            )
             .arg(
                 Arg::with_name("tcp-nodelay")
                     .long("tcp-nodelay")
@@ -564,6 +913,43 @@ impl Config {
                     .help("Run warmup until hitrate reaches target")
                     .takes_value(true),
             )
+            .arg(
+                Arg::with_name("warmup-timeout")
+                    .long("warmup-timeout")
+                    .value_name("Duration")
+                    .help("Abort if the warmup hasn't reached the hitrate in this time")
+                    .takes_value(true),
+            )
+            .arg(
+                Arg::with_name("warmup-clients")
+                    .long("warmup-clients")
+                    .value_name("# Clients")
+                    .help("Number of client threads used for the warmup")
+                    .takes_value(true),
+            )
+            .arg(
+                Arg::with_name("warmup-ratelimit")
+                    .long("warmup-ratelimit")
+                    .value_name("Per-second")
+                    .help("Ratelimit for requests per-second during the warmup")
+                    .takes_value(true),
+            )
+            .arg(
+                Arg::with_name("compression")
+                    .long("compression")
+                    .value_name("ALGORITHM")
+                    .help("Compress values before they are stored")
+                    .possible_value("lz4")
+                    .possible_value("zstd")
+                    .takes_value(true),
+            )
+            .arg(
+                Arg::with_name("compression-level")
+                    .long("compression-level")
+                    .value_name("LEVEL")
+                    .help("The level values are compressed at")
+                    .takes_value(true),
+            )
             .arg(
                 Arg::with_name("waterfall")
                     .long("waterfall")
@@ -571,6 +957,114 @@ impl Config {
                     .help("Render request latency PNG to file")
                     .takes_value(true),
             )
+            .arg(
+                Arg::with_name("bundle")
+                    .long("bundle")
+                    .value_name("FILE")
+                    .help("Write the config, metrics and outputs of the run to a tar file")
+                    .takes_value(true),
+            )
+            .arg(
+                Arg::with_name("summary")
+                    .long("summary")
+                    .value_name("FILE")
+                    .help("Write the summary of the run to a JSON file")
+                    .takes_value(true),
+            )
+            .arg(
+                Arg::with_name("sparklines")
+                    .long("sparklines")
+                    .value_name("Windows")
+                    .help("Print trend lines of the rate and p99 latency over this many windows")
+                    .takes_value(true),
+            )
+            .arg(
+                Arg::with_name("popularity")
+                    .long("popularity")
+                    .value_name("FILE")
+                    .help("Write the number of requests for each key to a CSV file")
+                    .takes_value(true),
+            )
+            .arg(
+                Arg::with_name("exemplars")
+                    .long("exemplars")
+                    .value_name("N")
+                    .help("Log the N slowest requests of each window")
+                    .takes_value(true),
+            )
+            .arg(
+                Arg::with_name("trace")
+                    .long("trace")
+                    .help("Write a trace id at the start of the value of each set"),
+            )
+            .arg(
+                Arg::with_name("log-slow")
+                    .long("log-slow")
+                    .value_name("LATENCY")
+                    .help("Log each request slower than this, such as 50ms")
+                    .takes_value(true),
+            )
+            .arg(
+                Arg::with_name("run-id")
+                    .long("run-id")
+                    .value_name("ID")
+                    .help("Identify the run in its outputs, which are named after it")
+                    .takes_value(true),
+            )
+            .arg(
+                Arg::with_name("label")
+                    .long("label")
+                    .value_name("KEY=VALUE")
+                    .help("Label the run in its outputs")
+                    .takes_value(true)
+                    .multiple(true)
+                    .number_of_values(1),
+            )
+            .arg(
+                Arg::with_name("preset")
+                    .long("preset")
+                    .value_name("NAME")
+                    .help("Use a builtin workload as the base of the config")
+                    .possible_values(&preset_names)
+                    .takes_value(true),
+            )
+            .arg(
+                Arg::with_name("ycsb")
+                    .long("ycsb")
+                    .value_name("WORKLOAD")
+                    .help("Run a YCSB core workload, from a to d, or a YCSB workload file")
+                    .takes_value(true),
+            )
+            .arg(
+                Arg::with_name("set")
+                    .long("set")
+                    .value_name("KEY=VALUE")
+                    .help("Override a key in the config, eg: general.clients=4")
+                    .takes_value(true)
+                    .multiple(true)
+                    .number_of_values(1),
+            )
+            .arg(
+                Arg::with_name("shard")
+                    .long("shard")
+                    .value_name("i/N")
+                    .help("Run as instance i of N processes, each with its share of the keyspace, endpoints and request rate")
+                    .takes_value(true),
+            )
+            .arg(
+                Arg::with_name("keyfile")
+                    .long("keyfile")
+                    .value_name("FILE")
+                    .help("Preload the keys in the file and read only those keys")
+                    .takes_value(true),
+            )
+            .arg(
+                Arg::with_name("preload-timeout")
+                    .long("preload-timeout")
+                    .value_name("Duration")
+                    .help("Abort if the keys haven't been preloaded in this time")
+                    .takes_value(true),
+            )
             .arg(
                 Arg::with_name("tls-key")
                     .long("tls-key")
@@ -591,16 +1085,87 @@ impl Config {
                     .value_name("File")
                     .help("Certificate Authority for TLS authentication")
                     .takes_value(true),
+            )
+            .subcommand(
+                SubCommand::with_name("dashboard")
+                    .about("Print a Grafana dashboard for the stats of this config"),
+            )
+            .subcommand(
+                SubCommand::with_name("generate-config")
+                    .about("Print an example config for a protocol")
+                    .arg(
+                        Arg::with_name("protocol")
+                            .long("protocol")
+                            .value_name("PROTOCOL")
+                            .help("The protocol, eg: memcache or redis_resp")
+                            .takes_value(true)
+                            .default_value("memcache"),
+                    ),
             );
 
         let matches = app.get_matches();
+        let dashboard = matches.subcommand_matches("dashboard").is_some();
+
+        if let Some(generate) = matches.subcommand_matches("generate-config") {
+            let name = generate.value_of("protocol").unwrap();
+            let protocol = match name {
+                "pelikan-rds" => Protocol::PelikanRds,
+                "redis" => Protocol::RedisResp,
+                "redis-inline" => Protocol::RedisInline,
+                "redis-auto" => Protocol::RedisAuto,
+                "thrift-cache" => Protocol::ThriftCache,
+                _ => Protocol::from(name.to_string()),
+            };
+            if let Protocol::Custom(_) = protocol {
+                println!("ERROR: unknown protocol: {}", name);
+                std::process::exit(1);
+            }
+            print!("{}", example::example(protocol));
+            std::process::exit(0);
+        }
+
+        let overrides: Vec<String> = matches
+            .values_of("set")
+            .map(|v| v.map(|v| v.to_string()).collect())
+            .unwrap_or_default();
+
+        let preset = matches.value_of("preset");
 
         let mut config = if let Some(file) = matches.value_of("config") {
-            Config::load_from_file(file)
+            Config::load_from_file(file, preset, &overrides)
         } else {
-            println!("NOTE: using builtin base configuration");
-            Default::default()
+            // the dashboard is written to stdout
+            if !dashboard && preset.is_none() {
+                println!("NOTE: using builtin base configuration");
+            }
+            if overrides.is_empty() && preset.is_none() {
+                Default::default()
+            } else {
+                // a preset replaces the builtin workload
+                let content = match preset {
+                    Some(_) => String::new(),
+                    None => Config::default().to_toml(),
+                };
+                let mut config = Config::load(&content, preset, &overrides, "builtin");
+                // there is no config file to pass on to agents
+                config.source = None;
+                config
+            }
         };
+        config.dashboard = dashboard;
+        config.overrides = overrides;
+
+        if let Some(ycsb) = matches.value_of("ycsb") {
+            let ycsb = Ycsb::from_arg(ycsb, config.ycsb.take()).unwrap_or_else(|e| {
+                println!("ERROR: invalid ycsb workload: {}", e);
+                std::process::exit(1);
+            });
+            config.ycsb = Some(ycsb);
+            if let Err(e) = config.expand_ycsb() {
+                println!("ERROR: {}", e);
+                std::process::exit(1);
+            }
+        }
 
         if let Some(listen) = matches.value_of("listen") {
             let _ = listen.parse::<SocketAddr>().unwrap_or_else(|_| {
@@ -618,33 +1183,88 @@ impl Config {
             config.general.set_admin(Some(admin.to_string()));
         }
 
+        if let Some(start_at) = parse_numeric_arg(&matches, "start-at") {
+            config.general.set_start_at(Some(start_at as u64));
+        }
+
+        if let Some(grace_period) = parse_duration_arg(&matches, "grace-period") {
+            config.general.set_grace_period(grace_period);
+        }
+
         if let Some(clients) = parse_numeric_arg(&matches, "clients") {
             config.general.set_clients(clients);
         }
 
-        if let Some(interval) = parse_numeric_arg(&matches, "interval") {
+        if let Some(interval) = parse_duration_arg(&matches, "interval") {
             config.general.set_interval(interval);
         }
 
         if let Some(windows) = parse_numeric_arg(&matches, "windows") {
             config.general.set_windows(Some(windows));
+            config.general.set_duration(None);
+        }
+
+        if let Some(duration) = parse_duration_arg(&matches, "duration") {
+            config.general.set_duration(Some(duration));
+        }
+
+        // the run ends once the operations are complete
+        if config.general.duration().is_none()
+            && config.operations().is_some()
+            && !matches.is_present("windows")
+        {
+            config.general.set_windows(None);
         }
 
         if let Some(poolsize) = parse_numeric_arg(&matches, "poolsize") {
             config.general.set_poolsize(poolsize);
         }
 
-        if let Some(request_ratelimit) = parse_numeric_arg(&matches, "request-ratelimit") {
+        if let Some(pipeline) = parse_numeric_arg(&matches, "pipeline") {
+            config.general.set_pipeline(pipeline);
+        }
+
+        if let Some(request_ratelimit) = parse_count_arg(&matches, "request-ratelimit") {
             config
                 .general
                 .set_request_ratelimit(Some(request_ratelimit));
         }
 
+        if let Some(request_batch) = parse_numeric_arg(&matches, "request-batch") {
+            config.general.set_request_batch(request_batch);
+        }
+
+        if let Some(request_burst) = parse_count_arg(&matches, "request-burst") {
+            config.general.set_request_burst(Some(request_burst));
+        }
+
+        if let Some(partition) = matches.value_of("request-partition") {
+            config.general.set_request_partition(match partition {
+                "shared" => Partition::Shared,
+                "equal" => Partition::Equal,
+                "weighted" => Partition::Weighted,
+                // the possible values are checked by the argument parser
+                _ => unreachable!(),
+            });
+        }
+
+        if let Some(weights) = matches.values_of("request-weights") {
+            let weights = weights
+                .map(|weight| {
+                    weight.trim().parse().unwrap_or_else(|_| {
+                        println!("ERROR: could not parse request-weights: {}", weight);
+                        std::process::exit(1);
+                    })
+                })
+                .collect();
+            config.general.set_request_weights(Some(weights));
+        }
+
         if let Some(request_timeout) = parse_numeric_arg(&matches, "request-timeout") {
             config.general.set_request_timeout(request_timeout);
         }
 
-        if let Some(connect_ratelimit) = parse_numeric_arg(&matches, "connect-ratelimit") {
+        if let Some(connect_ratelimit) = parse_count_arg(&matches, "connect-ratelimit") {
             config
                 .general
                 .set_connect_ratelimit(Some(connect_ratelimit));
@@ -654,14 +1274,40 @@ impl Config {
             config.general.set_connect_timeout(connect_timeout);
         }
 
-        if let Some(close_rate) = parse_numeric_arg(&matches, "close-rate") {
+        if let Some(close_rate) = parse_count_arg(&matches, "close-rate") {
             config.general.set_close_rate(Some(close_rate));
         }
 
+        if let Some(percent) = parse_float_arg(&matches, "pushback-backoff") {
+            config.general.set_pushback_backoff(Some(percent));
+        }
+        if let Some(percent) = config.general.pushback_backoff() {
+            if !(0.0..100.0).contains(&percent) {
+                println!("ERROR: pushback-backoff must be at least 0 and less than 100");
+                std::process::exit(1);
+            }
+            if config.request_ratelimit().is_none() {
+                println!("ERROR: pushback-backoff requires a request ratelimit");
+                std::process::exit(1);
+            }
+        }
+
         if matches.is_present("soft-timeout") {
             config.general.set_soft_timeout(true);
         }
 
+        if matches.is_present("numa") {
+            config.general.set_numa(true);
+        }
+
+        if matches.is_present("numa-bind-endpoints") {
+            config.general.set_numa_bind_endpoints(true);
+        }
+
+        if matches.is_present("raise-fd-limit") {
+            config.general.set_raise_fd_limit(true);
+        }
+
         if let Some(warmup_hitrate) = parse_float_arg(&matches, "warmup-hitrate") {
             if warmup_hitrate > 1.0 {
                 println!("ERROR: warmup-hitrate is greater than 1.0");
@@ -674,6 +1320,60 @@ impl Config {
             config.general.set_warmup_hitrate(Some(warmup_hitrate));
         }
 
+        if let Some(warmup_timeout) = parse_duration_arg(&matches, "warmup-timeout") {
+            config.general.set_warmup_timeout(Some(warmup_timeout));
+        }
+
+        if let Some(clients) = parse_numeric_arg(&matches, "warmup-clients") {
+            config
+                .warmup
+                .get_or_insert_with(Default::default)
+                .set_clients(Some(clients));
+        }
+
+        if let Some(limit) = parse_count_arg(&matches, "warmup-ratelimit") {
+            config
+                .warmup
+                .get_or_insert_with(Default::default)
+                .set_request_ratelimit(Some(limit));
+        }
+
+        if let Some(algorithm) = matches.value_of("compression") {
+            let algorithm = Algorithm::parse(algorithm).unwrap_or_else(|e| {
+                println!("ERROR: {}", e);
+                std::process::exit(1);
+            });
+            let level = config.compression.as_ref().and_then(|c| c.level());
+            let mut compression = Compression::new(algorithm);
+            compression.set_level(level);
+            config.compression = Some(compression);
+        }
+
+        if let Some(level) = parse_numeric_arg(&matches, "compression-level") {
+            match config.compression {
+                Some(ref mut compression) => compression.set_level(Some(level as i32)),
+                None => {
+                    println!("ERROR: compression-level requires a compression algorithm");
+                    std::process::exit(1);
+                }
+            }
+        }
+
+        if config.request_batch() == 0 {
+            println!("ERROR: request-batch must be at least 1");
+            std::process::exit(1);
+        }
+
+        if let Err(e) = config.validate_partition() {
+            println!("ERROR: {}", e);
+            std::process::exit(1);
+        }
+
+        if config.pipeline() == 0 {
+            println!("ERROR: pipeline must be at least 1");
+            std::process::exit(1);
+        }
+
         if matches.is_present("endpoint") {
             let mut endpoints = Vec::new();
 
@@ -692,23 +1392,60 @@ impl Config {
             config.general.set_endpoints(Some(endpoints));
         }
 
-        config
-            .general
-            .set_logging(match matches.occurrences_of("verbose") {
-                0 => Level::Info,
-                1 => Level::Debug,
-                _ => Level::Trace,
-            });
-
-        if matches.is_present("service") {
-            config.general.set_windows(None);
-        }
-
-        if matches.is_present("tcp-nodelay") {
-            config.general.set_tcp_nodelay(true);
-        }
+        if matches.is_present("compare") {
+            let mut endpoints = Vec::new();
 
-        if let Some(protocol) = matches.value_of("protocol") {
+            for endpoint in matches.values_of("compare").unwrap() {
+                let mut addrs = endpoint.to_socket_addrs().unwrap_or_else(|_| {
+                    println!("ERROR: compare address is malformed: {}", endpoint);
+                    std::process::exit(1);
+                });
+                addrs.next().unwrap_or_else(|| {
+                    println!("ERROR: failed to resolve address: {}", endpoint);
+                    std::process::exit(1);
+                });
+                endpoints.push(endpoint.to_string());
+            }
+
+            config.general.set_compare(Some(endpoints));
+        }
+
+        if matches.is_present("shadow") {
+            let mut endpoints = Vec::new();
+
+            for endpoint in matches.values_of("shadow").unwrap() {
+                let mut addrs = endpoint.to_socket_addrs().unwrap_or_else(|_| {
+                    println!("ERROR: shadow address is malformed: {}", endpoint);
+                    std::process::exit(1);
+                });
+                addrs.next().unwrap_or_else(|| {
+                    println!("ERROR: failed to resolve address: {}", endpoint);
+                    std::process::exit(1);
+                });
+                endpoints.push(endpoint.to_string());
+            }
+
+            config.general.set_shadow(Some(endpoints));
+        }
+
+        config
+            .general
+            .set_logging(match matches.occurrences_of("verbose") {
+                0 => Level::Info,
+                1 => Level::Debug,
+                _ => Level::Trace,
+            });
+
+        if matches.is_present("service") {
+            config.general.set_windows(None);
+            config.general.set_duration(None);
+        }
+
+        if matches.is_present("tcp-nodelay") {
+            config.general.set_tcp_nodelay(true);
+        }
+
+        if let Some(protocol) = matches.value_of("protocol") {
             config.general.set_protocol(match protocol {
                 "echo" => Protocol::Echo,
                 "memcache" => Protocol::Memcache,
@@ -716,6 +1453,7 @@ impl Config {
                 "ping" => Protocol::Ping,
                 "redis" => Protocol::RedisResp,
                 "redis-inline" => Protocol::RedisInline,
+                "redis-auto" => Protocol::RedisAuto,
                 "thrift-cache" => Protocol::ThriftCache,
                 _ => {
                     fatal!("unknown protocol: {}", protocol);
@@ -737,9 +1475,643 @@ impl Config {
             config.general.set_waterfall(Some(waterfall.to_string()));
         }
 
+        if let Some(bundle) = matches.value_of("bundle") {
+            config.general.set_bundle(Some(bundle.to_string()));
+        }
+
+        if let Some(summary) = matches.value_of("summary") {
+            config.general.set_summary(Some(summary.to_string()));
+        }
+
+        if let Some(windows) = parse_numeric_arg(&matches, "sparklines") {
+            config.general.set_sparklines(Some(windows));
+        }
+
+        if let Some(popularity) = matches.value_of("popularity") {
+            config.general.set_popularity(Some(popularity.to_string()));
+        }
+
+        if let Some(exemplars) = parse_numeric_arg(&matches, "exemplars") {
+            config.general.set_exemplars(Some(exemplars));
+        }
+
+        if matches.is_present("trace") {
+            config.general.set_trace(true);
+        }
+
+        if let Some(log_slow) = parse_latency_arg(&matches, "log-slow") {
+            config.general.set_log_slow(Some(log_slow));
+        }
+
+        if config.general.trace() && config.exemplars().is_none() && config.log_slow().is_none() {
+            println!("ERROR: trace requires exemplars or log_slow");
+            std::process::exit(1);
+        }
+
+        if let Some(run_id) = matches.value_of("run-id") {
+            config.general.set_run_id(Some(run_id.to_string()));
+        }
+
+        if let Some(values) = matches.values_of("label") {
+            let mut labels = config.general.labels().unwrap_or_default();
+            for label in values {
+                let mut parts = label.splitn(2, '=');
+                match (parts.next(), parts.next()) {
+                    (Some(key), Some(value)) if !key.is_empty() => {
+                        labels.insert(key.to_string(), value.to_string());
+                    }
+                    _ => {
+                        println!("ERROR: label is not KEY=VALUE: {}", label);
+                        std::process::exit(1);
+                    }
+                }
+            }
+            config.general.set_labels(Some(labels));
+        }
+
+        // outputs are named after the run
+        if let Some(run_id) = config.general.run_id() {
+            if let Some(waterfall) = config.general.waterfall() {
+                let waterfall = Metadata::stamp(&waterfall, Some(&run_id));
+                config.general.set_waterfall(Some(waterfall));
+            }
+            if let Some(bundle) = config.general.bundle() {
+                let bundle = Metadata::stamp(&bundle, Some(&run_id));
+                config.general.set_bundle(Some(bundle));
+            }
+            if let Some(summary) = config.general.summary() {
+                let summary = Metadata::stamp(&summary, Some(&run_id));
+                config.general.set_summary(Some(summary));
+            }
+            if let Some(popularity) = config.general.popularity() {
+                let popularity = Metadata::stamp(&popularity, Some(&run_id));
+                config.general.set_popularity(Some(popularity));
+            }
+        }
+
+        if let Some(keyfile) = matches.value_of("keyfile") {
+            config.general.set_keyfile(Some(keyfile.to_string()));
+        }
+        if let Some(preload_timeout) = parse_duration_arg(&matches, "preload-timeout") {
+            config.general.set_preload_timeout(preload_timeout);
+        }
+        if let Err(e) = config.load_keyfile() {
+            println!("ERROR: failed to load keyfile: {}", e);
+            std::process::exit(1);
+        }
+
+        if matches.is_present("agent") {
+            config.general.set_agent(true);
+        }
+
+        if matches.is_present("agents") {
+            let agents = matches
+                .values_of("agents")
+                .unwrap()
+                .map(|v| v.to_string())
+                .collect();
+            config.general.set_agents(Some(agents));
+        }
+
+        if let Some(discovery) = matches.value_of("discover") {
+            let mut discovery = Discovery::parse(discovery).unwrap_or_else(|e| {
+                println!("ERROR: {}", e);
+                std::process::exit(1);
+            });
+            discovery.set_refresh(config.discovery.as_ref().and_then(|d| d.refresh()));
+            config.discovery = Some(discovery);
+        }
+
+        if let Some(refresh) = parse_duration_arg(&matches, "discover-refresh") {
+            match config.discovery {
+                Some(ref mut discovery) => discovery.set_refresh(Some(refresh)),
+                None => {
+                    println!("ERROR: discover-refresh requires a discovery source");
+                    std::process::exit(1);
+                }
+            }
+        }
+
+        if let Err(e) = config.resolve() {
+            println!("ERROR: {}", e);
+            std::process::exit(1);
+        }
+
+        if let Some(shard) = matches.value_of("shard") {
+            let shard = Shard::parse(shard).unwrap_or_else(|e| {
+                println!("ERROR: invalid shard: {}", e);
+                std::process::exit(1);
+            });
+            if !config.groups.is_empty() {
+                println!("ERROR: a config with groups cannot be sharded");
+                std::process::exit(1);
+            }
+            if let Err(e) = config.set_shard(shard) {
+                println!("ERROR: cannot shard the config: {}", e);
+                std::process::exit(1);
+            }
+        }
+
+        if let Some(agents) = config.general.agents() {
+            if config.shard.is_some() {
+                println!("ERROR: agents cannot be coordinated by a shard");
+                std::process::exit(1);
+            }
+            if config.pushback_backoff().is_some() {
+                println!("ERROR: pushback-backoff cannot be used with agents");
+                std::process::exit(1);
+            }
+            if config.agent() {
+                println!("ERROR: an agent cannot coordinate other agents");
+                std::process::exit(1);
+            }
+            if config.compare() {
+                println!("ERROR: agents cannot be used with compare endpoints");
+                std::process::exit(1);
+            }
+            if config.discovery_refresh().is_some() {
+                println!("ERROR: discover-refresh cannot be used with agents");
+                std::process::exit(1);
+            }
+            if !config.groups.is_empty() {
+                println!("ERROR: groups cannot be used with agents");
+                std::process::exit(1);
+            }
+            if config.source.is_none() {
+                println!("ERROR: coordinating agents requires a config file");
+                std::process::exit(1);
+            }
+            for agent in agents {
+                if agent
+                    .to_socket_addrs()
+                    .ok()
+                    .and_then(|mut a| a.next())
+                    .is_none()
+                {
+                    println!("ERROR: agent address is malformed: {}", agent);
+                    std::process::exit(1);
+                }
+            }
+        }
+
         config
     }
 
+    /// parse a config from the contents of a TOML file
+    pub fn from_toml(content: &str) -> Result<Config, toml::de::Error> {
+        Config::from_toml_with_codecs(content, &Registry::default())
+    }
+
+    /// parse a config whose protocols may be those of codecs which were added
+    /// to the registry, see `Runner::set_codecs`
+    pub fn from_toml_with_codecs(
+        content: &str,
+        codecs: &Registry,
+    ) -> Result<Config, toml::de::Error> {
+        let mut config = Config::parse(content, codecs)?;
+        config.resolve().map_err(de::Error::custom)?;
+        Ok(config)
+    }
+
+    /// parse a config without resolving it, as the command line may still
+    /// change it
+    fn parse(content: &str, codecs: &Registry) -> Result<Config, toml::de::Error> {
+        let mut config: Config = toml::from_str(content)?;
+        config.expand_ycsb().map_err(de::Error::custom)?;
+        if config.keyspace.is_empty() {
+            return Err(de::Error::custom("at least one keyspace is required"));
+        }
+        let protocols = std::iter::once(config.protocol())
+            .chain(config.groups.iter().filter_map(|group| group.protocol()));
+        for protocol in protocols {
+            if codecs.get(&protocol).is_none() {
+                return Err(de::Error::custom(format!(
+                    "unknown protocol: {}",
+                    protocol.name()
+                )));
+            }
+        }
+        config.source = Some(content.to_string());
+        Ok(config)
+    }
+
+    /// check the settings which the config was given, wherever they came
+    /// from, and derive the ones which depend on them
+    fn resolve(&mut self) -> Result<(), String> {
+        if self.interval() == 0 {
+            return Err("interval must be at least 1 second".to_string());
+        }
+
+        // the run is cut into whole windows, rounding up
+        if let Some(duration) = self.general.duration() {
+            let windows = duration.div_ceil(self.interval());
+            self.general.set_windows(Some(windows.max(1)));
+        }
+
+        for fault in &self.chaos {
+            fault
+                .validate()
+                .map_err(|e| format!("chaos fault is invalid: {}", e))?;
+        }
+
+        if let Some(ref thresholds) = self.thresholds {
+            thresholds
+                .validate()
+                .map_err(|e| format!("thresholds are invalid: {}", e))?;
+        }
+
+        if let (Some(agents), Some(rate)) = (self.general.agents(), self.request_ratelimit()) {
+            // each agent needs a ratelimit of its own to send at all
+            if rate < agents.len() {
+                return Err(format!(
+                    "request_ratelimit of {} is less than one request per second for each of {} agents",
+                    rate,
+                    agents.len()
+                ));
+            }
+        }
+
+        self.validate_burst()?;
+
+        if let Some(ref warmup) = self.warmup {
+            warmup
+                .validate()
+                .map_err(|e| format!("warmup is invalid: {}", e))?;
+        }
+
+        if let Some(ref compression) = self.compression {
+            compression
+                .validate()
+                .map_err(|e| format!("compression is invalid: {}", e))?;
+            if self.protocol() != Protocol::Memcache {
+                return Err("compression is only supported by the memcache protocol".to_string());
+            }
+        }
+
+        for (index, group) in self.groups.iter().enumerate() {
+            group
+                .validate()
+                .map_err(|e| format!("group {} is invalid: {}", group.name(), e))?;
+            if self.groups[..index]
+                .iter()
+                .any(|other| other.name() == group.name())
+            {
+                return Err(format!("group name is repeated: {}", group.name()));
+            }
+            // the commands of this config's workload may not be supported by
+            // another protocol
+            if group.keyspace().is_empty() && group.protocol().is_some_and(|p| p != self.protocol())
+            {
+                return Err(format!(
+                    "group {} is invalid: a protocol of its own needs keyspaces of its own",
+                    group.name()
+                ));
+            }
+        }
+
+        if !self.groups.is_empty() && self.compare() {
+            return Err("groups cannot be used with compare endpoints".to_string());
+        }
+
+        if let Some(ref discovery) = self.discovery {
+            discovery
+                .validate()
+                .map_err(|e| format!("discovery is invalid: {}", e))?;
+            if self.general.endpoints().is_some() {
+                return Err("endpoints cannot be listed when they are discovered".to_string());
+            }
+            // the endpoints are discovered before the config is sharded, so
+            // that each shard takes its part of them
+            let endpoints = crate::discovery::discover(discovery)
+                .map_err(|e| format!("endpoint discovery failed: {}", e))?;
+            self.general
+                .set_endpoints(Some(endpoints.iter().map(|e| e.to_string()).collect()));
+        }
+
+        Ok(())
+    }
+
+    /// take this shard's part of the endpoints and the request ratelimits,
+    /// the keys are split when the generators are created
+    fn set_shard(&mut self, shard: Shard) -> Result<(), String> {
+        for keyspace in &self.keyspace {
+            if keyspace.count() < shard.count() {
+                return Err("a keyspace has fewer keys than there are shards".to_string());
+            }
+        }
+        if let Some(ref keys) = self.keys {
+            if keys.len() < shard.count() {
+                return Err("the keyfile has fewer keys than there are shards".to_string());
+            }
+        }
+        if let Some(endpoints) = self.general.endpoints() {
+            self.general
+                .set_endpoints(Some(shard.endpoints(&endpoints)));
+        }
+        if let Some(compare) = self.general.compare() {
+            self.general.set_compare(Some(shard.endpoints(&compare)));
+        }
+        if let Some(limit) = self.general.request_ratelimit() {
+            self.general.set_request_ratelimit(Some(shard.rate(limit)));
+        }
+        self.validate_partition()?;
+        if let Some(burst) = self.general.request_burst() {
+            self.general.set_request_burst(Some(shard.rate(burst)));
+            self.validate_burst()?;
+        }
+        if let Some(ref mut warmup) = self.warmup {
+            if let Some(limit) = warmup.request_ratelimit() {
+                warmup.set_request_ratelimit(Some(shard.rate(limit)));
+            }
+        }
+        self.shard = Some(shard);
+        Ok(())
+    }
+
+    /// replace the keyspaces with the one for the YCSB workload, if there is
+    /// one
+    fn expand_ycsb(&mut self) -> Result<(), String> {
+        if let Some(ref ycsb) = self.ycsb {
+            self.keyspace = vec![ycsb.keyspace()?];
+        }
+        Ok(())
+    }
+
+    /// the number of the slowest requests of each window to log
+    pub fn exemplars(&self) -> Option<usize> {
+        self.general.exemplars().filter(|n| *n > 0)
+    }
+
+    /// whether sets carry a trace id in their value
+    pub fn trace(&self) -> bool {
+        self.general.trace()
+    }
+
+    /// the latency in microseconds above which each request is logged
+    pub fn log_slow(&self) -> Option<usize> {
+        self.general.log_slow().filter(|us| *us > 0)
+    }
+
+    /// identifies the run in its outputs
+    pub fn run_id(&self) -> Option<String> {
+        self.general.run_id()
+    }
+
+    /// labels for the run in its outputs
+    pub fn labels(&self) -> BTreeMap<String, String> {
+        self.general.labels().unwrap_or_default()
+    }
+
+    /// a hash of the settings, leaving out the ones which identify the run
+    /// and name its outputs, so that runs of the same config have the same
+    /// hash
+    pub fn hash(&self) -> u64 {
+        let mut config = self.clone();
+        config.general.set_run_id(None);
+        config.general.set_labels(None);
+        config.general.set_waterfall(None);
+        config.general.set_bundle(None);
+        config.general.set_summary(None);
+        config.general.set_popularity(None);
+        crc::crc64::checksum_ecma(config.to_toml().as_bytes())
+    }
+
+    /// the number of operations after which the run ends, if it is limited
+    pub fn operations(&self) -> Option<usize> {
+        self.ycsb.as_ref().and_then(|ycsb| ycsb.operationcount())
+    }
+
+    /// render the config as TOML, including the settings from the command
+    /// line, so that it can be loaded to repeat the run
+    pub fn to_toml(&self) -> String {
+        toml::to_string(self).expect("failed to render config")
+    }
+
+    /// whether this instance waits to be configured and started by a
+    /// coordinator
+    pub fn agent(&self) -> bool {
+        self.general.agent()
+    }
+
+    /// the admin addresses of the agents this instance coordinates
+    pub fn agents(&self) -> Vec<SocketAddr> {
+        self.general
+            .agents()
+            .unwrap_or_default()
+            .iter()
+            .map(|v| v.to_socket_addrs().unwrap().next().unwrap())
+            .collect()
+    }
+
+    /// the agent's share of the request ratelimit, the remainder is spread
+    /// across the first agents so the shares add up to the rate
+    pub fn agent_ratelimit(&self, index: usize) -> Option<usize> {
+        let agents = self.general.agents()?.len().max(1);
+        let rate = self.request_ratelimit()?;
+        Some(rate / agents + if index < rate % agents { 1 } else { 0 })
+    }
+
+    /// an agent's part of the request burst, which is shared in proportion
+    /// to the rate
+    fn agent_burst(&self, request_ratelimit: usize) -> Option<usize> {
+        let burst = self.general.request_burst()?;
+        let total = self.request_ratelimit()?;
+        let burst = burst as u128 * request_ratelimit as u128 / total.max(1) as u128;
+        Some((burst as usize).max(1))
+    }
+
+    /// render the config for an agent, with its share of the request
+    /// ratelimit. Settings which belong to the agent's host are removed.
+    pub fn agent_config(&self, request_ratelimit: Option<usize>) -> String {
+        let source = self.source.as_deref().unwrap_or("");
+        let mut toml: toml::Value =
+            toml::from_str(source).unwrap_or_else(|_| toml::Value::Table(Default::default()));
+        let root = toml.as_table_mut().expect("config is not a table");
+        let general = root
+            .entry("general".to_string())
+            .or_insert_with(|| toml::Value::Table(Default::default()))
+            .as_table_mut()
+            .expect("general section is not a table");
+        // warmup isn't coordinated, so agents start measuring right away
+        for key in &[
+            "admin",
+            "listen",
+            "agents",
+            "waterfall",
+            "bundle",
+            "summary",
+            "popularity",
+            "windows",
+            "duration",
+            "warmup_hitrate",
+            "warmup_timeout",
+            "start_at",
+        ] {
+            general.remove(*key);
+        }
+        if let Some(endpoints) = self.general.endpoints() {
+            general.insert(
+                "endpoints".to_string(),
+                toml::Value::Array(endpoints.into_iter().map(toml::Value::String).collect()),
+            );
+        }
+        general.insert(
+            "interval".to_string(),
+            toml::Value::Integer(self.interval() as i64),
+        );
+        match request_ratelimit {
+            Some(rate) => {
+                general.insert(
+                    "request_ratelimit".to_string(),
+                    toml::Value::Integer(rate as i64),
+                );
+            }
+            None => {
+                general.remove("request_ratelimit");
+            }
+        }
+        if let Some(burst) = request_ratelimit.and_then(|rate| self.agent_burst(rate)) {
+            general.insert(
+                "request_burst".to_string(),
+                toml::Value::Integer(burst as i64),
+            );
+        }
+        // the agents are sent the endpoints the coordinator discovered
+        root.remove("discovery");
+        // the coordinator checks the thresholds against the merged metrics
+        root.remove("thresholds");
+        root.remove("warmup");
+        // the coordinator ends the run, so agents don't count operations
+        if let Some(ref ycsb) = self.ycsb {
+            let mut ycsb = ycsb.clone();
+            ycsb.set_operationcount(None);
+            root.insert(
+                "ycsb".to_string(),
+                toml::Value::try_from(ycsb).expect("failed to render ycsb"),
+            );
+        }
+        toml.to_string()
+    }
+
+    /// apply the settings which belong to this agent's host to a config
+    /// received from the coordinator. Agents run until they are stopped by
+    /// the coordinator.
+    pub fn for_agent(mut self, local: &Config) -> Config {
+        self.general.set_admin(local.general.admin());
+        self.general.set_listen(local.general.listen());
+        self.general.set_logging(local.general.logging());
+        self.general.set_waterfall(local.general.waterfall());
+        self.general.set_bundle(local.general.bundle());
+        self.general.set_summary(local.general.summary());
+        self.general.set_popularity(local.general.popularity());
+        self.general.set_windows(None);
+        self.general.set_agent(true);
+        // the agent reads the keyfile from the same path on its own host
+        if let Err(e) = self.load_keyfile() {
+            fatal!("failed to load keyfile: {}", e);
+        }
+        self
+    }
+
+    /// whether a second group of endpoints is driven with the same workload
+    pub fn compare(&self) -> bool {
+        self.general.compare().is_some()
+    }
+
+    /// the shadow endpoints which receive a copy of each request
+    pub fn shadow(&self) -> Vec<SocketAddr> {
+        self.general
+            .shadow()
+            .unwrap_or_default()
+            .iter()
+            .map(|v| v.to_socket_addrs().unwrap().next().unwrap())
+            .collect()
+    }
+
+    /// the config for the second group in a comparison, which sends the same
+    /// workload to the compare endpoints. Outputs which belong to the process
+    /// are left to the first group.
+    pub fn for_compare(&self) -> Config {
+        let mut config = self.clone();
+        config.general.set_endpoints(self.general.compare());
+        config.general.set_compare(None);
+        config.discovery = None;
+        config.groups.clear();
+        config.general.set_shadow(None);
+        config.general.set_listen(None);
+        config.general.set_admin(None);
+        config.general.set_waterfall(None);
+        config.general.set_bundle(None);
+        config.general.set_summary(None);
+        config.general.set_popularity(None);
+        config
+    }
+
+    /// the client groups which run alongside the clients of the config
+    pub fn groups(&self) -> &[Group] {
+        &self.groups
+    }
+
+    /// the config for a client group, which has the group's endpoints and
+    /// request ratelimit and otherwise the settings of this config, except
+    /// where the group overrides them. Outputs which belong to the process
+    /// are left to the clients of this config.
+    pub fn for_group(&self, group: &Group) -> Config {
+        let mut config = self.clone();
+        config.groups.clear();
+        config.discovery = None;
+        config.chaos.clear();
+        config
+            .general
+            .set_endpoints(Some(group.endpoints().to_vec()));
+        if let Some(protocol) = group.protocol() {
+            config.general.set_protocol(protocol);
+        }
+        if let Some(clients) = group.clients() {
+            config.general.set_clients(clients);
+        }
+        if let Some(poolsize) = group.poolsize() {
+            config.general.set_poolsize(poolsize);
+        }
+        config
+            .general
+            .set_request_ratelimit(group.request_ratelimit());
+        config.general.set_request_burst(None);
+        config.general.set_request_partition(Partition::Shared);
+        config.general.set_request_weights(None);
+        config.general.set_pushback_backoff(None);
+        if config.protocol() != Protocol::Memcache {
+            config.compression = None;
+        }
+        // a group with a workload of its own doesn't use the keys or the
+        // YCSB workload of this config
+        if !group.keyspace().is_empty() {
+            config.keyspace = group.keyspace().to_vec();
+            config.ycsb = None;
+            config.keys = None;
+            config.general.set_keyfile(None);
+        }
+        if let Some(thresholds) = group.thresholds() {
+            config.thresholds = Some(thresholds);
+        }
+        config.general.set_compare(None);
+        config.general.set_shadow(None);
+        config.general.set_listen(None);
+        config.general.set_admin(None);
+        config.general.set_waterfall(None);
+        config.general.set_bundle(None);
+        config.general.set_summary(None);
+        config.general.set_popularity(None);
+        config
+    }
+
+    /// the wall-clock time at which the run should start
+    pub fn start_at(&self) -> Option<SystemTime> {
+        self.general
+            .start_at()
+            .map(|secs| UNIX_EPOCH + Duration::from_secs(secs))
+    }
+
     /// the duration of each integration window in seconds
     pub fn interval(&self) -> usize {
         self.general.interval()
@@ -760,6 +2132,11 @@ impl Config {
         self.general.poolsize()
     }
 
+    /// the number of requests which may be in-flight on each connection
+    pub fn pipeline(&self) -> usize {
+        self.general.pipeline()
+    }
+
     /// get listen address
     pub fn listen(&self) -> Option<SocketAddr> {
         self.general
@@ -800,10 +2177,128 @@ impl Config {
         self.general.request_distribution()
     }
 
+    /// the number of request tokens taken from the ratelimiter at once
+    pub fn request_batch(&self) -> usize {
+        self.general.request_batch()
+    }
+
+    /// how the request ratelimit is divided between the clients
+    pub fn request_partition(&self) -> Partition {
+        self.general.request_partition()
+    }
+
+    /// the weight of each client's share of the request ratelimit, which are
+    /// equal unless the partition is weighted. Shared clients have no weights.
+    pub fn request_weights(&self) -> Option<Vec<usize>> {
+        match self.request_partition() {
+            Partition::Shared => None,
+            Partition::Equal => Some(vec![1; self.clients()]),
+            Partition::Weighted => self.general.request_weights(),
+        }
+    }
+
+    fn validate_partition(&self) -> Result<(), String> {
+        let partition = self.request_partition();
+        let weights = self.general.request_weights();
+        if partition != Partition::Shared && self.request_ratelimit().is_none() {
+            return Err("request-partition requires a request ratelimit".to_string());
+        }
+        match (partition, weights) {
+            (Partition::Weighted, None) => {
+                return Err("a weighted request-partition requires request-weights".to_string());
+            }
+            (Partition::Weighted, Some(weights)) => {
+                if weights.len() != self.clients() {
+                    return Err(format!(
+                        "request-weights has {} weights for {} clients",
+                        weights.len(),
+                        self.clients()
+                    ));
+                } else if weights.contains(&0) {
+                    return Err("request-weights must be at least 1".to_string());
+                }
+            }
+            (_, Some(_)) => {
+                return Err("request-weights requires a weighted request-partition".to_string());
+            }
+            (_, None) => {}
+        }
+        // each client's share of the rate, on each agent, must be at least
+        // one request per second
+        if let (Some(weights), Some(rate)) = (self.request_weights(), self.request_ratelimit()) {
+            let agents = self.general.agents().map(|a| a.len()).unwrap_or(1).max(1);
+            if rate / agents < weights.iter().sum() {
+                return Err(format!(
+                    "request ratelimit of {} leaves a client of the request-partition less than one request per second",
+                    rate
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// the burst is held in whole batches, so each client's share of it must
+    /// hold at least one batch, as must each agent's share
+    fn validate_burst(&self) -> Result<(), String> {
+        let burst = match self.general.request_burst() {
+            Some(burst) => burst,
+            None => return Ok(()),
+        };
+        if self.request_ratelimit().is_none() {
+            return Err("request-burst requires a request ratelimit".to_string());
+        }
+        self.validate_share(burst)?;
+        let agents = self.general.agents().map(|a| a.len()).unwrap_or(0);
+        for index in 0..agents {
+            if let Some(burst) = self
+                .agent_ratelimit(index)
+                .and_then(|r| self.agent_burst(r))
+            {
+                self.validate_share(burst)
+                    .map_err(|e| format!("the share of agent {}: {}", index + 1, e))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// check that a burst leaves each client at least one batch
+    fn validate_share(&self, burst: usize) -> Result<(), String> {
+        let weights = self.request_weights().unwrap_or_else(|| vec![1]);
+        let total = weights.iter().sum::<usize>().max(1);
+        let smallest = weights.iter().copied().min().unwrap_or(1);
+        if burst * smallest / total < self.request_batch() {
+            return Err(format!(
+                "request-burst of {} leaves a client less than a request batch of {}",
+                burst,
+                self.request_batch()
+            ));
+        }
+        Ok(())
+    }
+
+    /// the most requests the request ratelimiter lets through at once, which
+    /// defaults to a batch for each client, or for each weight of a weighted
+    /// partition
+    pub fn request_burst(&self) -> usize {
+        self.general.request_burst().unwrap_or_else(|| {
+            let clients = match self.request_weights() {
+                Some(weights) => weights.iter().sum(),
+                None => self.clients(),
+            };
+            clients * self.request_batch()
+        })
+    }
+
     pub fn request_timeout(&self) -> usize {
         self.general.request_timeout()
     }
 
+    /// the time allowed after SIGTERM to drain the clients and write the
+    /// results before exiting
+    pub fn grace_period(&self) -> Duration {
+        Duration::from_secs(self.general.grace_period() as u64)
+    }
+
     pub fn connect_timeout(&self) -> usize {
         self.general.connect_timeout()
     }
@@ -816,10 +2311,50 @@ impl Config {
         self.general.soft_timeout()
     }
 
+    /// whether client threads are placed on NUMA nodes
+    pub fn numa(&self) -> bool {
+        self.general.numa()
+    }
+
+    /// whether each NUMA node only connects to a subset of the endpoints
+    pub fn numa_bind_endpoints(&self) -> bool {
+        self.general.numa() && self.general.numa_bind_endpoints()
+    }
+
+    /// whether the soft open files limit is raised if it's too low
+    pub fn raise_fd_limit(&self) -> bool {
+        self.general.raise_fd_limit()
+    }
+
+    /// the file descriptors the clients need, for the connections to the
+    /// endpoints, shadow endpoints and any compare endpoints and for the
+    /// poller of each client thread, including the clients of any groups
+    pub fn file_descriptors(&self) -> usize {
+        let clients = self.clients().max(self.warmup_clients());
+        let mut endpoints = self.endpoints().len() + self.shadow().len();
+        let mut pollers = clients;
+        if let Some(compare) = self.general.compare() {
+            endpoints += compare.len();
+            pollers += clients;
+        }
+        let groups: usize = self
+            .groups
+            .iter()
+            .map(|group| self.for_group(group).file_descriptors())
+            .sum();
+        clients * self.poolsize() * endpoints + pollers + groups
+    }
+
     pub fn close_rate(&self) -> Option<usize> {
         self.general.close_rate()
     }
 
+    /// the percentage of the responses in a window which may push back before
+    /// the request ratelimit is lowered, if the rate adapts to push-back
+    pub fn pushback_backoff(&self) -> Option<f64> {
+        self.general.pushback_backoff()
+    }
+
     pub fn tcp_nodelay(&self) -> bool {
         self.general.tcp_nodelay()
     }
@@ -840,19 +2375,187 @@ impl Config {
         self.general.warmup_hitrate()
     }
 
+    /// the number of client threads during the warmup, which defaults to
+    /// the number for the run
+    pub fn warmup_clients(&self) -> usize {
+        self.warmup
+            .as_ref()
+            .and_then(|warmup| warmup.clients())
+            .unwrap_or_else(|| self.clients())
+    }
+
+    /// the request ratelimit during the warmup, which is unlimited by default
+    pub fn warmup_ratelimit(&self) -> Option<usize> {
+        self.warmup
+            .as_ref()
+            .and_then(|warmup| warmup.request_ratelimit())
+    }
+
+    pub fn warmup_timeout(&self) -> Option<Duration> {
+        self.general
+            .warmup_timeout()
+            .filter(|seconds| *seconds > 0)
+            .map(|seconds| Duration::from_secs(seconds as u64))
+    }
+
     pub fn waterfall(&self) -> Option<String> {
         self.general.waterfall()
     }
 
-    fn load_from_file(filename: &str) -> Config {
-        let mut file = std::fs::File::open(filename).expect("failed to open workload file");
-        let mut content = String::new();
-        file.read_to_string(&mut content).expect("failed to read");
-        let toml = toml::from_str(&content);
-        match toml {
-            Ok(toml) => toml,
+    /// whether to print a dashboard instead of running
+    pub fn dashboard(&self) -> bool {
+        self.dashboard
+    }
+
+    /// read the keys from the keyfile in the config, if there is one
+    pub fn load_keyfile(&mut self) -> Result<(), String> {
+        self.keys = match self.general.keyfile() {
+            Some(path) => Some(Arc::new(Keyfile::load(&path)?)),
+            None => None,
+        };
+        Ok(())
+    }
+
+    /// this process's position among the shards, if the run is sharded
+    pub fn shard(&self) -> Option<Shard> {
+        self.shard
+    }
+
+    /// how long the keys of the keyfile may take to preload
+    pub fn preload_timeout(&self) -> Duration {
+        Duration::from_secs(self.general.preload_timeout() as u64)
+    }
+
+    /// the keys which are preloaded and read, if a keyfile was loaded
+    pub fn keyfile(&self) -> Option<&Arc<Keyfile>> {
+        self.keys.as_ref()
+    }
+
+    /// how values are compressed, if they are
+    pub fn compression(&self) -> Option<&Compression> {
+        self.compression.as_ref()
+    }
+
+    /// where the endpoints are discovered from, if they aren't listed
+    pub fn discovery(&self) -> Option<&Discovery> {
+        self.discovery.as_ref()
+    }
+
+    /// how often the endpoints are discovered again during the run
+    pub fn discovery_refresh(&self) -> Option<Duration> {
+        self.discovery
+            .as_ref()
+            .and_then(|d| d.refresh())
+            .filter(|secs| *secs > 0)
+            .map(|secs| Duration::from_secs(secs as u64))
+    }
+
+    /// discover the endpoints again, returning this shard's part of them
+    pub fn discover_endpoints(&self) -> Result<Vec<SocketAddr>, String> {
+        let discovery = match self.discovery {
+            Some(ref discovery) => discovery,
+            None => return Ok(self.endpoints()),
+        };
+        let endpoints = crate::discovery::discover(discovery)?;
+        Ok(match self.shard {
+            Some(shard) => shard.endpoints(&endpoints),
+            None => endpoints,
+        })
+    }
+
+    /// the faults the clients inject into the workload
+    pub fn chaos(&self) -> &[Fault] {
+        &self.chaos
+    }
+
+    /// the file to write the archive of the run to
+    pub fn bundle(&self) -> Option<String> {
+        self.general.bundle()
+    }
+
+    /// the file to write the summary of the run to
+    pub fn summary(&self) -> Option<String> {
+        self.general.summary()
+    }
+
+    /// the number of windows shown in the trend lines, if they are printed
+    pub fn sparklines(&self) -> Option<usize> {
+        self.general.sparklines().filter(|windows| *windows > 0)
+    }
+
+    /// the file to write the number of requests for each key to
+    pub fn popularity(&self) -> Option<String> {
+        self.general.popularity()
+    }
+
+    /// the limits the run is checked against when it ends
+    pub fn thresholds(&self) -> Thresholds {
+        self.thresholds.clone().unwrap_or_default()
+    }
+
+    fn load_from_file(filename: &str, preset: Option<&str>, overrides: &[String]) -> Config {
+        let content = include::resolve(Path::new(filename), &|path| {
+            let content = std::fs::read_to_string(path)
+                .map_err(|e| format!("failed to read {}: {}", path.display(), e))?;
+            env::interpolate_env(&content).map_err(|e| format!("{}: {}", path.display(), e))
+        })
+        .unwrap_or_else(|e| {
+            println!("ERROR: failed to load config: {}", filename);
+            println!("{}", e);
+            std::process::exit(1);
+        });
+        Config::load(&content, preset, overrides, filename)
+    }
+
+    /// parse a config on top of its preset and apply the overrides to it,
+    /// exiting if any of them is invalid. A preset given on the command line
+    /// takes the place of one in the config.
+    fn load(content: &str, preset: Option<&str>, overrides: &[String], name: &str) -> Config {
+        let mut toml: toml::Value = toml::from_str(content).unwrap_or_else(|e| {
+            println!("Failed to parse TOML config: {}", name);
+            println!("{}", e);
+            std::process::exit(1);
+        });
+        let preset = match toml.as_table_mut().and_then(|t| t.remove("preset")) {
+            _ if preset.is_some() => preset.map(|p| p.to_string()),
+            Some(toml::Value::String(preset)) => Some(preset),
+            Some(_) => {
+                println!("ERROR: preset must be a name: {}", name);
+                std::process::exit(1);
+            }
+            None => None,
+        };
+        let content = if overrides.is_empty() && preset.is_none() {
+            content.to_string()
+        } else {
+            if let Some(ref preset) = preset {
+                let base = presets::get(preset).unwrap_or_else(|| {
+                    println!(
+                        "ERROR: unknown preset: {}, expected one of: {}",
+                        preset,
+                        presets::names().join(", ")
+                    );
+                    std::process::exit(1);
+                });
+                let mut merged: toml::Value = toml::from_str(base).expect("invalid preset");
+                include::merge(&mut merged, toml);
+                toml = merged;
+            }
+            for setting in overrides {
+                if let Err(e) = overrides::apply(&mut toml, setting) {
+                    println!("ERROR: invalid override: {}", e);
+                    std::process::exit(1);
+                }
+            }
+            toml.to_string()
+        };
+        match Config::parse(&content, &Registry::default()) {
+            Ok(mut config) => {
+                config.preset = preset;
+                config
+            }
             Err(e) => {
-                println!("Failed to parse TOML config: {}", filename);
+                println!("Failed to parse TOML config: {}", name);
                 println!("{}", e);
                 std::process::exit(1);
             }
@@ -862,26 +2565,88 @@ impl Config {
     pub fn generator(&self) -> Generator {
         let mut keyspaces = Vec::new();
         for keyspace in &self.keyspace {
-            keyspaces.push(keyspace.generator());
+            keyspaces.push(keyspace.generator(self.shard));
+        }
+        Generator {
+            keyspaces,
+            keys: self.keys.clone(),
+            preload: None,
+            shard: self.shard,
         }
-        Generator { keyspaces }
+    }
+
+    /// a generator which sets this client's share of the keys in the keyfile,
+    /// the clients of each shard take turns within the shard's keys
+    pub fn preload_generator(&self, client: usize, clients: usize) -> Generator {
+        let mut generator = self.generator();
+        let (shard, shards) = self.shard.map_or((0, 1), |s| (s.index(), s.count()));
+        generator.preload = Some(Preload {
+            next: Cell::new(client * shards + shard),
+            step: clients.max(1) * shards,
+        });
+        generator
     }
 
     pub fn print(&self) {
         info!("-----");
-        info!("Protocol: {:?}", self.protocol());
+        info!("Protocol: {}", self.protocol().name());
+        if let Some(start_at) = self.general.start_at() {
+            info!("Config: Start At: {}", start_at);
+        }
         let endpoints = self.endpoints();
         for endpoint in &endpoints {
             info!("Config: Endpoint: {}", endpoint,);
         }
+        if self.compare() {
+            for endpoint in self.for_compare().endpoints() {
+                info!("Config: Compare: {}", endpoint,);
+            }
+        }
+        for endpoint in self.shadow() {
+            info!("Config: Shadow: {}", endpoint,);
+        }
+        if let Some(ref preset) = self.preset {
+            info!("Config: Preset: {}", preset);
+        }
+        if let Some(shard) = self.shard {
+            info!("Config: Shard: {}", shard);
+        }
+        if let Some(ref keys) = self.keys {
+            info!("Config: Keyfile: {} keys", keys.len());
+        }
+        if let Some(ref ycsb) = self.ycsb {
+            info!("Config: YCSB: {}", ycsb.describe());
+            if let Some(operations) = ycsb.operationcount() {
+                info!("Config: YCSB: Operations: {}", operations);
+            }
+        }
+        for fault in &self.chaos {
+            info!("Config: Chaos: {} {}", fault.name(), fault.schedule());
+        }
+        for group in &self.groups {
+            let config = self.for_group(group);
+            info!(
+                "Config: Group: {} Protocol: {} Endpoints: {} Clients: {} Poolsize: {} Ratelimit (/s): {}",
+                group.name(),
+                config.protocol().name(),
+                group.endpoints().len(),
+                config.clients(),
+                config.poolsize(),
+                config
+                    .request_ratelimit()
+                    .map(|v| format!("{}", v))
+                    .unwrap_or_else(|| "Unlimited".to_string()),
+            );
+        }
         info!(
             "Config: TLS: {}",
             self.tls_ca().is_some() && self.tls_cert().is_some() && self.tls_key().is_some()
         );
         info!(
-            "Config: Clients: {} Poolsize: {} Endpoints: {}",
+            "Config: Clients: {} Poolsize: {} Pipeline: {} Endpoints: {}",
             self.clients(),
             self.poolsize(),
+            self.pipeline(),
             endpoints.len(),
         );
         info!(
@@ -891,20 +2656,56 @@ impl Config {
             self.clients() * self.poolsize() * endpoints.len(),
         );
         info!(
-            "Config: Ratelimit (/s): Connect: {} Request: {}",
+            "Config: Ratelimit (/s): Connect: {} Request: {} Batch: {} Burst: {}",
             self.connect_ratelimit()
                 .map(|v| format!("{}", v))
                 .unwrap_or_else(|| "Unlimited".to_string()),
             self.request_ratelimit()
                 .map(|v| format!("{}", v))
                 .unwrap_or_else(|| "Unlimited".to_string()),
+            self.request_batch(),
+            self.request_burst(),
         );
+        if let Some(weights) = self.request_weights() {
+            info!(
+                "Config: Ratelimit Partition: {} Weights: {:?}",
+                self.request_partition().name(),
+                weights,
+            );
+        }
+        if let Some(hitrate) = self.warmup_hitrate() {
+            info!(
+                "Config: Warmup: Hitrate: {:.2}% Clients: {} Ratelimit (/s): {}",
+                100.0 * hitrate,
+                self.warmup_clients(),
+                self.warmup_ratelimit()
+                    .map(|v| format!("{}", v))
+                    .unwrap_or_else(|| "Unlimited".to_string()),
+            );
+        }
+        if let Some(compression) = self.compression() {
+            info!("Config: Compression: Algorithm: {}", compression);
+        }
+        if let Some(ref discovery) = self.discovery {
+            info!(
+                "Config: Discovery: {} Refresh: {}",
+                discovery,
+                self.discovery_refresh()
+                    .map(|r| format!("{}s", r.as_secs()))
+                    .unwrap_or_else(|| "Never".to_string()),
+            );
+        }
         info!(
             "Config: Timeout (us): Connect: {} Request: {} Mode: {}",
             self.connect_timeout(),
             self.request_timeout(),
             if self.soft_timeout() { "Soft" } else { "Hard" },
         );
+        info!(
+            "Config: NUMA: {} Bind Endpoints: {}",
+            self.numa(),
+            self.numa_bind_endpoints(),
+        );
         let windows = self
             .windows()
             .map(|v| format!("{}", v))
@@ -928,6 +2729,14 @@ impl Config {
                 keyspace.values.len()
             );
         }
+        if !self.overrides.is_empty() {
+            for setting in &self.overrides {
+                info!("Config: Override: {}", setting);
+            }
+            for line in self.to_toml().lines() {
+                info!("Config: Resolved: {}", line);
+            }
+        }
     }
 }
 
@@ -941,6 +2750,37 @@ fn parse_numeric_arg(matches: &ArgMatches, key: &str) -> Option<usize> {
     })
 }
 
+/// a helper function to parse a count argument, which may have a unit, by
+/// name from `ArgMatches`
+fn parse_count_arg(matches: &ArgMatches, key: &str) -> Option<usize> {
+    matches.value_of(key).map(|f| {
+        units::parse_count(f).unwrap_or_else(|e| {
+            println!("ERROR: could not parse {}: {}", key, e);
+            process::exit(1);
+        })
+    })
+}
+
+/// a helper function to parse a latency argument by name from `ArgMatches`
+fn parse_latency_arg(matches: &ArgMatches, key: &str) -> Option<usize> {
+    matches.value_of(key).map(|f| {
+        units::parse_latency(f).unwrap_or_else(|e| {
+            println!("ERROR: could not parse {}: {}", key, e);
+            process::exit(1);
+        })
+    })
+}
+
+/// a helper function to parse a duration argument by name from `ArgMatches`
+fn parse_duration_arg(matches: &ArgMatches, key: &str) -> Option<usize> {
+    matches.value_of(key).map(|f| {
+        duration::parse(f).unwrap_or_else(|e| {
+            println!("ERROR: could not parse {}: {}", key, e);
+            process::exit(1);
+        })
+    })
+}
+
 /// a helper function to parse a floating point argument by name from `ArgMatches`
 fn parse_float_arg(matches: &ArgMatches, key: &str) -> Option<f64> {
     matches.value_of(key).map(|f| {
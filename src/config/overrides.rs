@@ -0,0 +1,121 @@
+// Copyright 2021 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+//! Overrides for arbitrary config keys, given on the command line as
+//! `section.key=value`. Tables in an array, such as a keyspace, are selected
+//! by their index: `keyspace.0.length=16`. Values are parsed as TOML, falling
+//! back to a string, so `general.protocol=redis` needs no quotes.
+
+use toml::Value;
+
+/// apply an override to the TOML of a config
+pub fn apply(toml: &mut Value, setting: &str) -> Result<(), String> {
+    let (path, value) = match setting.find('=') {
+        Some(index) => (&setting[..index], &setting[index + 1..]),
+        None => return Err(format!("expected key=value: {}", setting)),
+    };
+    let keys: Vec<&str> = path.trim().split('.').collect();
+    if keys.iter().any(|k| k.is_empty()) {
+        return Err(format!("malformed key: {}", path));
+    }
+    let value = parse(value.trim());
+
+    let (last, parents) = keys.split_last().unwrap();
+    let mut current = toml;
+    for key in parents {
+        current = child(current, key, path)?;
+    }
+    match current {
+        Value::Table(table) => {
+            table.insert(last.to_string(), value);
+        }
+        Value::Array(array) => {
+            let index = index(last, array.len(), path)?;
+            array[index] = value;
+        }
+        _ => return Err(format!("not a table: {}", path)),
+    }
+    Ok(())
+}
+
+fn parse(value: &str) -> Value {
+    match toml::from_str::<Value>(&format!("value = {}", value)) {
+        Ok(Value::Table(mut table)) => table.remove("value").unwrap(),
+        _ => Value::String(value.to_string()),
+    }
+}
+
+/// the child of a table or array, creating missing tables
+fn child<'a>(current: &'a mut Value, key: &str, path: &str) -> Result<&'a mut Value, String> {
+    match current {
+        Value::Table(table) => Ok(table
+            .entry(key.to_string())
+            .or_insert_with(|| Value::Table(Default::default()))),
+        Value::Array(array) => {
+            let index = index(key, array.len(), path)?;
+            Ok(&mut array[index])
+        }
+        _ => Err(format!("not a table: {}", path)),
+    }
+}
+
+fn index(key: &str, len: usize, path: &str) -> Result<usize, String> {
+    match key.parse::<usize>() {
+        Ok(index) if index < len => Ok(index),
+        _ => Err(format!("no index {} with {} entries: {}", key, len, path)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn overrides() {
+        let mut toml: Value =
+            toml::from_str("[general]\nclients = 1\n[[keyspace]]\nlength = 8\n").unwrap();
+        apply(&mut toml, "general.clients=4").unwrap();
+        apply(&mut toml, "general.protocol=redis").unwrap();
+        apply(&mut toml, "general.endpoints=[\"127.0.0.1:6379\"]").unwrap();
+        apply(&mut toml, "keyspace.0.length = 16").unwrap();
+        apply(&mut toml, "metrics.enabled=true").unwrap();
+
+        assert_eq!(toml["general"]["clients"], Value::Integer(4));
+        assert_eq!(
+            toml["general"]["protocol"],
+            Value::String("redis".to_string())
+        );
+        assert_eq!(
+            toml["general"]["endpoints"][0].as_str(),
+            Some("127.0.0.1:6379")
+        );
+        assert_eq!(toml["keyspace"][0]["length"], Value::Integer(16));
+        assert_eq!(toml["metrics"]["enabled"], Value::Boolean(true));
+
+        assert!(apply(&mut toml, "general.clients").is_err());
+        assert!(apply(&mut toml, "keyspace.1.length=16").is_err());
+        assert!(apply(&mut toml, "general..clients=1").is_err());
+        assert!(apply(&mut toml, "general.clients.max=1").is_err());
+    }
+
+    #[test]
+    fn config() {
+        use crate::config::Config;
+
+        let mut toml: Value = toml::from_str(&Config::default().to_toml()).unwrap();
+        apply(&mut toml, "general.clients=4").unwrap();
+        apply(&mut toml, "keyspace.0.values.0.length=128").unwrap();
+        let config = Config::from_toml(&toml.to_string()).unwrap();
+        assert_eq!(config.clients(), 4);
+
+        // the duration is cut into windows, rounding up
+        apply(&mut toml, "general.interval=60").unwrap();
+        apply(&mut toml, "general.duration=90").unwrap();
+        let config = Config::from_toml(&toml.to_string()).unwrap();
+        assert_eq!(config.windows(), Some(2));
+
+        apply(&mut toml, "general.cleints=4").unwrap();
+        assert!(Config::from_toml(&toml.to_string()).is_err());
+    }
+}
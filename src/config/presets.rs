@@ -0,0 +1,47 @@
+// Copyright 2021 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+//! Named workloads which are built into rpc-perf. A preset is used as the base
+//! of the config, so any of its settings can be overridden by the config file
+//! or the command line. The presets are in `configs/presets`.
+
+const PRESETS: &[(&str, &str)] = &[
+    (
+        "memtier-default",
+        include_str!("../../configs/presets/memtier-default.toml"),
+    ),
+    (
+        "small-object-read-heavy",
+        include_str!("../../configs/presets/small-object-read-heavy.toml"),
+    ),
+    (
+        "write-heavy-ttl",
+        include_str!("../../configs/presets/write-heavy-ttl.toml"),
+    ),
+];
+
+/// the TOML of the preset with the name
+pub fn get(name: &str) -> Option<&'static str> {
+    PRESETS.iter().find(|(n, _)| *n == name).map(|(_, p)| *p)
+}
+
+/// the names of the presets
+pub fn names() -> Vec<&'static str> {
+    PRESETS.iter().map(|(name, _)| *name).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+
+    #[test]
+    fn presets() {
+        for name in names() {
+            let config = Config::from_toml(get(name).unwrap()).unwrap();
+            assert!(!config.generator().keyspaces.is_empty(), "{}", name);
+        }
+        assert!(get("unknown").is_none());
+    }
+}
@@ -0,0 +1,254 @@
+// Copyright 2021 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+//! With `--shard i/N`, several rpc-perf processes share one config. Each
+//! process sends its share of the request ratelimit to its share of the
+//! endpoints, and the keys are split between the processes so that together
+//! they cover the keyspace with the configured distribution. A uniform key is
+//! chosen from the shard's part of the keys, while a zipfian or latest key is
+//! chosen from the whole keyspace until one of the shard's keys comes up, so
+//! that the hottest keys stay as hot as they would be with a single process.
+
+use std::fmt;
+
+/// the position of this process among the shards, which is zero based here
+/// but one based on the command line
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Shard {
+    index: usize,
+    count: usize,
+}
+
+impl Shard {
+    /// parse a shard from `i/N`, where `i` is from 1 to `N`
+    pub fn parse(value: &str) -> Result<Shard, String> {
+        let mut parts = value.splitn(2, '/');
+        let index = parts.next().unwrap_or("");
+        let count = parts
+            .next()
+            .ok_or_else(|| format!("expected i/N, got: {}", value))?;
+        let index: usize = index
+            .trim()
+            .parse()
+            .map_err(|_| format!("invalid shard: {}", index))?;
+        let count: usize = count
+            .trim()
+            .parse()
+            .map_err(|_| format!("invalid shard count: {}", count))?;
+        if count == 0 {
+            return Err("shard count must be at least 1".to_string());
+        }
+        if index == 0 || index > count {
+            return Err(format!("shard must be from 1 to {}", count));
+        }
+        Ok(Shard {
+            index: index - 1,
+            count,
+        })
+    }
+
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    pub fn count(&self) -> usize {
+        self.count
+    }
+
+    /// the number of the items, such as keys, which belong to this shard
+    pub fn share(&self, items: usize) -> usize {
+        (items + self.count - 1 - self.index) / self.count
+    }
+
+    /// the item for the nth item of this shard
+    pub fn item(&self, n: usize) -> usize {
+        n * self.count + self.index
+    }
+
+    /// whether the item belongs to this shard
+    pub fn owns(&self, item: usize) -> bool {
+        item % self.count == self.index
+    }
+
+    /// this shard's part of a rate, the remainder goes to the first shards
+    pub fn rate(&self, rate: usize) -> usize {
+        self.share(rate).max(1)
+    }
+
+    /// the endpoints for this shard. With fewer endpoints than shards, the
+    /// shards take turns to share each endpoint.
+    pub fn endpoints<T: Clone>(&self, endpoints: &[T]) -> Vec<T> {
+        if endpoints.len() < self.count {
+            return endpoints
+                .get(self.index % endpoints.len().max(1))
+                .cloned()
+                .into_iter()
+                .collect();
+        }
+        endpoints
+            .iter()
+            .skip(self.index)
+            .step_by(self.count)
+            .cloned()
+            .collect()
+    }
+}
+
+impl fmt::Display for Shard {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}/{}", self.index + 1, self.count)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parsed() {
+        let shard = Shard::parse("2/3").unwrap();
+        assert_eq!(shard.to_string(), "2/3");
+        assert_eq!(shard.index(), 1);
+        assert_eq!(shard.count(), 3);
+        assert!(Shard::parse("0/3").is_err());
+        assert!(Shard::parse("4/3").is_err());
+        assert!(Shard::parse("1/0").is_err());
+        assert!(Shard::parse("1").is_err());
+        assert!(Shard::parse("a/3").is_err());
+    }
+
+    #[test]
+    fn partitioned() {
+        let shards: Vec<Shard> = (1..=3)
+            .map(|i| Shard::parse(&format!("{}/3", i)).unwrap())
+            .collect();
+
+        // the keys of the shards are distinct and cover the keyspace
+        let mut keys: Vec<usize> = shards
+            .iter()
+            .flat_map(|shard| (0..shard.share(10)).map(move |n| shard.item(n)))
+            .collect();
+        keys.sort_unstable();
+        assert_eq!(keys, (0..10).collect::<Vec<usize>>());
+
+        let rates: Vec<usize> = shards.iter().map(|shard| shard.rate(1000)).collect();
+        assert_eq!(rates, vec![334, 333, 333]);
+
+        let endpoints = ["a", "b", "c", "d"];
+        assert_eq!(shards[0].endpoints(&endpoints), vec!["a", "d"]);
+        assert_eq!(shards[1].endpoints(&endpoints), vec!["b"]);
+        assert_eq!(shards[2].endpoints(&endpoints[..2]), vec!["a"]);
+        assert!(shards[1].owns(4) && !shards[1].owns(5));
+    }
+
+    #[test]
+    fn skewed() {
+        use rand::SeedableRng;
+
+        let keyspace: crate::config::Keyspace = toml::from_str(
+            "length = 4\n\
+             count = 1000\n\
+             weight = 1\n\
+             distribution = \"zipfian\"\n\
+             commands = [{action = \"get\", weight = 1}]\n\
+             values = [{length = 1, weight = 1}]",
+        )
+        .unwrap();
+        let mut rng = rand::rngs::StdRng::seed_from_u64(0);
+        let hottest = |generator: &crate::config::KeyspaceGenerator,
+                       rng: &mut rand::rngs::StdRng,
+                       shard: Option<Shard>| {
+            let mut counts = vec![0; 1000];
+            for _ in 0..20_000 {
+                let key: usize = generator.choose_key(rng).parse().unwrap();
+                assert!(shard.is_none_or(|shard| shard.owns(key)));
+                counts[key] += 1;
+            }
+            *counts.iter().max().unwrap()
+        };
+        let whole = hottest(&keyspace.generator(None), &mut rng, None);
+        // each shard's hottest key is at least as hot as the hottest key of
+        // the keyspace
+        for i in 1..=4 {
+            let shard = Shard::parse(&format!("{}/4", i)).unwrap();
+            let generator = keyspace.generator(Some(shard));
+            assert!(hottest(&generator, &mut rng, Some(shard)) as f64 > 0.9 * whole as f64);
+        }
+    }
+
+    #[test]
+    fn burst() {
+        use crate::config::Config;
+
+        let general = "[general]\n\
+                       protocol = \"memcache\"\n\
+                       clients = 2\n\
+                       request_ratelimit = 1000\n\
+                       request_batch = 10\n";
+        let keyspace = "[[keyspace]]\n\
+                        length = 8\n\
+                        weight = 1\n\
+                        commands = [{action = \"get\", weight = 1}]\n\
+                        values = [{length = 16, weight = 1}]";
+        let config = Config::from_toml(&format!("{}{}", general, keyspace)).unwrap();
+        assert_eq!(config.request_burst(), 20);
+        let config =
+            Config::from_toml(&format!("{}request_burst = \"2k\"\n{}", general, keyspace)).unwrap();
+        assert_eq!(config.request_burst(), 2000);
+
+        // the burst is held in whole batches
+        for burst in &["0", "9"] {
+            let toml = format!("{}request_burst = {}\n{}", general, burst, keyspace);
+            assert!(Config::from_toml(&toml).is_err());
+        }
+        let toml = format!(
+            "{}request_burst = 30\n\
+             request_partition = \"weighted\"\n\
+             request_weights = [3, 1]\n{}",
+            general, keyspace
+        );
+        assert!(Config::from_toml(&toml).is_err());
+        let weighted = toml.replace("request_burst = 30\n", "");
+        assert_eq!(Config::from_toml(&weighted).unwrap().request_burst(), 40);
+        let unlimited = general.replace("request_ratelimit = 1000\n", "request_burst = 20\n");
+        assert!(Config::from_toml(&format!("{}{}", unlimited, keyspace)).is_err());
+
+        // the burst is split between the shards along with the rate
+        let toml = format!("{}request_burst = 25\n{}", general, keyspace);
+        let mut config = Config::from_toml(&toml).unwrap();
+        config.set_shard(Shard::parse("1/2").unwrap()).unwrap();
+        assert_eq!(config.request_burst(), 13);
+        assert_eq!(config.request_ratelimit(), Some(500));
+        let mut config = Config::from_toml(&toml).unwrap();
+        assert!(config.set_shard(Shard::parse("1/3").unwrap()).is_err());
+    }
+
+    #[test]
+    fn weighted() {
+        use crate::config::Config;
+
+        let toml = "[general]\n\
+                    protocol = \"memcache\"\n\
+                    clients = 2\n\
+                    request_ratelimit = 5\n\
+                    request_partition = \"weighted\"\n\
+                    request_weights = [3, 1]\n\
+                    [[keyspace]]\n\
+                    length = 8\n\
+                    weight = 1\n\
+                    commands = [{action = \"get\", weight = 1}]\n\
+                    values = [{length = 16, weight = 1}]";
+        let config = Config::from_toml(toml).unwrap();
+        assert!(config.validate_partition().is_ok());
+        let config = Config::from_toml(&toml.replace("= 5", "= 3")).unwrap();
+        assert!(config.validate_partition().is_err());
+
+        // each shard's part of the rate must still cover the weights
+        let mut config = Config::from_toml(toml).unwrap();
+        assert!(config.set_shard(Shard::parse("1/2").unwrap()).is_err());
+        let mut config = Config::from_toml(&toml.replace("= 5", "= 8")).unwrap();
+        config.set_shard(Shard::parse("2/2").unwrap()).unwrap();
+        assert_eq!(config.request_ratelimit(), Some(4));
+    }
+}
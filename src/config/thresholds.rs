@@ -0,0 +1,89 @@
+// Copyright 2021 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+use crate::config::units;
+
+use serde_derive::*;
+
+/// Limits which the whole run is checked against when it ends. Latencies are
+/// request latency percentiles in microseconds, the error rate and hitrate
+/// are percentages and the throughput is in responses per second.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Thresholds {
+    p50: Option<u64>,
+    p90: Option<u64>,
+    p99: Option<u64>,
+    p999: Option<u64>,
+    p9999: Option<u64>,
+    error_rate: Option<f64>,
+    hitrate: Option<f64>,
+    #[serde(default, deserialize_with = "units::optional_count")]
+    throughput: Option<usize>,
+}
+
+impl Thresholds {
+    /// the highest allowed latency, in microseconds, for each percentile
+    pub fn latency(&self) -> Vec<(f64, u64)> {
+        [
+            (50.0, self.p50),
+            (90.0, self.p90),
+            (99.0, self.p99),
+            (99.9, self.p999),
+            (99.99, self.p9999),
+        ]
+        .iter()
+        .filter_map(|(percentile, limit)| limit.map(|limit| (*percentile, limit)))
+        .collect()
+    }
+
+    /// the highest allowed percentage of requests which failed or timed out
+    pub fn error_rate(&self) -> Option<f64> {
+        self.error_rate
+    }
+
+    /// the lowest allowed percentage of lookups which hit
+    pub fn hitrate(&self) -> Option<f64> {
+        self.hitrate
+    }
+
+    /// the lowest allowed responses per second
+    pub fn throughput(&self) -> Option<usize> {
+        self.throughput
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        for (name, percent) in &[("error_rate", self.error_rate), ("hitrate", self.hitrate)] {
+            if let Some(percent) = percent {
+                if !(0.0..=100.0).contains(percent) {
+                    return Err(format!("{} must be from 0 to 100", name));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn thresholds() {
+        let thresholds: Thresholds = toml::from_str("p99 = 2000\np50 = 500\nhitrate = 90").unwrap();
+        assert_eq!(thresholds.latency(), vec![(50.0, 500), (99.0, 2000)]);
+        assert_eq!(thresholds.hitrate(), Some(90.0));
+        assert_eq!(thresholds.throughput(), None);
+        assert!(thresholds.validate().is_ok());
+
+        let thresholds: Thresholds = toml::from_str("throughput = \"100k\"").unwrap();
+        assert_eq!(thresholds.throughput(), Some(100_000));
+        let thresholds: Thresholds = toml::from_str("throughput = 1000.0").unwrap();
+        assert_eq!(thresholds.throughput(), Some(1000));
+
+        let thresholds: Thresholds = toml::from_str("error_rate = 101.0").unwrap();
+        assert!(thresholds.validate().is_err());
+        assert!(toml::from_str::<Thresholds>("p95 = 1000").is_err());
+    }
+}
@@ -0,0 +1,217 @@
+// Copyright 2021 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+//! Rates and counts in the config may be given with a decimal suffix, such as
+//! `250k` or `1.5M`, sizes with a byte unit, such as `16KiB` or `1MB`, and
+//! latencies with a time unit, such as `50ms` or `1.5s`. The suffix of a count
+//! is case-insensitive, except that millions must be `M`, as `m` would read as
+//! minutes next to durations such as `15m`.
+
+use serde::de::{self, Deserializer, Visitor};
+
+use std::fmt;
+
+const COUNT_UNITS: &[(&str, f64)] = &[("k", 1e3), ("m", 1e6), ("g", 1e9)];
+
+const SIZE_UNITS: &[(&str, f64)] = &[
+    ("b", 1.0),
+    ("kb", 1e3),
+    ("mb", 1e6),
+    ("gb", 1e9),
+    ("kib", 1024.0),
+    ("mib", 1048576.0),
+    ("gib", 1073741824.0),
+];
+
+const LATENCY_UNITS: &[(&str, f64)] = &[("us", 1.0), ("ms", 1e3), ("s", 1e6)];
+
+/// parse a number with an optional unit, which must come to a whole number
+fn parse(value: &str, units: &[(&str, f64)]) -> Result<usize, String> {
+    let value = value.trim();
+    let digits = value.replace('_', "");
+    if let Ok(n) = digits.parse() {
+        return Ok(n);
+    }
+    let split = digits
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(digits.len());
+    let (number, unit) = digits.split_at(split);
+    let multiplier = units
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case(unit.trim()))
+        .map(|(_, multiplier)| *multiplier)
+        .ok_or_else(|| format!("invalid unit: {}", value))?;
+    let number: f64 = number
+        .parse()
+        .map_err(|_| format!("invalid number: {}", value))?;
+    let n = number * multiplier;
+    if n.fract() != 0.0 || n > usize::MAX as f64 {
+        return Err(format!("not a whole number: {}", value));
+    }
+    Ok(n as usize)
+}
+
+/// parse a count or rate, such as `250k`
+pub fn parse_count(value: &str) -> Result<usize, String> {
+    if value.trim().ends_with('m') {
+        return Err(format!("ambiguous unit, use M for millions: {}", value));
+    }
+    parse(value, COUNT_UNITS)
+}
+
+/// parse a size into bytes, such as `16KiB`
+pub fn parse_size(value: &str) -> Result<usize, String> {
+    parse(value, SIZE_UNITS)
+}
+
+/// parse a latency into microseconds, such as `50ms`
+pub fn parse_latency(value: &str) -> Result<usize, String> {
+    parse(value, LATENCY_UNITS)
+}
+
+struct UnitVisitor {
+    parse: fn(&str) -> Result<usize, String>,
+    expecting: &'static str,
+}
+
+impl<'de> Visitor<'de> for UnitVisitor {
+    type Value = usize;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str(self.expecting)
+    }
+
+    fn visit_i64<E: de::Error>(self, value: i64) -> Result<usize, E> {
+        if value < 0 {
+            return Err(E::custom("value must not be negative"));
+        }
+        Ok(value as usize)
+    }
+
+    fn visit_u64<E: de::Error>(self, value: u64) -> Result<usize, E> {
+        Ok(value as usize)
+    }
+
+    fn visit_f64<E: de::Error>(self, value: f64) -> Result<usize, E> {
+        if value < 0.0 {
+            return Err(E::custom("value must not be negative"));
+        }
+        if value.fract() != 0.0 || value > usize::MAX as f64 {
+            return Err(E::custom(format!("not a whole number: {}", value)));
+        }
+        Ok(value as usize)
+    }
+
+    fn visit_str<E: de::Error>(self, value: &str) -> Result<usize, E> {
+        (self.parse)(value).map_err(E::custom)
+    }
+}
+
+/// deserialize a count or rate
+pub fn count<'de, D: Deserializer<'de>>(deserializer: D) -> Result<usize, D::Error> {
+    deserializer.deserialize_any(UnitVisitor {
+        parse: parse_count,
+        expecting: "a number or a count such as \"250k\"",
+    })
+}
+
+/// deserialize an optional count or rate
+pub fn optional_count<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<usize>, D::Error> {
+    count(deserializer).map(Some)
+}
+
+/// deserialize a size in bytes
+pub fn size<'de, D: Deserializer<'de>>(deserializer: D) -> Result<usize, D::Error> {
+    deserializer.deserialize_any(UnitVisitor {
+        parse: parse_size,
+        expecting: "a number of bytes or a size such as \"16KiB\"",
+    })
+}
+
+/// deserialize an optional latency in microseconds
+pub fn optional_latency<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<usize>, D::Error> {
+    deserializer
+        .deserialize_any(UnitVisitor {
+            parse: parse_latency,
+            expecting: "a number of microseconds or a latency such as \"50ms\"",
+        })
+        .map(Some)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_derive::Deserialize;
+
+    #[derive(Deserialize)]
+    struct Settings {
+        #[serde(default, deserialize_with = "optional_count")]
+        rate: Option<usize>,
+        #[serde(deserialize_with = "size")]
+        length: usize,
+        #[serde(default, deserialize_with = "optional_latency")]
+        slow: Option<usize>,
+    }
+
+    #[test]
+    fn counts() {
+        assert_eq!(parse_count("1000"), Ok(1000));
+        assert_eq!(parse_count("250k"), Ok(250_000));
+        assert_eq!(parse_count("1.5M"), Ok(1_500_000));
+        assert_eq!(parse_count("2G"), Ok(2_000_000_000));
+        assert_eq!(parse_count("10_000"), Ok(10_000));
+        assert!(parse_count("1.5").is_err());
+        assert!(parse_count("1.0001k").is_err());
+        assert!(parse_count("10x").is_err());
+        assert!(parse_count("k").is_err());
+        assert!(parse_count("16KiB").is_err());
+        assert!(parse_count("1m").is_err());
+    }
+
+    #[test]
+    fn sizes() {
+        assert_eq!(parse_size("64"), Ok(64));
+        assert_eq!(parse_size("64B"), Ok(64));
+        assert_eq!(parse_size("16KiB"), Ok(16_384));
+        assert_eq!(parse_size("1MB"), Ok(1_000_000));
+        assert_eq!(parse_size("1.5 MiB"), Ok(1_572_864));
+        assert!(parse_size("16k").is_err());
+        assert!(parse_size("0.5B").is_err());
+    }
+
+    #[test]
+    fn latencies() {
+        assert_eq!(parse_latency("500"), Ok(500));
+        assert_eq!(parse_latency("250us"), Ok(250));
+        assert_eq!(parse_latency("50ms"), Ok(50_000));
+        assert_eq!(parse_latency("1.5s"), Ok(1_500_000));
+        assert!(parse_latency("0.5us").is_err());
+        assert!(parse_latency("1m").is_err());
+    }
+
+    #[test]
+    fn deserialize() {
+        let settings: Settings = toml::from_str("rate = \"250k\"\nlength = \"4KiB\"").unwrap();
+        assert_eq!(settings.rate, Some(250_000));
+        assert_eq!(settings.length, 4096);
+        let settings: Settings = toml::from_str("length = 100").unwrap();
+        assert_eq!(settings.rate, None);
+        assert_eq!(settings.length, 100);
+        assert_eq!(settings.slow, None);
+        let settings: Settings = toml::from_str("length = 1\nslow = \"50ms\"").unwrap();
+        assert_eq!(settings.slow, Some(50_000));
+        assert!(toml::from_str::<Settings>("length = \"4KB/s\"").is_err());
+
+        // whole floats, such as a throughput of 1000.0, are accepted
+        let settings: Settings = toml::from_str("rate = 1000.0\nlength = 64.0").unwrap();
+        assert_eq!(settings.rate, Some(1000));
+        assert_eq!(settings.length, 64);
+        assert!(toml::from_str::<Settings>("rate = 1.5\nlength = 1").is_err());
+        assert!(toml::from_str::<Settings>("rate = -1.0\nlength = 1").is_err());
+    }
+}
@@ -0,0 +1,65 @@
+// Copyright 2021 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+use crate::config::units;
+
+use serde_derive::*;
+
+/// How the warmup is run. Large caches take a long time to fill at the rate
+/// which is measured, so the warmup may use more clients and its own request
+/// ratelimit. Without a ratelimit the warmup is unlimited.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Warmup {
+    clients: Option<usize>,
+    #[serde(default, deserialize_with = "units::optional_count")]
+    request_ratelimit: Option<usize>,
+}
+
+impl Warmup {
+    /// the number of client threads, if it differs from the run
+    pub fn clients(&self) -> Option<usize> {
+        self.clients
+    }
+
+    pub fn set_clients(&mut self, clients: Option<usize>) {
+        self.clients = clients;
+    }
+
+    /// the requests per second sent during the warmup
+    pub fn request_ratelimit(&self) -> Option<usize> {
+        self.request_ratelimit
+    }
+
+    pub fn set_request_ratelimit(&mut self, limit: Option<usize>) {
+        self.request_ratelimit = limit;
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if self.clients == Some(0) {
+            return Err("clients must be at least 1".to_string());
+        }
+        if self.request_ratelimit == Some(0) {
+            return Err("request_ratelimit must be at least 1".to_string());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn warmup() {
+        let warmup: Warmup = toml::from_str("clients = 16\nrequest_ratelimit = \"2M\"").unwrap();
+        assert_eq!(warmup.clients(), Some(16));
+        assert_eq!(warmup.request_ratelimit(), Some(2_000_000));
+        assert!(warmup.validate().is_ok());
+
+        let warmup: Warmup = toml::from_str("clients = 0").unwrap();
+        assert!(warmup.validate().is_err());
+        assert!(toml::from_str::<Warmup>("hitrate = 0.9").is_err());
+    }
+}
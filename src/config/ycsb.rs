@@ -0,0 +1,302 @@
+// Copyright 2021 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+//! The parameters of the YCSB core workloads, which are expanded into a
+//! keyspace so that results can be compared with published YCSB results.
+//! Reads map to gets, while updates and inserts map to sets. Inserts don't
+//! grow the keyspace. Scans and read-modify-writes aren't supported, which
+//! rules out workloads E and F.
+
+use crate::config::{Action, Class, Command, KeyDistribution, Keyspace, Value};
+
+use serde_derive::*;
+
+#[derive(Copy, Clone, Debug, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Workload {
+    A,
+    B,
+    C,
+    D,
+    E,
+    F,
+}
+
+impl Workload {
+    /// the workload with a name such as `a` or `workloada`
+    pub fn parse(name: &str) -> Option<Self> {
+        let name = name.to_lowercase();
+        match name.strip_prefix("workload").unwrap_or(&name) {
+            "a" => Some(Workload::A),
+            "b" => Some(Workload::B),
+            "c" => Some(Workload::C),
+            "d" => Some(Workload::D),
+            "e" => Some(Workload::E),
+            "f" => Some(Workload::F),
+            _ => None,
+        }
+    }
+}
+
+/// The settings use the names of the YCSB properties.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Ycsb {
+    workload: Option<Workload>,
+    recordcount: Option<usize>,
+    operationcount: Option<usize>,
+    requestdistribution: Option<KeyDistribution>,
+    fieldcount: Option<usize>,
+    fieldlength: Option<usize>,
+    readproportion: Option<f64>,
+    updateproportion: Option<f64>,
+    insertproportion: Option<f64>,
+    scanproportion: Option<f64>,
+    readmodifywriteproportion: Option<f64>,
+}
+
+/// the proportions of read, update, insert, scan and read-modify-write
+/// operations, and the request distribution
+type Mix = ([f64; 5], KeyDistribution);
+
+impl Ycsb {
+    /// parse the settings from a YCSB workload file. Properties which don't
+    /// apply, such as the workload class, are ignored.
+    pub fn from_properties(content: &str) -> Result<Self, String> {
+        let mut table = toml::value::Table::new();
+        for line in content.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') || line.starts_with('!') {
+                continue;
+            }
+            let (key, value) = match line.find(&['=', ':'][..]) {
+                Some(index) => (line[..index].trim(), line[index + 1..].trim()),
+                None => return Err(format!("expected key=value: {}", line)),
+            };
+            let value = match key {
+                "recordcount" | "operationcount" | "fieldcount" | "fieldlength" => value
+                    .parse()
+                    .map(toml::Value::Integer)
+                    .map_err(|_| format!("{} is not a number: {}", key, value))?,
+                "readproportion"
+                | "updateproportion"
+                | "insertproportion"
+                | "scanproportion"
+                | "readmodifywriteproportion" => value
+                    .parse()
+                    .map(toml::Value::Float)
+                    .map_err(|_| format!("{} is not a number: {}", key, value))?,
+                "requestdistribution" => toml::Value::String(value.to_string()),
+                _ => continue,
+            };
+            table.insert(key.to_string(), value);
+        }
+        toml::Value::Table(table)
+            .try_into()
+            .map_err(|e| e.to_string())
+    }
+
+    /// the settings for a command line argument, which is either the name of
+    /// a core workload, applied to the current settings, or a workload file
+    pub fn from_arg(arg: &str, current: Option<Ycsb>) -> Result<Self, String> {
+        if !std::path::Path::new(arg).exists() {
+            if let Some(workload) = Workload::parse(arg) {
+                let mut ycsb = current.unwrap_or_default();
+                ycsb.workload = Some(workload);
+                return Ok(ycsb);
+            }
+        }
+        let content =
+            std::fs::read_to_string(arg).map_err(|e| format!("failed to read {}: {}", arg, e))?;
+        Self::from_properties(&content)
+    }
+
+    /// the number of operations after which the run ends
+    pub fn operationcount(&self) -> Option<usize> {
+        self.operationcount
+    }
+
+    pub fn set_operationcount(&mut self, operationcount: Option<usize>) {
+        self.operationcount = operationcount;
+    }
+
+    pub fn recordcount(&self) -> usize {
+        self.recordcount.unwrap_or(1000).max(1)
+    }
+
+    fn mix(&self) -> Mix {
+        use KeyDistribution::*;
+        let (mut proportions, distribution) = match self.workload {
+            Some(Workload::A) => ([0.5, 0.5, 0.0, 0.0, 0.0], Zipfian),
+            Some(Workload::B) => ([0.95, 0.05, 0.0, 0.0, 0.0], Zipfian),
+            Some(Workload::C) => ([1.0, 0.0, 0.0, 0.0, 0.0], Zipfian),
+            Some(Workload::D) => ([0.95, 0.0, 0.05, 0.0, 0.0], Latest),
+            Some(Workload::E) => ([0.0, 0.0, 0.05, 0.95, 0.0], Zipfian),
+            Some(Workload::F) => ([0.5, 0.0, 0.0, 0.0, 0.5], Zipfian),
+            // the defaults of the YCSB core workload
+            None => ([0.95, 0.05, 0.0, 0.0, 0.0], Uniform),
+        };
+        let settings = [
+            self.readproportion,
+            self.updateproportion,
+            self.insertproportion,
+            self.scanproportion,
+            self.readmodifywriteproportion,
+        ];
+        for (proportion, setting) in proportions.iter_mut().zip(settings.iter()) {
+            if let Some(setting) = setting {
+                *proportion = *setting;
+            }
+        }
+        (
+            proportions,
+            self.requestdistribution.unwrap_or(distribution),
+        )
+    }
+
+    /// the keyspace which generates the workload
+    pub fn keyspace(&self) -> Result<Keyspace, String> {
+        let ([read, update, insert, scan, rmw], distribution) = self.mix();
+        if [read, update, insert, scan, rmw].iter().any(|p| *p < 0.0) {
+            return Err("ycsb proportions must not be negative".to_string());
+        }
+        if scan > 0.0 {
+            return Err("ycsb scan operations are not supported".to_string());
+        }
+        // a read-modify-write would need a get and a set of the same key,
+        // timed as one operation
+        if rmw > 0.0 {
+            return Err("ycsb read-modify-write operations are not supported".to_string());
+        }
+        let weight = |proportion: f64| (proportion * 1000.0).round() as usize;
+        let commands: Vec<Command> = vec![
+            (Action::Get, weight(read)),
+            (Action::Set, weight(update + insert)),
+        ]
+        .into_iter()
+        .filter(|(_, weight)| *weight > 0)
+        .map(|(action, weight)| Command {
+            action,
+            weight,
+            ttl: None,
+            items: None,
+            watermark_low: None,
+            watermark_high: None,
+        })
+        .collect();
+        if commands.is_empty() {
+            return Err("ycsb workload has no operations".to_string());
+        }
+
+        let records = self.recordcount();
+        // records are numbered from 0, so the last needs this many digits
+        let length = ((records as f64).log10().ceil() as usize).max(1);
+        Ok(Keyspace {
+            length,
+            weight: 1,
+            count: Some(records),
+            hitrate: None,
+            distribution,
+            commands,
+            values: vec![Value {
+                length: self.fieldcount.unwrap_or(10) * self.fieldlength.unwrap_or(100),
+                weight: 1,
+                class: Class::Alphanumeric,
+            }],
+        })
+    }
+
+    /// describe the workload
+    pub fn describe(&self) -> String {
+        let ([read, update, insert, _, rmw], distribution) = self.mix();
+        let name = match self.workload {
+            Some(workload) => format!("{:?}", workload),
+            None => "Custom".to_string(),
+        };
+        format!(
+            "Workload: {} Records: {} Read: {} Update: {} Insert: {} RMW: {} Distribution: {:?}",
+            name,
+            self.recordcount(),
+            read,
+            update,
+            insert,
+            rmw,
+            distribution
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn weights(keyspace: &Keyspace) -> Vec<(Action, usize)> {
+        keyspace
+            .commands
+            .iter()
+            .map(|c| (c.action(), c.weight()))
+            .collect()
+    }
+
+    #[test]
+    fn workloads() {
+        let ycsb: Ycsb = toml::from_str("workload = \"a\"\nrecordcount = 100000").unwrap();
+        let keyspace = ycsb.keyspace().unwrap();
+        assert_eq!(keyspace.length, 5);
+        assert_eq!(keyspace.count, Some(100_000));
+        assert_eq!(keyspace.distribution, KeyDistribution::Zipfian);
+        assert_eq!(keyspace.values[0].length(), 1000);
+        assert_eq!(
+            weights(&keyspace),
+            vec![(Action::Get, 500), (Action::Set, 500)]
+        );
+
+        let ycsb = Ycsb::from_arg("c", None).unwrap();
+        assert_eq!(
+            weights(&ycsb.keyspace().unwrap()),
+            vec![(Action::Get, 1000)]
+        );
+
+        assert!(Ycsb::from_arg("workloadf", Some(ycsb))
+            .unwrap()
+            .keyspace()
+            .is_err());
+
+        let ycsb = Ycsb::from_arg("d", None).unwrap();
+        assert_eq!(
+            ycsb.keyspace().unwrap().distribution,
+            KeyDistribution::Latest
+        );
+
+        assert!(Ycsb::from_arg("e", None).unwrap().keyspace().is_err());
+    }
+
+    #[test]
+    fn properties() {
+        let ycsb = Ycsb::from_properties(
+            "# Yahoo! Cloud System Benchmark\n\
+             recordcount=1000\n\
+             operationcount=5000\n\
+             workload=site.ycsb.workloads.CoreWorkload\n\
+             readallfields=true\n\
+             readproportion=0.9\n\
+             updateproportion=0.1\n\
+             scanproportion=0\n\
+             insertproportion=0\n\
+             requestdistribution=uniform\n",
+        )
+        .unwrap();
+        assert_eq!(ycsb.operationcount(), Some(5000));
+        let keyspace = ycsb.keyspace().unwrap();
+        assert_eq!(keyspace.length, 3);
+        assert_eq!(keyspace.distribution, KeyDistribution::Uniform);
+        assert_eq!(
+            weights(&keyspace),
+            vec![(Action::Get, 900), (Action::Set, 100)]
+        );
+
+        assert!(Ycsb::from_properties("recordcount=many").is_err());
+        assert!(Ycsb::from_properties("requestdistribution=hotspot").is_err());
+    }
+}
@@ -0,0 +1,116 @@
+// Copyright 2021 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+//! A zipfian distribution of key ranks, using the algorithm from "Quickly
+//! Generating Billion-Record Synthetic Databases" by Gray et al, which is also
+//! the one used by YCSB.
+
+use rand::distributions::Distribution;
+use rand::Rng;
+
+/// the skew used by YCSB
+pub const THETA: f64 = 0.99;
+
+// beyond this many items the zeta function is approximated by an integral
+const EXACT_ZETA: usize = 1_000_000;
+
+#[derive(Clone, Debug)]
+pub struct Zipfian {
+    items: usize,
+    theta: f64,
+    alpha: f64,
+    zeta: f64,
+    eta: f64,
+}
+
+impl Zipfian {
+    pub fn new(items: usize, theta: f64) -> Self {
+        let items = items.max(1);
+        let zeta_n = zeta(items, theta);
+        let zeta_2 = zeta(2, theta);
+        let eta = (1.0 - (2.0 / items as f64).powf(1.0 - theta)) / (1.0 - zeta_2 / zeta_n);
+        Self {
+            items,
+            theta,
+            alpha: 1.0 / (1.0 - theta),
+            zeta: zeta_n,
+            eta,
+        }
+    }
+}
+
+impl Distribution<usize> for Zipfian {
+    /// a rank, where 0 is the most popular
+    fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> usize {
+        let u: f64 = rng.gen();
+        let uz = u * self.zeta;
+        if uz < 1.0 {
+            return 0;
+        }
+        if uz < 1.0 + 0.5_f64.powf(self.theta) {
+            return 1.min(self.items - 1);
+        }
+        let rank = self.items as f64 * (self.eta * u - self.eta + 1.0).powf(self.alpha);
+        (rank as usize).min(self.items - 1)
+    }
+}
+
+/// the sum of `1 / i^theta` for `i` from 1 to `n`
+fn zeta(n: usize, theta: f64) -> f64 {
+    let exact = n.min(EXACT_ZETA);
+    let mut sum: f64 = (1..=exact).map(|i| 1.0 / (i as f64).powf(theta)).sum();
+    if n > exact {
+        let integral = |x: f64| x.powf(1.0 - theta) / (1.0 - theta);
+        sum += integral(n as f64 + 0.5) - integral(exact as f64 + 0.5);
+    }
+    sum
+}
+
+/// spread the popular ranks over the keyspace, so that they aren't all
+/// adjacent, using the FNV-1a hash as YCSB does
+pub fn scramble(rank: usize, items: usize) -> usize {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for byte in (rank as u64).to_le_bytes().iter() {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+    (hash % items.max(1) as u64) as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn skew() {
+        let zipfian = Zipfian::new(1000, THETA);
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut counts = vec![0; 1000];
+        for _ in 0..100_000 {
+            counts[zipfian.sample(&mut rng)] += 1;
+        }
+        // the most popular item is sampled about 1/zeta(1000) of the time
+        let expected = 100_000.0 / zeta(1000, THETA);
+        assert!((counts[0] as f64 - expected).abs() < expected * 0.1);
+        assert!(counts[0] > counts[1] && counts[1] > counts[10]);
+        assert!(counts[10] > counts[999]);
+    }
+
+    #[test]
+    fn approximate() {
+        let exact: f64 = (1..=2 * EXACT_ZETA)
+            .map(|i| 1.0 / (i as f64).powf(THETA))
+            .sum();
+        assert!((zeta(2 * EXACT_ZETA, THETA) - exact).abs() / exact < 1e-6);
+    }
+
+    #[test]
+    fn scrambled() {
+        assert!(scramble(0, 10) < 10);
+        assert_ne!(scramble(0, 1000), scramble(1, 1000));
+        assert_eq!(scramble(5, 1), 0);
+    }
+}
@@ -0,0 +1,165 @@
+// Copyright 2021 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+//! The coordinator splits a run across remote agents. Each agent receives the
+//! config with its share of the request ratelimit, all agents start at the
+//! same time, and their counters and histograms are merged into the
+//! coordinator's metrics so that the normal outputs show the combined run.
+
+use crate::config::Config;
+use crate::stats::{Export, Metrics};
+
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+pub(crate) struct Coordinator {
+    config: Arc<Config>,
+    metrics: Arc<Metrics>,
+    agents: Vec<SocketAddr>,
+    previous: Vec<Export>,
+}
+
+impl Coordinator {
+    pub fn new(config: Arc<Config>, metrics: Arc<Metrics>) -> Self {
+        let agents = config.agents();
+        let previous = vec![Export::new(); agents.len()];
+        Self {
+            config,
+            metrics,
+            agents,
+            previous,
+        }
+    }
+
+    /// configure each agent and have them all start at the given time
+    pub fn start(&self, start: SystemTime) {
+        for (index, agent) in self.agents.iter().enumerate() {
+            let config = self.config.agent_config(self.config.agent_ratelimit(index));
+            if let Err(e) = request(agent, "PUT", "/agent/config", &config) {
+                fatal!("Failed to configure agent {}: {}", agent, e);
+            }
+        }
+        let start = start
+            .duration_since(UNIX_EPOCH)
+            .expect("start time is before the epoch");
+        let start = format!("{}", start.as_millis());
+        for agent in &self.agents {
+            if let Err(e) = request(agent, "PUT", "/agent/start", &start) {
+                fatal!("Failed to start agent {}: {}", agent, e);
+            }
+        }
+        info!("Started {} agents", self.agents.len());
+    }
+
+    /// merge the readings each agent has taken since the last collection
+    pub fn collect(&mut self) {
+        for (index, agent) in self.agents.iter().enumerate() {
+            let export = request(agent, "GET", "/agent/metrics", "").and_then(|body| {
+                Export::parse(&body).ok_or_else(|| "malformed metrics".to_string())
+            });
+            match export {
+                Ok(export) => {
+                    self.metrics.merge(&export.since(&self.previous[index]));
+                    self.previous[index] = export;
+                }
+                Err(e) => {
+                    warn!("Failed to collect metrics from agent {}: {}", agent, e);
+                }
+            }
+        }
+    }
+
+    /// stop all the agents
+    pub fn stop(&self) {
+        for agent in &self.agents {
+            if let Err(e) = request(agent, "PUT", "/agent/stop", "") {
+                warn!("Failed to stop agent {}: {}", agent, e);
+            }
+        }
+    }
+}
+
+/// make an HTTP request to an agent, returning the body of a successful
+/// response
+fn request(agent: &SocketAddr, method: &str, path: &str, body: &str) -> Result<String, String> {
+    let mut stream = TcpStream::connect_timeout(agent, TIMEOUT).map_err(|e| e.to_string())?;
+    let _ = stream.set_read_timeout(Some(TIMEOUT));
+    let _ = stream.set_write_timeout(Some(TIMEOUT));
+    write!(
+        stream,
+        "{} {} HTTP/1.0\r\nHost: {}\r\nContent-Length: {}\r\n\r\n{}",
+        method,
+        path,
+        agent,
+        body.len(),
+        body
+    )
+    .map_err(|e| e.to_string())?;
+    let mut response = String::new();
+    stream
+        .read_to_string(&mut response)
+        .map_err(|e| e.to_string())?;
+    let (head, body) = match response.find("\r\n\r\n") {
+        Some(end) => (&response[..end], &response[end + 4..]),
+        None => (response.as_str(), ""),
+    };
+    let status = head.lines().next().unwrap_or("");
+    if status.split_whitespace().nth(1) == Some("200") {
+        Ok(body.to_string())
+    } else {
+        Err(format!("unexpected response: {}", status))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shares() {
+        let toml = "[general]\n\
+                    agents = [\"127.0.0.1:9001\", \"127.0.0.1:9002\", \"127.0.0.1:9003\"]\n\
+                    request_ratelimit = 1000\n\
+                    [[keyspace]]\n\
+                    length = 8\n\
+                    weight = 1\n\
+                    commands = [{action = \"get\", weight = 1}]\n\
+                    values = [{length = 16, weight = 1}]";
+        let config = Config::from_toml(toml).unwrap();
+        let shares: Vec<usize> = (0..3).map(|i| config.agent_ratelimit(i).unwrap()).collect();
+        assert_eq!(shares, vec![334, 333, 333]);
+        assert_eq!(shares.iter().sum::<usize>(), 1000);
+
+        // each agent must be left some of the rate
+        let toml = toml.replace("request_ratelimit = 1000", "request_ratelimit = 2");
+        assert!(Config::from_toml(&toml).is_err());
+    }
+
+    #[test]
+    fn bursts() {
+        let toml = "[general]\n\
+                    agents = [\"127.0.0.1:9001\", \"127.0.0.1:9002\"]\n\
+                    request_ratelimit = 1000\n\
+                    request_batch = 64\n\
+                    request_burst = 128\n\
+                    [[keyspace]]\n\
+                    length = 8\n\
+                    weight = 1\n\
+                    commands = [{action = \"get\", weight = 1}]\n\
+                    values = [{length = 16, weight = 1}]";
+        let config = Config::from_toml(toml).unwrap();
+        let agent = config.agent_config(config.agent_ratelimit(1));
+        let agent = Config::from_toml(&agent).unwrap();
+        assert_eq!(agent.request_ratelimit(), Some(500));
+        assert_eq!(agent.request_burst(), 64);
+
+        // the agents would reject a share of the burst smaller than a batch
+        let toml = toml.replace("request_burst = 128", "request_burst = 64");
+        assert!(Config::from_toml(&toml).is_err());
+    }
+}
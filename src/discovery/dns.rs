@@ -0,0 +1,255 @@
+// Copyright 2021 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+//! A minimal DNS client which looks up SRV records with the first nameserver
+//! in `/etc/resolv.conf`. Responses which are truncated aren't retried over
+//! TCP, so a record should have no more targets than fit in a UDP response.
+
+use std::fs;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, UdpSocket};
+use std::time::Duration;
+
+const RESOLV_CONF: &str = "/etc/resolv.conf";
+const TIMEOUT: Duration = Duration::from_secs(2);
+const ATTEMPTS: usize = 3;
+
+const TYPE_SRV: u16 = 33;
+const CLASS_IN: u16 = 1;
+// the most compression pointers followed in a name, which guards against
+// pointer loops
+const MAX_JUMPS: usize = 16;
+
+/// a target of an SRV record
+#[derive(Clone, Debug, PartialEq)]
+pub struct Srv {
+    pub priority: u16,
+    pub weight: u16,
+    pub port: u16,
+    pub target: String,
+}
+
+/// look up the targets of the SRV record, ordered by priority and then by
+/// weight, highest first
+pub fn srv(name: &str) -> Result<Vec<Srv>, String> {
+    let nameserver = nameserver();
+    let bind: SocketAddr = if nameserver.is_ipv4() {
+        ([0, 0, 0, 0], 0).into()
+    } else {
+        ([0u16; 8], 0).into()
+    };
+    let socket = UdpSocket::bind(bind).map_err(|e| e.to_string())?;
+    socket
+        .set_read_timeout(Some(TIMEOUT))
+        .map_err(|e| e.to_string())?;
+
+    let id = rand::random();
+    let request = query(id, name)?;
+    let mut buf = [0; 4096];
+    for _ in 0..ATTEMPTS {
+        socket
+            .send_to(&request, nameserver)
+            .map_err(|e| e.to_string())?;
+        match socket.recv_from(&mut buf) {
+            Ok((length, from)) if from == nameserver => {
+                let mut records = parse(&buf[..length], id)?;
+                records.sort_by(|a, b| a.priority.cmp(&b.priority).then(b.weight.cmp(&a.weight)));
+                return Ok(records);
+            }
+            Ok(_) => continue,
+            Err(_) => continue,
+        }
+    }
+    Err(format!("no response from nameserver {}", nameserver))
+}
+
+/// the first nameserver in resolv.conf, or the local resolver
+fn nameserver() -> SocketAddr {
+    let ip = fs::read_to_string(RESOLV_CONF)
+        .ok()
+        .and_then(|conf| {
+            conf.lines()
+                .filter_map(|line| {
+                    let mut words = line.split_whitespace();
+                    match (words.next(), words.next()) {
+                        (Some("nameserver"), Some(ip)) => ip.parse::<IpAddr>().ok(),
+                        _ => None,
+                    }
+                })
+                .next()
+        })
+        .unwrap_or(IpAddr::V4(Ipv4Addr::LOCALHOST));
+    SocketAddr::new(ip, 53)
+}
+
+/// a recursive query for the SRV record
+fn query(id: u16, name: &str) -> Result<Vec<u8>, String> {
+    let mut request = Vec::with_capacity(512);
+    request.extend_from_slice(&id.to_be_bytes());
+    // recursion desired
+    request.extend_from_slice(&[0x01, 0x00]);
+    // one question, no answer, authority or additional records
+    request.extend_from_slice(&[0, 1, 0, 0, 0, 0, 0, 0]);
+    for label in name.trim_end_matches('.').split('.') {
+        if label.is_empty() || label.len() > 63 {
+            return Err(format!("invalid name: {}", name));
+        }
+        request.push(label.len() as u8);
+        request.extend_from_slice(label.as_bytes());
+    }
+    request.push(0);
+    request.extend_from_slice(&TYPE_SRV.to_be_bytes());
+    request.extend_from_slice(&CLASS_IN.to_be_bytes());
+    Ok(request)
+}
+
+/// parse the SRV records from the answers of a response
+fn parse(response: &[u8], id: u16) -> Result<Vec<Srv>, String> {
+    if response.len() < 12 {
+        return Err("response is too short".to_string());
+    }
+    if read_u16(response, 0)? != id {
+        return Err("response is for another query".to_string());
+    }
+    if response[2] & 0x02 != 0 {
+        return Err("response was truncated".to_string());
+    }
+    match response[3] & 0x0f {
+        0 => {}
+        3 => return Err("no such record".to_string()),
+        rcode => return Err(format!("nameserver error: {}", rcode)),
+    }
+    let questions = read_u16(response, 4)?;
+    let answers = read_u16(response, 6)?;
+
+    let mut offset = 12;
+    for _ in 0..questions {
+        offset = read_name(response, offset)?.1 + 4;
+    }
+    let mut records = Vec::new();
+    for _ in 0..answers {
+        offset = read_name(response, offset)?.1;
+        let kind = read_u16(response, offset)?;
+        let length = read_u16(response, offset + 8)? as usize;
+        let data = offset + 10;
+        if kind == TYPE_SRV {
+            records.push(Srv {
+                priority: read_u16(response, data)?,
+                weight: read_u16(response, data + 2)?,
+                port: read_u16(response, data + 4)?,
+                target: read_name(response, data + 6)?.0,
+            });
+        }
+        offset = data + length;
+    }
+    Ok(records)
+}
+
+fn read_u16(buf: &[u8], offset: usize) -> Result<u16, String> {
+    match buf.get(offset..offset + 2) {
+        Some(bytes) => Ok(u16::from_be_bytes([bytes[0], bytes[1]])),
+        None => Err("response is too short".to_string()),
+    }
+}
+
+/// read a possibly compressed name, returning the name and the offset after
+/// it in the record
+fn read_name(buf: &[u8], mut offset: usize) -> Result<(String, usize), String> {
+    let mut labels = Vec::new();
+    let mut end = None;
+    let mut jumps = 0;
+    loop {
+        let length = *buf.get(offset).ok_or("response is too short")? as usize;
+        if length == 0 {
+            offset += 1;
+            break;
+        }
+        if length & 0xc0 == 0xc0 {
+            jumps += 1;
+            if jumps > MAX_JUMPS {
+                return Err("name has too many pointers".to_string());
+            }
+            let pointer = read_u16(buf, offset)? as usize & 0x3fff;
+            end.get_or_insert(offset + 2);
+            offset = pointer;
+            continue;
+        }
+        let label = buf
+            .get(offset + 1..offset + 1 + length)
+            .ok_or("response is too short")?;
+        labels.push(String::from_utf8_lossy(label).into_owned());
+        offset += 1 + length;
+    }
+    Ok((labels.join("."), end.unwrap_or(offset)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a response to the query with an answer for each target, which refer
+    // back to the name in the question
+    fn response(id: u16, name: &str, targets: &[(u16, u16, u16, &str)]) -> Vec<u8> {
+        let mut response = query(id, name).unwrap();
+        response[2] |= 0x80;
+        response[7] = targets.len() as u8;
+        for (priority, weight, port, target) in targets {
+            let mut data = Vec::new();
+            data.extend_from_slice(&priority.to_be_bytes());
+            data.extend_from_slice(&weight.to_be_bytes());
+            data.extend_from_slice(&port.to_be_bytes());
+            for label in target.split('.') {
+                data.push(label.len() as u8);
+                data.extend_from_slice(label.as_bytes());
+            }
+            data.push(0);
+
+            response.extend_from_slice(&[0xc0, 12]);
+            response.extend_from_slice(&TYPE_SRV.to_be_bytes());
+            response.extend_from_slice(&CLASS_IN.to_be_bytes());
+            response.extend_from_slice(&60_u32.to_be_bytes());
+            response.extend_from_slice(&(data.len() as u16).to_be_bytes());
+            response.extend_from_slice(&data);
+        }
+        response
+    }
+
+    #[test]
+    fn parsed() {
+        let name = "_memcache._tcp.cache.example.com";
+        let buf = response(
+            42,
+            name,
+            &[
+                (10, 5, 11211, "a.example.com"),
+                (10, 5, 11212, "b.example.com"),
+            ],
+        );
+        assert_eq!(read_name(&buf, 12).unwrap().0, name);
+        let records = parse(&buf, 42).unwrap();
+        assert_eq!(
+            records,
+            vec![
+                Srv {
+                    priority: 10,
+                    weight: 5,
+                    port: 11211,
+                    target: "a.example.com".to_string()
+                },
+                Srv {
+                    priority: 10,
+                    weight: 5,
+                    port: 11212,
+                    target: "b.example.com".to_string()
+                },
+            ]
+        );
+
+        assert!(parse(&buf, 43).is_err());
+        assert!(parse(&buf[..buf.len() - 4], 42).is_err());
+        let mut missing = response(42, name, &[]);
+        missing[3] = 0x83;
+        assert_eq!(parse(&missing, 42), Err("no such record".to_string()));
+        assert!(query(1, "bad..name").is_err());
+    }
+}
@@ -0,0 +1,254 @@
+// Copyright 2021 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+//! Endpoint discovery. The endpoints are enumerated from a DNS SRV record or
+//! from a seed node of the cluster, so that large clusters don't need their
+//! hosts listed in the config. The endpoints are discovered at startup and
+//! may be refreshed during the run, in which case the clients pick up the
+//! changes from the shared `Endpoints`.
+
+mod dns;
+
+use crate::config::{Discovery, Source};
+
+use rustcommon_atomics::{Arithmetic, Atomic, AtomicU64, Ordering};
+
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpStream, ToSocketAddrs};
+use std::sync::Mutex;
+use std::time::Duration;
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// enumerate the endpoints, which are sorted so that every process which
+/// discovers the same cluster has them in the same order
+pub fn discover(discovery: &Discovery) -> Result<Vec<SocketAddr>, String> {
+    let mut endpoints = match discovery.source() {
+        Source::Srv => srv(discovery.target())?,
+        Source::RedisCluster => redis_cluster(discovery.target())?,
+        Source::Twemproxy => twemproxy(discovery.target())?,
+    };
+    endpoints.sort();
+    endpoints.dedup();
+    if endpoints.is_empty() {
+        return Err(format!("no endpoints found for {}", discovery));
+    }
+    Ok(endpoints)
+}
+
+/// the targets of the SRV record
+fn srv(name: &str) -> Result<Vec<SocketAddr>, String> {
+    let mut endpoints = Vec::new();
+    for record in dns::srv(name)? {
+        endpoints.push(resolve(&format!("{}:{}", record.target, record.port))?);
+    }
+    Ok(endpoints)
+}
+
+/// the masters of a redis cluster, from `CLUSTER NODES` on the seed node
+fn redis_cluster(seed: &str) -> Result<Vec<SocketAddr>, String> {
+    let mut stream = connect(seed)?;
+    stream
+        .write_all(b"CLUSTER NODES\r\n")
+        .map_err(|e| format!("failed to query {}: {}", seed, e))?;
+    let mut reader = BufReader::new(stream);
+    let mut header = String::new();
+    reader
+        .read_line(&mut header)
+        .map_err(|e| format!("failed to read from {}: {}", seed, e))?;
+    let length = match header.trim_end().strip_prefix('$') {
+        Some(length) => length
+            .parse::<usize>()
+            .map_err(|_| format!("unexpected response from {}: {}", seed, header.trim_end()))?,
+        None => {
+            return Err(format!(
+                "unexpected response from {}: {}",
+                seed,
+                header.trim_end()
+            ))
+        }
+    };
+    let mut nodes = vec![0; length];
+    reader
+        .read_exact(&mut nodes)
+        .map_err(|e| format!("failed to read from {}: {}", seed, e))?;
+    let mut endpoints = Vec::new();
+    for node in parse_cluster_nodes(&String::from_utf8_lossy(&nodes)) {
+        endpoints.push(resolve(&node)?);
+    }
+    Ok(endpoints)
+}
+
+/// the addresses of the masters which aren't failed, from the reply to
+/// `CLUSTER NODES`
+fn parse_cluster_nodes(nodes: &str) -> Vec<String> {
+    let mut addresses = Vec::new();
+    for line in nodes.lines() {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        if fields.len() < 3 {
+            continue;
+        }
+        let flags: Vec<&str> = fields[2].split(',').collect();
+        if !flags.contains(&"master")
+            || flags
+                .iter()
+                .any(|f| ["fail", "fail?", "handshake", "noaddr"].contains(f))
+        {
+            continue;
+        }
+        // ip:port@cport,hostname in recent versions, ip:port in old ones
+        let address = fields[1].split(&['@', ','][..]).next().unwrap();
+        if address.starts_with(':') {
+            continue;
+        }
+        addresses.push(address.to_string());
+    }
+    addresses
+}
+
+/// the servers of each pool in the stats of a twemproxy seed node
+fn twemproxy(seed: &str) -> Result<Vec<SocketAddr>, String> {
+    let mut stream = connect(seed)?;
+    let mut stats = String::new();
+    stream
+        .read_to_string(&mut stats)
+        .map_err(|e| format!("failed to read from {}: {}", seed, e))?;
+    let mut endpoints = Vec::new();
+    for server in parse_twemproxy_stats(&stats)? {
+        match resolve(&server) {
+            Ok(endpoint) => endpoints.push(endpoint),
+            // servers which are named in the twemproxy config are listed by
+            // their name rather than their address
+            Err(e) => warn!("Discovery: skipping twemproxy server: {}", e),
+        }
+    }
+    Ok(endpoints)
+}
+
+/// the servers in twemproxy stats, which are the objects within a pool that
+/// count server connections. Servers are listed as `host:port` or as
+/// `host:port:weight`.
+fn parse_twemproxy_stats(stats: &str) -> Result<Vec<String>, String> {
+    let stats: serde_json::Value =
+        serde_json::from_str(stats).map_err(|e| format!("malformed twemproxy stats: {}", e))?;
+    let mut servers = Vec::new();
+    for pool in stats.as_object().into_iter().flat_map(|s| s.values()) {
+        for (name, server) in pool.as_object().into_iter().flatten() {
+            if server.get("server_connections").is_none() {
+                continue;
+            }
+            let mut parts: Vec<&str> = name.rsplitn(3, ':').collect();
+            if parts.len() == 3 {
+                parts.remove(0);
+            }
+            parts.reverse();
+            servers.push(parts.join(":"));
+        }
+    }
+    Ok(servers)
+}
+
+fn connect(seed: &str) -> Result<TcpStream, String> {
+    let address = resolve(seed)?;
+    let stream = TcpStream::connect_timeout(&address, TIMEOUT)
+        .map_err(|e| format!("failed to connect to {}: {}", seed, e))?;
+    stream
+        .set_read_timeout(Some(TIMEOUT))
+        .map_err(|e| e.to_string())?;
+    Ok(stream)
+}
+
+fn resolve(address: &str) -> Result<SocketAddr, String> {
+    address
+        .to_socket_addrs()
+        .ok()
+        .and_then(|mut a| a.next())
+        .ok_or_else(|| format!("failed to resolve address: {}", address))
+}
+
+/// The discovered endpoints, which are shared by the runner and its clients.
+/// Each change bumps the version so that clients only need to compare it to
+/// notice a change.
+pub struct Endpoints {
+    version: AtomicU64,
+    endpoints: Mutex<Vec<SocketAddr>>,
+}
+
+impl Endpoints {
+    pub fn new(endpoints: Vec<SocketAddr>) -> Self {
+        Self {
+            version: AtomicU64::new(0),
+            endpoints: Mutex::new(endpoints),
+        }
+    }
+
+    pub fn version(&self) -> u64 {
+        self.version.load(Ordering::Relaxed)
+    }
+
+    pub fn get(&self) -> Vec<SocketAddr> {
+        self.endpoints.lock().unwrap().clone()
+    }
+
+    /// replace the endpoints, returns false if they haven't changed
+    pub fn set(&self, endpoints: Vec<SocketAddr>) -> bool {
+        let mut current = self.endpoints.lock().unwrap();
+        if *current == endpoints {
+            return false;
+        }
+        *current = endpoints;
+        self.version.fetch_add(1, Ordering::Relaxed);
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cluster_nodes() {
+        let nodes = "\
+07c37dfeb235213a872192d90877d0cd55635b91 127.0.0.1:30004@31004 slave e7d1eecce10fd6bb5eb35b9f99a514335d9ba9ca 0 1426238317239 4 connected
+67ed2db8d677e59ec4a4cefb06858cf2a1a89fa1 127.0.0.1:30002@31002 master - 0 1426238316232 2 connected 5461-10922
+292f8b365bb7edb5e285caf0b7e6ddc7265d2f4f 127.0.0.1:30003@31003,cache-3 master - 0 1426238318243 3 connected 10923-16383
+6ec23923021cf3ffec47632106199cb7f496ce01 127.0.0.1:30005@31005 master,fail - 1426238316232 0 5 disconnected
+e7d1eecce10fd6bb5eb35b9f99a514335d9ba9ca 127.0.0.1:30001 myself,master - 0 0 1 connected 0-5460
+824fe116063bc5fcf9f4ffd895bc17aee7731ac3 :0@0 master,noaddr - 1426238316232 0 6 disconnected
+";
+        assert_eq!(
+            parse_cluster_nodes(nodes),
+            vec!["127.0.0.1:30002", "127.0.0.1:30003", "127.0.0.1:30001"]
+        );
+    }
+
+    #[test]
+    fn twemproxy_stats() {
+        let stats = r#"{"service":"nutcracker", "source":"proxy", "version":"0.4.1",
+            "uptime":10, "timestamp":1426238316, "total_connections":4,
+            "curr_connections":3, "alpha": {"client_eof":0, "client_err":0,
+            "client_connections":0, "server_ejects":0, "forward_error":0,
+            "fragments":0, "10.0.0.1:11211:1": {"server_eof":0, "server_err":0,
+            "server_timedout":0, "server_connections":1, "requests":0},
+            "10.0.0.2:11211": {"server_eof":0, "server_err":0,
+            "server_timedout":0, "server_connections":1, "requests":0}}}"#;
+        assert_eq!(
+            parse_twemproxy_stats(stats).unwrap(),
+            vec!["10.0.0.1:11211", "10.0.0.2:11211"]
+        );
+        assert!(parse_twemproxy_stats("STAT pid 1").is_err());
+    }
+
+    #[test]
+    fn endpoints() {
+        let a: SocketAddr = "127.0.0.1:12321".parse().unwrap();
+        let b: SocketAddr = "127.0.0.1:12322".parse().unwrap();
+        let endpoints = Endpoints::new(vec![a]);
+        assert!(!endpoints.set(vec![a]));
+        assert_eq!(endpoints.version(), 0);
+        assert!(endpoints.set(vec![a, b]));
+        assert_eq!(endpoints.version(), 1);
+        assert_eq!(endpoints.get(), vec![a, b]);
+    }
+}
@@ -0,0 +1,25 @@
+// Copyright 2019-2020 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+#[macro_use]
+extern crate rustcommon_logger;
+
+pub mod bundle;
+mod client;
+pub mod codec;
+pub mod common;
+pub mod config;
+mod discovery;
+pub mod limits;
+pub mod metadata;
+mod numa;
+mod ratelimit;
+mod runner;
+mod session;
+pub mod stats;
+
+pub use crate::runner::Runner;
+
+use crate::common::*;
+use crate::config::Config;
@@ -0,0 +1,105 @@
+// Copyright 2021 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+//! Resource guardrails. Each connection is a file descriptor, so a run with
+//! many clients, endpoints or a large poolsize can reach the open files limit
+//! part way through connecting, which would show up as connect errors rather
+//! than as a clear failure. The limit is checked against the descriptors the
+//! run needs before it starts.
+
+use std::io::Error;
+
+/// descriptors which are needed besides the ones for the clients: the
+/// standard streams, the stats and admin listeners, the log, the keyfile and
+/// the outputs
+const RESERVED: u64 = 64;
+
+/// the soft and hard limits on the number of open files
+pub fn nofile() -> Result<(u64, u64), Error> {
+    let mut limit = libc::rlimit {
+        rlim_cur: 0,
+        rlim_max: 0,
+    };
+    if unsafe { libc::getrlimit(libc::RLIMIT_NOFILE, &mut limit) } == 0 {
+        Ok((limit.rlim_cur, limit.rlim_max))
+    } else {
+        Err(Error::last_os_error())
+    }
+}
+
+fn set_nofile(soft: u64, hard: u64) -> Result<(), Error> {
+    let limit = libc::rlimit {
+        rlim_cur: soft,
+        rlim_max: hard,
+    };
+    if unsafe { libc::setrlimit(libc::RLIMIT_NOFILE, &limit) } == 0 {
+        Ok(())
+    } else {
+        Err(Error::last_os_error())
+    }
+}
+
+/// the number of file descriptors the process has open
+#[cfg(target_os = "linux")]
+pub fn open_files() -> Option<u64> {
+    // the directory is read through a descriptor of its own
+    std::fs::read_dir("/proc/self/fd")
+        .ok()
+        .map(|entries| (entries.count() as u64).saturating_sub(1))
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn open_files() -> Option<u64> {
+    std::fs::read_dir("/dev/fd")
+        .ok()
+        .map(|entries| (entries.count() as u64).saturating_sub(1))
+}
+
+/// check that the open files limit covers the descriptors which the clients
+/// need. If `raise` is set, a soft limit which is too low is raised to what is
+/// needed when the hard limit allows it. Returns the soft limit the run has.
+pub fn check(clients: u64, raise: bool) -> Result<u64, String> {
+    let needed = clients + RESERVED;
+    let (soft, hard) =
+        nofile().map_err(|e| format!("failed to read the open files limit: {}", e))?;
+    debug!(
+        "open files: needed: {} limit: {} hard limit: {}",
+        needed, soft, hard
+    );
+    if soft >= needed {
+        return Ok(soft);
+    }
+    if !raise {
+        return Err(format!(
+            "the run needs {} file descriptors but the open files limit is {}, \
+             raise it with `ulimit -n {}` or --raise-fd-limit",
+            needed, soft, needed
+        ));
+    }
+    if hard < needed {
+        return Err(format!(
+            "the run needs {} file descriptors but the hard open files limit is {}, \
+             which only root can raise",
+            needed, hard
+        ));
+    }
+    set_nofile(needed, hard)
+        .map_err(|e| format!("failed to raise the open files limit to {}: {}", needed, e))?;
+    info!("Raised the open files limit from {} to {}", soft, needed);
+    Ok(needed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn limits() {
+        let (soft, hard) = nofile().unwrap();
+        assert!(soft <= hard);
+        assert!(open_files().unwrap() >= 3);
+        assert_eq!(check(0, false), Ok(soft));
+        assert!(check(u64::MAX - RESERVED, false).is_err());
+    }
+}
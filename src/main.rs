@@ -3,45 +3,58 @@
 // http://www.apache.org/licenses/LICENSE-2.0
 
 mod admin;
-mod client;
-mod codec;
-mod common;
-mod config;
-mod session;
-mod stats;
+mod agent;
+mod coordinator;
+mod signal;
 
 #[macro_use]
 extern crate rustcommon_logger;
 
-use crate::common::*;
+use rpc_perf::bundle::Bundle;
+use rpc_perf::limits;
+use rpc_perf::metadata::Metadata;
+use rpc_perf::{config, stats, Runner};
 
-use crate::client::*;
-use crate::codec::Codec;
-use crate::config::Config;
-use crate::config::Protocol;
-use crate::stats::{Metrics, Stat};
+use crate::agent::Agent;
+use crate::coordinator::Coordinator;
+use crate::stats::{Export, Metrics, Stat, SETTLE};
 
-use rand::thread_rng;
 use rustcommon_atomics::{Atomic, AtomicBool, Ordering};
 use rustcommon_logger::Logger;
-use rustcommon_ratelimiter::Ratelimiter;
 
 use std::convert::TryInto;
+use std::process;
 use std::sync::Arc;
 use std::thread;
-use std::time::{Duration, Instant};
+use std::time::{Duration, Instant, SystemTime};
 
 pub const VERSION: &str = env!("CARGO_PKG_VERSION");
 
 pub fn main() {
     let config = config::Config::new();
 
+    if config.dashboard() {
+        println!("{}", stats::dashboard(&config));
+        return;
+    }
+
     Logger::new()
         .label("rpc_perf")
         .level(config.logging())
         .init()
         .expect("Failed to initialize logger");
 
+    signal::init();
+
+    // an agent waits for the coordinator to send the config for the run
+    let (config, agent) = if config.agent() {
+        let mut agent = Agent::new(config.admin().unwrap());
+        let (received, start) = agent.configure(&config);
+        (received, Some((agent, start)))
+    } else {
+        (config, None)
+    };
+
     let config = Arc::new(config);
     let metrics = Arc::new(Metrics::new(config.clone()));
 
@@ -51,9 +64,76 @@ pub fn main() {
         Duration::new(config.interval().try_into().unwrap(), 0),
     );
 
+    // the requests are generated here unless the run is split across agents
+    let seed = if config.agents().is_empty() {
+        Some(rand::random())
+    } else {
+        None
+    };
+    let mut metadata = Metadata::new(&config, seed);
+
+    // ready once the workload is being measured
+    let ready = Arc::new(AtomicBool::new(false));
+
+    // the histogram buckets of each window are served on the stats port
+    let mut buckets = config.listen().map(|_| stats::Buckets::new());
+
+    info!("rpc-perf {} initializing...", VERSION);
+
+    config.print();
+
+    // fail before connecting if the open files limit is too low for the
+    // clients, the coordinator opens no connections of its own
+    if seed.is_some() {
+        if let Err(e) = limits::check(config.file_descriptors() as u64, config.raise_fd_limit()) {
+            fatal!("{}", e);
+        }
+    }
+
+    // the coordinator only merges the metrics from its agents, otherwise
+    // there is a runner for the endpoints and one for any compare group, and
+    // one for each client group
+    let mut coordinator = None;
+    let mut runners = Vec::new();
+    let mut groups = Vec::new();
+    if let Some(seed) = seed {
+        let mut runner = Runner::with_metrics(config.clone(), metrics.clone());
+        // clients with the same index in each group share a seed, so that they
+        // generate the same sequence of requests
+        runner.set_seed(seed);
+        runners.push(Arc::new(runner));
+        if config.compare() {
+            let compare = config.for_compare();
+            let mut runner = Runner::new(compare);
+            runner.set_seed(seed);
+            runners.push(Arc::new(runner));
+        }
+        for group in config.groups() {
+            let config = config.for_group(group);
+            groups.push(Arc::new(Runner::new(config)));
+        }
+    } else {
+        coordinator = Some(Coordinator::new(config.clone(), metrics.clone()));
+    }
+
+    // a redis_auto run speaks the protocol negotiated with each endpoint, and
+    // each agent negotiates with the endpoints from its own host
+    if let Some(negotiated) = runners.first().and_then(|runner| runner.negotiated()) {
+        metadata.set_negotiated(&negotiated);
+    }
+    info!("Run: {}", metadata.header());
+
     if let Some(stats_listen) = config.listen() {
         trace!("launching http stats");
-        let mut stats_http = stats::Http::new(stats_listen, metrics.inner(), None);
+        let mut stats_http = stats::Http::new(stats_listen, metrics.clone(), None);
+        stats_http.set_ready(ready.clone());
+        stats_http.set_metadata(metadata.clone());
+        for (group, runner) in config.groups().iter().zip(&groups) {
+            stats_http.add_group(group.name(), runner.metrics().clone());
+        }
+        if let Some(ref buckets) = buckets {
+            stats_http.set_buckets(buckets.latest());
+        }
         let _ = thread::Builder::new()
             .name("http".to_string())
             .spawn(move || loop {
@@ -61,57 +141,92 @@ pub fn main() {
             });
     }
 
-    info!("rpc-perf {} initializing...", VERSION);
+    let mut stats_compare = runners
+        .get(1)
+        .map(|_| stats::Compare::new(Duration::new(config.interval() as u64, 0)));
 
-    config.print();
+    let mut stats_groups = if groups.is_empty() {
+        None
+    } else {
+        Some(stats::Groups::new(
+            config
+                .groups()
+                .iter()
+                .map(|group| group.name().to_string())
+                .collect(),
+            Duration::new(config.interval() as u64, 0),
+        ))
+    };
 
-    do_warmup(config.clone(), &metrics);
+    for runner in runners.iter().chain(&groups) {
+        if let Err(e) = runner.preload().and_then(|_| runner.warmup()) {
+            fatal!("{}", e);
+        }
+    }
 
     let control = Arc::new(AtomicBool::new(true));
 
-    let request_ratelimiter = if let Some(limit) = config.request_ratelimit() {
-        let ratelimiter = Ratelimiter::new(config.clients() as u64, 1, limit as u64);
-        ratelimiter.set_strategy(config.request_distribution());
-        Some(Arc::new(ratelimiter))
-    } else {
-        None
-    };
+    let interval = Duration::new(config.interval() as u64, 0);
 
-    let connect_ratelimiter = if let Some(limit) = config.connect_ratelimit() {
-        Some(Arc::new(Ratelimiter::new(
-            config.clients() as u64,
-            1,
-            limit as u64,
-        )))
+    let start = if let Some((mut agent, start)) = agent {
+        let metrics = metrics.clone();
+        let control = control.clone();
+        let _ = thread::Builder::new()
+            .name("agent".to_string())
+            .spawn(move || loop {
+                agent.run(&metrics, &control);
+            });
+        Some(start)
+    } else if let Some(ref coordinator) = coordinator {
+        // leave the agents time to receive the start time
+        let earliest = SystemTime::now() + Duration::from_secs(1);
+        let start = align(config.start_at().unwrap_or(earliest), interval, earliest);
+        coordinator.start(start);
+        Some(start)
     } else {
-        None
+        config
+            .start_at()
+            .map(|start| align(start, interval, SystemTime::now()))
     };
 
-    let close_rate = if let Some(rate) = config.close_rate() {
-        Some(Arc::new(Ratelimiter::new(
-            config.clients() as u64,
-            1,
-            rate as u64,
-        )))
-    } else {
-        None
-    };
+    let mut first = Instant::now();
+    if let Some(start) = start {
+        let delay = start.duration_since(SystemTime::now()).unwrap_or_default();
+        info!("Starting in {:.1}s", delay.as_secs_f64());
+        first += delay;
+        thread::sleep(delay);
+    }
 
-    let client_config = ClientConfig {
-        config: config.clone(),
-        metrics: metrics.clone(),
-        control: control.clone(),
-        request_ratelimiter,
-        connect_ratelimiter,
-        close_rate,
-    };
+    // the clients flush their readings at each window boundary, so that
+    // the snapshot of each window has all of its readings
+    let mut windows = stats::Windows::new(first, interval);
+    for runner in runners.iter().chain(&groups) {
+        runner.metrics().set_window_start(windows.start());
+        if let Err(e) = runner.start() {
+            fatal!("{}", e);
+        }
+    }
 
-    let mut next = Instant::now() + Duration::new(config.interval() as u64, 0);
+    if let Some(windows) = config.windows() {
+        let runtime = interval * windows as u32;
+        info!(
+            "Running for {}s, until {}",
+            runtime.as_secs(),
+            rpc_perf::common::format_utc(SystemTime::now() + runtime)
+        );
+    }
 
-    launch_clients(client_config.clone());
+    let mut bundle = config.bundle().map(|_| {
+        let mut bundle = Bundle::new(metrics.clone());
+        for (group, runner) in config.groups().iter().zip(&groups) {
+            bundle.add_group(group.name(), runner.metrics().clone());
+        }
+        bundle
+    });
 
-    if let Some(listen) = config.admin() {
-        let mut admin_http = admin::Http::new(listen, client_config);
+    // an agent's admin port is used by the coordinator
+    if let Some(listen) = config.admin().filter(|_| !config.agent()) {
+        let mut admin_http = admin::Http::new(listen, runners.clone());
         let _ = thread::Builder::new()
             .name("admin".to_string())
             .spawn(move || loop {
@@ -119,135 +234,219 @@ pub fn main() {
             });
     }
 
-    loop {
+    ready.store(true, Ordering::SeqCst);
+    let measured = Instant::now();
+
+    while control.load(Ordering::SeqCst) {
         let now = Instant::now();
-        if next > now {
-            std::thread::sleep(std::time::Duration::from_millis(1));
-        } else {
+        let completed = config
+            .operations()
+            .map(|operations| {
+                metrics.reading(&Stat::ResponsesTotal).unwrap_or(0) >= operations as u64
+            })
+            .unwrap_or(false);
+        // the length of the window which just ended, and whether it is the
+        // last one
+        let (length, last) = if signal::terminated() || completed {
+            ready.store(false, Ordering::SeqCst);
+            if completed {
+                info!("Completed {} operations", config.operations().unwrap());
+            } else {
+                drain(&config, runners.iter().chain(&groups));
+            }
+            // the final window is cut short
+            if let Some(ref mut coordinator) = coordinator {
+                coordinator.collect();
+            }
             metrics.increment(&Stat::Window);
-            stats_stdout.print();
-
-            if let Some(max_window) = config.windows() {
-                if metrics.reading(&Stat::Window).unwrap() >= max_window as u64 {
-                    control.store(false, Ordering::SeqCst);
-                    break;
-                }
+            (
+                now.saturating_duration_since(windows.next() - interval),
+                true,
+            )
+        } else if let Some(tick) = windows.tick(now) {
+            if let Some(ref mut coordinator) = coordinator {
+                coordinator.collect();
             }
-
-            next += Duration::new(config.interval() as u64, 0);
-        }
-    }
-    if let Some(waterfall) = config.waterfall() {
-        metrics.save_waterfall(waterfall);
-    }
-}
-
-fn do_warmup(config: Arc<Config>, metrics: &Arc<Metrics>) {
-    if let Some(target) = config.warmup_hitrate() {
-        info!("-----");
-        info!("Warming the cache...");
-        let control = Arc::new(AtomicBool::new(true));
-
-        let client_config = ClientConfig {
-            config: config.clone(),
-            metrics: metrics.clone(),
-            control: control.clone(),
-            request_ratelimiter: None,
-            connect_ratelimiter: None,
-            close_rate: None,
+            // the boundaries which passed while the process was stalled are
+            // counted, so that the windows stay on the same boundaries, and
+            // their readings are reported as one longer window
+            if tick.missed > 0 {
+                warn!(
+                    "Missed {} windows, the process was stalled for {:.1}s",
+                    tick.missed,
+                    (now - SETTLE - (tick.boundary - interval * tick.missed as u32)).as_secs_f64(),
+                );
+                metrics.add(&Stat::WindowMissed, tick.missed);
+            }
+            metrics.add(&Stat::Window, tick.missed + 1);
+            (interval * (tick.missed + 1) as u32, false)
+        } else {
+            thread::sleep(Duration::from_millis(1));
+            continue;
         };
 
-        launch_clients(client_config);
-
-        let mut warm = 0;
-        loop {
-            std::thread::sleep(std::time::Duration::new(config.interval() as u64, 0));
-            metrics.increment(&Stat::Window);
-
-            let hit = metrics.reading(&Stat::ResponsesHit).unwrap_or(0) as f64;
-            let miss = metrics.reading(&Stat::ResponsesMiss).unwrap_or(0) as f64;
-            let hitrate = hit / (hit + miss);
-
-            debug!("Hit-rate: {:.2}%", hitrate * 100.0);
-            if hitrate >= target {
-                warm += 1;
-            } else {
-                warm = 0;
-            }
+        // every output of the window is from the same snapshot. The compare
+        // and group runners record into metrics of their own.
+        let snapshot = metrics.snapshot();
+        let compare = runners.get(1).map(|runner| runner.metrics().snapshot());
+        let group_snapshots: Vec<Export> = groups
+            .iter()
+            .map(|runner| runner.metrics().snapshot())
+            .collect();
+        stats_stdout.set_interval(length);
+        stats_stdout.print(&snapshot);
+        if let (Some(stats_compare), Some(compare)) = (&mut stats_compare, &compare) {
+            stats_compare.set_interval(length);
+            stats_compare.print([&snapshot, compare]);
+        }
+        if let Some(ref mut stats_groups) = stats_groups {
+            stats_groups.set_interval(length);
+            stats_groups.print(&group_snapshots);
+        }
+        if let Some(ref mut bundle) = bundle {
+            bundle.window(snapshot.clone(), &group_snapshots);
+        }
+        if let Some(ref mut buckets) = buckets {
+            buckets.window(metrics.reading(&Stat::Window).unwrap_or(0), snapshot);
+        }
+        if last {
+            break;
+        }
 
-            if warm >= 3 {
-                metrics.zero();
+        for runner in &runners {
+            runner.backoff();
+        }
+        if let Some(max_window) = config.windows() {
+            if metrics.reading(&Stat::Window).unwrap() >= max_window as u64 {
                 control.store(false, Ordering::SeqCst);
                 break;
             }
-
-            metrics.zero();
         }
-
-        info!("Warmup complete.");
+    }
+    if let Some(coordinator) = coordinator {
+        coordinator.stop();
+    }
+    for runner in runners.iter().chain(&groups) {
+        runner.stop();
+    }
+    let mut summary = stats::Summary::new(
+        metrics.export(),
+        measured.elapsed(),
+        metrics.reading(&Stat::Window).unwrap_or(0),
+        &config.thresholds(),
+    );
+    for (group, runner) in config.groups().iter().zip(&groups) {
+        summary.add_group(
+            group.name(),
+            stats::Summary::new(
+                runner.metrics().export(),
+                measured.elapsed(),
+                metrics.reading(&Stat::Window).unwrap_or(0),
+                &runner.config().thresholds(),
+            ),
+        );
+    }
+    summary.print();
+    if config.pushback_backoff().is_some() {
+        match runners.first().and_then(|runner| runner.knee()) {
+            Some(knee) => info!("Pushback: Knee: {} rps", knee),
+            None => info!("Pushback: no knee was found"),
+        }
+    }
+    if let Some(path) = config.summary() {
+        match std::fs::write(&path, summary.json(&metadata)) {
+            Ok(()) => info!("Saved summary: {}", path),
+            Err(e) => error!("failed to save summary {}: {}", path, e),
+        }
+    }
+    // with agents, each agent counts the keys of its own requests
+    if let (Some(path), Some(_)) = (config.popularity(), seed) {
+        let popularity = metrics.take_popularity();
+        info!(
+            "Popularity: Keys: {} Top 1%: {:.2}% Top 10%: {:.2}%",
+            popularity.keys(),
+            popularity.share(1.0),
+            popularity.share(10.0),
+        );
+        match std::fs::write(&path, popularity.csv()) {
+            Ok(()) => info!("Saved key popularity: {}", path),
+            Err(e) => error!("failed to save key popularity {}: {}", path, e),
+        }
+    }
+    if let Some(waterfall) = config.waterfall() {
+        metrics.save_waterfall(waterfall);
+    }
+    if let (Some(path), Some(bundle)) = (config.bundle(), bundle) {
+        match bundle.write(&path, &config, &metadata) {
+            Ok(()) => info!("Saved bundle: {}", path),
+            Err(e) => error!("failed to save bundle {}: {}", path, e),
+        }
+    }
+    if !summary.passed() {
+        process::exit(1);
     }
 }
 
-#[derive(Clone)]
-pub(crate) struct ClientConfig {
-    config: Arc<Config>,
-    metrics: Arc<Metrics>,
-    control: Arc<AtomicBool>,
-    request_ratelimiter: Option<Arc<Ratelimiter>>,
-    connect_ratelimiter: Option<Arc<Ratelimiter>>,
-    close_rate: Option<Arc<Ratelimiter>>,
+/// stop sending requests and wait for the responses to in-flight requests.
+/// The process exits if the results aren't written within the grace period.
+fn drain<'a>(config: &config::Config, runners: impl Iterator<Item = &'a Arc<Runner>>) {
+    let grace = config.grace_period();
+    info!("Received SIGTERM, draining for up to {}s", grace.as_secs());
+    let _ = thread::Builder::new()
+        .name("grace".to_string())
+        .spawn(move || {
+            thread::sleep(grace);
+            error!("grace period expired before the results were saved");
+            process::exit(1);
+        });
+
+    // requests which are still in-flight after the request timeout would be
+    // counted as timeouts anyway
+    let timeout = Duration::from_micros(config.request_timeout() as u64).min(grace);
+    for runner in runners {
+        runner.drain(timeout);
+    }
 }
 
-fn launch_clients(config: ClientConfig) {
-    let control = config.control.clone();
-    let metrics = config.metrics.clone();
-
-    for i in 0..config.config.clients() {
-        let request_ratelimiter = config.request_ratelimiter.clone();
-        let connect_ratelimiter = config.connect_ratelimiter.clone();
-        let close_rate = config.close_rate.clone();
-        let config = config.config.clone();
-        let mut codec: Box<dyn Codec> = match config.protocol() {
-            Protocol::Echo => Box::new(crate::codec::Echo::new()),
-            Protocol::Memcache => Box::new(crate::codec::Memcache::new()),
-            Protocol::ThriftCache => Box::new(crate::codec::ThriftCache::new()),
-            Protocol::PelikanRds => Box::new(crate::codec::PelikanRds::new()),
-            Protocol::Ping => Box::new(crate::codec::Ping::new()),
-            Protocol::RedisResp => {
-                Box::new(crate::codec::Redis::new(crate::codec::RedisMode::Resp))
-            }
-            Protocol::RedisInline => {
-                Box::new(crate::codec::Redis::new(crate::codec::RedisMode::Inline))
-            }
-        };
-
-        // TODO: use a different generator for warmup
-        codec.set_generator(config.generator());
-        codec.set_metrics(metrics.clone());
-
-        let mut client = Client::new(
-            i,
-            config.clone(),
-            connect_ratelimiter,
-            request_ratelimiter,
-            close_rate,
-            metrics.clone(),
-        );
-
-        let endpoints = config.endpoints();
-
-        for endpoint in endpoints {
-            client.add_endpoint(&endpoint);
+/// the first window boundary, counting from `start`, which is not before
+/// `earliest`. An instance which is late joins at a later boundary, so that
+/// its windows still line up with those of the other instances.
+fn align(start: SystemTime, interval: Duration, earliest: SystemTime) -> SystemTime {
+    match earliest.duration_since(start) {
+        Ok(late) if late > Duration::from_secs(0) => {
+            let interval = interval.as_nanos().max(1);
+            let windows = late.as_nanos().div_ceil(interval);
+            warn!("Start time has passed, skipping {} windows", windows);
+            start + Duration::from_nanos((windows * interval) as u64)
         }
+        _ => start,
+    }
+}
 
-        let control = control.clone();
-        let _ = thread::Builder::new()
-            .name(format!("client{}", i).to_string())
-            .spawn(move || {
-                let mut rng = thread_rng();
-                while control.load(Ordering::SeqCst) {
-                    client.run(&mut rng);
-                }
-            });
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn aligned() {
+        let start = SystemTime::UNIX_EPOCH + Duration::from_secs(1_600_000_000);
+        let interval = Duration::from_secs(10);
+        // a start time which hasn't passed is kept
+        assert_eq!(align(start, interval, start - interval), start);
+        assert_eq!(align(start, interval, start), start);
+        // a late instance joins at the next boundary
+        assert_eq!(
+            align(start, interval, start + Duration::from_secs(15)),
+            start + interval * 2
+        );
+        assert_eq!(
+            align(start, interval, start + Duration::from_nanos(1)),
+            start + interval
+        );
+        // an instance which is exactly on a boundary joins at that boundary
+        assert_eq!(
+            align(start, interval, start + interval * 2),
+            start + interval * 2
+        );
     }
 }
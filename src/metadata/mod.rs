@@ -0,0 +1,218 @@
+// Copyright 2021 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+//! Metadata which identifies a run, so that results from a large sweep can
+//! be attributed to the run which produced them. It is included in the stdout
+//! header, the outputs of the stats port and the bundle.
+
+use crate::common::format_utc;
+use crate::config::{Config, VERSION};
+
+use serde_json::{Map, Value};
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// the commit rpc-perf was built from, if it was recorded by the build
+pub const COMMIT: Option<&str> = option_env!("RPC_PERF_COMMIT");
+
+#[derive(Clone, Debug)]
+pub struct Metadata {
+    run_id: Option<String>,
+    labels: Vec<(String, String)>,
+    config_hash: String,
+    negotiated: Option<String>,
+    seed: Option<u64>,
+    hostname: String,
+    started: SystemTime,
+}
+
+impl Metadata {
+    /// the metadata of a run which starts now. The seed is the one which the
+    /// request generators are seeded with, if the requests are generated
+    /// here.
+    pub fn new(config: &Config, seed: Option<u64>) -> Self {
+        Self {
+            run_id: config.run_id(),
+            labels: config.labels().into_iter().collect(),
+            config_hash: format!("{:016x}", config.hash()),
+            negotiated: None,
+            seed,
+            hostname: hostname(),
+            started: SystemTime::now(),
+        }
+    }
+
+    /// record the protocols which were negotiated with the endpoints
+    pub fn set_negotiated(&mut self, protocol: &str) {
+        self.negotiated = Some(protocol.to_string());
+    }
+
+    pub fn started(&self) -> SystemTime {
+        self.started
+    }
+
+    /// the metadata as keys and values, in a fixed order
+    pub fn fields(&self) -> Vec<(String, String)> {
+        let mut fields = Vec::new();
+        if let Some(ref run_id) = self.run_id {
+            fields.push(("run_id".to_string(), run_id.clone()));
+        }
+        fields.push(("version".to_string(), VERSION.to_string()));
+        fields.push((
+            "commit".to_string(),
+            COMMIT.unwrap_or("unknown").to_string(),
+        ));
+        fields.push(("config_hash".to_string(), self.config_hash.clone()));
+        if let Some(ref negotiated) = self.negotiated {
+            fields.push(("negotiated".to_string(), negotiated.clone()));
+        }
+        if let Some(seed) = self.seed {
+            fields.push(("seed".to_string(), seed.to_string()));
+        }
+        fields.push(("hostname".to_string(), self.hostname.clone()));
+        let started = self
+            .started
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        fields.push(("started".to_string(), started.to_string()));
+        for (key, value) in &self.labels {
+            fields.push((format!("label_{}", key), value.clone()));
+        }
+        fields
+    }
+
+    /// describe the run in a line for the stdout header
+    pub fn header(&self) -> String {
+        let mut header = String::new();
+        if let Some(ref run_id) = self.run_id {
+            header += &format!("ID: {} ", run_id);
+        }
+        header += &format!("Config: {} ", self.config_hash);
+        if let Some(ref negotiated) = self.negotiated {
+            header += &format!("Negotiated: {} ", negotiated);
+        }
+        if let Some(seed) = self.seed {
+            header += &format!("Seed: {} ", seed);
+        }
+        header += &format!(
+            "Host: {} Started: {}",
+            self.hostname,
+            format_utc(self.started)
+        );
+        for (key, value) in &self.labels {
+            header += &format!(" {}={}", key, value);
+        }
+        header
+    }
+
+    /// an info metric in the Prometheus format, which carries the metadata
+    /// in its labels
+    pub fn prometheus(&self) -> String {
+        let labels: Vec<String> = self
+            .fields()
+            .iter()
+            .map(|(key, value)| {
+                let key: String = key
+                    .chars()
+                    .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+                    .collect();
+                let value = value
+                    .replace('\\', "\\\\")
+                    .replace('"', "\\\"")
+                    .replace('\n', "\\n");
+                format!("{}=\"{}\"", key, value)
+            })
+            .collect();
+        format!("rpc_perf_run_info{{{}}} 1\n", labels.join(","))
+    }
+
+    pub fn json(&self) -> Value {
+        let mut map = Map::new();
+        for (key, value) in self.fields() {
+            map.insert(key, Value::String(value));
+        }
+        Value::Object(map)
+    }
+
+    /// the path with the run id added before the extension, so that the
+    /// outputs of runs in a sweep don't overwrite each other
+    pub fn stamp(path: &str, run_id: Option<&str>) -> String {
+        let run_id = match run_id {
+            Some(run_id) => run_id,
+            None => return path.to_string(),
+        };
+        let name_start = path.rfind('/').map(|i| i + 1).unwrap_or(0);
+        match path[name_start..].rfind('.') {
+            Some(dot) if dot > 0 => {
+                let dot = name_start + dot;
+                format!("{}-{}{}", &path[..dot], run_id, &path[dot..])
+            }
+            _ => format!("{}-{}", path, run_id),
+        }
+    }
+}
+
+#[cfg(unix)]
+fn hostname() -> String {
+    let mut buf = [0u8; 256];
+    // safety: the buffer is valid for its length
+    let result = unsafe { libc::gethostname(buf.as_mut_ptr() as *mut libc::c_char, buf.len()) };
+    if result != 0 {
+        return "unknown".to_string();
+    }
+    let len = buf.iter().position(|b| *b == 0).unwrap_or(buf.len());
+    String::from_utf8_lossy(&buf[..len]).to_string()
+}
+
+#[cfg(not(unix))]
+fn hostname() -> String {
+    std::env::var("COMPUTERNAME").unwrap_or_else(|_| "unknown".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stamp() {
+        assert_eq!(Metadata::stamp("waterfall.png", None), "waterfall.png");
+        assert_eq!(
+            Metadata::stamp("out/waterfall.png", Some("run-7")),
+            "out/waterfall-run-7.png"
+        );
+        assert_eq!(
+            Metadata::stamp("out.d/waterfall", Some("a")),
+            "out.d/waterfall-a"
+        );
+        assert_eq!(Metadata::stamp(".hidden", Some("a")), ".hidden-a");
+    }
+
+    #[test]
+    fn outputs() {
+        let mut metadata = Metadata {
+            run_id: Some("sweep \"1\"".to_string()),
+            labels: vec![("team".to_string(), "cache".to_string())],
+            config_hash: "0123456789abcdef".to_string(),
+            negotiated: None,
+            seed: Some(42),
+            hostname: "host".to_string(),
+            started: UNIX_EPOCH,
+        };
+        let prometheus = metadata.prometheus();
+        assert!(prometheus.starts_with("rpc_perf_run_info{run_id=\"sweep \\\"1\\\"\","));
+        assert!(prometheus.contains(",seed=\"42\","));
+        assert!(prometheus.ends_with(",label_team=\"cache\"} 1\n"));
+        assert_eq!(metadata.json()["config_hash"], "0123456789abcdef");
+        assert_eq!(
+            metadata.header(),
+            "ID: sweep \"1\" Config: 0123456789abcdef Seed: 42 Host: host \
+             Started: 1970-01-01 00:00:00 UTC team=cache"
+        );
+
+        metadata.set_negotiated("resp2");
+        assert_eq!(metadata.json()["negotiated"], "resp2");
+        assert!(metadata.header().contains(" Negotiated: resp2 Seed: 42 "));
+    }
+}
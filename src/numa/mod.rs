@@ -0,0 +1,172 @@
+// Copyright 2021 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+//! NUMA topology discovery and thread placement. Client threads are bound to
+//! the CPUs of a single node before they allocate their buffers and open
+//! their connections, so the kernel's first-touch policy keeps that memory
+//! local to the node.
+
+use std::net::SocketAddr;
+
+const SYSFS_NODES: &str = "/sys/devices/system/node";
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Node {
+    id: usize,
+    cpus: Vec<usize>,
+}
+
+impl Node {
+    pub fn id(&self) -> usize {
+        self.id
+    }
+
+    /// restrict the calling thread to the CPUs of this node
+    #[cfg(target_os = "linux")]
+    pub fn bind(&self) -> Result<(), std::io::Error> {
+        if self.cpus.is_empty() {
+            return Ok(());
+        }
+        // safety: cpu_set_t is a plain bitmask and the cpus are bounds checked
+        unsafe {
+            let mut set: libc::cpu_set_t = std::mem::zeroed();
+            for cpu in self
+                .cpus
+                .iter()
+                .filter(|c| **c < libc::CPU_SETSIZE as usize)
+            {
+                libc::CPU_SET(*cpu, &mut set);
+            }
+            if libc::sched_setaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &set) == 0 {
+                Ok(())
+            } else {
+                Err(std::io::Error::last_os_error())
+            }
+        }
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub fn bind(&self) -> Result<(), std::io::Error> {
+        Err(std::io::Error::new(
+            std::io::ErrorKind::Other,
+            "thread placement is only supported on linux",
+        ))
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Topology {
+    nodes: Vec<Node>,
+}
+
+impl Topology {
+    /// discover the NUMA nodes which have CPUs. If the topology can't be read
+    /// this returns a single node and threads are left unbound.
+    pub fn discover() -> Self {
+        let mut nodes = Vec::new();
+        if let Ok(entries) = std::fs::read_dir(SYSFS_NODES) {
+            for entry in entries.flatten() {
+                let name = entry.file_name();
+                let id = match name.to_str().and_then(|n| n.strip_prefix("node")) {
+                    Some(id) => match id.parse::<usize>() {
+                        Ok(id) => id,
+                        Err(_) => continue,
+                    },
+                    None => continue,
+                };
+                let cpus = std::fs::read_to_string(entry.path().join("cpulist"))
+                    .ok()
+                    .and_then(|list| parse_cpulist(&list));
+                match cpus {
+                    Some(cpus) if !cpus.is_empty() => nodes.push(Node { id, cpus }),
+                    _ => {
+                        // memory-only nodes can't run client threads
+                    }
+                }
+            }
+        }
+        if nodes.is_empty() {
+            warn!("Unable to discover NUMA topology, client threads will not be bound");
+            nodes.push(Node {
+                id: 0,
+                cpus: Vec::new(),
+            });
+        }
+        nodes.sort_by_key(|n| n.id);
+        Self { nodes }
+    }
+
+    /// the node for the given client, clients are spread evenly across nodes
+    pub fn node(&self, client: usize) -> &Node {
+        &self.nodes[client % self.nodes.len()]
+    }
+
+    pub fn nodes(&self) -> usize {
+        self.nodes.len()
+    }
+}
+
+/// the endpoints which clients on the node at `index` should connect to.
+/// Endpoints are assigned to nodes round-robin, and if there are more nodes
+/// than endpoints the extra nodes share them in the same way.
+pub fn endpoints_for(index: usize, nodes: usize, endpoints: &[SocketAddr]) -> Vec<SocketAddr> {
+    if endpoints.is_empty() || nodes == 0 {
+        return endpoints.to_vec();
+    }
+    if nodes > endpoints.len() {
+        return vec![endpoints[index % endpoints.len()]];
+    }
+    endpoints
+        .iter()
+        .enumerate()
+        .filter(|(i, _)| i % nodes == index)
+        .map(|(_, e)| *e)
+        .collect()
+}
+
+/// parse a kernel cpulist, eg: "0-3,8,10-11"
+fn parse_cpulist(list: &str) -> Option<Vec<usize>> {
+    let mut cpus = Vec::new();
+    for range in list.trim().split(',').filter(|r| !r.is_empty()) {
+        let mut parts = range.splitn(2, '-');
+        let start = parts.next()?.parse::<usize>().ok()?;
+        let end = match parts.next() {
+            Some(end) => end.parse::<usize>().ok()?,
+            None => start,
+        };
+        if end < start {
+            return None;
+        }
+        cpus.extend(start..=end);
+    }
+    Some(cpus)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cpulist() {
+        assert_eq!(parse_cpulist("0-3\n"), Some(vec![0, 1, 2, 3]));
+        assert_eq!(parse_cpulist("0-1,8,10-11"), Some(vec![0, 1, 8, 10, 11]));
+        assert_eq!(parse_cpulist("\n"), Some(vec![]));
+        assert_eq!(parse_cpulist("3-1"), None);
+        assert_eq!(parse_cpulist("a"), None);
+    }
+
+    #[test]
+    fn endpoints() {
+        let endpoints: Vec<SocketAddr> = (0..3)
+            .map(|i| format!("127.0.0.1:{}", 12321 + i).parse().unwrap())
+            .collect();
+        assert_eq!(
+            endpoints_for(0, 2, &endpoints),
+            vec![endpoints[0], endpoints[2]]
+        );
+        assert_eq!(endpoints_for(1, 2, &endpoints), vec![endpoints[1]]);
+        assert_eq!(endpoints_for(3, 4, &endpoints), vec![endpoints[0]]);
+        assert_eq!(endpoints_for(0, 1, &endpoints), endpoints);
+    }
+}
@@ -0,0 +1,221 @@
+// Copyright 2021 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+use rustcommon_ratelimiter::{Ratelimiter, Refill};
+
+use std::sync::{Arc, RwLock};
+
+/// A request ratelimiter which hands out tokens in batches, so that at high
+/// rates the clients only touch the shared ratelimiter once per batch. The
+/// batch size is reduced to a divisor of the rate, which keeps the long-run
+/// rate exact. The capacity is the most tokens which may be taken at once,
+/// and is held as whole batches, so it must hold at least one batch of the
+/// target size.
+pub struct BatchRatelimiter {
+    inner: RwLock<Batched>,
+    capacity: u64,
+    target: u64,
+    strategy: Refill,
+}
+
+/// the ratelimiter of a batch size, which counts whole batches
+struct Batched {
+    ratelimiter: Ratelimiter,
+    batch: u64,
+}
+
+impl Batched {
+    fn new(capacity: u64, rate: u64, target: u64, strategy: Refill) -> Self {
+        let batch = batch_size(rate, target);
+        let ratelimiter = Ratelimiter::new(capacity / batch, 1, rate / batch);
+        ratelimiter.set_strategy(strategy);
+        Self { ratelimiter, batch }
+    }
+}
+
+impl BatchRatelimiter {
+    pub fn new(capacity: u64, rate: u64, target: u64, strategy: Refill) -> Self {
+        let inner = Batched::new(capacity, rate, target, strategy);
+        if inner.batch != target {
+            warn!(
+                "request batch reduced to {} so that it divides the ratelimit of {}/s",
+                inner.batch, rate
+            );
+        }
+        Self {
+            inner: RwLock::new(inner),
+            capacity,
+            target,
+            strategy,
+        }
+    }
+
+    /// take a batch of tokens, returning the number of tokens taken
+    pub fn try_wait(&self) -> Option<u64> {
+        let inner = self.inner.read().unwrap();
+        if inner.ratelimiter.try_wait().is_ok() {
+            Some(inner.batch)
+        } else {
+            None
+        }
+    }
+
+    /// the rate in tokens per second
+    pub fn rate(&self) -> u64 {
+        let inner = self.inner.read().unwrap();
+        inner.ratelimiter.rate() * inner.batch
+    }
+
+    /// change the rate. If the batch size changes with it, the ratelimiter is
+    /// replaced, so that the capacity is still held in whole batches of the
+    /// new size.
+    pub fn set_rate(&self, rate: u64) {
+        let batch = batch_size(rate, self.target);
+        let mut inner = self.inner.write().unwrap();
+        if batch == inner.batch {
+            inner.ratelimiter.set_rate(rate / batch);
+        } else {
+            *inner = Batched::new(self.capacity, rate, self.target, self.strategy);
+        }
+    }
+}
+
+/// The request ratelimit, divided between the clients. Without weights, the
+/// clients take from one shared ratelimiter. With weights, each client has a
+/// ratelimiter of its own with a share of the rate and capacity in proportion
+/// to its weight.
+pub struct PartitionedRatelimiter {
+    weights: Option<Vec<u64>>,
+    ratelimiters: Vec<Arc<BatchRatelimiter>>,
+}
+
+impl PartitionedRatelimiter {
+    pub fn new(
+        capacity: u64,
+        rate: u64,
+        target: u64,
+        strategy: Refill,
+        weights: Option<Vec<u64>>,
+    ) -> Self {
+        let ratelimiters = match weights {
+            Some(ref weights) => divide(rate, weights)
+                .into_iter()
+                .zip(divide(capacity, weights))
+                .map(|(rate, capacity)| {
+                    Arc::new(BatchRatelimiter::new(capacity, rate, target, strategy))
+                })
+                .collect(),
+            None => vec![Arc::new(BatchRatelimiter::new(
+                capacity, rate, target, strategy,
+            ))],
+        };
+        Self {
+            weights,
+            ratelimiters,
+        }
+    }
+
+    /// the ratelimiter for the client
+    pub fn client(&self, index: usize) -> Arc<BatchRatelimiter> {
+        self.ratelimiters[index % self.ratelimiters.len()].clone()
+    }
+
+    /// the total rate in tokens per second
+    pub fn rate(&self) -> u64 {
+        self.ratelimiters.iter().map(|r| r.rate()).sum()
+    }
+
+    /// change the total rate, which is divided by the weights again. A rate
+    /// which would leave a client without tokens is raised to one token per
+    /// second for each weight, or for the shared ratelimiter.
+    pub fn set_rate(&self, rate: u64) {
+        let least = self
+            .weights
+            .as_ref()
+            .map(|weights| weights.iter().sum())
+            .unwrap_or(1);
+        let rate = rate.max(least);
+        match self.weights {
+            Some(ref weights) => {
+                for (ratelimiter, rate) in self.ratelimiters.iter().zip(divide(rate, weights)) {
+                    ratelimiter.set_rate(rate);
+                }
+            }
+            None => self.ratelimiters[0].set_rate(rate),
+        }
+    }
+}
+
+/// divide the total in proportion to the weights. The remainders go to the
+/// largest fractions, so the shares add up to the total.
+fn divide(total: u64, weights: &[u64]) -> Vec<u64> {
+    let sum: u64 = weights.iter().sum::<u64>().max(1);
+    let mut shares: Vec<u64> = weights.iter().map(|w| total * w / sum).collect();
+    let mut remainders: Vec<(u64, usize)> = weights
+        .iter()
+        .enumerate()
+        .map(|(i, w)| (total * w % sum, i))
+        .collect();
+    remainders.sort_by(|a, b| b.0.cmp(&a.0).then(a.1.cmp(&b.1)));
+    let left = total - shares.iter().sum::<u64>();
+    for (_, i) in remainders.iter().take(left as usize) {
+        shares[*i] += 1;
+    }
+    shares
+}
+
+/// the largest batch size, no greater than the target, which divides the rate
+fn batch_size(rate: u64, target: u64) -> u64 {
+    (1..=target.max(1).min(rate.max(1)))
+        .rev()
+        .find(|batch| rate.is_multiple_of(*batch))
+        .unwrap_or(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn batch() {
+        assert_eq!(batch_size(100_000, 1), 1);
+        assert_eq!(batch_size(100_000, 64), 50);
+        assert_eq!(batch_size(100_000, 100), 100);
+        assert_eq!(batch_size(1_000_003, 16), 1);
+        assert_eq!(batch_size(10, 64), 10);
+        assert_eq!(batch_size(0, 64), 1);
+    }
+
+    #[test]
+    fn divided() {
+        assert_eq!(divide(1000, &[1, 1, 1]), vec![334, 333, 333]);
+        assert_eq!(divide(1000, &[2, 1, 1]), vec![500, 250, 250]);
+        assert_eq!(divide(10, &[1, 2]), vec![3, 7]);
+        assert_eq!(divide(1, &[1, 1]), vec![1, 0]);
+
+        let ratelimiter = PartitionedRatelimiter::new(4, 1000, 1, Refill::Smooth, Some(vec![3, 1]));
+        assert_eq!(ratelimiter.client(0).rate(), 750);
+        assert_eq!(ratelimiter.client(1).rate(), 250);
+        ratelimiter.set_rate(100);
+        assert_eq!(ratelimiter.rate(), 100);
+        assert_eq!(ratelimiter.client(1).rate(), 25);
+        ratelimiter.set_rate(1);
+        assert_eq!(ratelimiter.rate(), 4);
+    }
+
+    #[test]
+    fn rebatched() {
+        let ratelimiter = BatchRatelimiter::new(640, 1_000_003, 64, Refill::Smooth);
+        assert_eq!(ratelimiter.inner.read().unwrap().batch, 1);
+
+        // the capacity is held in batches of the new size
+        ratelimiter.set_rate(1_000_000);
+        assert_eq!(ratelimiter.rate(), 1_000_000);
+        assert_eq!(ratelimiter.inner.read().unwrap().batch, 64);
+
+        ratelimiter.set_rate(500_000);
+        assert_eq!(ratelimiter.rate(), 500_000);
+        assert_eq!(ratelimiter.inner.read().unwrap().batch, 50);
+    }
+}
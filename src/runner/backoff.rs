@@ -0,0 +1,90 @@
+// Copyright 2021 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+//! With `pushback_backoff`, the request ratelimit is lowered after each window
+//! where too many of the responses pushed back, until the target copes. The
+//! knee is the highest rate which ran without push-back, below the rates which
+//! pushed back.
+
+// the share of the rate which is kept after a window with push-back
+const BACKOFF: f64 = 0.9;
+
+pub struct Backoff {
+    /// the percentage of the responses which may push back
+    threshold: f64,
+    signals: u64,
+    responses: u64,
+    /// the lowest rate which pushed back
+    pushed: Option<u64>,
+    /// the rates which didn't push back
+    clean: Vec<u64>,
+}
+
+impl Backoff {
+    pub fn new(threshold: f64) -> Self {
+        Self {
+            threshold,
+            signals: 0,
+            responses: 0,
+            pushed: None,
+            clean: Vec::new(),
+        }
+    }
+
+    /// record the window which ran at the rate, from the totals of the
+    /// push-back signals and the responses so far. Returns the lower rate if
+    /// the window pushed back.
+    pub fn window(&mut self, rate: u64, signals: u64, responses: u64) -> Option<u64> {
+        let delta = signals.saturating_sub(self.signals);
+        let total = responses.saturating_sub(self.responses);
+        self.signals = signals;
+        self.responses = responses;
+
+        let pushed = delta > 0 && 100.0 * delta as f64 > self.threshold * total as f64;
+        if !pushed {
+            if !self.clean.contains(&rate) {
+                self.clean.push(rate);
+            }
+            return None;
+        }
+        self.pushed = Some(self.pushed.map_or(rate, |p| p.min(rate)));
+        Some(((rate as f64 * BACKOFF) as u64).max(1))
+    }
+
+    /// the highest rate which ran without push-back, once push-back has been
+    /// seen
+    pub fn knee(&self) -> Option<u64> {
+        let pushed = self.pushed?;
+        self.clean
+            .iter()
+            .filter(|rate| **rate < pushed)
+            .max()
+            .copied()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backoff() {
+        let mut backoff = Backoff::new(1.0);
+        assert_eq!(backoff.window(1000, 0, 1000), None);
+        // 1% isn't more than the threshold
+        assert_eq!(backoff.window(1000, 10, 2000), None);
+        assert_eq!(backoff.knee(), None);
+
+        assert_eq!(backoff.window(1000, 110, 3000), Some(900));
+        assert_eq!(backoff.knee(), None);
+        assert_eq!(backoff.window(900, 200, 3900), Some(810));
+        assert_eq!(backoff.window(810, 200, 4710), None);
+        assert_eq!(backoff.knee(), Some(810));
+
+        // push-back at a lower rate moves the knee down
+        assert_eq!(backoff.window(810, 300, 5520), Some(729));
+        assert_eq!(backoff.window(729, 300, 6249), None);
+        assert_eq!(backoff.knee(), Some(729));
+    }
+}
@@ -0,0 +1,670 @@
+// Copyright 2021 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+//! The workload engine. A `Runner` drives the client threads for a config and
+//! records into its metrics, so that rpc-perf can be embedded in other test
+//! harnesses as well as run from the command line.
+
+mod backoff;
+mod warmup;
+
+use backoff::Backoff;
+use warmup::Progress;
+
+use crate::client::{Chaos, Client, SLOW_LOG_RATE};
+use crate::codec::{self, Codec, Constructor, Redis, RedisMode, Registry};
+use crate::config::{Config, Protocol};
+use crate::discovery::Endpoints;
+use crate::numa;
+use crate::ratelimit::{BatchRatelimiter, PartitionedRatelimiter};
+use crate::stats::{percent_gauge, Metrics, Stat};
+
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use rustcommon_atomics::{Atomic, AtomicBool, Ordering};
+use rustcommon_ratelimiter::Ratelimiter;
+
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// what the client threads are launched to do
+#[derive(Clone, Copy, PartialEq)]
+enum Phase {
+    Preload,
+    Warmup,
+    Measure,
+}
+
+pub struct Runner {
+    config: Arc<Config>,
+    metrics: Arc<Metrics>,
+    control: Arc<AtomicBool>,
+    draining: Arc<AtomicBool>,
+    // disconnected once all the client threads have exited
+    done: Mutex<Option<Receiver<()>>>,
+    request_ratelimiter: Option<PartitionedRatelimiter>,
+    warmup_ratelimiter: Option<Arc<BatchRatelimiter>>,
+    connect_ratelimiter: Option<Arc<Ratelimiter>>,
+    close_rate: Option<Arc<Ratelimiter>>,
+    slow_log: Option<Arc<Ratelimiter>>,
+    backoff: Option<Mutex<Backoff>>,
+    // the discovered endpoints, if they are refreshed during the run
+    endpoints: Option<Arc<Endpoints>>,
+    seed: u64,
+    codecs: Registry,
+    // the redis mode of each endpoint, for the redis_auto protocol
+    negotiated: Option<Arc<HashMap<SocketAddr, RedisMode>>>,
+    threads: Mutex<Vec<JoinHandle<()>>>,
+}
+
+impl Runner {
+    /// create a runner which records into new metrics for the config
+    pub fn new(config: Config) -> Self {
+        let config = Arc::new(config);
+        let metrics = Arc::new(Metrics::new(config.clone()));
+        Self::with_metrics(config, metrics)
+    }
+
+    /// create a runner which records into the provided metrics
+    pub fn with_metrics(config: Arc<Config>, metrics: Arc<Metrics>) -> Self {
+        let request_ratelimiter = if let Some(limit) = config.request_ratelimit() {
+            metrics.gauge(&Stat::RatelimitTarget, limit as u64);
+            Some(PartitionedRatelimiter::new(
+                config.request_burst() as u64,
+                limit as u64,
+                config.request_batch() as u64,
+                config.request_distribution(),
+                config
+                    .request_weights()
+                    .map(|weights| weights.into_iter().map(|w| w as u64).collect()),
+            ))
+        } else {
+            None
+        };
+
+        let warmup_ratelimiter = config.warmup_ratelimit().map(|limit| {
+            Arc::new(BatchRatelimiter::new(
+                (config.warmup_clients() * config.request_batch()) as u64,
+                limit as u64,
+                config.request_batch() as u64,
+                config.request_distribution(),
+            ))
+        });
+
+        let connect_ratelimiter = if let Some(limit) = config.connect_ratelimit() {
+            Some(Arc::new(Ratelimiter::new(
+                config.clients() as u64,
+                1,
+                limit as u64,
+            )))
+        } else {
+            None
+        };
+
+        let close_rate = if let Some(rate) = config.close_rate() {
+            Some(Arc::new(Ratelimiter::new(
+                config.clients() as u64,
+                1,
+                rate as u64,
+            )))
+        } else {
+            None
+        };
+
+        // the clients share a budget for logging slow requests
+        let slow_log = if config.log_slow().is_some() {
+            Some(Arc::new(Ratelimiter::new(SLOW_LOG_RATE, 1, SLOW_LOG_RATE)))
+        } else {
+            None
+        };
+
+        let backoff = config
+            .pushback_backoff()
+            .map(|threshold| Mutex::new(Backoff::new(threshold)));
+
+        let endpoints = config
+            .discovery_refresh()
+            .map(|_| Arc::new(Endpoints::new(config.endpoints())));
+
+        // each endpoint is probed for the protocol it speaks
+        let negotiated = if config.protocol() == Protocol::RedisAuto {
+            let timeout = Duration::from_micros(config.connect_timeout() as u64);
+            let negotiated = codec::negotiate(&config.endpoints(), timeout);
+            if negotiated.is_empty() {
+                warn!("No endpoint could be probed, using RESP");
+            }
+            Some(Arc::new(negotiated))
+        } else {
+            None
+        };
+
+        Self {
+            config,
+            metrics,
+            control: Arc::new(AtomicBool::new(false)),
+            draining: Arc::new(AtomicBool::new(false)),
+            done: Mutex::new(None),
+            request_ratelimiter,
+            warmup_ratelimiter,
+            connect_ratelimiter,
+            close_rate,
+            slow_log,
+            backoff,
+            endpoints,
+            seed: rand::random(),
+            codecs: Registry::default(),
+            negotiated,
+            threads: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// set the seed for the request generators. Clients with the same index
+    /// in runners with the same seed send the same sequence of requests.
+    pub fn set_seed(&mut self, seed: u64) {
+        self.seed = seed;
+    }
+
+    /// the seed for the request generators
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// use the codecs of the registry, such as the one the config was loaded
+    /// with, in place of the builtin codecs
+    pub fn set_codecs(&mut self, codecs: Registry) {
+        self.codecs = codecs;
+    }
+
+    /// register a codec for a protocol which isn't built in, it is used when
+    /// the `protocol` in the config has the same name
+    pub fn register_codec<F>(&mut self, name: &str, constructor: F)
+    where
+        F: Fn() -> Box<dyn Codec> + Send + Sync + 'static,
+    {
+        self.codecs.register(name, constructor);
+    }
+
+    /// the redis modes which were negotiated with the endpoints, for the
+    /// redis_auto protocol
+    pub fn negotiated(&self) -> Option<String> {
+        self.negotiated
+            .as_ref()
+            .filter(|negotiated| !negotiated.is_empty())
+            .map(|negotiated| codec::describe(negotiated))
+    }
+
+    pub fn config(&self) -> &Arc<Config> {
+        &self.config
+    }
+
+    /// the metrics which the clients record into
+    pub fn metrics(&self) -> &Arc<Metrics> {
+        &self.metrics
+    }
+
+    /// set each key in the keyfile, without ratelimits, then zero the
+    /// metrics. Returns immediately if no keyfile is configured, and an error
+    /// if the preload timeout passes first.
+    pub fn preload(&self) -> Result<(), String> {
+        let keys = match self.config.keyfile() {
+            Some(keys) => keys.len() as u64,
+            None => return Ok(()),
+        };
+        info!("-----");
+        info!("Preloading {} keys...", keys);
+        let control = Arc::new(AtomicBool::new(true));
+        let threads = self.launch(&control, Phase::Preload, rand::random())?;
+
+        let deadline = Instant::now() + self.config.preload_timeout();
+        let preloaded = loop {
+            thread::sleep(Duration::from_millis(100));
+            let responses = self.metrics.reading(&Stat::ResponsesTotal).unwrap_or(0);
+            if responses >= keys || Instant::now() >= deadline {
+                break responses;
+            }
+            debug!("Preloaded: {} of {} keys", responses, keys);
+        };
+
+        control.store(false, Ordering::SeqCst);
+        for thread in threads {
+            let _ = thread.join();
+        }
+        if preloaded < keys {
+            self.metrics.zero();
+            return Err(format!(
+                "Preload timed out after {}s: {} of {} keys were set",
+                self.config.preload_timeout().as_secs(),
+                preloaded,
+                keys
+            ));
+        }
+        let errors = self.metrics.reading(&Stat::ResponsesError).unwrap_or(0);
+        if errors > 0 {
+            warn!("{} errors while preloading keys", errors);
+        }
+        self.metrics.zero();
+
+        info!("Preload complete.");
+        Ok(())
+    }
+
+    /// run the warmup clients, with the warmup ratelimit if there is one,
+    /// until the warmup hit-rate from the config is reached, then zero the
+    /// metrics. Returns immediately if no warmup is configured, and returns an
+    /// error if the warmup timeout passes first.
+    pub fn warmup(&self) -> Result<(), String> {
+        let target = match self.config.warmup_hitrate() {
+            Some(target) => target,
+            None => return Ok(()),
+        };
+        info!("-----");
+        info!("Warming the cache...");
+        let control = Arc::new(AtomicBool::new(true));
+        let threads = self.launch(&control, Phase::Warmup, rand::random())?;
+
+        let interval = Duration::new(self.config.interval() as u64, 0);
+        let start = Instant::now();
+        let mut progress = Progress::new(target, interval);
+        let mut warm = 0;
+        let timed_out = loop {
+            thread::sleep(interval);
+            self.metrics.increment(&Stat::Window);
+
+            progress.window(
+                self.metrics.reading(&Stat::ResponsesHit).unwrap_or(0),
+                self.metrics.reading(&Stat::ResponsesMiss).unwrap_or(0),
+                self.metrics.reading(&Stat::CommandsSet).unwrap_or(0),
+            );
+            info!("{}", progress.describe());
+            if progress.is_warm() {
+                warm += 1;
+            } else {
+                warm = 0;
+            }
+
+            self.metrics.zero();
+
+            // the progress is set after the counters of the window are cleared,
+            // so that it can be read from the stats port until the next window
+            if let Some(hitrate) = progress.hitrate() {
+                self.metrics
+                    .gauge(&Stat::WarmupHitrate, percent_gauge(100.0 * hitrate));
+            }
+            if let Some(eta) = progress.eta() {
+                self.metrics.gauge(&Stat::WarmupEta, eta.as_secs());
+            }
+            self.metrics.gauge(&Stat::WarmupWritten, progress.written());
+
+            if warm >= 3 {
+                break false;
+            }
+            if let Some(timeout) = self.config.warmup_timeout() {
+                if start.elapsed() >= timeout {
+                    break true;
+                }
+            }
+        };
+
+        control.store(false, Ordering::SeqCst);
+        for thread in threads {
+            let _ = thread.join();
+        }
+        self.metrics.zero();
+
+        if timed_out {
+            return Err(format!(
+                "Warmup timed out after {}s: Target: {:.2}% Hit-rate: {} Keys Written: {}. \
+                 The cache may be too small to hold the keyspace at the target hit-rate",
+                start.elapsed().as_secs(),
+                100.0 * target,
+                progress
+                    .hitrate()
+                    .map(|h| format!("{:.2}%", 100.0 * h))
+                    .unwrap_or_else(|| "none".to_string()),
+                progress.written()
+            ));
+        }
+
+        info!("Warmup complete.");
+        Ok(())
+    }
+
+    /// launch the client threads, which run until `stop()` is called
+    pub fn start(&self) -> Result<(), String> {
+        let mut threads = self.threads.lock().unwrap();
+        if !threads.is_empty() {
+            return Ok(());
+        }
+        self.control.store(true, Ordering::SeqCst);
+        self.draining.store(false, Ordering::SeqCst);
+        *threads = self.launch(&self.control, Phase::Measure, self.seed)?;
+        if self.config.discovery().is_some() {
+            // set here as the metrics are zeroed after any warmup
+            let endpoints = match self.endpoints {
+                Some(ref endpoints) => endpoints.get().len(),
+                None => self.config.endpoints().len(),
+            };
+            self.metrics
+                .gauge(&Stat::DiscoveryEndpoints, endpoints as u64);
+        }
+        if let Some(thread) = self.launch_discovery() {
+            threads.push(thread);
+        }
+        Ok(())
+    }
+
+    /// stop the client threads and wait for them to exit, all their readings
+    /// are in the metrics once this returns
+    pub fn stop(&self) {
+        self.control.store(false, Ordering::SeqCst);
+        let threads: Vec<JoinHandle<()>> = self.threads.lock().unwrap().drain(..).collect();
+        for thread in threads {
+            let _ = thread.join();
+        }
+    }
+
+    /// stop sending requests and wait up to the timeout for the responses to
+    /// the requests which are in-flight, then stop the client threads
+    pub fn drain(&self, timeout: Duration) {
+        self.draining.store(true, Ordering::SeqCst);
+        if let Some(done) = self.done.lock().unwrap().take() {
+            match done.recv_timeout(timeout) {
+                Err(RecvTimeoutError::Timeout) => {
+                    warn!("gave up waiting for in-flight requests");
+                }
+                Ok(()) | Err(RecvTimeoutError::Disconnected) => {}
+            }
+        }
+        self.stop();
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.control.load(Ordering::SeqCst)
+    }
+
+    /// the current request ratelimit in requests per second
+    pub fn request_rate(&self) -> Option<u64> {
+        self.request_ratelimiter.as_ref().map(|r| r.rate())
+    }
+
+    /// change the request ratelimit, returns false if the config has no
+    /// request ratelimit to change
+    pub fn set_request_rate(&self, rate: u64) -> bool {
+        match self.request_ratelimiter {
+            Some(ref ratelimiter) => {
+                ratelimiter.set_rate(rate);
+                self.metrics
+                    .gauge(&Stat::RatelimitTarget, ratelimiter.rate());
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// lower the request ratelimit if too many of the responses in the last
+    /// window pushed back, this is called at the end of each window
+    pub fn backoff(&self) {
+        let mut backoff = match self.backoff {
+            Some(ref backoff) => backoff.lock().unwrap(),
+            None => return,
+        };
+        let rate = match self.request_rate() {
+            Some(rate) => rate,
+            None => return,
+        };
+        let signals = self.metrics.reading(&Stat::PushbackRejected).unwrap_or(0)
+            + self.metrics.reading(&Stat::PushbackStalled).unwrap_or(0);
+        let responses = self.metrics.reading(&Stat::ResponsesTotal).unwrap_or(0);
+        if let Some(lower) = backoff.window(rate, signals, responses) {
+            info!("Pushback: Ratelimit: {} rps -> {} rps", rate, lower);
+            self.set_request_rate(lower);
+        }
+        if let Some(knee) = backoff.knee() {
+            self.metrics.gauge(&Stat::PushbackKnee, knee);
+        }
+    }
+
+    /// the highest request rate which ran without push-back, once push-back
+    /// has been seen
+    pub fn knee(&self) -> Option<u64> {
+        self.backoff
+            .as_ref()
+            .and_then(|backoff| backoff.lock().unwrap().knee())
+    }
+
+    /// launch a thread which discovers the endpoints again at the refresh
+    /// interval, the clients connect to new endpoints and close their
+    /// connections to the ones which are gone
+    fn launch_discovery(&self) -> Option<JoinHandle<()>> {
+        let endpoints = self.endpoints.clone()?;
+        let refresh = self.config.discovery_refresh()?;
+        let config = self.config.clone();
+        let metrics = self.metrics.clone();
+        let control = self.control.clone();
+        let thread = thread::Builder::new()
+            .name("discovery".to_string())
+            .spawn(move || {
+                let mut last = Instant::now();
+                while control.load(Ordering::SeqCst) {
+                    thread::sleep(Duration::from_millis(100));
+                    if last.elapsed() < refresh {
+                        continue;
+                    }
+                    last = Instant::now();
+                    match config.discover_endpoints() {
+                        Ok(discovered) => {
+                            let count = discovered.len();
+                            if endpoints.set(discovered) {
+                                info!("Discovery: Endpoints: {}", count);
+                                metrics.gauge(&Stat::DiscoveryEndpoints, count as u64);
+                            }
+                        }
+                        Err(e) => warn!("Discovery: keeping the current endpoints: {}", e),
+                    }
+                }
+            });
+        match thread {
+            Ok(thread) => Some(thread),
+            Err(e) => {
+                error!("failed to launch discovery: {}", e);
+                None
+            }
+        }
+    }
+
+    fn launch(
+        &self,
+        control: &Arc<AtomicBool>,
+        phase: Phase,
+        seed: u64,
+    ) -> Result<Vec<JoinHandle<()>>, String> {
+        let topology = if self.config.numa() {
+            let topology = numa::Topology::discover();
+            debug!("numa nodes: {}", topology.nodes());
+            Some(topology)
+        } else {
+            None
+        };
+
+        let protocol = self.config.protocol();
+        let constructor: Constructor = match self.negotiated {
+            Some(ref negotiated) => {
+                let negotiated = negotiated.clone();
+                Arc::new(move || Box::new(Redis::negotiated(negotiated.clone())))
+            }
+            None => self
+                .codecs
+                .get(&protocol)
+                .ok_or_else(|| format!("no codec registered for protocol: {}", protocol.name()))?,
+        };
+
+        // faults are only injected into the measured workload, and all the
+        // clients follow the schedule from the same start
+        let chaos_start = Instant::now();
+
+        // only the measured clients are drained
+        let (done, receiver) = mpsc::channel();
+        let draining = if phase == Phase::Measure {
+            *self.done.lock().unwrap() = Some(receiver);
+            self.draining.clone()
+        } else {
+            Arc::new(AtomicBool::new(false))
+        };
+
+        // the warmup may use more clients than the run
+        let clients = if phase == Phase::Warmup {
+            self.config.warmup_clients()
+        } else {
+            self.config.clients()
+        };
+
+        let mut threads = Vec::new();
+        for i in 0..clients {
+            let (request_ratelimiter, connect_ratelimiter, close_rate) = match phase {
+                Phase::Measure => (
+                    self.request_ratelimiter.as_ref().map(|r| r.client(i)),
+                    self.connect_ratelimiter.clone(),
+                    self.close_rate.clone(),
+                ),
+                Phase::Warmup => (self.warmup_ratelimiter.clone(), None, None),
+                Phase::Preload => (None, None, None),
+            };
+            let slow_log = self.slow_log.clone();
+            let config = self.config.clone();
+            let constructor = constructor.clone();
+
+            let node = topology.as_ref().map(|t| t.node(i).clone());
+
+            // the numa node at `index` of `nodes` whose part of the endpoints
+            // the client connects to
+            let numa = match (&topology, &node) {
+                (Some(topology), Some(_)) if config.numa_bind_endpoints() => {
+                    Some((i % topology.nodes(), topology.nodes()))
+                }
+                _ => None,
+            };
+
+            let endpoints = match (numa, &node) {
+                (Some((index, nodes)), Some(node)) => {
+                    let endpoints = numa::endpoints_for(index, nodes, &config.endpoints());
+                    debug!(
+                        "client{} bound to numa node {} with endpoints: {:?}",
+                        i,
+                        node.id(),
+                        endpoints
+                    );
+                    endpoints
+                }
+                _ => config.endpoints(),
+            };
+
+            // only the measured clients follow the discovered endpoints
+            let discovery = match phase {
+                Phase::Measure => self.endpoints.clone(),
+                _ => None,
+            };
+
+            let control = control.clone();
+            let draining = draining.clone();
+            let done = done.clone();
+            let metrics = self.metrics.clone();
+            let thread = thread::Builder::new()
+                .name(format!("client{}", i))
+                .spawn(move || {
+                    // held until the thread exits
+                    let _done = done;
+
+                    if let Some(node) = node {
+                        if let Err(e) = node.bind() {
+                            warn!(
+                                "failed to bind client{} to numa node {}: {}",
+                                i,
+                                node.id(),
+                                e
+                            );
+                        }
+                    }
+
+                    // TODO: use a different generator for warmup
+                    let mut codec = constructor();
+                    if phase == Phase::Preload {
+                        codec.set_generator(config.preload_generator(i, config.clients()));
+                    } else {
+                        codec.set_generator(config.generator());
+                    }
+                    codec.set_metrics(metrics.clone());
+                    codec
+                        .common_mut()
+                        .set_compression(config.compression().cloned());
+                    if phase == Phase::Measure && config.popularity().is_some() {
+                        codec.common_mut().set_popularity();
+                    }
+
+                    // the client is created on its own thread, after binding,
+                    // so that its buffers are allocated on the local numa node
+                    let mut client = Client::new(
+                        i,
+                        config.clone(),
+                        codec,
+                        connect_ratelimiter,
+                        request_ratelimiter,
+                        close_rate,
+                        metrics.clone(),
+                    );
+
+                    for endpoint in endpoints {
+                        client.add_endpoint(&endpoint);
+                    }
+                    if let Some(discovery) = discovery {
+                        client.set_discovery(discovery, numa);
+                    }
+                    for shadow in config.shadow() {
+                        client.add_shadow(&shadow);
+                    }
+                    if let (Phase::Measure, Some(exemplars)) = (phase, config.exemplars()) {
+                        client.set_exemplars(exemplars);
+                    }
+                    if let (Some(threshold), Some(ratelimit)) = (config.log_slow(), slow_log) {
+                        client.set_slow_log(threshold, ratelimit);
+                    }
+                    if phase == Phase::Measure && !config.chaos().is_empty() {
+                        client.set_chaos(Chaos::new(
+                            config.chaos(),
+                            chaos_start,
+                            &config.endpoints(),
+                            config.clients(),
+                        ));
+                    }
+
+                    let mut rng = StdRng::seed_from_u64(seed.wrapping_add(i as u64));
+                    metrics.enable_local();
+                    while control.load(Ordering::SeqCst) {
+                        if draining.load(Ordering::SeqCst) {
+                            client.drain();
+                            if client.is_idle() {
+                                break;
+                            }
+                        }
+                        client.run(&mut rng);
+                    }
+                    client.flush();
+                });
+            match thread {
+                Ok(thread) => threads.push(thread),
+                Err(e) => error!("failed to launch client{}: {}", i, e),
+            }
+        }
+        Ok(threads)
+    }
+}
+
+impl Drop for Runner {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
@@ -0,0 +1,140 @@
+// Copyright 2021 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+//! The progress of the warmup towards the target hit-rate, which is reported
+//! each window with an estimate of the time left. The estimate follows the
+//! trend of the hit-rate, smoothed over the recent windows.
+
+use std::time::Duration;
+
+// the weight of the latest window in the trend
+const SMOOTHING: f64 = 0.5;
+
+pub struct Progress {
+    /// the target hit-rate as a ratio
+    target: f64,
+    interval: Duration,
+    windows: u64,
+    hitrate: Option<f64>,
+    trend: Option<f64>,
+    written: u64,
+}
+
+impl Progress {
+    pub fn new(target: f64, interval: Duration) -> Self {
+        Self {
+            target,
+            interval,
+            windows: 0,
+            hitrate: None,
+            trend: None,
+            written: 0,
+        }
+    }
+
+    /// record the hits, misses and sets of the window which just ended
+    pub fn window(&mut self, hits: u64, misses: u64, sets: u64) {
+        self.windows += 1;
+        self.written += sets;
+        let hitrate = if hits + misses == 0 {
+            None
+        } else {
+            Some(hits as f64 / (hits + misses) as f64)
+        };
+        if let (Some(previous), Some(current)) = (self.hitrate, hitrate) {
+            let change = current - previous;
+            self.trend = Some(match self.trend {
+                Some(trend) => SMOOTHING * change + (1.0 - SMOOTHING) * trend,
+                None => change,
+            });
+        }
+        self.hitrate = hitrate;
+    }
+
+    /// the hit-rate of the last window as a ratio
+    pub fn hitrate(&self) -> Option<f64> {
+        self.hitrate
+    }
+
+    /// whether the last window reached the target
+    pub fn is_warm(&self) -> bool {
+        self.hitrate.map(|h| h >= self.target).unwrap_or(false)
+    }
+
+    /// the number of sets sent so far
+    pub fn written(&self) -> u64 {
+        self.written
+    }
+
+    /// the estimated time until the target is reached, if the hit-rate is
+    /// rising
+    pub fn eta(&self) -> Option<Duration> {
+        if self.is_warm() {
+            return Some(Duration::from_secs(0));
+        }
+        match (self.hitrate, self.trend) {
+            (Some(hitrate), Some(trend)) if trend > 0.0 => {
+                let windows = ((self.target - hitrate) / trend).ceil();
+                Some(self.interval.mul_f64(windows))
+            }
+            _ => None,
+        }
+    }
+
+    /// describe the progress in a line for stdout
+    pub fn describe(&self) -> String {
+        let hitrate = self
+            .hitrate
+            .map(|h| format!("{:.2}%", 100.0 * h))
+            .unwrap_or_else(|| "none".to_string());
+        let trend = self
+            .trend
+            .map(|t| format!(" ({:+.2}%/window)", 100.0 * t))
+            .unwrap_or_default();
+        let eta = self
+            .eta()
+            .map(|eta| format!("{}s", eta.as_secs()))
+            .unwrap_or_else(|| "unknown".to_string());
+        format!(
+            "Warmup: Window: {} Hit-rate: {}{} Target: {:.2}% ETA: {} Keys Written: {}",
+            self.windows,
+            hitrate,
+            trend,
+            100.0 * self.target,
+            eta,
+            self.written
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn progress() {
+        let mut progress = Progress::new(0.9, Duration::from_secs(10));
+        progress.window(0, 0, 100);
+        assert_eq!(progress.hitrate(), None);
+        assert_eq!(progress.eta(), None);
+
+        progress.window(50, 50, 100);
+        progress.window(75, 25, 100);
+        assert_eq!(progress.eta(), Some(Duration::from_secs(10)));
+        assert_eq!(
+            progress.describe(),
+            "Warmup: Window: 3 Hit-rate: 75.00% (+25.00%/window) Target: 90.00% ETA: 10s \
+             Keys Written: 300"
+        );
+
+        // a falling hit-rate has no estimate
+        progress.window(30, 70, 0);
+        assert_eq!(progress.eta(), None);
+        assert!(!progress.is_warm());
+
+        progress.window(95, 5, 0);
+        assert!(progress.is_warm());
+        assert_eq!(progress.eta(), Some(Duration::from_secs(0)));
+    }
+}
@@ -2,6 +2,8 @@
 // Licensed under the Apache License, Version 2.0
 // http://www.apache.org/licenses/LICENSE-2.0
 
+use std::collections::VecDeque;
+use std::io::{IoSlice, Read, Write};
 use std::net::SocketAddr;
 use std::time::Instant;
 
@@ -11,6 +13,8 @@ use rustcommon_buffer::Buffer;
 use rustls::ClientSession;
 use rustls::Session as TlsSession;
 
+use crate::codec::Tag;
+
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum State {
     Connecting,
@@ -27,6 +31,46 @@ pub struct Session {
     state: State,
     token: Token,
     timestamp: Instant,
+    pending: usize,
+    reads: usize,
+    writes: usize,
+    shadow: bool,
+    reconnected: bool,
+    /// the sequence numbers of the mirrored requests awaiting a response,
+    /// each with the time it was sent
+    pub(crate) mirrored: VecDeque<(u64, Instant)>,
+    pub(crate) tags: VecDeque<Tag>,
+    /// the request being sampled, which is the only one in flight
+    pub(crate) sample: Option<Vec<u8>>,
+}
+
+/// wraps the socket to count the read and write syscalls made against it
+struct Counted<'a> {
+    stream: &'a mut TcpStream,
+    calls: &'a mut usize,
+}
+
+impl<'a> Read for Counted<'a> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        *self.calls += 1;
+        self.stream.read(buf)
+    }
+}
+
+impl<'a> Write for Counted<'a> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        *self.calls += 1;
+        self.stream.write(buf)
+    }
+
+    fn write_vectored(&mut self, bufs: &[IoSlice<'_>]) -> std::io::Result<usize> {
+        *self.calls += 1;
+        self.stream.write_vectored(bufs)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.stream.flush()
+    }
 }
 
 impl Session {
@@ -45,6 +89,14 @@ impl Session {
                 token,
                 state,
                 timestamp: Instant::now(),
+                pending: 0,
+                reads: 0,
+                writes: 0,
+                shadow: false,
+                reconnected: false,
+                mirrored: VecDeque::new(),
+                tags: VecDeque::new(),
+                sample: None,
             })
         } else {
             Err(())
@@ -83,9 +135,52 @@ impl Session {
         self.timestamp = timestamp;
     }
 
+    /// number of requests written to the session which are awaiting responses
+    pub fn pending(&self) -> usize {
+        self.pending
+    }
+
+    pub fn set_pending(&mut self, pending: usize) {
+        self.pending = pending;
+    }
+
+    /// whether the session is to a shadow endpoint, which receives a copy of
+    /// the requests sent to the primary endpoints
+    pub fn is_shadow(&self) -> bool {
+        self.shadow
+    }
+
+    pub fn set_shadow(&mut self, shadow: bool) {
+        self.shadow = shadow;
+    }
+
+    /// mark the session as replacing a connection which was closed, until
+    /// its first response is received
+    pub fn set_reconnected(&mut self, reconnected: bool) {
+        self.reconnected = reconnected;
+    }
+
+    /// whether this is the first response since the session replaced a
+    /// closed connection, which is only true once
+    pub fn take_reconnected(&mut self) -> bool {
+        std::mem::replace(&mut self.reconnected, false)
+    }
+
+    /// returns the number of read and write syscalls since the last call
+    pub fn take_syscalls(&mut self) -> (usize, usize) {
+        let calls = (self.reads, self.writes);
+        self.reads = 0;
+        self.writes = 0;
+        calls
+    }
+
     pub fn do_read(&mut self) -> Result<Option<usize>, std::io::Error> {
+        let mut stream = Counted {
+            stream: &mut self.stream,
+            calls: &mut self.reads,
+        };
         if let Some(ref mut tls) = self.tls {
-            match tls.read_tls(&mut self.stream) {
+            match tls.read_tls(&mut stream) {
                 Err(e) => Err(e),
                 Ok(0) => Ok(Some(0)),
                 Ok(_) => {
@@ -101,13 +196,17 @@ impl Session {
                 }
             }
         } else {
-            self.buffer.read_from(&mut self.stream)
+            self.buffer.read_from(&mut stream)
         }
     }
 
     pub fn do_write(&mut self) -> Result<Option<usize>, std::io::Error> {
+        let mut stream = Counted {
+            stream: &mut self.stream,
+            calls: &mut self.writes,
+        };
         if let Some(ref mut tls) = self.tls {
-            match tls.write_tls(&mut self.stream) {
+            match tls.write_tls(&mut stream) {
                 Ok(_) => {
                     if self.buffer.write_pending() > 0 {
                         self.buffer.write_to(tls)
@@ -118,7 +217,7 @@ impl Session {
                 Err(e) => Err(e),
             }
         } else {
-            self.buffer.write_to(&mut self.stream)
+            self.buffer.write_to(&mut stream)
         }
     }
 
@@ -0,0 +1,36 @@
+// Copyright 2021 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+//! Handling for SIGTERM, which orchestrators such as Kubernetes send before
+//! killing a process. The run is drained and its results are written instead
+//! of being lost.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static TERMINATED: AtomicBool = AtomicBool::new(false);
+
+#[cfg(unix)]
+extern "C" fn on_sigterm(_: libc::c_int) {
+    // only async-signal-safe operations are allowed here
+    TERMINATED.store(true, Ordering::SeqCst);
+}
+
+/// install the handler for SIGTERM
+#[cfg(unix)]
+pub fn init() {
+    // safety: the handler only stores to an atomic
+    let handler = on_sigterm as *const () as libc::sighandler_t;
+    let previous = unsafe { libc::signal(libc::SIGTERM, handler) };
+    if previous == libc::SIG_ERR {
+        warn!("failed to install SIGTERM handler");
+    }
+}
+
+#[cfg(not(unix))]
+pub fn init() {}
+
+/// whether SIGTERM has been received
+pub fn terminated() -> bool {
+    TERMINATED.load(Ordering::SeqCst)
+}
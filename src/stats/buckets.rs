@@ -0,0 +1,90 @@
+// Copyright 2021 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+//! The histogram buckets of each distribution over the last window, which
+//! are served on the stats port so that other tools can calculate any
+//! percentile and merge the histograms of separate instances. Each bucket is
+//! the lowest value it holds and its count. The buckets are log-linear with
+//! the same boundaries in every instance, so merging is adding the counts of
+//! buckets with the same value.
+
+use crate::stats::Export;
+
+use serde_json::{json, Map, Value};
+
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+
+pub struct Buckets {
+    previous: Export,
+    latest: Arc<Mutex<String>>,
+}
+
+impl Buckets {
+    pub fn new() -> Self {
+        Self {
+            previous: Export::new(),
+            latest: Arc::new(Mutex::new(render(0, &Export::new()))),
+        }
+    }
+
+    /// the buckets of the last window as JSON, which is updated as each
+    /// window ends
+    pub fn latest(&self) -> Arc<Mutex<String>> {
+        self.latest.clone()
+    }
+
+    /// record the buckets of the window which just ended, from the snapshot
+    /// taken at its end
+    pub fn window(&mut self, window: u64, current: Export) {
+        let content = render(window, &current.since(&self.previous));
+        self.previous = current;
+        *self.latest.lock().unwrap() = content;
+    }
+}
+
+impl Default for Buckets {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn render(window: u64, export: &Export) -> String {
+    let mut histograms: BTreeMap<&str, Vec<(u64, u64)>> = BTreeMap::new();
+    for ((stat, value), count) in &export.buckets {
+        histograms
+            .entry((*stat).into())
+            .or_default()
+            .push((*value, *count));
+    }
+    let mut map = Map::new();
+    for (name, mut buckets) in histograms {
+        buckets.sort_unstable();
+        map.insert(name.to_string(), json!(buckets));
+    }
+    let content = json!({
+        "window": window,
+        "histograms": Value::Object(map),
+    });
+    content.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stats::Stat;
+
+    #[test]
+    fn rendered() {
+        let mut export = Export::new();
+        export.buckets.insert((Stat::ResponsesLatency, 2048), 1);
+        export.buckets.insert((Stat::ResponsesLatency, 1024), 3);
+        export.buckets.insert((Stat::KeySize, 8), 4);
+        assert_eq!(
+            render(3, &export),
+            "{\"histograms\":{\"key/size\":[[8,4]],\
+             \"responses/latency\":[[1024,3],[2048,1]]},\"window\":3}"
+        );
+    }
+}
@@ -0,0 +1,116 @@
+// Copyright 2021 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+use crate::stats::{Export, Stat};
+
+use std::time::Duration;
+
+/// Prints a side-by-side summary of two groups of endpoints which are driven
+/// with the same workload. Changes are shown relative to the first group.
+pub struct Compare {
+    previous: [Export; 2],
+    interval: Duration,
+}
+
+impl Compare {
+    pub fn new(interval: Duration) -> Self {
+        Self {
+            previous: [Export::new(), Export::new()],
+            interval,
+        }
+    }
+
+    /// change the length of the window which the rates are calculated over
+    pub fn set_interval(&mut self, interval: Duration) {
+        self.interval = interval;
+    }
+
+    /// print the window which ended at the snapshots of the two groups
+    pub fn print(&mut self, snapshots: [&Export; 2]) {
+        let windows = [
+            snapshots[0].since(&self.previous[0]),
+            snapshots[1].since(&self.previous[1]),
+        ];
+        let get = |group: usize, stat: Stat| windows[group].counter(stat) as f64;
+
+        let seconds = self.interval.as_secs_f64();
+        let rate = [
+            get(0, Stat::ResponsesTotal) / seconds,
+            get(1, Stat::ResponsesTotal) / seconds,
+        ];
+        let success = [
+            percent(get(0, Stat::ResponsesOk), get(0, Stat::ResponsesTotal)),
+            percent(get(1, Stat::ResponsesOk), get(1, Stat::ResponsesTotal)),
+        ];
+        let hitrate = [
+            percent(
+                get(0, Stat::ResponsesHit),
+                get(0, Stat::ResponsesHit) + get(0, Stat::ResponsesMiss),
+            ),
+            percent(
+                get(1, Stat::ResponsesHit),
+                get(1, Stat::ResponsesHit) + get(1, Stat::ResponsesMiss),
+            ),
+        ];
+
+        info!(
+            "Compare: Rate (rps): A: {:.2} B: {:.2} Change: {}",
+            rate[0],
+            rate[1],
+            change(rate[0], rate[1])
+        );
+        info!(
+            "Compare: Success: A: {:.2}% B: {:.2}% Hit-rate: A: {:.2}% B: {:.2}%",
+            success[0], success[1], hitrate[0], hitrate[1],
+        );
+
+        let mut latency = Vec::new();
+        for (label, percentile) in &[("p50", 50.0), ("p90", 90.0), ("p99", 99.0), ("p999", 99.9)] {
+            let a = windows[0].percentile(Stat::ResponsesLatency, *percentile);
+            let b = windows[1].percentile(Stat::ResponsesLatency, *percentile);
+            latency.push(match (a, b) {
+                (Some(a), Some(b)) => format!(
+                    "{}: {}/{} ({})",
+                    label,
+                    a / 1000,
+                    b / 1000,
+                    change(a as f64, b as f64)
+                ),
+                _ => format!("{}: none", label),
+            });
+        }
+        info!("Compare: Request Latency (us) A/B: {}", latency.join(" "));
+        self.previous = [snapshots[0].clone(), snapshots[1].clone()];
+    }
+}
+
+fn percent(a: f64, b: f64) -> f64 {
+    if b == 0.0 {
+        100.0
+    } else {
+        100.0 * a / b
+    }
+}
+
+/// the relative change from `a` to `b`
+fn change(a: f64, b: f64) -> String {
+    if a == 0.0 {
+        "none".to_string()
+    } else {
+        format!("{:+.2}%", 100.0 * (b - a) / a)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn changes() {
+        assert_eq!(change(100.0, 110.0), "+10.00%");
+        assert_eq!(change(200.0, 150.0), "-25.00%");
+        assert_eq!(change(0.0, 10.0), "none");
+        assert_eq!(percent(0.0, 0.0), 100.0);
+    }
+}
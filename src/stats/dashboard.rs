@@ -0,0 +1,228 @@
+// Copyright 2021 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+//! A Grafana dashboard for the metrics which the stats port exposes in the
+//! Prometheus format. Panels for optional features are only included if the
+//! config enables them.
+
+use crate::config::Config;
+use crate::stats::Stat;
+
+use serde_json::{json, Value};
+
+const PERCENTILES: [f64; 6] = [50.0, 75.0, 90.0, 99.0, 99.9, 99.99];
+
+/// render the dashboard as JSON which can be imported into Grafana
+pub fn dashboard(config: &Config) -> String {
+    let mut panels = vec![
+        panel(
+            "Rate",
+            "reqps",
+            vec![
+                target(&rate(Stat::RequestsDequeued), "request"),
+                target(&rate(Stat::ResponsesTotal), "response"),
+            ],
+        ),
+        panel(
+            "Responses",
+            "reqps",
+            vec![
+                target(&rate(Stat::ResponsesOk), "ok"),
+                target(&rate(Stat::ResponsesError), "error"),
+                target(&rate(Stat::RequestsTimeout), "timeout"),
+            ],
+        ),
+        panel(
+            "Hit-rate",
+            "percentunit",
+            vec![target(
+                &format!(
+                    "{} / ({} + {})",
+                    rate(Stat::ResponsesHit),
+                    rate(Stat::ResponsesHit),
+                    rate(Stat::ResponsesMiss)
+                ),
+                "hit-rate",
+            )],
+        ),
+        percentiles("Request Latency", Stat::ResponsesLatency, "ns"),
+        percentiles("Connect Latency", Stat::ConnectionsLatency, "ns"),
+        panel(
+            "Connections",
+            "cps",
+            vec![
+                target(&rate(Stat::ConnectionsOpened), "opened"),
+                target(&rate(Stat::ConnectionsClosed), "closed"),
+                target(&rate(Stat::ConnectionsError), "error"),
+                target(&rate(Stat::ConnectionsTimeout), "timeout"),
+            ],
+        ),
+        panel(
+            "Client CPU",
+            "short",
+            vec![target(
+                &format!("{} / 1e9", rate(Stat::ProfileCpu)),
+                "cores",
+            )],
+        ),
+    ];
+    if config.request_ratelimit().is_some() {
+        panels.push(panel(
+            "Ratelimit",
+            "reqps",
+            vec![
+                target(&selector(&metric(Stat::RatelimitTarget, None)), "target"),
+                target(
+                    &selector(&metric(Stat::RatelimitAchieved, None)),
+                    "achieved",
+                ),
+            ],
+        ));
+    }
+    if !config.shadow().is_empty() {
+        panels.push(panel(
+            "Mirror",
+            "reqps",
+            vec![
+                target(&rate(Stat::MirrorMatched), "matched"),
+                target(&rate(Stat::MirrorDivergedOutcome), "diverged outcome"),
+                target(&rate(Stat::MirrorDivergedValue), "diverged value"),
+                target(&rate(Stat::MirrorUnmatched), "unmatched"),
+            ],
+        ));
+    }
+    if !config.chaos().is_empty() {
+        panels.push(panel(
+            "Chaos",
+            "short",
+            vec![
+                target(&selector(&metric(Stat::ChaosActive, None)), "active faults"),
+                target(&rate(Stat::ChaosDelayed), "delayed"),
+                target(&rate(Stat::ChaosDisconnected), "disconnected"),
+                target(&rate(Stat::ChaosBlackholed), "blackholed"),
+            ],
+        ));
+    }
+
+    // two panels per row
+    for (i, panel) in panels.iter_mut().enumerate() {
+        panel["id"] = json!(i + 1);
+        panel["gridPos"] = json!({
+            "h": 8,
+            "w": 12,
+            "x": (i % 2) * 12,
+            "y": (i / 2) * 8,
+        });
+    }
+
+    let dashboard = json!({
+        "title": format!("rpc-perf {}", config.protocol().name()),
+        "tags": ["rpc-perf"],
+        "timezone": "browser",
+        "refresh": format!("{}s", config.interval()),
+        "time": {"from": "now-1h", "to": "now"},
+        "schemaVersion": 26,
+        "templating": {
+            "list": [{
+                "name": "datasource",
+                "label": "Data Source",
+                "type": "datasource",
+                "query": "prometheus",
+            }]
+        },
+        "panels": panels,
+    });
+    serde_json::to_string_pretty(&dashboard).unwrap()
+}
+
+/// the name of the metric on the Prometheus endpoint, this follows the
+/// naming in `MetricsSnapshot::prometheus()`
+fn metric(stat: Stat, percentile: Option<f64>) -> String {
+    let name: &str = stat.into();
+    let name = match percentile {
+        Some(percentile) => format!("{}/p{:02}", name, percentile),
+        None => name.to_string(),
+    };
+    name.replace('/', "_")
+}
+
+/// a PromQL selector for the metric. Names for fractional percentiles are not
+/// valid identifiers, so they are matched by label instead.
+fn selector(name: &str) -> String {
+    if name.contains('.') {
+        format!("{{__name__=\"{}\"}}", name)
+    } else {
+        name.to_string()
+    }
+}
+
+/// the per-second rate of a counter
+fn rate(stat: Stat) -> String {
+    format!("sum(rate({}[1m]))", selector(&metric(stat, None)))
+}
+
+fn target(expr: &str, legend: &str) -> Value {
+    json!({
+        "expr": expr,
+        "legendFormat": legend,
+    })
+}
+
+fn percentiles(title: &str, stat: Stat, unit: &str) -> Value {
+    let targets = PERCENTILES
+        .iter()
+        .map(|percentile| {
+            let name = metric(stat, Some(*percentile));
+            target(
+                &format!("max({})", selector(&name)),
+                &format!("p{}", percentile),
+            )
+        })
+        .collect();
+    panel(title, unit, targets)
+}
+
+fn panel(title: &str, unit: &str, targets: Vec<Value>) -> Value {
+    json!({
+        "title": title,
+        "type": "graph",
+        "datasource": "$datasource",
+        "lines": true,
+        "linewidth": 1,
+        "fill": 1,
+        "legend": {"show": true},
+        "yaxes": [
+            {"format": unit, "min": 0, "show": true},
+            {"format": "short", "show": false},
+        ],
+        "targets": targets,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names() {
+        assert_eq!(metric(Stat::ResponsesTotal, None), "responses_total");
+        assert_eq!(
+            metric(Stat::ResponsesLatency, Some(50.0)),
+            "responses_latency_p50"
+        );
+        assert_eq!(
+            selector(&metric(Stat::ResponsesLatency, Some(99.9))),
+            "{__name__=\"responses_latency_p99.9\"}"
+        );
+    }
+
+    #[test]
+    fn panels() {
+        let dashboard: Value = serde_json::from_str(&dashboard(&Config::default())).unwrap();
+        let panels = dashboard["panels"].as_array().unwrap();
+        assert_eq!(panels.len(), 7);
+        assert_eq!(panels[3]["targets"].as_array().unwrap().len(), 6);
+        assert_eq!(panels[6]["gridPos"]["y"], json!(24));
+    }
+}
@@ -0,0 +1,147 @@
+// Copyright 2021 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+//! The slowest requests of each window, which are logged so that the tail
+//! latency can be correlated with the logs of the servers.
+
+use crate::codec::Tag;
+
+use serde_json::Value;
+
+use std::net::SocketAddr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Exemplar {
+    /// the latency in nanoseconds
+    pub latency: u64,
+    /// the time the response was received
+    pub time: SystemTime,
+    pub endpoint: SocketAddr,
+    pub tag: Tag,
+}
+
+impl Exemplar {
+    /// describe the request in a line for the log
+    pub fn describe(&self) -> String {
+        let time = self.time.duration_since(UNIX_EPOCH).unwrap_or_default();
+        let mut line = format!(
+            "Latency: {}us Endpoint: {} Time: {}.{:03}",
+            self.latency / 1000,
+            self.endpoint,
+            time.as_secs(),
+            time.subsec_millis()
+        );
+        if let Some(Ok(Value::String(name))) = self.tag.action.map(serde_json::to_value) {
+            line += &format!(" Command: {}", name);
+        }
+        if let Some(ref key) = self.tag.key {
+            line += &format!(" Key: {}", key);
+        }
+        if let Some(ref trace) = self.tag.trace {
+            line += &format!(" Trace: {}", trace);
+        }
+        line
+    }
+}
+
+/// The slowest requests, up to a limit, ordered from the slowest.
+#[derive(Clone, Debug)]
+pub struct Exemplars {
+    limit: usize,
+    slowest: Vec<Exemplar>,
+}
+
+impl Exemplars {
+    pub fn new(limit: usize) -> Self {
+        Self {
+            limit,
+            slowest: Vec::with_capacity(limit + 1),
+        }
+    }
+
+    /// whether a request with this latency would be kept
+    pub fn wants(&self, latency: u64) -> bool {
+        self.slowest.len() < self.limit
+            || self
+                .slowest
+                .last()
+                .map(|e| latency > e.latency)
+                .unwrap_or(false)
+    }
+
+    pub fn record(&mut self, exemplar: Exemplar) {
+        if !self.wants(exemplar.latency) {
+            return;
+        }
+        let index = self
+            .slowest
+            .iter()
+            .position(|e| e.latency < exemplar.latency)
+            .unwrap_or(self.slowest.len());
+        self.slowest.insert(index, exemplar);
+        self.slowest.truncate(self.limit);
+    }
+
+    /// move the requests recorded by another thread into these
+    pub fn merge(&mut self, other: &mut Exemplars) {
+        for exemplar in other.take() {
+            self.record(exemplar);
+        }
+    }
+
+    /// take the requests recorded so far, from the slowest
+    pub fn take(&mut self) -> Vec<Exemplar> {
+        std::mem::take(&mut self.slowest)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Action;
+
+    fn exemplar(latency: u64) -> Exemplar {
+        Exemplar {
+            latency,
+            time: UNIX_EPOCH,
+            endpoint: "127.0.0.1:11211".parse().unwrap(),
+            tag: Tag {
+                action: Some(Action::SarrayGet),
+                key: Some(format!("{}", latency)),
+                trace: None,
+            },
+        }
+    }
+
+    #[test]
+    fn slowest() {
+        let mut exemplars = Exemplars::new(3);
+        for latency in &[5, 1, 9, 3, 7] {
+            exemplars.record(exemplar(*latency));
+        }
+        let mut other = Exemplars::new(3);
+        other.record(exemplar(8));
+        other.record(exemplar(2));
+        exemplars.merge(&mut other);
+        assert!(other.take().is_empty());
+        assert!(!exemplars.wants(7));
+        let latencies: Vec<u64> = exemplars.take().iter().map(|e| e.latency).collect();
+        assert_eq!(latencies, vec![9, 8, 7]);
+        assert!(exemplars.take().is_empty());
+
+        assert!(!Exemplars::new(0).wants(1));
+    }
+
+    #[test]
+    fn described() {
+        let mut exemplar = exemplar(1_500_000);
+        exemplar.tag.trace = Some("00000000000000ab".to_string());
+        assert_eq!(
+            exemplar.describe(),
+            "Latency: 1500us Endpoint: 127.0.0.1:11211 Time: 0.000 \
+             Command: sarray_get Key: 1500000 Trace: 00000000000000ab"
+        );
+    }
+}
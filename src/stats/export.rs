@@ -0,0 +1,175 @@
+// Copyright 2021 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+//! A plain text export of the cumulative counters and histogram buckets of an
+//! instance, which another instance can merge into its own metrics. Each line
+//! is either `counter <name> <value>`, `bucket <name> <value> <count>` or
+//! `heatmap <second> <value> <count>`.
+
+use crate::stats::{histogram, Stat};
+
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Export {
+    pub(super) counters: HashMap<Stat, u64>,
+    pub(super) buckets: HashMap<(Stat, u64), u64>,
+    /// request latencies by the unix second the request was sent in. Unlike
+    /// the counters and buckets these aren't totals, they are the readings
+    /// taken since the previous export, see `Metrics::take_heatmap`.
+    pub(super) heatmap: HashMap<(u64, u64), u64>,
+}
+
+impl Export {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// parse an export, returning `None` if it is malformed
+    pub fn parse(content: &str) -> Option<Self> {
+        let mut export = Self::new();
+        for line in content.lines().filter(|l| !l.is_empty()) {
+            let mut parts = line.split_whitespace();
+            match (parts.next()?, parts.next(), parts.next(), parts.next()) {
+                ("counter", Some(name), Some(value), None) => {
+                    let stat = Stat::from_str(name).ok()?;
+                    export.counters.insert(stat, value.parse().ok()?);
+                }
+                ("bucket", Some(name), Some(value), Some(count)) => {
+                    let stat = Stat::from_str(name).ok()?;
+                    export
+                        .buckets
+                        .insert((stat, value.parse().ok()?), count.parse().ok()?);
+                }
+                ("heatmap", Some(second), Some(value), Some(count)) => {
+                    export.heatmap.insert(
+                        (second.parse().ok()?, value.parse().ok()?),
+                        count.parse().ok()?,
+                    );
+                }
+                _ => return None,
+            }
+        }
+        Some(export)
+    }
+
+    /// the change from an earlier export of the same instance. A reading
+    /// which went down was reset, so the new reading is all change.
+    pub fn since(&self, previous: &Export) -> Export {
+        fn delta(current: u64, previous: Option<&u64>) -> u64 {
+            match previous {
+                Some(previous) if *previous <= current => current - previous,
+                _ => current,
+            }
+        }
+        Export {
+            counters: self
+                .counters
+                .iter()
+                .map(|(stat, value)| (*stat, delta(*value, previous.counters.get(stat))))
+                .filter(|(_, value)| *value > 0)
+                .collect(),
+            buckets: self
+                .buckets
+                .iter()
+                .map(|(key, count)| (*key, delta(*count, previous.buckets.get(key))))
+                .filter(|(_, count)| *count > 0)
+                .collect(),
+            heatmap: self.heatmap.clone(),
+        }
+    }
+
+    /// include the waterfall readings, which agents send to the coordinator
+    pub fn set_heatmap(&mut self, heatmap: HashMap<(u64, u64), u64>) {
+        self.heatmap = heatmap;
+    }
+
+    /// the reading of a counter, which is zero if it isn't in the export
+    pub fn counter(&self, stat: Stat) -> u64 {
+        self.counters.get(&stat).copied().unwrap_or(0)
+    }
+
+    /// the value of a distribution at the given percentile
+    pub fn percentile(&self, stat: Stat, percentile: f64) -> Option<u64> {
+        let mut buckets: Vec<(u64, u64)> = self
+            .buckets
+            .iter()
+            .filter(|((s, _), _)| *s == stat)
+            .map(|((_, value), count)| (*value, *count))
+            .collect();
+        buckets.sort_unstable();
+        histogram::percentile(&buckets, percentile)
+    }
+}
+
+impl fmt::Display for Export {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut lines = Vec::new();
+        for (stat, value) in &self.counters {
+            let name: &str = (*stat).into();
+            lines.push(format!("counter {} {}", name, value));
+        }
+        for ((stat, value), count) in &self.buckets {
+            let name: &str = (*stat).into();
+            lines.push(format!("bucket {} {} {}", name, value, count));
+        }
+        for ((second, value), count) in &self.heatmap {
+            lines.push(format!("heatmap {} {} {}", second, value, count));
+        }
+        lines.sort();
+        for line in lines {
+            writeln!(f, "{}", line)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn roundtrip() {
+        let mut export = Export::new();
+        export.counters.insert(Stat::RequestsEnqueued, 10);
+        export.buckets.insert((Stat::ResponsesLatency, 1024), 3);
+        export.heatmap.insert((1_600_000_000, 1024), 2);
+        let content = export.to_string();
+        assert_eq!(
+            content,
+            "bucket responses/latency 1024 3\ncounter requests/enqueued 10\n\
+             heatmap 1600000000 1024 2\n"
+        );
+        assert_eq!(Export::parse(&content), Some(export));
+        assert_eq!(Export::parse("counter unknown/stat 1"), None);
+        assert_eq!(Export::parse("bucket responses/latency 1"), None);
+    }
+
+    #[test]
+    fn since() {
+        let mut previous = Export::new();
+        previous.counters.insert(Stat::RequestsEnqueued, 10);
+        previous.counters.insert(Stat::ResponsesOk, 8);
+        previous.buckets.insert((Stat::ResponsesLatency, 1024), 3);
+
+        let mut current = previous.clone();
+        current.counters.insert(Stat::RequestsEnqueued, 15);
+        current.counters.insert(Stat::ResponsesOk, 2);
+        current.buckets.insert((Stat::ResponsesLatency, 2048), 1);
+
+        let delta = current.since(&previous);
+        assert_eq!(delta.counters.get(&Stat::RequestsEnqueued), Some(&5));
+        assert_eq!(delta.counters.get(&Stat::ResponsesOk), Some(&2));
+        assert_eq!(delta.buckets.len(), 1);
+        assert_eq!(delta.buckets.get(&(Stat::ResponsesLatency, 2048)), Some(&1));
+        assert_eq!(current.percentile(Stat::ResponsesLatency, 50.0), Some(1024));
+        assert_eq!(
+            current.percentile(Stat::ResponsesLatency, 100.0),
+            Some(2048)
+        );
+        assert_eq!(delta.percentile(Stat::KeySize, 50.0), None);
+    }
+}
@@ -0,0 +1,77 @@
+// Copyright 2021 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+use crate::stats::{Export, Stat};
+
+use std::time::Duration;
+
+/// Prints the stats of each client group which runs alongside the clients of
+/// the config, each group recording into metrics of its own. The groups are
+/// summarized along with the run, see `Summary::add_group`.
+pub struct Groups {
+    names: Vec<String>,
+    previous: Vec<Export>,
+    interval: Duration,
+}
+
+impl Groups {
+    pub fn new(names: Vec<String>, interval: Duration) -> Self {
+        let previous = names.iter().map(|_| Export::new()).collect();
+        Self {
+            names,
+            previous,
+            interval,
+        }
+    }
+
+    /// change the length of the window which the rates are calculated over
+    pub fn set_interval(&mut self, interval: Duration) {
+        self.interval = interval;
+    }
+
+    /// print the window's stats for each group, from the snapshot of each
+    /// group's metrics taken at the end of the window, in the order of the
+    /// groups
+    pub fn print(&mut self, snapshots: &[Export]) {
+        let seconds = self.interval.as_secs_f64();
+        for (name, (snapshot, previous)) in self
+            .names
+            .iter()
+            .zip(snapshots.iter().zip(self.previous.iter_mut()))
+        {
+            let window = snapshot.since(previous);
+            let get = |stat: Stat| window.counter(stat) as f64;
+
+            let mut latency = Vec::new();
+            for (label, percentile) in &[("p50", 50.0), ("p99", 99.0), ("p999", 99.9)] {
+                latency.push(
+                    match window.percentile(Stat::ResponsesLatency, *percentile) {
+                        Some(value) => format!("{}: {}", label, value / 1000),
+                        None => format!("{}: none", label),
+                    },
+                );
+            }
+            info!(
+                "Group: {}: Rate (rps): {:.2} Success: {:.2}% Hit-rate: {:.2}% Latency (us): {}",
+                name,
+                get(Stat::ResponsesTotal) / seconds,
+                percent(get(Stat::ResponsesOk), get(Stat::ResponsesTotal)),
+                percent(
+                    get(Stat::ResponsesHit),
+                    get(Stat::ResponsesHit) + get(Stat::ResponsesMiss)
+                ),
+                latency.join(" "),
+            );
+            *previous = snapshot.clone();
+        }
+    }
+}
+
+fn percent(a: f64, b: f64) -> f64 {
+    if b == 0.0 {
+        100.0
+    } else {
+        100.0 * a / b
+    }
+}
@@ -0,0 +1,136 @@
+// Copyright 2021 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+use rustcommon_atomics::{Arithmetic, Atomic, AtomicU64, Ordering};
+
+// bits of precision kept for each value, giving a relative error below 1%
+const PRECISION: u32 = 7;
+const BUCKETS: usize = (65 - PRECISION as usize) << PRECISION;
+
+/// A log-linear histogram whose buckets can be exported and merged, so that
+/// distributions recorded by separate instances can be combined.
+pub struct Histogram {
+    buckets: Vec<AtomicU64>,
+}
+
+impl Histogram {
+    pub fn new() -> Self {
+        let mut buckets = Vec::with_capacity(BUCKETS);
+        buckets.resize_with(BUCKETS, || AtomicU64::new(0));
+        Self { buckets }
+    }
+
+    /// record `count` instances of `value`
+    pub fn increment(&self, value: u64, count: u64) {
+        self.buckets[index(value)].fetch_add(count, Ordering::Relaxed);
+    }
+
+    /// returns the lower bound and count of each non-empty bucket
+    pub fn buckets(&self) -> Vec<(u64, u64)> {
+        self.buckets
+            .iter()
+            .enumerate()
+            .map(|(i, count)| (value(i), count.load(Ordering::Relaxed)))
+            .filter(|(_, count)| *count > 0)
+            .collect()
+    }
+
+    /// the value at the given percentile, `None` if the histogram is empty
+    pub fn percentile(&self, percentile: f64) -> Option<u64> {
+        self::percentile(&self.buckets(), percentile)
+    }
+
+    pub fn clear(&self) {
+        for bucket in &self.buckets {
+            bucket.store(0, Ordering::Relaxed);
+        }
+    }
+}
+
+impl Default for Histogram {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// the value at the given percentile of buckets which are sorted by their
+/// value, `None` if they are empty
+pub fn percentile(buckets: &[(u64, u64)], percentile: f64) -> Option<u64> {
+    let total: u64 = buckets.iter().map(|(_, count)| count).sum();
+    if total == 0 {
+        return None;
+    }
+    let needed = ((percentile / 100.0) * total as f64).ceil().max(1.0) as u64;
+    let mut seen = 0;
+    for (value, count) in buckets {
+        seen += count;
+        if seen >= needed {
+            return Some(*value);
+        }
+    }
+    None
+}
+
+/// the lowest value in the bucket which holds `value`, readings can be
+/// rounded down to this without losing precision
+pub fn bucket(value: u64) -> u64 {
+    self::value(index(value))
+}
+
+fn index(value: u64) -> usize {
+    if value < (1 << (PRECISION + 1)) {
+        return value as usize;
+    }
+    // keep the top PRECISION + 1 bits, the leading one is implied by the group
+    let shift = 63 - value.leading_zeros() - PRECISION;
+    let mantissa = (value >> shift) as usize - (1 << PRECISION);
+    ((shift as usize + 1) << PRECISION) + mantissa
+}
+
+fn value(index: usize) -> u64 {
+    let group = index >> PRECISION;
+    if group <= 1 {
+        return index as u64;
+    }
+    let shift = group as u32 - 1;
+    let mantissa = (index & ((1 << PRECISION) - 1)) as u64 + (1 << PRECISION);
+    mantissa << shift
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn buckets() {
+        for v in &[0, 1, 255, 256, 257, 1_000, 123_456_789, u64::MAX] {
+            let lower = value(index(*v));
+            assert!(lower <= *v);
+            assert!((*v - lower) as f64 <= *v as f64 / 100.0, "{}", v);
+            assert!(index(*v) < BUCKETS);
+        }
+        assert_eq!(index(255), 255);
+        assert_eq!(index(256), 256);
+        assert_eq!(value(index(258)), 258);
+        assert_eq!(value(index(259)), 258);
+        assert_eq!(bucket(259), 258);
+        assert_eq!(bucket(bucket(123_456_789)), bucket(123_456_789));
+    }
+
+    #[test]
+    fn percentiles() {
+        let histogram = Histogram::new();
+        assert_eq!(histogram.percentile(50.0), None);
+        for v in 1..=100 {
+            histogram.increment(v, 1);
+        }
+        histogram.increment(1_000_000, 100);
+        assert_eq!(histogram.percentile(25.0), Some(50));
+        assert_eq!(histogram.percentile(50.0), Some(100));
+        assert_eq!(histogram.percentile(100.0), Some(value(index(1_000_000))));
+        assert_eq!(histogram.buckets().len(), 101);
+        histogram.clear();
+        assert!(histogram.buckets().is_empty());
+    }
+}
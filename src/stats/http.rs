@@ -3,27 +3,27 @@
 // http://www.apache.org/licenses/LICENSE-2.0
 
 use std::net::SocketAddr;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 use std::time::{Duration, Instant};
 
+use rustcommon_atomics::{Atomic, AtomicBool, Ordering};
 use rustcommon_logger::*;
-use rustcommon_metrics::*;
 use tiny_http::{Method, Response, Server};
 
-use super::MetricsSnapshot;
+use super::{Metrics, MetricsSnapshot};
+use crate::metadata::Metadata;
 
 pub struct Http {
     snapshot: MetricsSnapshot,
     server: Server,
     updated: Instant,
+    ready: Option<Arc<AtomicBool>>,
+    metadata: Option<Metadata>,
+    buckets: Option<Arc<Mutex<String>>>,
 }
 
 impl Http {
-    pub fn new(
-        address: SocketAddr,
-        metrics: Arc<Metrics<AtomicU64, AtomicU32>>,
-        count_label: Option<&str>,
-    ) -> Self {
+    pub fn new(address: SocketAddr, metrics: Arc<Metrics>, count_label: Option<&str>) -> Self {
         let server = tiny_http::Server::http(address);
         if server.is_err() {
             fatal!("Failed to open {} for HTTP Stats listener", address);
@@ -32,6 +32,57 @@ impl Http {
             snapshot: MetricsSnapshot::new(metrics, count_label),
             server: server.unwrap(),
             updated: Instant::now(),
+            ready: None,
+            metadata: None,
+            buckets: None,
+        }
+    }
+
+    /// report readiness on `/ready` from the flag, which is set while the
+    /// workload is being measured. Without a flag, the instance is always
+    /// ready.
+    pub fn set_ready(&mut self, ready: Arc<AtomicBool>) {
+        self.ready = Some(ready);
+    }
+
+    /// include the metadata of the run in the Prometheus and JSON outputs
+    pub fn set_metadata(&mut self, metadata: Metadata) {
+        self.metadata = Some(metadata);
+    }
+
+    /// include the metrics of a client group, labelled with the group's name
+    pub fn add_group(&mut self, name: &str, metrics: Arc<Metrics>) {
+        self.snapshot.add_group(name, metrics);
+    }
+
+    /// serve the histogram buckets of the last window on `/histograms.json`,
+    /// see `stats::Buckets`
+    pub fn set_buckets(&mut self, buckets: Arc<Mutex<String>>) {
+        self.buckets = Some(buckets);
+    }
+
+    fn prometheus(&self) -> String {
+        let mut content = self.snapshot.prometheus();
+        if let Some(ref metadata) = self.metadata {
+            content += &metadata.prometheus();
+        }
+        content
+    }
+
+    /// the stats as a JSON object, with the metadata under `run`
+    fn json(&self) -> String {
+        let content = self.snapshot.json(false);
+        match self.metadata {
+            Some(ref metadata) => {
+                let body = content.trim_start_matches('{');
+                let separator = if body.trim_start().starts_with('}') {
+                    ""
+                } else {
+                    ","
+                };
+                format!("{{\"run\":{}{}{}", metadata.json(), separator, body)
+            }
+            None => content,
         }
     }
 
@@ -54,13 +105,32 @@ impl Http {
                             crate::config::VERSION,
                         )));
                     }
+                    "/ready" => {
+                        if self.ready.as_ref().map(|r| r.load(Ordering::SeqCst)) == Some(false) {
+                            let _ = request.respond(
+                                Response::from_string("not ready\n").with_status_code(503),
+                            );
+                        } else {
+                            let _ = request.respond(Response::from_string("ready\n"));
+                        }
+                    }
                     "/metrics" => {
                         debug!("Serving Prometheus compatible stats");
-                        let _ = request.respond(Response::from_string(self.snapshot.prometheus()));
+                        let _ = request.respond(Response::from_string(self.prometheus()));
                     }
                     "/metrics.json" | "/vars.json" | "/admin/metrics.json" => {
                         debug!("Serving machine readable stats");
-                        let _ = request.respond(Response::from_string(self.snapshot.json(false)));
+                        let _ = request.respond(Response::from_string(self.json()));
+                    }
+                    "/histograms.json" => {
+                        debug!("Serving histogram buckets");
+                        let response = match self.buckets {
+                            Some(ref buckets) => {
+                                Response::from_string(buckets.lock().unwrap().clone())
+                            }
+                            None => Response::from_string("not found\n").with_status_code(404),
+                        };
+                        let _ = request.respond(response);
                     }
                     "/vars" => {
                         debug!("Serving human readable stats");
@@ -69,7 +139,7 @@ impl Http {
                     url => {
                         debug!("GET on non-existent url: {}", url);
                         debug!("Serving machine readable stats");
-                        let _ = request.respond(Response::from_string(self.snapshot.json(false)));
+                        let _ = request.respond(Response::from_string(self.json()));
                     }
                 },
                 method => {
@@ -0,0 +1,146 @@
+// Copyright 2021 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+//! Per-thread metric aggregation. Client threads record into a thread-local
+//! `Local` which is merged into the shared metrics periodically, so that the
+//! request path does not contend on shared atomics.
+
+use crate::stats::histogram;
+use crate::stats::Stat;
+
+use strum::IntoEnumIterator;
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+thread_local! {
+    pub(super) static LOCAL: RefCell<Option<Local>> = const { RefCell::new(None) };
+}
+
+/// Metric readings accumulated by a single thread since the last flush
+pub(super) struct Local {
+    counters: Vec<u64>,
+    buckets: HashMap<(Stat, u64), u32>,
+    // keyed by the whole seconds from the start of the batch and the value
+    heatmap: HashMap<(u64, u64), u32>,
+    start: Instant,
+}
+
+/// The readings taken from a `Local` which should be merged into the shared
+/// metrics. The heatmap is recorded at the second each request was sent in,
+/// so that a batch which spans a stall isn't smeared into the waterfall at a
+/// single time.
+pub(super) struct Batch {
+    pub counters: Vec<(Stat, u64)>,
+    pub buckets: Vec<(Stat, u64, u32)>,
+    pub heatmap: Vec<(Instant, u64, u32)>,
+}
+
+impl Local {
+    pub fn new() -> Self {
+        Self {
+            counters: vec![0; Stat::iter().count()],
+            buckets: HashMap::new(),
+            heatmap: HashMap::new(),
+            start: Instant::now(),
+        }
+    }
+
+    pub fn add(&mut self, stat: Stat, value: u64) {
+        self.counters[stat as usize] += value;
+    }
+
+    /// record a reading of a distribution. Readings are rounded down to the
+    /// bucket which holds them, so that there is one entry per bucket rather
+    /// than one per distinct value.
+    pub fn record(&mut self, stat: Stat, value: u64) {
+        let value = histogram::bucket(value);
+        *self.buckets.entry((stat, value)).or_insert(0) += 1;
+    }
+
+    pub fn heatmap(&mut self, time: Instant, value: u64) {
+        let value = histogram::bucket(value);
+        let offset = time.saturating_duration_since(self.start).as_secs();
+        *self.heatmap.entry((offset, value)).or_insert(0) += 1;
+    }
+
+    /// take all readings since the last call, resetting the local state
+    pub fn take(&mut self) -> Batch {
+        let start = self.start;
+        let counters = Stat::iter()
+            .zip(self.counters.iter_mut())
+            .filter(|(_, value)| **value > 0)
+            .map(|(stat, value)| (stat, std::mem::replace(value, 0)))
+            .collect();
+        let batch = Batch {
+            counters,
+            buckets: self
+                .buckets
+                .drain()
+                .map(|((stat, value), count)| (stat, value, count))
+                .collect(),
+            heatmap: self
+                .heatmap
+                .drain()
+                .map(|((offset, value), count)| (start + Duration::from_secs(offset), value, count))
+                .collect(),
+        };
+        self.start = Instant::now();
+        batch
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn take() {
+        let mut local = Local::new();
+        local.add(Stat::RequestsEnqueued, 1);
+        local.add(Stat::RequestsEnqueued, 2);
+        local.add(Stat::ResponsesOk, 1);
+        local.record(Stat::KeySize, 8);
+        local.record(Stat::KeySize, 8);
+        local.record(Stat::ValueSize, 64);
+        local.heatmap(local.start, 1_000);
+        local.heatmap(local.start + Duration::from_secs(2), 2_000);
+
+        let start = local.start;
+        let mut batch = local.take();
+        batch.counters.sort_by_key(|(stat, _)| *stat as usize);
+        assert_eq!(
+            batch.counters,
+            vec![(Stat::RequestsEnqueued, 3), (Stat::ResponsesOk, 1)]
+        );
+        batch.buckets.sort_by_key(|(stat, _, _)| *stat as usize);
+        assert_eq!(
+            batch.buckets,
+            vec![(Stat::KeySize, 8, 2), (Stat::ValueSize, 64, 1)]
+        );
+        batch.heatmap.sort_by_key(|(_, value, _)| *value);
+        assert_eq!(
+            batch.heatmap,
+            vec![
+                (start, 1_000, 1),
+                (start + Duration::from_secs(2), 2_000, 1)
+            ]
+        );
+
+        // readings within a bucket share an entry
+        local.record(Stat::ResponsesLatency, 1_000_001);
+        local.record(Stat::ResponsesLatency, 1_000_002);
+        let batch = local.take();
+        assert_eq!(
+            batch.buckets,
+            vec![(Stat::ResponsesLatency, histogram::bucket(1_000_001), 2)]
+        );
+
+        let batch = local.take();
+        assert!(batch.counters.is_empty());
+        assert!(batch.buckets.is_empty());
+        assert!(batch.heatmap.is_empty());
+    }
+}
@@ -2,142 +2,289 @@
 // Licensed under the Apache License, Version 2.0
 // http://www.apache.org/licenses/LICENSE-2.0
 
+mod buckets;
+mod compare;
+mod dashboard;
+mod exemplars;
+mod export;
+mod groups;
+mod histogram;
 mod http;
+mod local;
+mod popularity;
+mod samples;
 mod snapshot;
+mod sparkline;
 mod stat;
+mod summary;
+mod windows;
 
 use crate::Config;
 use crate::SECOND;
 
+pub use buckets::Buckets;
+pub use compare::Compare;
+pub use dashboard::dashboard;
+pub use exemplars::{Exemplar, Exemplars};
+pub use export::Export;
+pub use groups::Groups;
+pub use histogram::Histogram;
 pub use http::Http;
+use local::{Local, LOCAL};
+pub use popularity::Popularity;
 use rustcommon_heatmap::AtomicHeatmap;
 use rustcommon_metrics::*;
 use rustcommon_waterfall::{Palette, WaterfallBuilder};
+pub use samples::{Sample, Samples, SAMPLE_INTERVAL};
 pub use snapshot::MetricsSnapshot;
+pub use sparkline::Sparkline;
 pub use stat::Stat;
 use strum::IntoEnumIterator;
+pub use summary::Summary;
+pub use windows::{Tick, Windows, SETTLE};
 
 use std::collections::HashMap;
 use std::convert::TryInto;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 use std::time::Duration;
-use std::time::Instant;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
 
 pub struct StandardOut {
-    previous: HashMap<Stat, u64>,
+    previous: Export,
     metrics: Arc<Metrics>,
     interval: Duration,
+    // the trends of the response rate and the p99 latency
+    sparklines: Option<(Sparkline, Sparkline)>,
 }
 
 impl StandardOut {
     pub fn new(metrics: Arc<Metrics>, interval: Duration) -> Self {
+        let sparklines = metrics
+            .config
+            .sparklines()
+            .map(|windows| (Sparkline::new(windows), Sparkline::new(windows)));
         Self {
-            previous: HashMap::new(),
+            previous: Export::new(),
             metrics,
             interval,
+            sparklines,
         }
     }
 
-    pub fn print(&mut self) {
-        let mut current = HashMap::new();
-        for stat in [
-            Stat::Window,
-            Stat::ConnectionsTotal,
-            Stat::ConnectionsOpened,
-            Stat::ConnectionsError,
-            Stat::ConnectionsTimeout,
-            Stat::ConnectionsClosed,
-            Stat::CommandsGet,
-            Stat::CommandsSet,
-            Stat::RequestsDequeued,
-            Stat::RequestsEnqueued,
-            Stat::RequestsTimeout,
-            Stat::ResponsesOk,
-            Stat::ResponsesError,
-            Stat::ResponsesHit,
-            Stat::ResponsesMiss,
-            Stat::ResponsesTotal,
-        ]
-        .iter()
-        {
-            current.insert(*stat, self.metrics.reading(stat).unwrap_or(0));
-        }
+    /// change the length of the window which the rates are calculated over
+    pub fn set_interval(&mut self, interval: Duration) {
+        self.interval = interval;
+    }
+
+    /// print the window which ended at `snapshot`, the export of the metrics
+    /// taken at its end, so that all of its stats are from the same readings
+    pub fn print(&mut self, snapshot: &Export) {
+        let window = snapshot.since(&self.previous);
 
         info!("-----");
-        info!("Window: {}", current.get(&Stat::Window).unwrap());
+        info!(
+            "Window: {}",
+            self.metrics.reading(&Stat::Window).unwrap_or(0)
+        );
+        let missed = window.counter(Stat::WindowMissed);
+        let late = window.counter(Stat::WindowLateFlushes);
+        if missed + late > 0 {
+            info!("Window: Missed: {} Late Flushes: {}", missed, late);
+        }
         info!(
             "Connections: Attempts: {} Opened: {} Errors: {} Timeouts: {} Open: {}",
-            self.delta_count(&Stat::ConnectionsTotal, &current),
-            self.delta_count(&Stat::ConnectionsOpened, &current),
-            self.delta_count(&Stat::ConnectionsError, &current),
-            self.delta_count(&Stat::ConnectionsTimeout, &current),
-            self.metrics
-                .reading(&Stat::ConnectionsOpened)
-                .unwrap_or(0)
-                .saturating_sub(self.metrics.reading(&Stat::ConnectionsClosed).unwrap_or(0)),
+            window.counter(Stat::ConnectionsTotal),
+            window.counter(Stat::ConnectionsOpened),
+            window.counter(Stat::ConnectionsError),
+            window.counter(Stat::ConnectionsTimeout),
+            snapshot
+                .counter(Stat::ConnectionsOpened)
+                .saturating_sub(snapshot.counter(Stat::ConnectionsClosed)),
         );
         info!(
             "Commands: Get: {} Set: {}",
-            self.delta_count(&Stat::CommandsGet, &current),
-            self.delta_count(&Stat::CommandsSet, &current),
+            window.counter(Stat::CommandsGet),
+            window.counter(Stat::CommandsSet),
         );
-        self.display_percentiles(Stat::KeySize, "Keys", 1, "bytes");
-        self.display_percentiles(Stat::ValueSize, "Values", 1, "bytes");
+        self.display_percentiles(&window, Stat::KeySize, "Keys", 1, "bytes");
+        self.display_percentiles(&window, Stat::ValueSize, "Values", 1, "bytes");
         info!(
             "Requests: Sent: {} Timeout: {} Prepared: {} Queue Depth: {}",
-            self.delta_count(&Stat::RequestsDequeued, &current),
-            self.delta_count(&Stat::RequestsTimeout, &current),
-            self.delta_count(&Stat::RequestsEnqueued, &current),
-            self.metrics
-                .reading(&Stat::RequestsEnqueued)
-                .unwrap_or(0)
-                .saturating_sub(self.metrics.reading(&Stat::RequestsDequeued).unwrap_or(0)),
+            window.counter(Stat::RequestsDequeued),
+            window.counter(Stat::RequestsTimeout),
+            window.counter(Stat::RequestsEnqueued),
+            snapshot
+                .counter(Stat::RequestsEnqueued)
+                .saturating_sub(snapshot.counter(Stat::RequestsDequeued)),
         );
         info!(
             "Responses: Ok: {} Error: {} Hit: {} Miss: {}",
-            self.delta_count(&Stat::ResponsesOk, &current),
-            self.delta_count(&Stat::ResponsesError, &current),
-            self.delta_count(&Stat::ResponsesHit, &current),
-            self.delta_count(&Stat::ResponsesMiss, &current),
+            window.counter(Stat::ResponsesOk),
+            window.counter(Stat::ResponsesError),
+            window.counter(Stat::ResponsesHit),
+            window.counter(Stat::ResponsesMiss),
         );
+        let request_rate = self.rate(&Stat::RequestsDequeued, &window);
+        self.metrics
+            .gauge(&Stat::RequestsRate, request_rate.round() as u64);
         info!(
             "Rate: Request: {:.2} rps Response: {:.2} rps Connect: {:.2} cps",
-            self.rate(&Stat::RequestsDequeued, &current),
-            self.rate(&Stat::ResponsesTotal, &current),
-            self.rate(&Stat::ConnectionsTotal, &current),
+            request_rate,
+            self.rate(&Stat::ResponsesTotal, &window),
+            self.rate(&Stat::ConnectionsTotal, &window),
         );
+        let target = self.metrics.reading(&Stat::RatelimitTarget).unwrap_or(0);
+        if target > 0 {
+            // each request token is used to send one request
+            let achieved = self.rate(&Stat::RequestsEnqueued, &window);
+            self.metrics
+                .gauge(&Stat::RatelimitAchieved, achieved.round() as u64);
+            let divergence = 100.0 * (achieved - target as f64) / target as f64;
+            self.metrics
+                .gauge(&Stat::RatelimitDivergence, percent_gauge(divergence.abs()));
+            info!(
+                "Ratelimit: Target: {} rps Achieved: {:.2} rps Error: {:.2}%",
+                target, achieved, divergence,
+            );
+        }
         info!(
             "Success: Request: {:.2}% Response: {:.2}% Connect: {:.2}%",
-            self.delta_percent(&Stat::ResponsesTotal, &Stat::RequestsDequeued, &current,),
-            self.delta_percent(&Stat::ResponsesOk, &Stat::ResponsesTotal, &current,),
-            self.delta_percent(&Stat::ConnectionsOpened, &Stat::ConnectionsTotal, &current,),
+            self.delta_percent(&Stat::ResponsesTotal, &Stat::RequestsDequeued, &window),
+            self.delta_percent(&Stat::ResponsesOk, &Stat::ResponsesTotal, &window),
+            self.delta_percent(&Stat::ConnectionsOpened, &Stat::ConnectionsTotal, &window),
+        );
+        info!(
+            "Syscalls: Read: {} Write: {} Per-Request: {:.2}",
+            window.counter(Stat::SyscallsRead),
+            window.counter(Stat::SyscallsWrite),
+            self.syscalls_per_request(&window),
         );
+        let hitrate = self.hitrate(&Stat::ResponsesHit, &Stat::ResponsesMiss, &window);
+        self.metrics
+            .gauge(&Stat::ResponsesHitrate, percent_gauge(hitrate));
+        self.metrics.gauge(
+            &Stat::ResponsesErrorRate,
+            percent_gauge(self.error_rate(&window)),
+        );
+        info!("Hit-rate: {:.2}%", hitrate);
+        if let Some(open) = crate::limits::open_files() {
+            self.metrics.gauge(&Stat::ProcessFdsOpen, open);
+        }
+        if let Ok((limit, _)) = crate::limits::nofile() {
+            self.metrics.gauge(&Stat::ProcessFdsLimit, limit);
+        }
         info!(
-            "Hit-rate: {:.2}%",
-            self.hitrate(&Stat::ResponsesHit, &Stat::ResponsesMiss, &current)
+            "Profile: Loops: {} Wait: Socket: {:.2}% Ratelimit: {:.2}% CPU: {:.2}%",
+            window.counter(Stat::ProfileLoops),
+            self.thread_percent(&Stat::ProfileWaitSocket, &window),
+            self.thread_percent(&Stat::ProfileWaitRatelimit, &window),
+            self.thread_percent(&Stat::ProfileCpu, &window),
         );
-        self.display_percentiles(Stat::ConnectionsLatency, "Connect Latency", 1000, "us");
-        self.display_percentiles(Stat::ResponsesLatency, "Request Latency", 1000, "us");
-        self.previous = current;
+        if !self.metrics.config.shadow().is_empty() {
+            info!(
+                "Mirror: Matched: {} Diverged: Outcome: {} Value: {} Unmatched: {}",
+                window.counter(Stat::MirrorMatched),
+                window.counter(Stat::MirrorDivergedOutcome),
+                window.counter(Stat::MirrorDivergedValue),
+                window.counter(Stat::MirrorUnmatched),
+            );
+        }
+        let rejected = window.counter(Stat::PushbackRejected);
+        let stalled = window.counter(Stat::PushbackStalled);
+        if rejected + stalled > 0 || self.metrics.config.pushback_backoff().is_some() {
+            info!("Pushback: Rejected: {} Stalled: {}", rejected, stalled);
+        }
+        if self.metrics.config.compression().is_some() {
+            let raw = window.counter(Stat::CompressionBytesRaw);
+            let stored = window.counter(Stat::CompressionBytesStored);
+            let ratio = if stored == 0 {
+                0.0
+            } else {
+                raw as f64 / stored as f64
+            };
+            info!(
+                "Compression: Ratio: {:.2} Corrupted: {}",
+                ratio,
+                window.counter(Stat::CompressionCorrupted),
+            );
+            self.display_percentiles(&window, Stat::CompressionCompress, "Compress", 1, "ns");
+            self.display_percentiles(&window, Stat::CompressionDecompress, "Decompress", 1, "ns");
+        }
+        if !self.metrics.config.chaos().is_empty() {
+            info!(
+                "Chaos: Active: {} Delayed: {} Disconnected: {} Blackholed: {}",
+                self.metrics.reading(&Stat::ChaosActive).unwrap_or(0),
+                window.counter(Stat::ChaosDelayed),
+                window.counter(Stat::ChaosDisconnected),
+                window.counter(Stat::ChaosBlackholed),
+            );
+        }
+        self.display_percentiles(
+            &window,
+            Stat::ConnectionsLatency,
+            "Connect Latency",
+            1000,
+            "us",
+        );
+        self.display_percentiles(
+            &window,
+            Stat::ResponsesLatency,
+            "Request Latency",
+            1000,
+            "us",
+        );
+        if self.metrics.config.close_rate().is_some() {
+            self.display_percentiles(
+                &window,
+                Stat::ResponsesReconnectLatency,
+                "Reconnect Latency",
+                1000,
+                "us",
+            );
+        }
+        self.display_trends(&window);
+        for exemplar in self.metrics.take_exemplars() {
+            info!("Exemplar: {}", exemplar.describe());
+        }
+        self.previous = snapshot.clone();
     }
 
-    fn rate(&self, stat: &Stat, current: &HashMap<Stat, u64>) -> f64 {
-        let dv = self.delta_count(stat, current) as f64;
+    /// add the window to the trends and draw them
+    fn display_trends(&mut self, window: &Export) {
+        let rate = self.rate(&Stat::ResponsesTotal, window).round() as u64;
+        let p99 = window.percentile(Stat::ResponsesLatency, 99.0);
+        let (throughput, latency) = match self.sparklines {
+            Some(ref mut sparklines) => sparklines,
+            None => return,
+        };
+        throughput.push(rate);
+        // windows without responses have no latency to show
+        if let Some(p99) = p99 {
+            latency.push(p99 / 1000);
+        }
+        let (rate_min, rate_max) = throughput.range();
+        let (p99_min, p99_max) = latency.range();
+        info!(
+            "Trend: Rate: {} ({}-{} rps) p99: {} ({}-{} us)",
+            throughput.render(),
+            rate_min,
+            rate_max,
+            latency.render(),
+            p99_min,
+            p99_max,
+        );
+    }
+
+    fn rate(&self, stat: &Stat, window: &Export) -> f64 {
+        let dv = window.counter(*stat) as f64;
         let dt =
             self.interval.as_secs() as f64 + self.interval.subsec_nanos() as f64 / 1000000000.0;
         dv / dt
     }
 
-    fn delta_count(&self, stat: &Stat, current: &HashMap<Stat, u64>) -> u64 {
-        current
-            .get(stat)
-            .unwrap_or(&0)
-            .saturating_sub(*self.previous.get(stat).unwrap_or(&0))
-    }
-
-    fn delta_percent(&self, a: &Stat, b: &Stat, current: &HashMap<Stat, u64>) -> f64 {
-        let da = self.delta_count(a, current) as f64;
-        let db = self.delta_count(b, current) as f64;
+    fn delta_percent(&self, a: &Stat, b: &Stat, window: &Export) -> f64 {
+        let da = window.counter(*a) as f64;
+        let db = window.counter(*b) as f64;
         if db == 0.0 {
             100.0
         } else {
@@ -145,9 +292,9 @@ impl StandardOut {
         }
     }
 
-    fn hitrate(&self, a: &Stat, b: &Stat, current: &HashMap<Stat, u64>) -> f64 {
-        let da = self.delta_count(a, current) as f64;
-        let db = da + self.delta_count(b, current) as f64;
+    fn hitrate(&self, a: &Stat, b: &Stat, window: &Export) -> f64 {
+        let da = window.counter(*a) as f64;
+        let db = da + window.counter(*b) as f64;
         if db == 0.0 {
             100.0
         } else {
@@ -155,42 +302,79 @@ impl StandardOut {
         }
     }
 
-    fn display_percentiles(&self, stat: Stat, label: &str, divisor: u64, unit: &str) {
-        let p25 = self
-            .metrics
-            .percentile(&stat, 25.0)
+    /// the percentage of the requests sent which failed or timed out
+    fn error_rate(&self, window: &Export) -> f64 {
+        let sent = window.counter(Stat::RequestsDequeued) as f64;
+        let errors =
+            (window.counter(Stat::ResponsesError) + window.counter(Stat::RequestsTimeout)) as f64;
+        if sent == 0.0 {
+            0.0
+        } else {
+            100.0 * errors / sent
+        }
+    }
+
+    /// the share of client thread time, in nanoseconds, used by the stat
+    fn thread_percent(&self, stat: &Stat, window: &Export) -> f64 {
+        let used = window.counter(*stat) as f64;
+        // a coordinator's metrics include the client threads of each agent
+        let threads = self.metrics.config.clients() * self.metrics.config.agents().len().max(1);
+        let available = threads as f64
+            * (self.interval.as_secs() as f64 * SECOND as f64
+                + self.interval.subsec_nanos() as f64);
+        if available == 0.0 {
+            0.0
+        } else {
+            100.0 * used / available
+        }
+    }
+
+    fn syscalls_per_request(&self, window: &Export) -> f64 {
+        let syscalls = window.counter(Stat::SyscallsRead) + window.counter(Stat::SyscallsWrite);
+        let requests = window.counter(Stat::RequestsDequeued);
+        if requests == 0 {
+            0.0
+        } else {
+            syscalls as f64 / requests as f64
+        }
+    }
+
+    fn display_percentiles(
+        &self,
+        window: &Export,
+        stat: Stat,
+        label: &str,
+        divisor: u64,
+        unit: &str,
+    ) {
+        let p25 = window
+            .percentile(stat, 25.0)
             .map(|v| format!("{}", v / divisor))
-            .unwrap_or_else(|_| "none".to_string());
-        let p50 = self
-            .metrics
-            .percentile(&stat, 50.0)
+            .unwrap_or_else(|| "none".to_string());
+        let p50 = window
+            .percentile(stat, 50.0)
             .map(|v| format!("{}", v / divisor))
-            .unwrap_or_else(|_| "none".to_string());
-        let p75 = self
-            .metrics
-            .percentile(&stat, 75.0)
+            .unwrap_or_else(|| "none".to_string());
+        let p75 = window
+            .percentile(stat, 75.0)
             .map(|v| format!("{}", v / divisor))
-            .unwrap_or_else(|_| "none".to_string());
-        let p90 = self
-            .metrics
-            .percentile(&stat, 90.0)
+            .unwrap_or_else(|| "none".to_string());
+        let p90 = window
+            .percentile(stat, 90.0)
             .map(|v| format!("{}", v / divisor))
-            .unwrap_or_else(|_| "none".to_string());
-        let p99 = self
-            .metrics
-            .percentile(&stat, 99.0)
+            .unwrap_or_else(|| "none".to_string());
+        let p99 = window
+            .percentile(stat, 99.0)
             .map(|v| format!("{}", v / divisor))
-            .unwrap_or_else(|_| "none".to_string());
-        let p999 = self
-            .metrics
-            .percentile(&stat, 99.9)
+            .unwrap_or_else(|| "none".to_string());
+        let p999 = window
+            .percentile(stat, 99.9)
             .map(|v| format!("{}", v / divisor))
-            .unwrap_or_else(|_| "none".to_string());
-        let p9999 = self
-            .metrics
-            .percentile(&stat, 99.99)
+            .unwrap_or_else(|| "none".to_string());
+        let p9999 = window
+            .percentile(stat, 99.99)
             .map(|v| format!("{}", v / divisor))
-            .unwrap_or_else(|_| "none".to_string());
+            .unwrap_or_else(|| "none".to_string());
         info!(
             "{} ({}): p25: {} p50: {} p75: {} p90: {} p99: {} p999: {} p9999: {}",
             label, unit, p25, p50, p75, p90, p99, p999, p9999
@@ -198,10 +382,34 @@ impl StandardOut {
     }
 }
 
+/// a percentage as a gauge, which is in hundredths of a percent
+pub(crate) fn percent_gauge(percent: f64) -> u64 {
+    (percent * 100.0).round() as u64
+}
+
+/// latency readings for the waterfall by the unix second and the value
+pub type HeatmapReadings = HashMap<(u64, u64), u64>;
+
 #[derive(Clone)]
 pub struct Metrics {
     inner: Arc<rustcommon_metrics::Metrics<AtomicU64, AtomicU32>>,
     heatmap: Arc<Option<Arc<AtomicHeatmap<u64, AtomicU32>>>>,
+    histograms: Arc<HashMap<Stat, Histogram>>,
+    // the histograms at the last snapshot and their change over the window
+    // which ended there, which the percentiles are calculated from
+    window: Arc<Mutex<(Export, Export)>>,
+    // latency readings which haven't been taken by `take_heatmap()` yet, by
+    // the unix second and the value. Agents keep them so that the waterfall
+    // of the coordinator covers the whole run.
+    pending_heatmap: Option<Arc<Mutex<HeatmapReadings>>>,
+    // an instant and the system time it corresponds to, to convert the time
+    // of heatmap readings to and from unix seconds
+    epoch: (Instant, SystemTime),
+    exemplars: Arc<Mutex<Exemplars>>,
+    popularity: Arc<Mutex<Popularity>>,
+    samples: Arc<Mutex<Samples>>,
+    // the start of the windows, once the run has started
+    window_start: Arc<Mutex<Option<Instant>>>,
     config: Arc<Config>,
 }
 
@@ -214,8 +422,21 @@ impl Metrics {
         self.inner.reading(stat)
     }
 
-    pub fn percentile(&self, stat: &Stat, percentile: f64) -> Result<u64, MetricsError> {
-        self.inner.percentile(stat, percentile)
+    /// the value of a distribution at the percentile over the window which
+    /// ended at the last snapshot
+    pub fn percentile(&self, stat: &Stat, percentile: f64) -> Option<u64> {
+        self.window.lock().unwrap().1.percentile(*stat, percentile)
+    }
+
+    /// take the snapshot of the window which just ended, which is the export
+    /// of the metrics at its end. The percentiles are calculated over the
+    /// change since the previous snapshot from then on.
+    pub fn snapshot(&self) -> Export {
+        let current = self.export();
+        let mut window = self.window.lock().unwrap();
+        window.1 = current.since(&window.0);
+        window.0 = current.clone();
+        current
     }
 
     pub fn new(config: Arc<Config>) -> Self {
@@ -240,6 +461,23 @@ impl Metrics {
         let metrics = Self {
             inner: Arc::new(rustcommon_metrics::Metrics::new()),
             heatmap: Arc::new(heatmap),
+            histograms: Arc::new(
+                Stat::iter()
+                    .filter(|stat| matches!(stat.source(), Source::Distribution))
+                    .map(|stat| (stat, Histogram::new()))
+                    .collect(),
+            ),
+            exemplars: Arc::new(Mutex::new(Exemplars::new(config.exemplars().unwrap_or(0)))),
+            popularity: Arc::new(Mutex::new(Popularity::new())),
+            samples: Arc::new(Mutex::new(Samples::default())),
+            window: Arc::new(Mutex::new((Export::new(), Export::new()))),
+            pending_heatmap: if config.agent() {
+                Some(Arc::new(Mutex::new(HashMap::new())))
+            } else {
+                None
+            },
+            epoch: (Instant::now(), SystemTime::now()),
+            window_start: Arc::new(Mutex::new(None)),
             config,
         };
         metrics.register();
@@ -249,62 +487,251 @@ impl Metrics {
     pub fn register(&self) {
         for stat in Stat::iter() {
             self.inner.register(&stat);
-            match stat {
-                Stat::ConnectionsLatency
-                | Stat::ResponsesLatency
-                | Stat::KeySize
-                | Stat::ValueSize => {
-                    self.inner.add_summary(
-                        &stat,
-                        Summary::heatmap(
-                            1_000_000_000,
-                            3,
-                            Duration::new(self.config.interval().try_into().unwrap(), 0),
-                            Duration::new((self.config.interval() as f64 / 10.0).ceil() as u64, 0),
-                        ),
-                    );
-                }
-                _ => {}
-            }
+            // the distributions are kept in the histograms, which are the
+            // source of their percentiles
             self.inner.add_output(&stat, Output::Reading);
-            for percentile in &[50.0, 75.0, 90.0, 99.0, 99.9, 99.99] {
-                self.inner
-                    .add_output(&stat, Output::Percentile(*percentile))
+        }
+    }
+
+    /// record readings for the calling thread locally until `flush()` is
+    /// called, instead of updating the shared metrics for each reading
+    pub fn enable_local(&self) {
+        LOCAL.with(|local| *local.borrow_mut() = Some(Local::new()));
+    }
+
+    /// merge the calling thread's local readings into the shared metrics
+    pub fn flush(&self) {
+        let batch = LOCAL.with(|local| local.borrow_mut().as_mut().map(|l| l.take()));
+        if let Some(batch) = batch {
+            for (stat, value) in batch.counters {
+                let _ = self.inner.increment_counter(&stat, value);
+            }
+            for (stat, value, count) in batch.buckets {
+                self.record_count(&stat, value, count);
+            }
+            for (time, value, count) in batch.heatmap {
+                self.record_heatmap(time, value, count);
             }
         }
     }
 
-    pub fn increment(&self, statistic: &dyn Statistic<AtomicU64, AtomicU32>) {
-        let _ = self.inner.increment_counter(statistic, 1);
+    pub fn increment(&self, statistic: &Stat) {
+        self.add(statistic, 1)
     }
 
-    pub fn time_interval(
-        &self,
-        statistic: &dyn Statistic<AtomicU64, AtomicU32>,
-        start: Instant,
-        stop: Instant,
-    ) {
+    pub fn add(&self, statistic: &Stat, value: u64) {
+        let recorded = LOCAL.with(|local| match local.borrow_mut().as_mut() {
+            Some(local) => {
+                local.add(*statistic, value);
+                true
+            }
+            None => false,
+        });
+        if !recorded {
+            let _ = self.inner.increment_counter(statistic, value);
+        }
+    }
+
+    /// set a gauge to the given value, gauges are always written to the shared
+    /// metrics
+    pub fn gauge(&self, statistic: &Stat, value: u64) {
+        let _ = self.inner.record_gauge(statistic, Instant::now(), value);
+    }
+
+    pub fn time_interval(&self, statistic: &Stat, start: Instant, stop: Instant) {
         let duration = stop - start;
         let value = duration.as_secs() * SECOND as u64 + duration.subsec_nanos() as u64;
-        let _ = self.inner.record_bucket(statistic, start, value, 1);
+        self.record(statistic, value);
     }
 
-    pub fn distribution(&self, statistic: &dyn Statistic<AtomicU64, AtomicU32>, value: u64) {
-        let _ = self
-            .inner
-            .record_bucket(statistic, Instant::now(), value, 1);
+    pub fn distribution(&self, statistic: &Stat, value: u64) {
+        self.record(statistic, value);
+    }
+
+    fn record(&self, statistic: &Stat, value: u64) {
+        let recorded = LOCAL.with(|local| match local.borrow_mut().as_mut() {
+            Some(local) => {
+                local.record(*statistic, value);
+                true
+            }
+            None => false,
+        });
+        if !recorded {
+            self.record_count(statistic, value, 1);
+        }
+    }
+
+    /// record `count` instances of a value for a distribution
+    pub fn record_count(&self, statistic: &Stat, value: u64, count: u32) {
+        if let Some(histogram) = self.histograms.get(statistic) {
+            histogram.increment(value, count as u64);
+        }
+    }
+
+    /// export the counters and distributions so they can be merged into the
+    /// metrics of another instance
+    pub fn export(&self) -> Export {
+        let mut export = Export::new();
+        for stat in Stat::iter() {
+            match stat.source() {
+                Source::Counter if stat != Stat::Window => {
+                    export
+                        .counters
+                        .insert(stat, self.reading(&stat).unwrap_or(0));
+                }
+                Source::Distribution => {
+                    if let Some(histogram) = self.histogram(&stat) {
+                        for (value, count) in histogram.buckets() {
+                            export.buckets.insert((stat, value), count);
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+        export
+    }
+
+    /// add the readings from an export to these metrics
+    pub fn merge(&self, export: &Export) {
+        for (stat, value) in &export.counters {
+            self.add(stat, *value);
+        }
+        for ((stat, value), count) in &export.buckets {
+            let mut count = *count;
+            while count > 0 {
+                let n = count.min(u32::MAX as u64);
+                self.record_count(stat, *value, n as u32);
+                count -= n;
+            }
+        }
+        for ((second, value), count) in &export.heatmap {
+            let time = self.instant(UNIX_EPOCH + Duration::from_secs(*second));
+            let mut count = *count;
+            while count > 0 {
+                let n = count.min(u32::MAX as u64);
+                self.record_heatmap(time, *value, n as u32);
+                count -= n;
+            }
+        }
+    }
+
+    /// the mergeable histogram of all values recorded for a distribution
+    pub fn histogram(&self, statistic: &Stat) -> Option<&Histogram> {
+        self.histograms.get(statistic)
+    }
+
+    /// move the slowest requests recorded by a client thread into these
+    /// metrics
+    pub fn merge_exemplars(&self, exemplars: &mut Exemplars) {
+        self.exemplars.lock().unwrap().merge(exemplars);
+    }
+
+    /// take the slowest requests since this was last called
+    pub fn take_exemplars(&self) -> Vec<Exemplar> {
+        self.exemplars.lock().unwrap().take()
+    }
+
+    /// move the key counts recorded by a client thread into these metrics
+    pub fn merge_popularity(&self, popularity: &mut Popularity) {
+        self.popularity.lock().unwrap().merge(popularity);
+    }
+
+    /// take the key counts recorded so far
+    pub fn take_popularity(&self) -> Popularity {
+        std::mem::take(&mut *self.popularity.lock().unwrap())
+    }
+
+    /// keep a request and its response, replacing the oldest sample
+    pub fn record_sample(&self, sample: Sample) {
+        self.samples.lock().unwrap().record(sample);
+    }
+
+    /// the most recent requests with their responses, from the oldest
+    pub fn samples(&self) -> Vec<Sample> {
+        self.samples.lock().unwrap().samples()
+    }
+
+    /// set the start of the windows, the client threads flush their readings
+    /// at each window boundary from then on
+    pub fn set_window_start(&self, start: Instant) {
+        *self.window_start.lock().unwrap() = Some(start);
+    }
+
+    /// the first window boundary after `now`, if the windows have started
+    pub fn next_window(&self, now: Instant) -> Option<Instant> {
+        self.window_start.lock().unwrap().map(|start| {
+            windows::next_boundary(start, Duration::new(self.config.interval() as u64, 0), now)
+        })
     }
 
     pub fn zero(&self) {
         self.inner.clear();
+        for histogram in self.histograms.values() {
+            histogram.clear();
+        }
+        *self.window.lock().unwrap() = (Export::new(), Export::new());
+        self.take_heatmap();
+        self.exemplars.lock().unwrap().take();
+        self.take_popularity();
         self.register();
     }
 
     pub fn heatmap_increment(&self, start: Instant, stop: Instant) {
+        if self.heatmap.is_none() && self.pending_heatmap.is_none() {
+            return;
+        }
         let latency = stop - start;
         let latency = latency.as_secs() * SECOND as u64 + latency.subsec_nanos() as u64;
+        let recorded = LOCAL.with(|local| match local.borrow_mut().as_mut() {
+            Some(local) => {
+                local.heatmap(start, latency);
+                true
+            }
+            None => false,
+        });
+        if !recorded {
+            self.record_heatmap(start, latency, 1);
+        }
+    }
+
+    fn record_heatmap(&self, time: Instant, value: u64, count: u32) {
         if let Some(ref heatmap) = *self.heatmap {
-            heatmap.increment(start, latency, 1);
+            heatmap.increment(time, value, count);
+        }
+        if let Some(ref pending) = self.pending_heatmap {
+            let second = self
+                .system_time(time)
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs();
+            *pending.lock().unwrap().entry((second, value)).or_insert(0) += count as u64;
+        }
+    }
+
+    /// take the latency readings for the waterfall since this was last
+    /// called, which are only kept by agents
+    pub fn take_heatmap(&self) -> HeatmapReadings {
+        match self.pending_heatmap {
+            Some(ref pending) => std::mem::take(&mut *pending.lock().unwrap()),
+            None => HashMap::new(),
+        }
+    }
+
+    fn system_time(&self, time: Instant) -> SystemTime {
+        let (instant, system) = self.epoch;
+        if time >= instant {
+            system + (time - instant)
+        } else {
+            system - (instant - time)
+        }
+    }
+
+    fn instant(&self, time: SystemTime) -> Instant {
+        let (instant, system) = self.epoch;
+        match time.duration_since(system) {
+            Ok(after) => instant + after,
+            Err(e) => instant - e.duration(),
         }
     }
 
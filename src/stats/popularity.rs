@@ -0,0 +1,105 @@
+// Copyright 2021 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+//! The number of requests generated for each key over the measured run. It is
+//! written at the end of the run, from the most requested key, so that the
+//! distribution of the keys can be checked against the config and fed into
+//! cache simulators.
+
+use std::collections::HashMap;
+
+#[derive(Clone, Debug, Default)]
+pub struct Popularity {
+    counts: HashMap<String, u64>,
+}
+
+impl Popularity {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record(&mut self, key: &str) {
+        match self.counts.get_mut(key) {
+            Some(count) => *count += 1,
+            None => {
+                self.counts.insert(key.to_string(), 1);
+            }
+        }
+    }
+
+    /// move the counts recorded by another thread into these
+    pub fn merge(&mut self, other: &mut Popularity) {
+        for (key, count) in other.counts.drain() {
+            *self.counts.entry(key).or_insert(0) += count;
+        }
+    }
+
+    /// the number of distinct keys
+    pub fn keys(&self) -> usize {
+        self.counts.len()
+    }
+
+    /// the keys with their counts, from the most requested
+    pub fn ranked(&self) -> Vec<(&str, u64)> {
+        let mut ranked: Vec<(&str, u64)> = self
+            .counts
+            .iter()
+            .map(|(key, count)| (key.as_str(), *count))
+            .collect();
+        ranked.sort_unstable_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+        ranked
+    }
+
+    /// the percentage of the requests which were for the most requested
+    /// `percent` of the keys
+    pub fn share(&self, percent: f64) -> f64 {
+        let ranked = self.ranked();
+        let total: u64 = ranked.iter().map(|(_, count)| count).sum();
+        if total == 0 {
+            return 0.0;
+        }
+        let top = (ranked.len() as f64 * percent / 100.0).ceil() as usize;
+        let requests: u64 = ranked.iter().take(top).map(|(_, count)| count).sum();
+        100.0 * requests as f64 / total as f64
+    }
+
+    /// the counts as CSV, with the rank of each key
+    pub fn csv(&self) -> String {
+        let mut csv = "rank,key,count\n".to_string();
+        for (rank, (key, count)) in self.ranked().iter().enumerate() {
+            csv += &format!("{},{},{}\n", rank + 1, key, count);
+        }
+        csv
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counted() {
+        let mut popularity = Popularity::new();
+        for key in &["b", "a", "b", "c", "b", "a"] {
+            popularity.record(key);
+        }
+        let mut other = Popularity::new();
+        other.record("c");
+        other.record("d");
+        popularity.merge(&mut other);
+        assert_eq!(other.keys(), 0);
+        assert_eq!(popularity.keys(), 4);
+        assert_eq!(
+            popularity.ranked(),
+            vec![("b", 3), ("a", 2), ("c", 2), ("d", 1)]
+        );
+        assert_eq!(popularity.share(25.0), 37.5);
+        assert_eq!(popularity.share(100.0), 100.0);
+        assert_eq!(
+            popularity.csv(),
+            "rank,key,count\n1,b,3\n2,a,2\n3,c,2\n4,d,1\n"
+        );
+        assert_eq!(Popularity::new().share(10.0), 0.0);
+    }
+}
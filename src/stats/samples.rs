@@ -0,0 +1,134 @@
+// Copyright 2021 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+//! A sample of recent requests with their responses, as they were on the
+//! wire. They are served on the admin port, so that what the codec generates
+//! can be checked on a live run without capturing packets.
+
+use serde_json::{json, Value};
+
+use std::collections::VecDeque;
+use std::net::SocketAddr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// how many of the most recent samples are kept
+pub const SAMPLES: usize = 16;
+/// how often each client samples a request
+pub const SAMPLE_INTERVAL: Duration = Duration::from_secs(1);
+/// the most bytes of a request or response which are kept
+const SAMPLE_BYTES: usize = 512;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Sample {
+    /// the time the response was received
+    pub time: SystemTime,
+    pub client: usize,
+    pub endpoint: SocketAddr,
+    pub request: Vec<u8>,
+    pub response: Vec<u8>,
+    /// how the codec decoded the response
+    pub outcome: String,
+}
+
+impl Sample {
+    pub fn new(
+        client: usize,
+        endpoint: SocketAddr,
+        request: &[u8],
+        response: &[u8],
+        outcome: String,
+    ) -> Self {
+        Self {
+            time: SystemTime::now(),
+            client,
+            endpoint,
+            request: request[..request.len().min(SAMPLE_BYTES)].to_vec(),
+            response: response[..response.len().min(SAMPLE_BYTES)].to_vec(),
+            outcome,
+        }
+    }
+
+    pub fn json(&self) -> Value {
+        let time = self.time.duration_since(UNIX_EPOCH).unwrap_or_default();
+        json!({
+            "time": time.as_secs_f64(),
+            "client": self.client,
+            "endpoint": self.endpoint.to_string(),
+            "request": {
+                "hex": hex(&self.request),
+                "text": text(&self.request),
+            },
+            "response": {
+                "hex": hex(&self.response),
+                "text": text(&self.response),
+                "outcome": self.outcome,
+            },
+        })
+    }
+}
+
+/// The most recent samples, from the oldest.
+#[derive(Clone, Debug, Default)]
+pub struct Samples {
+    samples: VecDeque<Sample>,
+}
+
+impl Samples {
+    pub fn record(&mut self, sample: Sample) {
+        self.samples.push_back(sample);
+        while self.samples.len() > SAMPLES {
+            self.samples.pop_front();
+        }
+    }
+
+    pub fn samples(&self) -> Vec<Sample> {
+        self.samples.iter().cloned().collect()
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// the bytes as text, with the bytes which aren't printable escaped so that
+/// binary protocols can be read as well
+fn text(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .flat_map(|b| std::ascii::escape_default(*b))
+        .map(char::from)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn samples() {
+        let endpoint = "127.0.0.1:12321".parse().unwrap();
+        let mut samples = Samples::default();
+        for i in 0..(SAMPLES + 2) {
+            samples.record(Sample::new(
+                i,
+                endpoint,
+                b"get 0\r\n",
+                b"END\r\n",
+                "Miss".to_string(),
+            ));
+        }
+        let samples = samples.samples();
+        assert_eq!(samples.len(), SAMPLES);
+        assert_eq!(samples[0].client, 2);
+
+        let json = samples[0].json();
+        assert_eq!(json["request"]["hex"], "67657420300d0a");
+        assert_eq!(json["request"]["text"], "get 0\\r\\n");
+        assert_eq!(json["response"]["outcome"], "Miss");
+
+        let sample = Sample::new(0, endpoint, &[0; 1024], &[0xff], String::new());
+        assert_eq!(sample.request.len(), SAMPLE_BYTES);
+        assert_eq!(text(&sample.response), "\\xff");
+    }
+}
@@ -2,53 +2,80 @@
 // Licensed under the Apache License, Version 2.0
 // http://www.apache.org/licenses/LICENSE-2.0
 
-use std::collections::HashMap;
 use std::sync::Arc;
 use std::time::Instant;
 
-use rustcommon_metrics::*;
+use rustcommon_metrics::{Output, Source, Statistic};
+use strum::IntoEnumIterator;
+
+use super::{Metrics, Stat};
+
+/// the percentiles which are exported for each distribution
+const PERCENTILES: [f64; 6] = [50.0, 75.0, 90.0, 99.0, 99.9, 99.99];
+
+/// The metrics of a client group, which are labelled with the group's name.
+struct Group {
+    name: String,
+    metrics: Arc<Metrics>,
+    snapshot: Vec<(String, u64)>,
+}
 
 pub struct MetricsSnapshot {
-    metrics: Arc<Metrics<AtomicU64, AtomicU32>>,
-    snapshot: HashMap<Metric<AtomicU64, AtomicU32>, u64>,
+    metrics: Arc<Metrics>,
+    snapshot: Vec<(String, u64)>,
+    groups: Vec<Group>,
     refreshed: Instant,
     count_label: Option<String>,
 }
 
 impl MetricsSnapshot {
-    pub fn new(metrics: Arc<Metrics<AtomicU64, AtomicU32>>, count_label: Option<&str>) -> Self {
+    pub fn new(metrics: Arc<Metrics>, count_label: Option<&str>) -> Self {
         Self {
             metrics,
-            snapshot: HashMap::new(),
+            snapshot: Vec::new(),
+            groups: Vec::new(),
             refreshed: Instant::now(),
             count_label: count_label.map(std::string::ToString::to_string),
         }
     }
 
+    /// include the metrics of a client group, labelled with its name
+    pub fn add_group(&mut self, name: &str, metrics: Arc<Metrics>) {
+        self.groups.push(Group {
+            name: name.to_string(),
+            metrics,
+            snapshot: Vec::new(),
+        });
+    }
+
+    /// take the readings of the counters and gauges, and the percentiles of
+    /// the distributions over the last window
     pub fn refresh(&mut self) {
-        self.snapshot = self.metrics.snapshot();
+        self.snapshot = readings(&self.metrics, self.count_label.as_deref());
+        for group in &mut self.groups {
+            group.snapshot = readings(&group.metrics, self.count_label.as_deref());
+        }
         self.refreshed = Instant::now();
     }
 
+    /// the readings of the groups, each with the name of its group
+    fn group_readings(&self) -> impl Iterator<Item = (&str, &str, u64)> {
+        self.groups.iter().flat_map(|group| {
+            group
+                .snapshot
+                .iter()
+                .map(move |(label, value)| (group.name.as_str(), label.as_str(), *value))
+        })
+    }
+
     pub fn prometheus(&self) -> String {
         let mut data = Vec::new();
-        for (metric, value) in &self.snapshot {
-            let label = metric.statistic().name();
-            let output = metric.output();
-            match output {
-                Output::Reading => {
-                    if let Some(ref count_label) = self.count_label {
-                        data.push(format!("{}/{} {}", label, count_label, value));
-                    } else {
-                        data.push(format!("{} {}", label, value));
-                    }
-                }
-                Output::Percentile(percentile) => {
-                    data.push(format!("{}/p{:02} {}", label, percentile, value));
-                }
-            }
+        for (label, value) in &self.snapshot {
+            data.push(format!("{} {}", label, value));
+        }
+        for (group, label, value) in self.group_readings() {
+            data.push(format!("{}{{group=\"{}\"}} {}", label, group, value));
         }
-        data.sort();
         let mut content = data.join("\n");
         content += "\n";
         let parts: Vec<&str> = content.split('/').collect();
@@ -57,23 +84,12 @@ impl MetricsSnapshot {
 
     pub fn human(&self) -> String {
         let mut data = Vec::new();
-        for (metric, value) in &self.snapshot {
-            let label = metric.statistic().name();
-            let output = metric.output();
-            match output {
-                Output::Reading => {
-                    if let Some(ref count_label) = self.count_label {
-                        data.push(format!("{}/{}: {}", label, count_label, value));
-                    } else {
-                        data.push(format!("{}: {}", label, value));
-                    }
-                }
-                Output::Percentile(percentile) => {
-                    data.push(format!("{}/p{:02}: {}", label, percentile, value));
-                }
-            }
+        for (label, value) in &self.snapshot {
+            data.push(format!("{}: {}", label, value));
+        }
+        for (group, label, value) in self.group_readings() {
+            data.push(format!("group/{}/{}: {}", group, label, value));
         }
-        data.sort();
         let mut content = data.join("\n");
         content += "\n";
         content
@@ -85,23 +101,12 @@ impl MetricsSnapshot {
             head += "\n  ";
         }
         let mut data = Vec::new();
-        for (metric, value) in &self.snapshot {
-            let label = metric.statistic().name();
-            let output = metric.output();
-            match output {
-                Output::Reading => {
-                    if let Some(ref count_label) = self.count_label {
-                        data.push(format!("\"{}/{}\": {}", label, count_label, value));
-                    } else {
-                        data.push(format!("\"{}\": {}", label, value));
-                    }
-                }
-                Output::Percentile(percentile) => {
-                    data.push(format!("\"{}/p{:02}\": {}", label, percentile, value));
-                }
-            }
+        for (label, value) in &self.snapshot {
+            data.push(format!("\"{}\": {}", label, value));
+        }
+        for (group, label, value) in self.group_readings() {
+            data.push(format!("\"group/{}/{}\": {}", group, label, value));
         }
-        data.sort();
         let body = if pretty {
             data.join(",\n  ")
         } else {
@@ -116,3 +121,26 @@ impl MetricsSnapshot {
         content
     }
 }
+
+fn readings(metrics: &Metrics, count_label: Option<&str>) -> Vec<(String, u64)> {
+    let mut snapshot = Vec::new();
+    for (metric, value) in metrics.inner().snapshot() {
+        let label = metric.statistic().name();
+        if let Output::Reading = metric.output() {
+            if let Some(count_label) = count_label {
+                snapshot.push((format!("{}/{}", label, count_label), value));
+            } else {
+                snapshot.push((label.to_string(), value));
+            }
+        }
+    }
+    for stat in Stat::iter().filter(|s| matches!(s.source(), Source::Distribution)) {
+        for percentile in &PERCENTILES {
+            if let Some(value) = metrics.percentile(&stat, *percentile) {
+                snapshot.push((format!("{}/p{:02}", stat.name(), percentile), value));
+            }
+        }
+    }
+    snapshot.sort();
+    snapshot
+}
@@ -0,0 +1,79 @@
+// Copyright 2021 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+//! A trend line of the last few windows, drawn with one character per window
+//! so that drift in a long run can be seen on the console. The characters are
+//! plain ASCII so that the line survives any log pipeline.
+
+use std::collections::VecDeque;
+
+// from the lowest to the highest value of the windows shown
+const LEVELS: &[u8] = b"_.:-=+*#%@";
+
+#[derive(Clone, Debug)]
+pub struct Sparkline {
+    limit: usize,
+    values: VecDeque<u64>,
+}
+
+impl Sparkline {
+    /// a sparkline of the last `limit` windows
+    pub fn new(limit: usize) -> Self {
+        Self {
+            limit,
+            values: VecDeque::with_capacity(limit + 1),
+        }
+    }
+
+    pub fn push(&mut self, value: u64) {
+        self.values.push_back(value);
+        while self.values.len() > self.limit {
+            self.values.pop_front();
+        }
+    }
+
+    /// the lowest and highest of the windows shown
+    pub fn range(&self) -> (u64, u64) {
+        let min = self.values.iter().min().copied().unwrap_or(0);
+        let max = self.values.iter().max().copied().unwrap_or(0);
+        (min, max)
+    }
+
+    /// draw the windows, scaled from the lowest to the highest of them. A
+    /// flat line is drawn in the middle.
+    pub fn render(&self) -> String {
+        let (min, max) = self.range();
+        let top = LEVELS.len() - 1;
+        self.values
+            .iter()
+            .map(|value| {
+                let level = if max == min {
+                    top / 2
+                } else {
+                    ((value - min) as f64 / (max - min) as f64 * top as f64).round() as usize
+                };
+                LEVELS[level] as char
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rendered() {
+        let mut sparkline = Sparkline::new(4);
+        assert_eq!(sparkline.render(), "");
+        sparkline.push(5);
+        sparkline.push(5);
+        assert_eq!(sparkline.render(), "==");
+        for value in &[0, 10, 20, 30, 40] {
+            sparkline.push(*value);
+        }
+        assert_eq!(sparkline.render(), "_-*@");
+        assert_eq!(sparkline.range(), (10, 40));
+    }
+}
@@ -9,6 +9,14 @@ use strum_macros::{EnumIter, EnumString, IntoStaticStr};
 pub enum Stat {
     #[strum(serialize = "window")]
     Window,
+    /// window boundaries which passed while the main thread was stalled, whose
+    /// readings are reported with the window which ends at the next boundary
+    #[strum(serialize = "window/missed")]
+    WindowMissed,
+    /// flushes by client threads which were too late for the snapshot of the
+    /// window their readings belong to
+    #[strum(serialize = "window/late_flushes")]
+    WindowLateFlushes,
     #[strum(serialize = "requests/enqueued")]
     RequestsEnqueued,
     #[strum(serialize = "requests/dequeued")]
@@ -35,6 +43,10 @@ pub enum Stat {
     ConnectionsLatency,
     #[strum(serialize = "responses/latency")]
     ResponsesLatency,
+    /// the latency of the first response on each connection which replaced
+    /// one closed by the close rate, which isn't in `responses/latency`
+    #[strum(serialize = "responses/latency/reconnect")]
+    ResponsesReconnectLatency,
     #[strum(serialize = "responses/total")]
     ResponsesTotal,
     #[strum(serialize = "responses/ok")]
@@ -45,6 +57,25 @@ pub enum Stat {
     ResponsesHit,
     #[strum(serialize = "responses/miss")]
     ResponsesMiss,
+    /// the hit-rate of the last window, in hundredths of a percent
+    #[strum(serialize = "responses/hitrate")]
+    ResponsesHitrate,
+    /// the share of the requests sent in the last window which failed or
+    /// timed out, in hundredths of a percent
+    #[strum(serialize = "responses/error_rate")]
+    ResponsesErrorRate,
+    /// the requests sent per second in the last window
+    #[strum(serialize = "requests/rate")]
+    RequestsRate,
+    /// the hit-rate of the last warmup window, in hundredths of a percent
+    #[strum(serialize = "warmup/hitrate")]
+    WarmupHitrate,
+    /// the estimated seconds until the warmup reaches its hit-rate
+    #[strum(serialize = "warmup/eta")]
+    WarmupEta,
+    /// the number of sets sent during the warmup
+    #[strum(serialize = "warmup/written")]
+    WarmupWritten,
     #[strum(serialize = "commands/create")]
     CommandsCreate,
     #[strum(serialize = "commands/delete")]
@@ -67,6 +98,75 @@ pub enum Stat {
     CommandsTrim,
     #[strum(serialize = "commands/truncate")]
     CommandsTruncate,
+    #[strum(serialize = "syscalls/read")]
+    SyscallsRead,
+    #[strum(serialize = "syscalls/write")]
+    SyscallsWrite,
+    #[strum(serialize = "ratelimit/target")]
+    RatelimitTarget,
+    #[strum(serialize = "ratelimit/achieved")]
+    RatelimitAchieved,
+    /// how far the achieved rate is from the target, in either direction, in
+    /// hundredths of a percent of the target
+    #[strum(serialize = "ratelimit/divergence")]
+    RatelimitDivergence,
+    #[strum(serialize = "profile/loops")]
+    ProfileLoops,
+    #[strum(serialize = "profile/wait/socket")]
+    ProfileWaitSocket,
+    #[strum(serialize = "profile/wait/ratelimit")]
+    ProfileWaitRatelimit,
+    #[strum(serialize = "profile/cpu")]
+    ProfileCpu,
+    /// the file descriptors the process has open
+    #[strum(serialize = "process/fds/open")]
+    ProcessFdsOpen,
+    /// the soft limit on open files
+    #[strum(serialize = "process/fds/limit")]
+    ProcessFdsLimit,
+    #[strum(serialize = "mirror/matched")]
+    MirrorMatched,
+    #[strum(serialize = "mirror/diverged/outcome")]
+    MirrorDivergedOutcome,
+    #[strum(serialize = "mirror/diverged/value")]
+    MirrorDivergedValue,
+    #[strum(serialize = "mirror/unmatched")]
+    MirrorUnmatched,
+    /// responses refusing the request because the server is out of memory,
+    /// loading or busy
+    #[strum(serialize = "pushback/rejected")]
+    PushbackRejected,
+    /// writes which couldn't be completed because the socket was full
+    #[strum(serialize = "pushback/stalled")]
+    PushbackStalled,
+    /// the highest request rate which ran without push-back, once push-back
+    /// has been seen
+    #[strum(serialize = "pushback/knee")]
+    PushbackKnee,
+    /// the time taken to compress each value which is written
+    #[strum(serialize = "compression/compress")]
+    CompressionCompress,
+    /// the time taken to decompress each value which is read
+    #[strum(serialize = "compression/decompress")]
+    CompressionDecompress,
+    #[strum(serialize = "compression/bytes/raw")]
+    CompressionBytesRaw,
+    #[strum(serialize = "compression/bytes/stored")]
+    CompressionBytesStored,
+    /// hits with a value which didn't decompress
+    #[strum(serialize = "compression/corrupted")]
+    CompressionCorrupted,
+    /// the number of endpoints which were discovered
+    #[strum(serialize = "discovery/endpoints")]
+    DiscoveryEndpoints,
+    #[strum(serialize = "chaos/active")]
+    ChaosActive,
+    #[strum(serialize = "chaos/delayed")]
+    ChaosDelayed,
+    #[strum(serialize = "chaos/disconnected")]
+    ChaosDisconnected,
+    #[strum(serialize = "chaos/blackholed")]
+    ChaosBlackholed,
     #[strum(serialize = "key/size")]
     KeySize,
     #[strum(serialize = "value/size")]
@@ -80,9 +180,27 @@ impl Statistic<AtomicU64, AtomicU32> for Stat {
 
     fn source(&self) -> Source {
         match self {
-            Self::KeySize | Self::ValueSize | Self::ConnectionsLatency | Self::ResponsesLatency => {
-                Source::Distribution
-            }
+            Self::KeySize
+            | Self::ValueSize
+            | Self::ConnectionsLatency
+            | Self::ResponsesLatency
+            | Self::ResponsesReconnectLatency
+            | Self::CompressionCompress
+            | Self::CompressionDecompress => Source::Distribution,
+            Self::ResponsesHitrate
+            | Self::ResponsesErrorRate
+            | Self::RequestsRate
+            | Self::WarmupHitrate
+            | Self::WarmupEta
+            | Self::WarmupWritten
+            | Self::RatelimitTarget
+            | Self::RatelimitAchieved
+            | Self::RatelimitDivergence
+            | Self::PushbackKnee
+            | Self::DiscoveryEndpoints
+            | Self::ProcessFdsOpen
+            | Self::ProcessFdsLimit
+            | Self::ChaosActive => Source::Gauge,
             _ => Source::Counter,
         }
     }
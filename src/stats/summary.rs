@@ -0,0 +1,366 @@
+// Copyright 2021 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+//! The summary of a whole run, printed after the last window so that the
+//! results don't have to be pieced together from the windows. The run is
+//! checked against the thresholds in the config, and each client group
+//! against the thresholds of its own config.
+
+use crate::config::Thresholds;
+use crate::metadata::Metadata;
+use crate::stats::{Export, Histogram, Stat};
+
+use serde_json::{json, Map, Value};
+
+use std::time::Duration;
+
+const PERCENTILES: [(f64, &str); 6] = [
+    (50.0, "p50"),
+    (90.0, "p90"),
+    (99.0, "p99"),
+    (99.9, "p999"),
+    (99.99, "p9999"),
+    (100.0, "max"),
+];
+
+struct Check {
+    name: String,
+    actual: String,
+    passed: bool,
+}
+
+pub struct Summary {
+    elapsed: Duration,
+    windows: u64,
+    export: Export,
+    latency: Histogram,
+    checks: Vec<Check>,
+    groups: Vec<(String, Summary)>,
+}
+
+impl Summary {
+    /// summarize the metrics recorded over the time the run was measured
+    pub fn new(export: Export, elapsed: Duration, windows: u64, thresholds: &Thresholds) -> Self {
+        let latency = Histogram::new();
+        for ((stat, value), count) in &export.buckets {
+            if *stat == Stat::ResponsesLatency {
+                latency.increment(*value, *count);
+            }
+        }
+        let mut summary = Self {
+            elapsed,
+            windows,
+            export,
+            latency,
+            checks: Vec::new(),
+            groups: Vec::new(),
+        };
+        summary.check(thresholds);
+        summary
+    }
+
+    /// include the summary of a client group, which the run only passes if
+    /// the group does too
+    pub fn add_group(&mut self, name: &str, summary: Summary) {
+        self.groups.push((name.to_string(), summary));
+    }
+
+    fn check(&mut self, thresholds: &Thresholds) {
+        for (percentile, limit) in thresholds.latency() {
+            let label = PERCENTILES
+                .iter()
+                .find(|(p, _)| *p == percentile)
+                .map(|(_, label)| *label)
+                .unwrap_or("");
+            let actual = self.latency(percentile);
+            self.checks.push(Check {
+                name: format!("{} <= {}us", label, limit),
+                actual: actual
+                    .map(|v| format!("{}us", v))
+                    .unwrap_or_else(|| "none".to_string()),
+                passed: actual.map(|v| v <= limit).unwrap_or(false),
+            });
+        }
+        if let Some(limit) = thresholds.error_rate() {
+            self.checks.push(Check {
+                name: format!("error_rate <= {}%", limit),
+                actual: format!("{:.2}%", self.error_rate()),
+                passed: self.error_rate() <= limit,
+            });
+        }
+        if let Some(limit) = thresholds.hitrate() {
+            self.checks.push(Check {
+                name: format!("hitrate >= {}%", limit),
+                actual: format!("{:.2}%", self.hitrate()),
+                passed: self.hitrate() >= limit,
+            });
+        }
+        if let Some(limit) = thresholds.throughput() {
+            self.checks.push(Check {
+                name: format!("throughput >= {} rps", limit),
+                actual: format!("{:.2} rps", self.throughput()),
+                passed: self.throughput() >= limit as f64,
+            });
+        }
+    }
+
+    fn count(&self, stat: Stat) -> u64 {
+        *self.export.counters.get(&stat).unwrap_or(&0)
+    }
+
+    /// the request latency at the percentile in microseconds
+    pub fn latency(&self, percentile: f64) -> Option<u64> {
+        self.latency.percentile(percentile).map(|v| v / 1000)
+    }
+
+    /// responses per second
+    pub fn throughput(&self) -> f64 {
+        let elapsed = self.elapsed.as_secs_f64();
+        if elapsed == 0.0 {
+            0.0
+        } else {
+            self.count(Stat::ResponsesTotal) as f64 / elapsed
+        }
+    }
+
+    /// the percentage of requests sent which failed or timed out
+    pub fn error_rate(&self) -> f64 {
+        let sent = self.count(Stat::RequestsDequeued);
+        if sent == 0 {
+            0.0
+        } else {
+            let errors = self.count(Stat::ResponsesError) + self.count(Stat::RequestsTimeout);
+            100.0 * errors as f64 / sent as f64
+        }
+    }
+
+    /// the percentage of lookups which hit
+    pub fn hitrate(&self) -> f64 {
+        let hits = self.count(Stat::ResponsesHit);
+        let lookups = hits + self.count(Stat::ResponsesMiss);
+        if lookups == 0 {
+            100.0
+        } else {
+            100.0 * hits as f64 / lookups as f64
+        }
+    }
+
+    /// whether the run, and each of its groups, is within all of the
+    /// thresholds
+    pub fn passed(&self) -> bool {
+        self.checks.iter().all(|check| check.passed)
+            && self.groups.iter().all(|(_, group)| group.passed())
+    }
+
+    /// whether there are any thresholds to check
+    fn checked(&self) -> bool {
+        !self.checks.is_empty() || self.groups.iter().any(|(_, group)| group.checked())
+    }
+
+    pub fn print(&self) {
+        info!("-----");
+        info!(
+            "Summary: Duration: {:.2}s Windows: {}",
+            self.elapsed.as_secs_f64(),
+            self.windows
+        );
+        info!(
+            "Summary: Connections: Attempts: {} Opened: {} Errors: {} Timeouts: {}",
+            self.count(Stat::ConnectionsTotal),
+            self.count(Stat::ConnectionsOpened),
+            self.count(Stat::ConnectionsError),
+            self.count(Stat::ConnectionsTimeout),
+        );
+        info!(
+            "Summary: Requests: Sent: {} Timeout: {}",
+            self.count(Stat::RequestsDequeued),
+            self.count(Stat::RequestsTimeout),
+        );
+        info!(
+            "Summary: Responses: Total: {} Ok: {} Error: {} Hit: {} Miss: {}",
+            self.count(Stat::ResponsesTotal),
+            self.count(Stat::ResponsesOk),
+            self.count(Stat::ResponsesError),
+            self.count(Stat::ResponsesHit),
+            self.count(Stat::ResponsesMiss),
+        );
+        info!(
+            "Summary: Throughput: {:.2} rps Error-rate: {:.2}% Hit-rate: {:.2}%",
+            self.throughput(),
+            self.error_rate(),
+            self.hitrate(),
+        );
+        let percentiles: Vec<String> = PERCENTILES
+            .iter()
+            .map(|(percentile, label)| {
+                let value = self
+                    .latency(*percentile)
+                    .map(|v| v.to_string())
+                    .unwrap_or_else(|| "none".to_string());
+                format!("{}: {}", label, value)
+            })
+            .collect();
+        info!("Summary: Request Latency (us): {}", percentiles.join(" "));
+        for check in &self.checks {
+            info!(
+                "Summary: Threshold: {} Actual: {} {}",
+                check.name,
+                check.actual,
+                if check.passed { "Pass" } else { "Fail" },
+            );
+        }
+        for (name, group) in &self.groups {
+            info!(
+                "Group: {}: Summary: Throughput: {:.2} rps Errors: {:.2}% Hit-rate: {:.2}% \
+                 p50: {} p99: {} p999: {}",
+                name,
+                group.throughput(),
+                group.error_rate(),
+                group.hitrate(),
+                micros(group.latency(50.0)),
+                micros(group.latency(99.0)),
+                micros(group.latency(99.9)),
+            );
+            for check in &group.checks {
+                info!(
+                    "Group: {}: Summary: Threshold: {} Actual: {} {}",
+                    name,
+                    check.name,
+                    check.actual,
+                    if check.passed { "Pass" } else { "Fail" },
+                );
+            }
+        }
+        if self.checked() {
+            if self.passed() {
+                info!("Summary: Result: Pass");
+            } else {
+                error!("Summary: Result: Fail");
+            }
+        }
+    }
+
+    /// the summary as a JSON document, with the metadata of the run
+    pub fn json(&self, metadata: &Metadata) -> String {
+        let mut summary = self.value();
+        summary["run"] = metadata.json();
+        serde_json::to_string_pretty(&summary).unwrap() + "\n"
+    }
+
+    /// the summary as a JSON object, with the summary of each group under
+    /// `groups`
+    fn value(&self) -> Value {
+        let mut latency = Map::new();
+        for (percentile, label) in PERCENTILES.iter() {
+            latency.insert(label.to_string(), json!(self.latency(*percentile)));
+        }
+        let thresholds: Vec<Value> = self
+            .checks
+            .iter()
+            .map(|check| {
+                json!({
+                    "threshold": check.name,
+                    "actual": check.actual,
+                    "passed": check.passed,
+                })
+            })
+            .collect();
+        let mut summary = json!({
+            "duration": self.elapsed.as_secs_f64(),
+            "windows": self.windows,
+            "connections": {
+                "attempts": self.count(Stat::ConnectionsTotal),
+                "opened": self.count(Stat::ConnectionsOpened),
+                "error": self.count(Stat::ConnectionsError),
+                "timeout": self.count(Stat::ConnectionsTimeout),
+            },
+            "requests": {
+                "sent": self.count(Stat::RequestsDequeued),
+                "timeout": self.count(Stat::RequestsTimeout),
+            },
+            "responses": {
+                "total": self.count(Stat::ResponsesTotal),
+                "ok": self.count(Stat::ResponsesOk),
+                "error": self.count(Stat::ResponsesError),
+                "hit": self.count(Stat::ResponsesHit),
+                "miss": self.count(Stat::ResponsesMiss),
+            },
+            "throughput": self.throughput(),
+            "error_rate": self.error_rate(),
+            "hitrate": self.hitrate(),
+            "latency_us": latency,
+            "thresholds": thresholds,
+            "passed": self.passed(),
+        });
+        if !self.groups.is_empty() {
+            let groups: Map<String, Value> = self
+                .groups
+                .iter()
+                .map(|(name, group)| (name.clone(), group.value()))
+                .collect();
+            summary["groups"] = Value::Object(groups);
+        }
+        summary
+    }
+}
+
+fn micros(latency: Option<u64>) -> String {
+    latency
+        .map(|v| format!("{}us", v))
+        .unwrap_or_else(|| "none".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn summary(thresholds: &str) -> Summary {
+        let export = Export::parse(
+            "counter requests/dequeued 1000\n\
+             counter requests/timeout 5\n\
+             counter responses/total 995\n\
+             counter responses/error 5\n\
+             counter responses/hit 800\n\
+             counter responses/miss 200\n\
+             bucket responses/latency 102400 900\n\
+             bucket responses/latency 2048000 95\n",
+        )
+        .unwrap();
+        let thresholds: Thresholds = toml::from_str(thresholds).unwrap();
+        Summary::new(export, Duration::from_secs(10), 2, &thresholds)
+    }
+
+    #[test]
+    fn totals() {
+        let summary = summary("");
+        assert_eq!(summary.throughput(), 99.5);
+        assert_eq!(summary.error_rate(), 1.0);
+        assert_eq!(summary.hitrate(), 80.0);
+        assert_eq!(summary.latency(50.0), Some(102));
+        assert_eq!(summary.latency(99.0), Some(2048));
+        assert!(summary.passed());
+    }
+
+    #[test]
+    fn thresholds() {
+        assert!(summary("p50 = 102\nerror_rate = 1.0\nthroughput = 99").passed());
+        assert!(!summary("p99 = 1000").passed());
+        assert!(!summary("hitrate = 90").passed());
+
+        let summary = summary("p50 = 102\np99 = 1000");
+        let checks: Vec<bool> = summary.checks.iter().map(|c| c.passed).collect();
+        assert_eq!(checks, vec![true, false]);
+    }
+
+    #[test]
+    fn groups() {
+        let mut run = summary("p50 = 102");
+        run.add_group("redis", summary("p99 = 1000"));
+        assert!(!run.passed());
+        let value = run.value();
+        assert_eq!(value["passed"], json!(false));
+        assert_eq!(value["groups"]["redis"]["passed"], json!(false));
+        assert_eq!(value["groups"]["redis"]["latency_us"]["p99"], json!(2048));
+    }
+}
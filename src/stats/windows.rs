@@ -0,0 +1,124 @@
+// Copyright 2021 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+//! The window boundaries of a run. Boundaries are at whole multiples of the
+//! interval from the start of the run, so that windows don't drift when the
+//! main thread is late. Client threads flush their readings as they cross a
+//! boundary and the window's snapshot is taken shortly after it, so that the
+//! readings of each window are all in its snapshot and none of the next
+//! window's are. Boundaries which pass while the process is stalled are
+//! detected rather than reported as a burst of empty windows.
+
+use std::time::{Duration, Instant};
+
+/// how long after a boundary the snapshot of the window is taken, which
+/// leaves the client threads time to flush, as they poll every millisecond
+pub const SETTLE: Duration = Duration::from_millis(20);
+
+/// the first boundary after `now`
+pub fn next_boundary(start: Instant, interval: Duration, now: Instant) -> Instant {
+    let interval = interval.as_nanos().max(1);
+    let elapsed = now.saturating_duration_since(start).as_nanos();
+    let windows = elapsed / interval + 1;
+    start + Duration::from_nanos((windows * interval) as u64)
+}
+
+/// A window boundary which has passed.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Tick {
+    /// the latest boundary which has passed
+    pub boundary: Instant,
+    /// the boundaries before it which passed since the last tick
+    pub missed: u64,
+}
+
+pub struct Windows {
+    start: Instant,
+    interval: Duration,
+    next: Instant,
+}
+
+impl Windows {
+    /// the windows of a run which starts at `start`
+    pub fn new(start: Instant, interval: Duration) -> Self {
+        Self {
+            start,
+            interval,
+            next: start + interval,
+        }
+    }
+
+    pub fn start(&self) -> Instant {
+        self.start
+    }
+
+    pub fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    /// the next boundary
+    pub fn next(&self) -> Instant {
+        self.next
+    }
+
+    /// the time at which the snapshot for the next boundary is due
+    pub fn due(&self) -> Instant {
+        self.next + SETTLE
+    }
+
+    /// the boundary whose snapshot is due at `now`, if any. When more than
+    /// one boundary has passed, only the latest is ticked and the others are
+    /// counted as missed.
+    pub fn tick(&mut self, now: Instant) -> Option<Tick> {
+        if now < self.due() {
+            return None;
+        }
+        let next = next_boundary(self.start, self.interval, now - SETTLE);
+        let boundary = next - self.interval;
+        let passed = (boundary - self.next).as_nanos() / self.interval.as_nanos().max(1);
+        self.next = next;
+        Some(Tick {
+            boundary,
+            missed: passed as u64,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn windows() {
+        let start = Instant::now();
+        let second = Duration::from_secs(1);
+        assert_eq!(next_boundary(start, second, start), start + second);
+        assert_eq!(
+            next_boundary(start, second, start + second * 3 / 2),
+            start + second * 2
+        );
+
+        let mut windows = Windows::new(start, second);
+        assert_eq!(windows.tick(start + second), None);
+        assert_eq!(
+            windows.tick(start + second + SETTLE),
+            Some(Tick {
+                boundary: start + second,
+                missed: 0
+            })
+        );
+        assert_eq!(windows.next(), start + second * 2);
+        assert_eq!(windows.tick(start + second * 2), None);
+
+        // stalled past two more boundaries
+        assert_eq!(
+            windows.tick(start + second * 9 / 2),
+            Some(Tick {
+                boundary: start + second * 4,
+                missed: 2
+            })
+        );
+        assert_eq!(windows.next(), start + second * 5);
+    }
+}